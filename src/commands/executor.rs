@@ -1,11 +1,14 @@
-use super::{RedisCommand, RedisResponse};
+use super::{GetExOption, RedisCommand, RedisResponse, SetOptions, ZAddOptions};
 use crate::server::event_loop_handle::EventLoopHandle;
 use crate::storage::repl_config::ReplConfig;
+use crate::storage::stream_member::StreamReadResult;
+use crate::storage::zset_member::{ScoreBound, ZAddOutcome};
 use crate::storage::{
-    MemoryStorage, Replication, Storage, StorageGeo, StorageList, StoragePubSub, StorageStream,
-    StorageZSet,
+    MemoryStorage, Replication, Storage, StorageGeo, StorageHash, StorageList, StoragePubSub,
+    StorageSet, StorageStream, StorageZSet,
 };
 use mio::Token;
+use std::collections::HashMap;
 
 pub trait CommandExecutor {
     fn execute(&mut self, command: RedisCommand, token: Token) -> RedisResponse;
@@ -13,6 +16,10 @@ pub trait CommandExecutor {
 pub struct RedisCommandExecutor {
     storage: MemoryStorage,
     handle: EventLoopHandle,
+    protocols: HashMap<Token, u8>, // RESP version chosen with HELLO
+    default_protocol: u8,          // for connections that haven't sent HELLO
+    loading: bool,                 // set while a dataset is still being loaded
+    enable_debug_command: bool,
 }
 
 impl RedisCommandExecutor {
@@ -20,9 +27,20 @@ impl RedisCommandExecutor {
         Self {
             storage: MemoryStorage::new(handle.clone(), repl_config),
             handle,
+            protocols: HashMap::new(),
+            default_protocol: 2,
+            loading: false,
+            enable_debug_command: true,
         }
     }
 
+    // Feed commands straight to `execute` without sockets or an event loop.
+    // Replies come back as usual; what would go through the event loop
+    // (unblocking, pub/sub messages, replication) is dropped.
+    pub fn in_memory(repl_config: ReplConfig) -> Self {
+        Self::new(EventLoopHandle::detached(), repl_config)
+    }
+
     pub fn new_with_file(
         handle: EventLoopHandle,
         directory: String,
@@ -31,23 +49,52 @@ impl RedisCommandExecutor {
     ) -> Self {
         let mut storage = MemoryStorage::new(handle.clone(), repl_config);
         storage.read_from_persistent_storage(&directory, &db_file_name);
-        Self { storage, handle }
+        Self {
+            storage,
+            handle,
+            protocols: HashMap::new(),
+            default_protocol: 2,
+            loading: false,
+            enable_debug_command: true,
+        }
+    }
+
+    pub fn set_default_protocol(&mut self, version: u8) {
+        self.default_protocol = version;
+    }
+
+    pub fn set_enable_debug_command(&mut self, enabled: bool) {
+        self.enable_debug_command = enabled;
+    }
+
+    // Loading happens before the listener accepts today, but background
+    // loading or AOF replay would flip this while clients are connected
+    pub fn set_loading(&mut self, loading: bool) {
+        self.loading = loading;
+    }
+
+    // Commands served while loading; they don't touch the dataset
+    fn allowed_while_loading(command: &RedisCommand) -> bool {
+        matches!(
+            command,
+            RedisCommand::INFO(_)
+                | RedisCommand::SUBSCRIBE(_)
+                | RedisCommand::UNSUBSCRIBE(_)
+                | RedisCommand::PSUBSCRIBE(_)
+                | RedisCommand::PUNSUBSCRIBE(_)
+                | RedisCommand::PUBLISH(_, _)
+                | RedisCommand::HELLO(_)
+                | RedisCommand::REPLCONF(_, _)
+        )
     }
 
     fn execute_subscribed(&mut self, command: RedisCommand, token: Token) -> RedisResponse {
         log::debug!("Executing subscribed command: {:?}", command);
         match command {
-            RedisCommand::SUBSCRIBE(channel) => {
-                if channel.is_empty() {
-                    return RedisResponse::error("No channels provided for SUBSCRIBE");
-                }
-                let count = self.storage.subscribe(token, channel.clone());
-                RedisResponse::Array(vec![
-                    RedisResponse::BulkString(Some("subscribe".to_string())),
-                    RedisResponse::BulkString(Some(channel.clone())),
-                    RedisResponse::Integer(count as i64),
-                ])
-            }
+            RedisCommand::SUBSCRIBE(_)
+            | RedisCommand::UNSUBSCRIBE(_)
+            | RedisCommand::PSUBSCRIBE(_)
+            | RedisCommand::PUNSUBSCRIBE(_) => self.change_subscriptions(command, token),
             RedisCommand::Ping(message) => match message {
                 Some(msg) => RedisResponse::Array(vec![
                     RedisResponse::BulkString(Some("pong".to_string())),
@@ -57,17 +104,6 @@ impl RedisCommandExecutor {
                     RedisResponse::BulkString(Some("pong".to_string())),
                     RedisResponse::BulkString(Some("".to_string())),
                 ]),
-            },
-                        RedisCommand::UNSUBSCRIBE(channel) => {
-                if channel.is_empty() {
-                    return RedisResponse::error("No channels provided for UNSUBSCRIBE");
-                }
-                let count = self.storage.unsubscribe(token, channel.clone());
-                RedisResponse::Array(vec![
-                    RedisResponse::BulkString(Some("unsubscribe".to_string())),
-                    RedisResponse::BulkString(Some(channel.clone())),
-                    RedisResponse::Integer(count as i64),
-                ])
             },
             _ => RedisResponse::error(
                 format!("Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context", command.to_string()).as_str(),
@@ -75,6 +111,58 @@ impl RedisCommandExecutor {
         }
     }
 
+    // Each channel gets its own confirmation, carrying the client's
+    // subscription count once that channel has been handled
+    fn change_subscriptions(&mut self, command: RedisCommand, token: Token) -> RedisResponse {
+        type Update = fn(&mut MemoryStorage, Token, String) -> usize;
+        let (kind, channels, update): (&str, Vec<String>, Update) = match command {
+            RedisCommand::SUBSCRIBE(channels) => ("subscribe", channels, MemoryStorage::subscribe),
+            RedisCommand::PSUBSCRIBE(patterns) => {
+                ("psubscribe", patterns, MemoryStorage::psubscribe)
+            }
+            RedisCommand::UNSUBSCRIBE(channels) if channels.is_empty() => (
+                "unsubscribe",
+                self.storage.get_subscriptions(token),
+                MemoryStorage::unsubscribe,
+            ),
+            RedisCommand::UNSUBSCRIBE(channels) => {
+                ("unsubscribe", channels, MemoryStorage::unsubscribe)
+            }
+            RedisCommand::PUNSUBSCRIBE(patterns) if patterns.is_empty() => (
+                "punsubscribe",
+                self.storage.get_pattern_subscriptions(token),
+                MemoryStorage::punsubscribe,
+            ),
+            RedisCommand::PUNSUBSCRIBE(patterns) => {
+                ("punsubscribe", patterns, MemoryStorage::punsubscribe)
+            }
+            _ => return RedisResponse::error("Not a subscription command"),
+        };
+        let confirmation = |channel: Option<String>, count: usize| {
+            RedisResponse::Array(vec![
+                RedisResponse::BulkString(Some(kind.to_string())),
+                RedisResponse::BulkString(channel),
+                RedisResponse::Integer(count as i64),
+            ])
+        };
+
+        // Unsubscribing from everything with nothing to drop still gets a reply
+        if channels.is_empty() {
+            let count = self.storage.get_subscriptions(token).len()
+                + self.storage.get_pattern_subscriptions(token).len();
+            return confirmation(None, count);
+        }
+        RedisResponse::Multiple(
+            channels
+                .into_iter()
+                .map(|channel| {
+                    let count = update(&mut self.storage, token, channel.clone());
+                    confirmation(Some(channel), count)
+                })
+                .collect(),
+        )
+    }
+
     pub fn is_slave_connection(&self) -> bool {
         self.storage.repl_config.is_slave()
     }
@@ -82,12 +170,260 @@ impl RedisCommandExecutor {
     pub fn get_master_addr(&self) -> Option<String> {
         self.storage.repl_config.get_master_addr()
     }
+
+    fn stream_entry_response(id: String, fields: Vec<(String, String)>) -> RedisResponse {
+        let mut field_array = Vec::new();
+        for (field, value) in fields {
+            field_array.push(RedisResponse::BulkString(Some(field)));
+            field_array.push(RedisResponse::BulkString(Some(value)));
+        }
+        RedisResponse::Array(vec![
+            RedisResponse::BulkString(Some(id)),
+            RedisResponse::Array(field_array),
+        ])
+    }
+
+    // LPOP and RPOP reply with a single element without a count, and with an
+    // array otherwise, which is null when the key does not exist
+    fn pop_response(popped: Option<Vec<String>>, count: Option<i64>) -> RedisResponse {
+        match (popped, count) {
+            (Some(items), None) => RedisResponse::BulkString(items.into_iter().next()),
+            (None, None) => RedisResponse::nil(),
+            // Empty for a count of 0, since empty lists are never kept
+            (Some(items), Some(_)) => {
+                RedisResponse::Array(items.into_iter().map(RedisResponse::SimpleString).collect())
+            }
+            (None, Some(_)) => RedisResponse::null_array(),
+        }
+    }
+
+    // XREAD and XREADGROUP reply with each stream's entries, or a null array
+    // when there is nothing to read
+    fn stream_read_response(results: StreamReadResult) -> RedisResponse {
+        if results.is_empty() {
+            return RedisResponse::null_array();
+        }
+        RedisResponse::Array(
+            results
+                .into_iter()
+                .map(|(key, entries)| {
+                    RedisResponse::Array(vec![
+                        RedisResponse::BulkString(Some(key)),
+                        RedisResponse::Array(
+                            entries
+                                .into_iter()
+                                .map(|(id, fields)| Self::stream_entry_response(id, fields))
+                                .collect(),
+                        ),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    // The members an SINTER/SUNION/SDIFF family command combines its keys into,
+    // None when one of them is not a set
+    fn set_operation(&self, command: &RedisCommand, keys: &[String]) -> Option<Vec<String>> {
+        if keys.iter().any(|key| self.is_wrong_type(key, "set")) {
+            return None;
+        }
+        Some(match command {
+            RedisCommand::SINTER(_) | RedisCommand::SINTERSTORE(_, _) => self.storage.sinter(keys),
+            RedisCommand::SUNION(_) | RedisCommand::SUNIONSTORE(_, _) => self.storage.sunion(keys),
+            _ => self.storage.sdiff(keys),
+        })
+    }
+
+    // INCR, INCRBY, DECR and DECRBY; a None delta could not be represented
+    fn incr_by(
+        &mut self,
+        command: &RedisCommand,
+        key: String,
+        delta: Option<i64>,
+    ) -> RedisResponse {
+        let Some(delta) = delta else {
+            return RedisResponse::error("decrement would overflow");
+        };
+        match self.storage.incr_by(key, delta) {
+            Ok(value) => {
+                self.storage.replicate_command(command.clone());
+                RedisResponse::Integer(value)
+            }
+            Err(err_msg) => RedisResponse::error(&err_msg),
+        }
+    }
+
+    // ZINCRBY and ZADD INCR reply with the member's new score, or nil when a
+    // flag stopped the update
+    fn zincrby(
+        &mut self,
+        key: String,
+        increment: f64,
+        member: String,
+        options: &ZAddOptions,
+    ) -> RedisResponse {
+        match self
+            .storage
+            .zadd(key.clone(), increment, member.clone(), options)
+        {
+            ZAddOutcome::Skipped => RedisResponse::nil(),
+            ZAddOutcome::NaN => RedisResponse::error("resulting score is not a number (NaN)"),
+            _ => {
+                let score = self.storage.zscore(&key, &member);
+                self.replicate_zadd(key, member);
+                RedisResponse::BulkString(score.map(|score| score.to_string()))
+            }
+        }
+    }
+
+    // Replicas get the member's resulting score, so flags and increments
+    // can't play out differently there
+    fn replicate_zadd(&mut self, key: String, member: String) {
+        if let Some(score) = self.storage.zscore(&key, &member) {
+            self.storage.replicate_command(RedisCommand::ZADD(
+                key,
+                score,
+                member,
+                ZAddOptions::default(),
+            ));
+        }
+    }
+
+    // Members of a sorted set between two ZRANGEBYSCORE-style bounds
+    fn members_by_score(
+        &self,
+        key: &str,
+        min: &str,
+        max: &str,
+    ) -> Result<Vec<(String, f64)>, String> {
+        let min = ScoreBound::parse(min)?;
+        let max = ScoreBound::parse(max)?;
+        Ok(self.storage.zrangebyscore(key, &min, &max))
+    }
+
+    // True when the key holds a live value of a type other than `expected`
+    fn is_wrong_type(&self, key: &str, expected: &str) -> bool {
+        let key_type = self.storage.get_type(key);
+        key_type != "none" && key_type != expected
+    }
+
+    // Apply a command received from the master link. Its effects are kept but
+    // the reply is dropped, except for REPLCONF GETACK which the master awaits.
+    pub fn execute_replicated(
+        &mut self,
+        command: RedisCommand,
+        token: Token,
+        bytes: usize,
+    ) -> Option<RedisResponse> {
+        let response = match &command {
+            RedisCommand::REPLCONF(subcommand, _) if subcommand.eq_ignore_ascii_case("GETACK") => {
+                // The acknowledged offset excludes the GETACK itself
+                Some(self.replica_ack())
+            }
+            _ => {
+                self.execute(command, token);
+                None
+            }
+        };
+        self.storage
+            .repl_config
+            .add_replication_offset(bytes as u64);
+        response
+    }
+
+    // What a blocked client gets when its timeout fires: WAIT reports the
+    // replicas that made it, blocking pops and reads a null array
+    pub fn timeout_response(&mut self, token: Token) -> RedisResponse {
+        self.storage.forget_blocked_client(token);
+        match self.storage.take_wait_timeout(token) {
+            Some(acked) => RedisResponse::Integer(acked as i64),
+            None => RedisResponse::null_array(),
+        }
+    }
+
+    // Drop the state kept for a connection that has gone away
+    pub fn forget_client(&mut self, token: Token) {
+        self.storage.forget_blocked_client(token);
+        self.storage.remove_replication_client(token);
+        self.storage.forget_subscriber(token);
+        self.protocols.remove(&token);
+    }
+
+    pub fn load_snapshot(&mut self, payload: &[u8]) {
+        self.storage.load_snapshot(payload);
+    }
+
+    // REPLCONF ACK <offset>, sent to the master on GETACK and periodically
+    pub fn replica_ack(&self) -> RedisResponse {
+        let offset = self.storage.repl_config.get_replication_offset();
+        RedisResponse::Array(vec![
+            RedisResponse::BulkString(Some("REPLCONF".to_string())),
+            RedisResponse::BulkString(Some("ACK".to_string())),
+            RedisResponse::BulkString(Some(offset.to_string())),
+        ])
+    }
+
+    fn protocol(&self, token: Token) -> u8 {
+        self.protocols
+            .get(&token)
+            .copied()
+            .unwrap_or(self.default_protocol)
+    }
+
+    // Field/value replies are maps in RESP3 and flat arrays in RESP2
+    fn map_response(&self, token: Token, pairs: Vec<(String, RedisResponse)>) -> RedisResponse {
+        let pairs = pairs
+            .into_iter()
+            .map(|(key, value)| (RedisResponse::BulkString(Some(key)), value));
+        if self.protocol(token) == 3 {
+            RedisResponse::Map(pairs.collect())
+        } else {
+            RedisResponse::Array(pairs.flat_map(|(key, value)| [key, value]).collect())
+        }
+    }
+
+    fn hello(&mut self, token: Token, version: Option<u8>) -> RedisResponse {
+        if let Some(version) = version {
+            if version != 2 && version != 3 {
+                return RedisResponse::RawError("NOPROTO unsupported protocol version".to_string());
+            }
+            self.protocols.insert(token, version);
+        }
+        let role = if self.is_slave_connection() {
+            "replica"
+        } else {
+            "master"
+        };
+        let bulk = |value: &str| RedisResponse::BulkString(Some(value.to_string()));
+        let fields = vec![
+            ("server".to_string(), bulk("redis")),
+            ("version".to_string(), bulk("7.2.0")),
+            (
+                "proto".to_string(),
+                RedisResponse::Integer(self.protocol(token) as i64),
+            ),
+            ("id".to_string(), RedisResponse::Integer(token.0 as i64)),
+            ("mode".to_string(), bulk("standalone")),
+            ("role".to_string(), bulk(role)),
+            ("modules".to_string(), RedisResponse::Array(vec![])),
+        ];
+        self.map_response(token, fields)
+    }
+
+    pub fn subscription_count(&self, token: Token) -> usize {
+        self.storage.get_subscriptions(token).len()
+    }
 }
 
 pub trait Transactions {
     fn start_transaction(&mut self, token: mio::Token);
     fn exec_transaction(&mut self, token: mio::Token);
     fn discard_transaction(&mut self, token: mio::Token);
+    // Writes between these calls reach replicas wrapped in MULTI/EXEC
+    fn begin_exec_propagation(&mut self);
+    fn end_exec_propagation(&mut self);
+    // Drops the client's WATCHed keys, true when one was modified meanwhile
+    fn end_watch(&mut self, token: mio::Token) -> bool;
     fn is_transaction_command(&self, command: &RedisCommand) -> bool {
         matches!(
             command,
@@ -108,15 +444,34 @@ impl Transactions for RedisCommandExecutor {
     fn discard_transaction(&mut self, token: mio::Token) {
         self.handle.discard_queue(token);
     }
+
+    fn begin_exec_propagation(&mut self) {
+        self.storage.start_transaction_propagation();
+    }
+
+    fn end_exec_propagation(&mut self) {
+        self.storage.end_transaction_propagation();
+    }
+
+    fn end_watch(&mut self, token: mio::Token) -> bool {
+        self.storage.unwatch(token)
+    }
 }
 
 impl CommandExecutor for RedisCommandExecutor {
     fn execute(&mut self, command: RedisCommand, token: Token) -> RedisResponse {
         log::debug!("Executing command: {:?}", command);
 
-        if self.storage.get_subscriptions(token).len() > 0 {
+        if self.loading && !Self::allowed_while_loading(&command) {
+            return RedisResponse::RawError(
+                "LOADING Redis is loading the dataset in memory".to_string(),
+            );
+        }
+
+        if self.storage.is_subscribed(token) {
             return self.execute_subscribed(command, token);
         }
+        self.storage.touch(&command.keys());
 
         match command.clone() {
             RedisCommand::Ping(message) => match message {
@@ -128,41 +483,175 @@ impl CommandExecutor for RedisCommandExecutor {
                 Some(value) => RedisResponse::BulkString(Some(value)),
                 None => RedisResponse::nil(),
             },
-            RedisCommand::Set(key, value) => {
-                if self.is_slave_connection() {
-                    println!("SET called on slave");
+            RedisCommand::GETEX(key, option) => {
+                if self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                let value = self.storage.get(&key);
+                if let (Some(value), Some(option)) = (&value, option) {
+                    let expiry = match option {
+                        GetExOption::Expire(expiry) => Some(expiry),
+                        GetExOption::Persist => None,
+                    };
+                    self.storage
+                        .set_expiry(&key, expiry.map(|expiry| expiry.deadline()));
+                    // Replicas rewrite the whole value with the new deadline
+                    self.storage.replicate_command(RedisCommand::Set(
+                        key,
+                        value.clone(),
+                        SetOptions {
+                            expiry,
+                            ..SetOptions::default()
+                        },
+                    ));
+                }
+                RedisResponse::BulkString(value)
+            }
+            RedisCommand::Set(key, value, options) => {
+                // GET must not overwrite a key it cannot return
+                if options.get && self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                let previous = if options.get {
+                    self.storage.get(&key)
                 } else {
-                    println!("SET called on master");
+                    None
+                };
+                let exists = self.storage.get_type(&key) != "none";
+                // A refused NX/XX set replies nil, or the old value with GET
+                if (options.nx && exists) || (options.xx && !exists) {
+                    return RedisResponse::BulkString(previous);
+                }
+                match options.expiry {
+                    Some(expiry) => self.storage.set_with_expiry(key, value, expiry.deadline()),
+                    None if options.keepttl => self.storage.set_keep_ttl(key, value),
+                    None => self.storage.set(key, value),
                 }
-                self.storage.set(key, value);
-                println!("Completed SET command");
                 self.storage.replicate_command(command.clone());
-                println!("Completed replication of SET command");
-                RedisResponse::ok()
+                if options.get {
+                    RedisResponse::BulkString(previous)
+                } else {
+                    RedisResponse::ok()
+                }
             }
-            RedisCommand::Del(keys) => {
+            // Replicas get the plain SET or DEL each of these amounts to
+            RedisCommand::GETSET(key, value) => {
+                if self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                let previous = self.storage.getset(key.clone(), value.clone());
+                self.storage.replicate_command(RedisCommand::Set(
+                    key,
+                    value,
+                    SetOptions::default(),
+                ));
+                RedisResponse::BulkString(previous)
+            }
+            RedisCommand::GETDEL(key) => {
+                if self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                let value = self.storage.getdel(&key);
+                if value.is_some() {
+                    self.storage.replicate_command(RedisCommand::Del(vec![key]));
+                }
+                RedisResponse::BulkString(value)
+            }
+            RedisCommand::SETNX(key, value) => {
+                let set = self.storage.setnx(key.clone(), value.clone());
+                if set {
+                    self.storage.replicate_command(RedisCommand::Set(
+                        key,
+                        value,
+                        SetOptions::default(),
+                    ));
+                }
+                RedisResponse::Integer(set as i64)
+            }
+            RedisCommand::Del(keys) | RedisCommand::UNLINK(keys) => {
                 let deleted = self.storage.delete_multiple(keys);
-                self.storage.replicate_command(command.clone());
+                if deleted > 0 {
+                    self.storage.replicate_command(command.clone());
+                }
                 RedisResponse::Integer(deleted as i64)
             }
             RedisCommand::Exists(keys) => {
                 let exists = self.storage.exists_multiple(&keys);
                 RedisResponse::Integer(exists as i64)
             }
-            RedisCommand::SetWithExpiry(key, value, expiry) => {
-                self.storage.set_with_expiry(key, value, expiry);
+            RedisCommand::MGET(keys) => RedisResponse::Array(
+                self.storage
+                    .get_multiple(&keys)
+                    .into_iter()
+                    .map(RedisResponse::BulkString)
+                    .collect(),
+            ),
+            RedisCommand::MSET(pairs) => {
+                self.storage.set_multiple(pairs);
                 self.storage.replicate_command(command.clone());
                 RedisResponse::ok()
             }
+            RedisCommand::EXPIRE(key, expiry) => {
+                let updated = self.storage.expire(&key, expiry.deadline());
+                if updated {
+                    self.storage.replicate_command(command.clone());
+                }
+                RedisResponse::Integer(updated as i64)
+            }
+            RedisCommand::PERSIST(key) => {
+                let persisted = self.storage.persist(&key);
+                if persisted {
+                    self.storage.replicate_command(command.clone());
+                }
+                RedisResponse::Integer(persisted as i64)
+            }
+            RedisCommand::RENAME(key, destination) => {
+                match self.storage.rename(&key, destination) {
+                    Ok(()) => {
+                        self.storage.replicate_command(command.clone());
+                        RedisResponse::ok()
+                    }
+                    Err(err_msg) => RedisResponse::error(&err_msg),
+                }
+            }
+            RedisCommand::COPY(key, destination, replace) => {
+                if key == destination {
+                    return RedisResponse::error("source and destination objects are the same");
+                }
+                let copied = self.storage.copy(&key, destination, replace);
+                if copied {
+                    self.storage.replicate_command(command.clone());
+                }
+                RedisResponse::Integer(copied as i64)
+            }
+            RedisCommand::TTL(key) => RedisResponse::Integer(self.storage.ttl(&key)),
+            RedisCommand::PTTL(key) => RedisResponse::Integer(self.storage.pttl(&key)),
+            // The push goes out first, a blocked client it serves pops afterwards
             RedisCommand::RPUSH(key, value) => {
-                let length = self.storage.rpush(key, value);
-                self.storage.replicate_command(command.clone());
-                RedisResponse::Integer(length as i64)
+                if self.is_wrong_type(&key, "list") {
+                    return RedisResponse::wrong_type();
+                }
+                match self.storage.rpush(key.clone(), value) {
+                    Some(length) => {
+                        self.storage.replicate_command(command.clone());
+                        self.storage.serve_blocked_clients(&key);
+                        RedisResponse::Integer(length as i64)
+                    }
+                    None => RedisResponse::wrong_type(),
+                }
             }
             RedisCommand::LPUSH(key, value) => {
-                let length = self.storage.lpush(key, value);
-                self.storage.replicate_command(command.clone());
-                RedisResponse::Integer(length as i64)
+                if self.is_wrong_type(&key, "list") {
+                    return RedisResponse::wrong_type();
+                }
+                match self.storage.lpush(key.clone(), value) {
+                    Some(length) => {
+                        self.storage.replicate_command(command.clone());
+                        self.storage.serve_blocked_clients(&key);
+                        RedisResponse::Integer(length as i64)
+                    }
+                    None => RedisResponse::wrong_type(),
+                }
             }
             RedisCommand::LLEN(key) => {
                 let length = self.storage.llen(&key);
@@ -187,45 +676,187 @@ impl CommandExecutor for RedisCommandExecutor {
                 }
             }
             RedisCommand::LPOP(key, count) => {
-                let count = count.unwrap_or(1) as usize; // Default to 1 if not specified
-                match self.storage.lpop(&key, count) {
-                    Some(items) => {
-                        if items.is_empty() {
-                            RedisResponse::nil()
-                        } else {
-                            if count == 1 {
-                                RedisResponse::BulkString(Some(items[0].clone()))
-                            } else {
-                                RedisResponse::Array(
-                                    items
-                                        .into_iter()
-                                        .map(|item| RedisResponse::SimpleString(item))
-                                        .collect(),
-                                )
-                            }
-                        }
+                if self.is_wrong_type(&key, "list") {
+                    return RedisResponse::wrong_type();
+                }
+                let popped = self.storage.lpop(&key, count.unwrap_or(1) as usize);
+                if popped.as_ref().is_some_and(|items| !items.is_empty()) {
+                    self.storage.replicate_command(command.clone());
+                }
+                Self::pop_response(popped, count)
+            }
+            RedisCommand::RPOP(key, count) => {
+                if self.is_wrong_type(&key, "list") {
+                    return RedisResponse::wrong_type();
+                }
+                let popped = self.storage.rpop(&key, count.unwrap_or(1) as usize);
+                if popped.as_ref().is_some_and(|items| !items.is_empty()) {
+                    self.storage.replicate_command(command.clone());
+                }
+                Self::pop_response(popped, count)
+            }
+            RedisCommand::LINDEX(key, index) => {
+                if self.is_wrong_type(&key, "list") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::BulkString(self.storage.lindex(&key, index))
+            }
+            RedisCommand::LSET(key, index, value) => {
+                if self.is_wrong_type(&key, "list") {
+                    return RedisResponse::wrong_type();
+                }
+                match self.storage.lset(&key, index, value) {
+                    Ok(()) => {
+                        self.storage.replicate_command(command.clone());
+                        RedisResponse::ok()
                     }
-                    None => RedisResponse::nil(),
+                    Err(err_msg) => RedisResponse::error(&err_msg),
+                }
+            }
+            RedisCommand::LTRIM(key, start, end) => {
+                if self.is_wrong_type(&key, "list") {
+                    return RedisResponse::wrong_type();
+                }
+                self.storage.ltrim(&key, start, end);
+                self.storage.replicate_command(command.clone());
+                RedisResponse::ok()
+            }
+            RedisCommand::LREM(key, count, value) => {
+                if self.is_wrong_type(&key, "list") {
+                    return RedisResponse::wrong_type();
+                }
+                let removed = self.storage.lrem(&key, count, &value);
+                if removed > 0 {
+                    self.storage.replicate_command(command.clone());
                 }
+                RedisResponse::Integer(removed as i64)
             }
-            RedisCommand::BLPOP(keys, timeout) => {
-                let resp = self.storage.blpop(keys, token, timeout);
-                if resp.is_some() {
-                    return RedisResponse::BulkString(resp.unwrap().get(1).cloned());
+            RedisCommand::LINSERT(key, before, pivot, value) => {
+                if self.is_wrong_type(&key, "list") {
+                    return RedisResponse::wrong_type();
                 }
-                return RedisResponse::Blocked;
+                let length = self.storage.linsert(&key, before, &pivot, value);
+                if length > 0 {
+                    self.storage.replicate_command(command.clone());
+                }
+                RedisResponse::Integer(length)
             }
-            RedisCommand::BRPOP(keys, timeout) => {
-                let resp = self.storage.brpop(keys, token, timeout);
-                if resp.is_some() {
-                    return RedisResponse::BulkString(resp.unwrap().get(1).cloned());
+            RedisCommand::LPOS(key, element, options) => {
+                if self.is_wrong_type(&key, "list") {
+                    return RedisResponse::wrong_type();
+                }
+                let positions = self.storage.lpos(
+                    &key,
+                    &element,
+                    options.rank.unwrap_or(1),
+                    options.count.unwrap_or(1),
+                    options.maxlen.unwrap_or(0),
+                );
+                // Without COUNT the reply is the first match alone
+                match options.count {
+                    Some(_) => RedisResponse::Array(
+                        positions
+                            .into_iter()
+                            .map(|i| RedisResponse::Integer(i as i64))
+                            .collect(),
+                    ),
+                    None => positions
+                        .first()
+                        .map_or(RedisResponse::nil(), |&i| RedisResponse::Integer(i as i64)),
                 }
-                return RedisResponse::Blocked;
             }
-            RedisCommand::INCR(key) => match self.storage.incr(key) {
-                Some(value) => RedisResponse::Integer(value),
-                None => RedisResponse::error("value is not an integer or out of range"),
+            // The key and the element, as when a blocked client is served
+            RedisCommand::BLPOP(keys, timeout) => match self.storage.blpop(keys, token, timeout) {
+                Some(popped) => {
+                    self.storage
+                        .replicate_command(RedisCommand::LPOP(popped[0].clone(), None));
+                    RedisResponse::Array(
+                    popped
+                        .into_iter()
+                        .map(|item| RedisResponse::BulkString(Some(item)))
+                        .collect(),
+                    )
+                }
+                None => RedisResponse::Blocked,
+            },
+            RedisCommand::BRPOP(keys, timeout) => match self.storage.brpop(keys, token, timeout) {
+                Some(popped) => {
+                    self.storage
+                        .replicate_command(RedisCommand::RPOP(popped[0].clone(), None));
+                    RedisResponse::Array(
+                    popped
+                        .into_iter()
+                        .map(|item| RedisResponse::BulkString(Some(item)))
+                        .collect(),
+                    )
+                }
+                None => RedisResponse::Blocked,
             },
+            RedisCommand::INCR(key) => self.incr_by(&command, key, Some(1)),
+            RedisCommand::INCRBY(key, amount) => self.incr_by(&command, key, Some(amount)),
+            RedisCommand::DECR(key) => self.incr_by(&command, key, Some(-1)),
+            // Negating i64::MIN would overflow before the addition is checked
+            RedisCommand::DECRBY(key, amount) => self.incr_by(&command, key, amount.checked_neg()),
+            RedisCommand::INCRBYFLOAT(key, amount) => {
+                if self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                match self.storage.incr_by_float(key, amount) {
+                    Ok(value) => {
+                        self.storage.replicate_command(command.clone());
+                        RedisResponse::BulkString(Some(value))
+                    }
+                    Err(err_msg) => RedisResponse::error(&err_msg),
+                }
+            }
+            RedisCommand::STRLEN(key) => {
+                if self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::Integer(self.storage.strlen(&key) as i64)
+            }
+            RedisCommand::GETRANGE(key, start, end) => {
+                if self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::BulkString(Some(self.storage.getrange(&key, start, end)))
+            }
+            RedisCommand::SETRANGE(key, offset, value) => {
+                if self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                match self.storage.setrange(key, offset, value) {
+                    Ok(length) => {
+                        self.storage.replicate_command(command.clone());
+                        RedisResponse::Integer(length as i64)
+                    }
+                    Err(err_msg) => RedisResponse::error(&err_msg),
+                }
+            }
+            RedisCommand::APPEND(key, value) => {
+                if self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                match self.storage.append(key, &value) {
+                    Ok(length) => {
+                        self.storage.replicate_command(command.clone());
+                        RedisResponse::Integer(length as i64)
+                    }
+                    Err(err_msg) => RedisResponse::error(&err_msg),
+                }
+            }
+            RedisCommand::BITCOUNT(key, range) => {
+                if self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::Integer(self.storage.bitcount(&key, range.as_ref()) as i64)
+            }
+            RedisCommand::BITPOS(key, bit, range) => {
+                if self.is_wrong_type(&key, "string") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::Integer(self.storage.bitpos(&key, bit, range.as_ref()))
+            }
             RedisCommand::MULTI => {
                 self.start_transaction(token);
                 RedisResponse::Empty
@@ -238,10 +869,201 @@ impl CommandExecutor for RedisCommandExecutor {
                 self.discard_transaction(token);
                 RedisResponse::Empty
             }
-            RedisCommand::ZADD(key, score, member) => {
-                let added = self.storage.zadd(key, score, member);
+            RedisCommand::WATCH(keys) => {
+                self.storage.watch(token, keys);
+                RedisResponse::ok()
+            }
+            RedisCommand::UNWATCH => {
+                self.storage.unwatch(token);
+                RedisResponse::ok()
+            }
+            RedisCommand::ZADD(key, score, member, options) => {
+                if self.is_wrong_type(&key, "zset") {
+                    return RedisResponse::wrong_type();
+                }
+                if options.incr {
+                    return self.zincrby(key, score, member, &options);
+                }
+                // CH also counts members whose score changed
+                let outcome = self
+                    .storage
+                    .zadd(key.clone(), score, member.clone(), &options);
+                if matches!(outcome, ZAddOutcome::Added | ZAddOutcome::Updated) {
+                    self.replicate_zadd(key, member);
+                }
+                let counted = match outcome {
+                    ZAddOutcome::Added => true,
+                    ZAddOutcome::Updated => options.ch,
+                    ZAddOutcome::Unchanged | ZAddOutcome::Skipped | ZAddOutcome::NaN => false,
+                };
+                RedisResponse::Integer(counted as i64)
+            }
+            RedisCommand::ZINCRBY(key, increment, member) => {
+                if self.is_wrong_type(&key, "zset") {
+                    return RedisResponse::wrong_type();
+                }
+                let options = ZAddOptions {
+                    incr: true,
+                    ..ZAddOptions::default()
+                };
+                self.zincrby(key, increment, member, &options)
+            }
+            RedisCommand::HSET(key, pairs) | RedisCommand::HMSET(key, pairs) => {
+                if self.is_wrong_type(&key, "hash") {
+                    return RedisResponse::wrong_type();
+                }
+                let added = self.storage.hset(key, pairs);
+                self.storage.replicate_command(command.clone());
+                // HMSET is the deprecated form and only acknowledges the write
+                if matches!(command, RedisCommand::HMSET(_, _)) {
+                    RedisResponse::ok()
+                } else {
+                    RedisResponse::Integer(added as i64)
+                }
+            }
+            RedisCommand::HGET(key, field) => {
+                if self.is_wrong_type(&key, "hash") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::BulkString(self.storage.hget(&key, &field))
+            }
+            RedisCommand::HDEL(key, fields) => {
+                if self.is_wrong_type(&key, "hash") {
+                    return RedisResponse::wrong_type();
+                }
+                let removed = self.storage.hdel(&key, &fields);
+                if removed > 0 {
+                    self.storage.replicate_command(command.clone());
+                }
+                RedisResponse::Integer(removed as i64)
+            }
+            RedisCommand::HLEN(key) => {
+                if self.is_wrong_type(&key, "hash") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::Integer(self.storage.hlen(&key) as i64)
+            }
+            RedisCommand::HEXISTS(key, field) => {
+                if self.is_wrong_type(&key, "hash") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::Integer(self.storage.hexists(&key, &field) as i64)
+            }
+            RedisCommand::HSTRLEN(key, field) => {
+                if self.is_wrong_type(&key, "hash") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::Integer(self.storage.hstrlen(&key, &field) as i64)
+            }
+            RedisCommand::HGETALL(key) => {
+                if self.is_wrong_type(&key, "hash") {
+                    return RedisResponse::wrong_type();
+                }
+                let fields = self
+                    .storage
+                    .hgetall(&key)
+                    .into_iter()
+                    .map(|(field, value)| (field, RedisResponse::BulkString(Some(value))))
+                    .collect();
+                self.map_response(token, fields)
+            }
+            RedisCommand::SADD(key, members) => {
+                if self.is_wrong_type(&key, "set") {
+                    return RedisResponse::wrong_type();
+                }
+                let added = self.storage.sadd(key, members);
+                if added > 0 {
+                    self.storage.replicate_command(command.clone());
+                }
                 RedisResponse::Integer(added as i64)
             }
+            RedisCommand::SREM(key, members) => {
+                if self.is_wrong_type(&key, "set") {
+                    return RedisResponse::wrong_type();
+                }
+                let removed = self.storage.srem(&key, &members);
+                if removed > 0 {
+                    self.storage.replicate_command(command.clone());
+                }
+                RedisResponse::Integer(removed as i64)
+            }
+            RedisCommand::SMEMBERS(key) => {
+                if self.is_wrong_type(&key, "set") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::Array(
+                    self.storage
+                        .smembers(&key)
+                        .into_iter()
+                        .map(|member| RedisResponse::BulkString(Some(member)))
+                        .collect(),
+                )
+            }
+            RedisCommand::SISMEMBER(key, member) => {
+                if self.is_wrong_type(&key, "set") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::Integer(self.storage.sismember(&key, &member) as i64)
+            }
+            RedisCommand::SCARD(key) => {
+                if self.is_wrong_type(&key, "set") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::Integer(self.storage.scard(&key) as i64)
+            }
+            RedisCommand::SPOP(key, count) => {
+                if self.is_wrong_type(&key, "set") {
+                    return RedisResponse::wrong_type();
+                }
+                let popped = self.storage.spop(&key, count.unwrap_or(1));
+                // Replicas remove the same members rather than picking their own
+                if !popped.is_empty() {
+                    self.storage
+                        .replicate_command(RedisCommand::SREM(key, popped.clone()));
+                }
+                match count {
+                    Some(_) => RedisResponse::Array(
+                        popped
+                            .into_iter()
+                            .map(|member| RedisResponse::BulkString(Some(member)))
+                            .collect(),
+                    ),
+                    None => RedisResponse::BulkString(popped.into_iter().next()),
+                }
+            }
+            RedisCommand::SINTER(ref keys)
+            | RedisCommand::SUNION(ref keys)
+            | RedisCommand::SDIFF(ref keys) => match self.set_operation(&command, keys) {
+                Some(members) => RedisResponse::Array(
+                    members
+                        .into_iter()
+                        .map(|member| RedisResponse::BulkString(Some(member)))
+                        .collect(),
+                ),
+                None => RedisResponse::wrong_type(),
+            },
+            // Only the source keys are type checked, the destination is
+            // overwritten whatever it holds
+            RedisCommand::SINTERSTORE(ref destination, ref keys)
+            | RedisCommand::SUNIONSTORE(ref destination, ref keys)
+            | RedisCommand::SDIFFSTORE(ref destination, ref keys) => {
+                let Some(members) = self.set_operation(&command, keys) else {
+                    return RedisResponse::wrong_type();
+                };
+                let stored = self.storage.sstore(destination.clone(), members);
+                self.storage.replicate_command(command.clone());
+                RedisResponse::Integer(stored as i64)
+            }
+            RedisCommand::SINTERCARD(keys, limit) => {
+                if keys.iter().any(|key| self.is_wrong_type(key, "set")) {
+                    return RedisResponse::wrong_type();
+                }
+                let cardinality = self.storage.sinter(&keys).len();
+                RedisResponse::Integer(match limit {
+                    0 => cardinality,
+                    limit => cardinality.min(limit),
+                } as i64)
+            }
             RedisCommand::ZRANK(key, member) => match self.storage.zrank(&key, &member) {
                 Some(rank) => RedisResponse::Integer(rank as i64),
                 None => RedisResponse::nil(),
@@ -261,26 +1083,122 @@ impl CommandExecutor for RedisCommandExecutor {
                 }
                 None => RedisResponse::Array(vec![]),
             },
-            RedisCommand::ZCARD(key) => {
-                let count = self.storage.zcard(&key);
-                RedisResponse::Integer(count as i64)
+            RedisCommand::ZRANGEBYSCORE(key, min, max, with_scores) => {
+                let members = match self.members_by_score(&key, &min, &max) {
+                    Ok(members) => members,
+                    Err(err_msg) => return RedisResponse::error(&err_msg),
+                };
+                RedisResponse::Array(
+                    members
+                        .into_iter()
+                        .flat_map(|(member, score)| {
+                            let score = with_scores
+                                .then(|| RedisResponse::BulkString(Some(score.to_string())));
+                            std::iter::once(RedisResponse::BulkString(Some(member))).chain(score)
+                        })
+                        .collect(),
+                )
             }
-            RedisCommand::ZSCORE(key, member) => match self.storage.zscore(&key, &member) {
-                Some(score) => RedisResponse::BulkString(Some(score.to_string())),
-                None => RedisResponse::nil(),
-            },
+            RedisCommand::ZCOUNT(key, min, max) => {
+                match (ScoreBound::parse(&min), ScoreBound::parse(&max)) {
+                    (Ok(min), Ok(max)) => {
+                        RedisResponse::Integer(self.storage.zcount(&key, &min, &max) as i64)
+                    }
+                    (Err(err_msg), _) | (_, Err(err_msg)) => RedisResponse::error(&err_msg),
+                }
+            }
+            RedisCommand::ZMSCORE(key, members) => RedisResponse::Array(
+                self.storage
+                    .zmscore(&key, &members)
+                    .into_iter()
+                    .map(|score| RedisResponse::BulkString(score.map(|score| score.to_string())))
+                    .collect(),
+            ),
+            RedisCommand::ZRANGESTORE(destination, source, min, max, by_score) => {
+                let members = if by_score {
+                    match self.members_by_score(&source, &min, &max) {
+                        Ok(members) => members,
+                        Err(err_msg) => return RedisResponse::error(&err_msg),
+                    }
+                } else {
+                    let (Ok(start), Ok(end)) = (min.parse::<i64>(), max.parse::<i64>()) else {
+                        return RedisResponse::error("value is not an integer or out of range");
+                    };
+                    self.storage
+                        .zrange(&source, start, end)
+                        .unwrap_or_default()
+                        .into_iter()
+                        .filter_map(|member| {
+                            let score = self.storage.zscore(&source, &member)?;
+                            Some((member, score))
+                        })
+                        .collect()
+                };
+                let stored = self.storage.zstore(destination, members);
+                self.storage.replicate_command(command.clone());
+                RedisResponse::Integer(stored as i64)
+            }
+            RedisCommand::ZCARD(key) => {
+                let count = self.storage.zcard(&key);
+                RedisResponse::Integer(count as i64)
+            }
+            RedisCommand::ZSCORE(key, member) => match self.storage.zscore(&key, &member) {
+                Some(score) => RedisResponse::BulkString(Some(score.to_string())),
+                None => RedisResponse::nil(),
+            },
             RedisCommand::ZREM(key, member) => {
                 let removed = self.storage.zrem(&key, &member);
                 if removed {
+                    self.storage.replicate_command(command.clone());
                     RedisResponse::Integer(1)
                 } else {
                     RedisResponse::Integer(0)
                 }
             }
             RedisCommand::TYPE(key) => RedisResponse::SimpleString(self.storage.get_type(&key)),
-            RedisCommand::XADD(key, id, fields) => {
-                match self.storage.xadd(key, id.unwrap(), fields) {
-                    Ok(entry_id) => RedisResponse::BulkString(Some(entry_id)),
+            RedisCommand::OBJECT(subcommand, key) => match subcommand.to_uppercase().as_str() {
+                "ENCODING" => match self.storage.object_encoding(&key) {
+                    Some(encoding) => RedisResponse::BulkString(Some(encoding)),
+                    None => RedisResponse::error("no such key"),
+                },
+                "IDLETIME" => match self.storage.object_idletime(&key) {
+                    Some(idle) => RedisResponse::Integer(idle as i64),
+                    None => RedisResponse::error("no such key"),
+                },
+                _ => RedisResponse::error("Unsupported OBJECT subcommand"),
+            },
+            RedisCommand::DEBUG(_, _) if !self.enable_debug_command => RedisResponse::error(
+                "DEBUG command not allowed. Set the enable-debug-command option to yes and restart the server.",
+            ),
+            RedisCommand::DEBUG(subcommand, key) => match subcommand.to_uppercase().as_str() {
+                "OBJECT" => match self.storage.debug_object(&key) {
+                    Some(description) => RedisResponse::SimpleString(description),
+                    None => RedisResponse::error("no such key"),
+                },
+                _ => RedisResponse::error("Unsupported DEBUG subcommand"),
+            },
+            RedisCommand::XADD(key, id, fields, maxlen) => {
+                match self.storage.xadd(key.clone(), id.unwrap(), fields.clone()) {
+                    Ok(entry_id) => {
+                        if let Some(maxlen) = maxlen {
+                            // The entry stays, and replicas trim with the same MAXLEN
+                            if let Err(err_msg) =
+                                self.storage.xtrim(&key, "MAXLEN", &maxlen.to_string())
+                            {
+                                log::error!("XADD MAXLEN trim failed: {}", err_msg);
+                            }
+                        }
+                        // Replicas must store the ID the master generated
+                        self.storage.replicate_command(RedisCommand::XADD(
+                            key.clone(),
+                            Some(entry_id.clone()),
+                            fields,
+                            maxlen,
+                        ));
+                        // Deliveries to group readers it wakes follow the XADD
+                        self.storage.serve_blocked_readers(&key);
+                        RedisResponse::BulkString(Some(entry_id))
+                    }
                     Err(err_msg) => {
                         log::debug!("XADD error: {}", err_msg);
                         RedisResponse::error(&err_msg)
@@ -312,49 +1230,193 @@ impl CommandExecutor for RedisCommandExecutor {
                 }
                 None => RedisResponse::Array(vec![]),
             },
-            RedisCommand::XREAD(block, streams) => {
-                match self.storage.xread(token, block, streams) {
-                    Some(results) => {
-                        if results.is_empty() {
-                            RedisResponse::Array(vec![])
-                        } else {
+            RedisCommand::XLEN(key) => {
+                if self.is_wrong_type(&key, "stream") {
+                    return RedisResponse::wrong_type();
+                }
+                RedisResponse::Integer(self.storage.xlen(&key) as i64)
+            }
+            RedisCommand::XDEL(key, ids) => {
+                if self.is_wrong_type(&key, "stream") {
+                    return RedisResponse::wrong_type();
+                }
+                let deleted = self.storage.xdel(&key, ids);
+                self.storage.replicate_command(command.clone());
+                RedisResponse::Integer(deleted as i64)
+            }
+            RedisCommand::XTRIM(key, strategy, threshold) => {
+                if self.is_wrong_type(&key, "stream") {
+                    return RedisResponse::wrong_type();
+                }
+                match self.storage.xtrim(&key, &strategy, &threshold) {
+                    Ok(trimmed) => {
+                        self.storage.replicate_command(command.clone());
+                        RedisResponse::Integer(trimmed as i64)
+                    }
+                    Err(err_msg) => RedisResponse::error(&err_msg),
+                }
+            }
+            RedisCommand::XINFO(subcommand, key, group) => match subcommand.to_uppercase().as_str()
+            {
+                _ if self.is_wrong_type(&key, "stream") => RedisResponse::wrong_type(),
+                "STREAM" => match self.storage.xinfo_stream(&key) {
+                    Some(info) => {
+                        let entry = |entry: Option<(String, Vec<(String, String)>)>| match entry {
+                            Some((id, fields)) => Self::stream_entry_response(id, fields),
+                            None => RedisResponse::nil(),
+                        };
+                        let field = |name: &str| RedisResponse::BulkString(Some(name.to_string()));
+                        RedisResponse::Array(vec![
+                            field("length"),
+                            RedisResponse::Integer(info.length as i64),
+                            field("last-generated-id"),
+                            RedisResponse::BulkString(Some(info.last_generated_id)),
+                            field("max-deleted-entry-id"),
+                            RedisResponse::BulkString(Some(info.max_deleted_id)),
+                            field("entries-added"),
+                            RedisResponse::Integer(info.entries_added as i64),
+                            field("groups"),
+                            RedisResponse::Integer(info.groups as i64),
+                            field("first-entry"),
+                            entry(info.first_entry),
+                            field("last-entry"),
+                            entry(info.last_entry),
+                        ])
+                    }
+                    None => RedisResponse::error("no such key"),
+                },
+                "GROUPS" => match self.storage.xinfo_groups(&key) {
+                    Some(groups) => {
+                        let field = |name: &str| RedisResponse::BulkString(Some(name.to_string()));
+                        let counter = |value: Option<u64>| match value {
+                            Some(value) => RedisResponse::Integer(value as i64),
+                            None => RedisResponse::nil(),
+                        };
+                        RedisResponse::Array(
+                            groups
+                                .into_iter()
+                                .map(|group| {
+                                    RedisResponse::Array(vec![
+                                        field("name"),
+                                        RedisResponse::BulkString(Some(group.name)),
+                                        field("consumers"),
+                                        RedisResponse::Integer(group.consumers as i64),
+                                        field("pending"),
+                                        RedisResponse::Integer(group.pending as i64),
+                                        field("last-delivered-id"),
+                                        RedisResponse::BulkString(Some(group.last_delivered_id)),
+                                        field("entries-read"),
+                                        counter(group.entries_read),
+                                        field("lag"),
+                                        counter(group.lag),
+                                    ])
+                                })
+                                .collect(),
+                        )
+                    }
+                    None => RedisResponse::error("no such key"),
+                },
+                "CONSUMERS" => {
+                    let group = group.unwrap_or_default();
+                    match self.storage.xinfo_consumers(&key, &group) {
+                        Ok(consumers) => {
+                            let field =
+                                |name: &str| RedisResponse::BulkString(Some(name.to_string()));
                             RedisResponse::Array(
-                                results
+                                consumers
                                     .into_iter()
-                                    .map(|(key, entries)| {
-                                        let entry_array: Vec<RedisResponse> = entries
-                                            .into_iter()
-                                            .map(|(id, fields)| {
-                                                let mut field_array = Vec::new();
-                                                for (field, value) in fields {
-                                                    field_array.push(RedisResponse::BulkString(
-                                                        Some(field),
-                                                    ));
-                                                    field_array.push(RedisResponse::BulkString(
-                                                        Some(value),
-                                                    ));
-                                                }
-                                                RedisResponse::Array(vec![
-                                                    RedisResponse::BulkString(Some(id)),
-                                                    RedisResponse::Array(field_array),
-                                                ])
-                                            })
-                                            .collect();
+                                    .map(|consumer| {
                                         RedisResponse::Array(vec![
-                                            RedisResponse::BulkString(Some(key)),
-                                            RedisResponse::Array(entry_array),
+                                            field("name"),
+                                            RedisResponse::BulkString(Some(consumer.name)),
+                                            field("pending"),
+                                            RedisResponse::Integer(consumer.pending as i64),
+                                            field("idle"),
+                                            RedisResponse::Integer(consumer.idle as i64),
+                                            field("inactive"),
+                                            RedisResponse::Integer(
+                                                consumer.inactive.map_or(-1, |ms| ms as i64),
+                                            ),
                                         ])
                                     })
                                     .collect(),
                             )
                         }
+                        Err(err_msg) => RedisResponse::RawError(err_msg),
+                    }
+                }
+                _ => RedisResponse::error("Unsupported XINFO subcommand"),
+            },
+            RedisCommand::XREAD(block, count, streams) => {
+                match self.storage.xread(token, block, count, streams) {
+                    Some(results) => Self::stream_read_response(results),
+                    None => RedisResponse::Blocked,
+                }
+            }
+            RedisCommand::XGROUPCREATE(key, group, id, mkstream) => {
+                if self.is_wrong_type(&key, "stream") {
+                    return RedisResponse::wrong_type();
+                }
+                match self.storage.xgroup_create(&key, group, &id, mkstream) {
+                    Ok(()) => {
+                        self.storage.replicate_command(command.clone());
+                        RedisResponse::ok()
+                    }
+                    Err(err_msg) => RedisResponse::RawError(err_msg),
+                }
+            }
+            RedisCommand::XGROUPSETID(key, group, id, entries_read) => {
+                if self.is_wrong_type(&key, "stream") {
+                    return RedisResponse::wrong_type();
+                }
+                match self.storage.xgroup_setid(&key, &group, &id, entries_read) {
+                    Ok(()) => {
+                        self.storage.replicate_command(command.clone());
+                        RedisResponse::ok()
                     }
-                    None => RedisResponse::Empty,
+                    Err(err_msg) => RedisResponse::RawError(err_msg),
+                }
+            }
+            // Claimed entries are propagated by the storage, one XCLAIM each
+            RedisCommand::XCLAIM(key, group, consumer, min_idle_time, ids, options) => {
+                if self.is_wrong_type(&key, "stream") {
+                    return RedisResponse::wrong_type();
+                }
+                match self
+                    .storage
+                    .xclaim(&key, &group, &consumer, min_idle_time, &ids, &options)
+                {
+                    Ok(claimed) => RedisResponse::Array(
+                        claimed
+                            .into_iter()
+                            .map(|(id, fields)| {
+                                if options.justid {
+                                    RedisResponse::BulkString(Some(id))
+                                } else {
+                                    Self::stream_entry_response(id, fields)
+                                }
+                            })
+                            .collect(),
+                    ),
+                    Err(err_msg) => RedisResponse::RawError(err_msg),
+                }
+            }
+            RedisCommand::XREADGROUP(group, consumer, count, block, streams) => {
+                match self
+                    .storage
+                    .xreadgroup(token, &group, &consumer, count, block, streams)
+                {
+                    Ok(Some(results)) => Self::stream_read_response(results),
+                    Ok(None) => RedisResponse::Blocked,
+                    Err(err_msg) => RedisResponse::RawError(err_msg),
                 }
             }
             RedisCommand::GEOADD(key, longitude, latitude, member) => {
                 match self.storage.geoadd(key, longitude, latitude, member) {
-                    Ok(added) => RedisResponse::Integer(added as i64),
+                    Ok(added) => {
+                        self.storage.replicate_command(command.clone());
+                        RedisResponse::Integer(added as i64)
+                    }
                     Err(err_msg) => {
                         log::debug!("GEOADD error: {}", err_msg);
                         RedisResponse::error(&err_msg)
@@ -370,47 +1432,42 @@ impl CommandExecutor for RedisCommandExecutor {
                             RedisResponse::BulkString(Some(lon.to_string())),
                             RedisResponse::BulkString(Some(lat.to_string())),
                         ]),
-                        None => RedisResponse::NullArray,
+                        None => RedisResponse::null_array(),
                     })
                     .collect();
                 RedisResponse::Array(response_array)
             }
             RedisCommand::GEODIST(key, from, to) => match self.storage.geodist(&key, &from, &to) {
-                Some(distance) => RedisResponse::BulkString(Some(format!("{:.5}", distance))),
+                Some(distance) => RedisResponse::BulkString(Some(format!("{:.4}", distance))),
                 None => RedisResponse::nil(),
             },
-            RedisCommand::GEOSEARCH(key, lon, lat, use_radius, dist, unit) => {
-                match self
-                    .storage
-                    .geosearch(&key, lon, lat, use_radius, dist, unit)
-                {
-                    Some(members) => {
-                        if members.is_empty() {
-                            RedisResponse::Array(vec![])
-                        } else {
-                            RedisResponse::Array(
-                                members
-                                    .into_iter()
-                                    .map(|member| RedisResponse::SimpleString(member))
-                                    .collect(),
-                            )
-                        }
-                    }
+            RedisCommand::GEOSEARCH(key, lon, lat, shape, unit) => {
+                match self.storage.geosearch(&key, lon, lat, shape, unit) {
+                    Some(members) => RedisResponse::Array(
+                        members
+                            .into_iter()
+                            .map(|member| RedisResponse::BulkString(Some(member)))
+                            .collect(),
+                    ),
                     None => RedisResponse::Array(vec![]),
                 }
             }
-            RedisCommand::CONFIG(subcommand, parameter) => {
-                match subcommand.to_uppercase().as_str() {
-                    "GET" => match self.storage.config_get(&parameter) {
-                        Some(value) => RedisResponse::Array(vec![
-                            RedisResponse::BulkString(Some(parameter)),
-                            RedisResponse::BulkString(Some(value)),
-                        ]),
-                        None => RedisResponse::Array(vec![]),
-                    },
-                    _ => RedisResponse::error("Unsupported CONFIG subcommand"),
+            RedisCommand::CONFIG(subcommand, args) => match subcommand.to_uppercase().as_str() {
+                "GET" => {
+                    let parameters = self
+                        .storage
+                        .config_get(&args[0])
+                        .into_iter()
+                        .map(|(name, value)| (name, RedisResponse::BulkString(Some(value))))
+                        .collect();
+                    self.map_response(token, parameters)
                 }
-            }
+                "SET" => match self.storage.config_set(&args[0], &args[1]) {
+                    Ok(()) => RedisResponse::ok(),
+                    Err(err_msg) => RedisResponse::error(&err_msg),
+                },
+                _ => RedisResponse::error("Unsupported CONFIG subcommand"),
+            },
             RedisCommand::KEYS(pattern) => {
                 let keys = self.storage.get_keys(&pattern);
                 if keys.is_empty() {
@@ -423,22 +1480,31 @@ impl CommandExecutor for RedisCommandExecutor {
                     )
                 }
             }
+            RedisCommand::SCAN(cursor, pattern, count) => {
+                let (next, keys) = self.storage.scan(cursor, pattern.as_deref(), count);
+                RedisResponse::Array(vec![
+                    RedisResponse::BulkString(Some(next.to_string())),
+                    RedisResponse::Array(
+                        keys.into_iter()
+                            .map(|key| RedisResponse::BulkString(Some(key)))
+                            .collect(),
+                    ),
+                ])
+            }
+            RedisCommand::RANDOMKEY => RedisResponse::BulkString(self.storage.random_key()),
             RedisCommand::INFO(_) => {
                 let info = self.storage.get_info_replication();
                 RedisResponse::BulkString(Some(info))
             }
+            RedisCommand::SAVE => match self.storage.save() {
+                Ok(()) => RedisResponse::ok(),
+                Err(err) => RedisResponse::error(&format!("Failed to save the RDB file: {}", err)),
+            },
 
-            RedisCommand::SUBSCRIBE(channel) => {
-                if channel.is_empty() {
-                    return RedisResponse::error("No channels provided for SUBSCRIBE");
-                }
-                let count = self.storage.subscribe(token, channel.clone());
-                RedisResponse::Array(vec![
-                    RedisResponse::BulkString(Some("subscribe".to_string())),
-                    RedisResponse::BulkString(Some(channel.clone())),
-                    RedisResponse::Integer(count as i64),
-                ])
-            }
+            RedisCommand::SUBSCRIBE(_)
+            | RedisCommand::UNSUBSCRIBE(_)
+            | RedisCommand::PSUBSCRIBE(_)
+            | RedisCommand::PUNSUBSCRIBE(_) => self.change_subscriptions(command, token),
 
             RedisCommand::PUBLISH(channel, message) => {
                 if channel.is_empty() {
@@ -448,16 +1514,30 @@ impl CommandExecutor for RedisCommandExecutor {
                 RedisResponse::Integer(count as i64)
             }
 
-            RedisCommand::UNSUBSCRIBE(channel) => {
-                if channel.is_empty() {
-                    return RedisResponse::error("No channels provided for UNSUBSCRIBE");
+            // A replica reporting its offset expects no reply
+            RedisCommand::REPLCONF(subcommand, offset)
+                if subcommand.eq_ignore_ascii_case("ACK") =>
+            {
+                match offset.parse::<u64>() {
+                    Ok(offset) => self.storage.ack_replica(token, offset),
+                    Err(_) => log::warn!("Ignoring REPLCONF ACK with offset {:?}", offset),
                 }
-                let count = self.storage.unsubscribe(token, channel.clone());
-                RedisResponse::Array(vec![
-                    RedisResponse::BulkString(Some("unsubscribe".to_string())),
-                    RedisResponse::BulkString(Some(channel.clone())),
-                    RedisResponse::Integer(count as i64),
-                ])
+                RedisResponse::Empty
+            }
+
+            RedisCommand::WAIT(numreplicas, timeout) => {
+                if self.storage.repl_config.is_slave() {
+                    return RedisResponse::error("WAIT cannot be used with replica instances.");
+                }
+                // Later writes don't move the target of this WAIT
+                let offset = self.storage.repl_config.get_replication_offset();
+                let acked = self.storage.acked_replicas(offset);
+                if acked >= numreplicas {
+                    return RedisResponse::Integer(acked as i64);
+                }
+                self.storage
+                    .wait_for_replicas(token, offset, numreplicas, timeout);
+                RedisResponse::Blocked
             }
 
             RedisCommand::REPLCONF(_, _) => {
@@ -476,6 +1556,3641 @@ impl CommandExecutor for RedisCommandExecutor {
                     .to_string(),
                 )
             }
+            // Only the error contract is implemented: there is never a failover
+            // in progress, and one cannot be started without replicas
+            RedisCommand::FAILOVER(abort) => {
+                if self.is_slave_connection() {
+                    RedisResponse::error("FAILOVER is not valid when server is a replica.")
+                } else if abort {
+                    RedisResponse::error("No failover in progress.")
+                } else if self.storage.replica_count() == 0 {
+                    RedisResponse::error("FAILOVER requires connected replicas.")
+                } else {
+                    RedisResponse::error("FAILOVER is not supported by this server.")
+                }
+            }
+
+            // Connection-specific subcommands such as INFO are answered by the event loop
+            RedisCommand::CLIENT(subcommand, _) => match subcommand.as_str() {
+                // Accepted for client library compatibility, there is no eviction
+                "NO-EVICT" | "NO-TOUCH" => RedisResponse::ok(),
+                _ => RedisResponse::error("Unsupported CLIENT subcommand"),
+            },
+            RedisCommand::HELLO(version) => self.hello(token, version),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandParser;
+    use crate::server::event_loop_handle::EventLoopMessage;
+    use std::sync::mpsc::Receiver;
+
+    fn executor() -> RedisCommandExecutor {
+        RedisCommandExecutor::in_memory(ReplConfig::new_master("127.0.0.1".to_string(), 6379))
+    }
+
+    // An executor along with what it asks of the event loop
+    fn executor_with_messages() -> (RedisCommandExecutor, Receiver<EventLoopMessage>) {
+        let (handle, receiver) = EventLoopHandle::channel();
+        let executor = RedisCommandExecutor::new(
+            handle,
+            ReplConfig::new_master("127.0.0.1".to_string(), 6379),
+        );
+        (executor, receiver)
+    }
+
+    fn run(executor: &mut RedisCommandExecutor, args: &[&str]) -> RedisResponse {
+        run_as(executor, Token(1), args)
+    }
+
+    fn run_as(executor: &mut RedisCommandExecutor, token: Token, args: &[&str]) -> RedisResponse {
+        match CommandParser::parse(args.iter().map(|arg| arg.to_string()).collect()) {
+            Ok(command) => executor.execute(command, token),
+            Err(error) => RedisResponse::error(&error),
+        }
+    }
+
+    fn bulk(value: &str) -> RedisResponse {
+        RedisResponse::BulkString(Some(value.to_string()))
+    }
+
+    // Reruns a command until it gives the expected reply or five seconds pass
+    fn wait_for_reply(
+        executor: &mut RedisCommandExecutor,
+        args: &[&str],
+        expected: RedisResponse,
+    ) -> RedisResponse {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        loop {
+            let reply = run(executor, args);
+            if reply == expected || std::time::Instant::now() > deadline {
+                return reply;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
         }
     }
+
+    #[test]
+    fn test_object_encoding_strings() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "12345"]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "k"]),
+            bulk("int")
+        );
+
+        run(&mut executor, &["SET", "k", "hello"]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "k"]),
+            bulk("embstr")
+        );
+
+        run(&mut executor, &["SET", "k", &"x".repeat(50)]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "k"]),
+            bulk("raw")
+        );
+    }
+
+    #[test]
+    fn test_object_encoding_embstr_limit_is_44_bytes() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", &"x".repeat(44)]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "k"]),
+            bulk("embstr")
+        );
+
+        run(&mut executor, &["SET", "k", &"x".repeat(45)]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "k"]),
+            bulk("raw")
+        );
+    }
+
+    #[test]
+    fn test_object_encoding_missing_key() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "missing"]),
+            RedisResponse::error("no such key")
+        );
+    }
+
+    #[test]
+    fn test_integer_values_keep_their_text() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "n", "12345"]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "n"]),
+            bulk("int")
+        );
+        assert_eq!(run(&mut executor, &["GET", "n"]), bulk("12345"));
+        assert_eq!(
+            run(&mut executor, &["SET", "n", "6789", "GET"]),
+            bulk("12345")
+        );
+
+        assert_eq!(
+            run(&mut executor, &["INCR", "n"]),
+            RedisResponse::Integer(6790)
+        );
+        assert_eq!(run(&mut executor, &["GET", "n"]), bulk("6790"));
+    }
+
+    fn simple(value: &str) -> RedisResponse {
+        RedisResponse::SimpleString(value.to_string())
+    }
+
+    #[test]
+    fn test_type_reports_every_kind_of_value() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "string", "v"]);
+        run(&mut executor, &["RPUSH", "list", "a"]);
+        run(&mut executor, &["SADD", "set", "a"]);
+        run(&mut executor, &["HSET", "hash", "f", "v"]);
+        run(&mut executor, &["ZADD", "zset", "1", "a"]);
+        run(&mut executor, &["XADD", "stream", "*", "f", "v"]);
+        for key in ["string", "list", "set", "hash", "zset", "stream"] {
+            assert_eq!(run(&mut executor, &["TYPE", key]), simple(key));
+        }
+        assert_eq!(run(&mut executor, &["TYPE", "missing"]), simple("none"));
+
+        run(&mut executor, &["SADD", "expiring", "a"]);
+        run(&mut executor, &["PEXPIRE", "expiring", "1"]);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(run(&mut executor, &["TYPE", "expiring"]), simple("none"));
+    }
+
+    #[test]
+    fn test_expired_keys_are_not_counted() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "live", "v"]);
+        run(&mut executor, &["SET", "short", "v", "PX", "1"]);
+        run(&mut executor, &["SET", "gone", "v", "PX", "1"]);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+
+        assert_eq!(
+            run(&mut executor, &["EXISTS", "short"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["EXISTS", "live", "short", "gone"]),
+            RedisResponse::Integer(1)
+        );
+        run(&mut executor, &["SET", "other", "v", "PX", "1"]);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(
+            run(&mut executor, &["DEL", "live", "other"]),
+            RedisResponse::Integer(1)
+        );
+    }
+
+    #[test]
+    fn test_list_deleted_when_emptied() {
+        let mut executor = executor();
+        run(&mut executor, &["RPUSH", "list", "a", "b"]);
+        run(&mut executor, &["LPOP", "list", "2"]);
+        assert_eq!(run(&mut executor, &["TYPE", "list"]), simple("none"));
+        assert_eq!(
+            run(&mut executor, &["EXISTS", "list"]),
+            RedisResponse::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_zset_deleted_when_emptied() {
+        let mut executor = executor();
+        run(&mut executor, &["ZADD", "zset", "1", "a"]);
+        assert_eq!(
+            run(&mut executor, &["ZREM", "zset", "a"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(run(&mut executor, &["TYPE", "zset"]), simple("none"));
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "zset"]),
+            RedisResponse::error("no such key")
+        );
+    }
+
+    #[test]
+    fn test_only_streams_persist_while_empty() {
+        use crate::storage::stream_member::Stream;
+        use crate::storage::Unit;
+        use std::collections::BTreeSet;
+
+        assert!(Unit::new_list(vec![], None)
+            .implementation
+            .is_empty_collection());
+        assert!(Unit::new_zset(BTreeSet::new(), None)
+            .implementation
+            .is_empty_collection());
+        assert!(!Unit::new_stream(Stream::new(), None)
+            .implementation
+            .is_empty_collection());
+    }
+
+    #[test]
+    fn test_debug_object_reports_stream_counters() {
+        let mut executor = executor();
+        for i in 1..=5 {
+            let id = format!("{}-1", i);
+            assert_eq!(
+                run(&mut executor, &["XADD", "s", &id, "field", "value"]),
+                bulk(&id)
+            );
+        }
+        let debug = match run(&mut executor, &["DEBUG", "OBJECT", "s"]) {
+            RedisResponse::SimpleString(debug) => debug,
+            other => panic!("unexpected reply: {:?}", other),
+        };
+        assert!(debug.ends_with(" entries_added:5 max_deleted_id:0-0"));
+    }
+
+    #[test]
+    fn test_set_get_returns_previous_value() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["SET", "k", "v1", "GET"]),
+            RedisResponse::nil()
+        );
+        assert_eq!(run(&mut executor, &["SET", "k", "v2", "GET"]), bulk("v1"));
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("v2"));
+    }
+
+    #[test]
+    fn test_set_get_on_wrong_type_leaves_key_untouched() {
+        let mut executor = executor();
+        run(&mut executor, &["RPUSH", "k", "a"]);
+        assert_eq!(
+            run(&mut executor, &["SET", "k", "v", "GET"]),
+            RedisResponse::wrong_type()
+        );
+        assert_eq!(
+            run(&mut executor, &["LRANGE", "k", "0", "-1"]),
+            RedisResponse::Array(vec![simple("a")])
+        );
+    }
+
+    #[test]
+    fn test_push_on_wrong_type_leaves_key_untouched() {
+        let mut executor = executor();
+        run(&mut executor, &["HSET", "hash", "field", "v"]);
+        run(&mut executor, &["SET", "string", "v"]);
+        for args in [
+            &["RPUSH", "hash", "a"][..],
+            &["LPUSH", "hash", "a"],
+            &["RPUSH", "string", "a"],
+            &["LPUSH", "string", "a"],
+        ] {
+            assert_eq!(run(&mut executor, args), RedisResponse::wrong_type());
+        }
+        assert_eq!(run(&mut executor, &["HGET", "hash", "field"]), bulk("v"));
+        assert_eq!(run(&mut executor, &["GET", "string"]), bulk("v"));
+    }
+
+    #[test]
+    fn test_pop_on_wrong_type_leaves_key_untouched() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "string", "v"]);
+        for args in [
+            &["LPOP", "string"][..],
+            &["RPOP", "string"],
+            &["LPOP", "string", "2"],
+            &["RPOP", "string", "2"],
+        ] {
+            assert_eq!(run(&mut executor, args), RedisResponse::wrong_type());
+        }
+        assert_eq!(run(&mut executor, &["GET", "string"]), bulk("v"));
+    }
+
+    #[test]
+    fn test_debug_object_is_deterministic() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "hello"]);
+        let first = run(&mut executor, &["DEBUG", "OBJECT", "k"]);
+        assert_eq!(
+            first,
+            simple("refcount:1 encoding:embstr serializedlength:6 lru:0 lru_seconds_idle:0")
+        );
+        assert_eq!(
+            run(&mut executor, &["DEBUG", "OBJECT", "k"]).to_resp(),
+            first.to_resp()
+        );
+    }
+
+    #[test]
+    fn test_zset_encoding_honors_listpack_value_limit() {
+        let mut executor = executor();
+        run(&mut executor, &["ZADD", "small", "1", "a"]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "small"]),
+            bulk("listpack")
+        );
+
+        run(&mut executor, &["ZADD", "long", "1", &"m".repeat(65)]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "long"]),
+            bulk("skiplist")
+        );
+    }
+
+    #[test]
+    fn test_config_set_listpack_thresholds() {
+        let mut executor = executor();
+        run(&mut executor, &["ZADD", "z", "1", "a"]);
+        run(&mut executor, &["ZADD", "z", "2", "b"]);
+        assert_eq!(
+            run(
+                &mut executor,
+                &["CONFIG", "SET", "zset-max-listpack-entries", "1"]
+            ),
+            RedisResponse::ok()
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["CONFIG", "GET", "zset-max-listpack-entries"]
+            ),
+            RedisResponse::Array(vec![bulk("zset-max-listpack-entries"), bulk("1")])
+        );
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "z"]),
+            bulk("skiplist")
+        );
+        assert!(matches!(
+            run(
+                &mut executor,
+                &["CONFIG", "SET", "hash-max-listpack-value", "big"]
+            ),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_string_ranges_on_integer_values() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "n", "12345"]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "ENCODING", "n"]),
+            bulk("int")
+        );
+        assert_eq!(
+            run(&mut executor, &["STRLEN", "n"]),
+            RedisResponse::Integer(5)
+        );
+        assert_eq!(
+            run(&mut executor, &["GETRANGE", "n", "0", "2"]),
+            bulk("123")
+        );
+
+        assert_eq!(
+            run(&mut executor, &["SETRANGE", "n", "1", "99"]),
+            RedisResponse::Integer(5)
+        );
+        assert_eq!(run(&mut executor, &["GET", "n"]), bulk("19945"));
+        assert_eq!(
+            run(&mut executor, &["INCR", "n"]),
+            RedisResponse::Integer(19946)
+        );
+    }
+
+    #[test]
+    fn test_setrange_pads_missing_key() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["SETRANGE", "k", "2", "ab"]),
+            RedisResponse::Integer(4)
+        );
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("\0\0ab"));
+        assert_eq!(
+            run(&mut executor, &["STRLEN", "missing"]),
+            RedisResponse::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_setrange_pads_past_existing_value() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "Hello", "EX", "100"]);
+        assert_eq!(
+            run(&mut executor, &["SETRANGE", "k", "8", "!"]),
+            RedisResponse::Integer(9)
+        );
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("Hello\0\0\0!"));
+        assert_eq!(
+            run(&mut executor, &["TTL", "k"]),
+            RedisResponse::Integer(100)
+        );
+
+        // An empty value only reports the length and creates nothing
+        assert_eq!(
+            run(&mut executor, &["SETRANGE", "k", "20", ""]),
+            RedisResponse::Integer(9)
+        );
+        assert_eq!(
+            run(&mut executor, &["SETRANGE", "empty", "3", ""]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["EXISTS", "empty"]),
+            RedisResponse::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_getrange_boundaries() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "mykey", "This is a string"]);
+        let getrange = |executor: &mut RedisCommandExecutor, start: &str, end: &str| {
+            run(executor, &["GETRANGE", "mykey", start, end])
+        };
+
+        assert_eq!(getrange(&mut executor, "0", "3"), bulk("This"));
+        assert_eq!(getrange(&mut executor, "-3", "-1"), bulk("ing"));
+        assert_eq!(getrange(&mut executor, "0", "-1"), bulk("This is a string"));
+        assert_eq!(getrange(&mut executor, "10", "100"), bulk("string"));
+        assert_eq!(getrange(&mut executor, "5", "2"), bulk(""));
+        assert_eq!(getrange(&mut executor, "-1", "-3"), bulk(""));
+        assert_eq!(getrange(&mut executor, "-100", "3"), bulk("This"));
+
+        run(&mut executor, &["SET", "mykey", "short"]);
+        assert_eq!(getrange(&mut executor, "10", "100"), bulk(""));
+        assert_eq!(
+            run(&mut executor, &["GETRANGE", "missing", "0", "-1"]),
+            bulk("")
+        );
+    }
+
+    #[test]
+    fn test_xinfo_stream_tracks_added_and_deleted() {
+        let mut executor = executor();
+        for i in 1..=5 {
+            let id = format!("{}-1", i);
+            assert_eq!(
+                run(&mut executor, &["XADD", "s", &id, "field", "value"]),
+                bulk(&id)
+            );
+        }
+        assert_eq!(
+            run(&mut executor, &["XDEL", "s", "2-1", "5-1", "9-9"]),
+            RedisResponse::Integer(2)
+        );
+
+        let info = match run(&mut executor, &["XINFO", "STREAM", "s"]) {
+            RedisResponse::Array(info) => info,
+            other => panic!("unexpected reply: {:?}", other),
+        };
+        assert_eq!(
+            info[0..8],
+            [
+                bulk("length"),
+                RedisResponse::Integer(3),
+                bulk("last-generated-id"),
+                bulk("5-1"),
+                bulk("max-deleted-entry-id"),
+                bulk("5-1"),
+                bulk("entries-added"),
+                RedisResponse::Integer(5),
+            ]
+        );
+
+        // The deleted top ID is still the floor for new entries
+        assert!(matches!(
+            run(&mut executor, &["XADD", "s", "5-1", "field", "value"]),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_xtrim_maxlen() {
+        let mut executor = executor();
+        for i in 1..=4 {
+            run(&mut executor, &["XADD", "s", &format!("{}-0", i), "f", "v"]);
+        }
+        assert_eq!(
+            run(&mut executor, &["XTRIM", "s", "MAXLEN", "=", "1"]),
+            RedisResponse::Integer(3)
+        );
+        assert_eq!(
+            run(&mut executor, &["XRANGE", "s", "-", "+"]),
+            RedisResponse::Array(vec![RedisResponse::Array(vec![
+                bulk("4-0"),
+                RedisResponse::Array(vec![bulk("f"), bulk("v")]),
+            ])])
+        );
+        // Trimming everything leaves an empty stream behind
+        run(&mut executor, &["XTRIM", "s", "MAXLEN", "0"]);
+        assert_eq!(run(&mut executor, &["TYPE", "s"]), simple("stream"));
+    }
+
+    #[test]
+    fn test_xread_without_data_returns_null_array() {
+        let mut executor = executor();
+        let reply = run(&mut executor, &["XREAD", "STREAMS", "missing", "0"]);
+        assert_eq!(reply.to_resp(), "*-1\r\n");
+
+        run(&mut executor, &["XADD", "s", "1-1", "f", "v"]);
+        let reply = run(&mut executor, &["XREAD", "STREAMS", "s", "1-1"]);
+        assert_eq!(reply.to_resp(), "*-1\r\n");
+    }
+
+    #[test]
+    fn test_empty_and_null_array_replies() {
+        let mut executor = executor();
+        // Reading a missing key is an empty result, not a null one
+        for args in [
+            &["LRANGE", "missing", "0", "-1"][..],
+            &["ZRANGE", "missing", "0", "-1"],
+            &["SMEMBERS", "missing"],
+            &["XRANGE", "missing", "-", "+"],
+        ] {
+            assert_eq!(run(&mut executor, args).to_resp(), "*0\r\n", "{:?}", args);
+        }
+        // but a counted pop from it is null
+        for args in [&["LPOP", "missing", "2"], &["RPOP", "missing", "2"]] {
+            assert_eq!(run(&mut executor, args).to_resp(), "*-1\r\n", "{:?}", args);
+        }
+
+        run(&mut executor, &["RPUSH", "l", "a", "b"]);
+        assert_eq!(
+            run(&mut executor, &["LPOP", "l", "1"]),
+            RedisResponse::Array(vec![simple("a")])
+        );
+        assert_eq!(
+            run(&mut executor, &["BLPOP", "l", "0"]),
+            RedisResponse::Array(vec![bulk("l"), bulk("b")])
+        );
+    }
+
+    fn now_ms() -> u128 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    }
+
+    #[test]
+    fn test_queued_pxat_keeps_its_deadline() {
+        let mut master = executor();
+        master.storage.enable_propagation_log();
+
+        // A transaction parses its commands when they are queued, well
+        // before EXEC runs them
+        let deadline = (now_ms() + 100_000).to_string();
+        let queued = [
+            vec!["SET", "k", "v", "PXAT", deadline.as_str()],
+            vec!["SET", "other", "v"],
+            vec!["PEXPIREAT", "other", deadline.as_str()],
+        ]
+        .map(|args| {
+            CommandParser::parse(args.iter().map(|arg| arg.to_string()).collect()).unwrap()
+        });
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        for command in queued {
+            master.execute(command, Token(1));
+        }
+
+        let log = master.storage.propagation_log();
+        assert_eq!(log[0], ["SET", "k", "v", "PXAT", deadline.as_str()]);
+        assert_eq!(log[2], ["PEXPIREAT", "other", deadline.as_str()]);
+    }
+
+    #[test]
+    fn test_set_ex_propagates_as_pxat() {
+        let mut master = executor();
+        master.storage.enable_propagation_log();
+
+        let before = now_ms();
+        run(&mut master, &["SET", "k", "v", "EX", "100"]);
+        let after = now_ms();
+        run(&mut master, &["SET", "plain", "v"]);
+
+        let log = master.storage.propagation_log();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0][..4], ["SET", "k", "v", "PXAT"]);
+        let deadline: u128 = log[0][4].parse().unwrap();
+        assert!((before + 100_000..=after + 100_000).contains(&deadline));
+        assert_eq!(log[1], ["SET", "plain", "v"]);
+
+        // Replicas must accept the rewritten form
+        let mut replica = executor();
+        run(
+            &mut replica,
+            &log[0].iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+        assert_eq!(run(&mut replica, &["GET", "k"]), bulk("v"));
+    }
+
+    #[test]
+    fn test_incrbyfloat_propagates_as_set_keepttl() {
+        let mut master = executor();
+        run(&mut master, &["SET", "f", "10.5", "EX", "100"]);
+        master.storage.enable_propagation_log();
+        run(&mut master, &["INCRBYFLOAT", "f", "0.1"]);
+
+        let log = master.storage.propagation_log().to_vec();
+        assert_eq!(log, [vec!["SET", "f", "10.6", "KEEPTTL"]]);
+
+        let mut replica = executor();
+        run(&mut replica, &["SET", "f", "10.5", "EX", "100"]);
+        run(
+            &mut replica,
+            &log[0].iter().map(String::as_str).collect::<Vec<_>>(),
+        );
+        assert_eq!(run(&mut replica, &["GET", "f"]), bulk("10.6"));
+        assert_eq!(
+            run(&mut replica, &["TTL", "f"]),
+            RedisResponse::Integer(100)
+        );
+    }
+
+    #[test]
+    fn test_zrangestore_propagates() {
+        let mut master = executor();
+        for (score, member) in [("1", "a"), ("2", "b"), ("3", "c")] {
+            run(&mut master, &["ZADD", "src", score, member]);
+        }
+        master.storage.enable_propagation_log();
+        run(
+            &mut master,
+            &["ZRANGESTORE", "dst", "src", "(1", "+inf", "BYSCORE"],
+        );
+        run(&mut master, &["ZRANGESTORE", "first", "src", "0", "0"]);
+
+        let log = master.storage.propagation_log().to_vec();
+        assert_eq!(
+            log,
+            [
+                vec!["ZRANGESTORE", "dst", "src", "(1", "+inf", "BYSCORE"],
+                vec!["ZRANGESTORE", "first", "src", "0", "0"],
+            ]
+        );
+
+        let mut replica = executor();
+        for (score, member) in [("1", "a"), ("2", "b"), ("3", "c")] {
+            run(&mut replica, &["ZADD", "src", score, member]);
+        }
+        for args in &log {
+            run(
+                &mut replica,
+                &args.iter().map(String::as_str).collect::<Vec<_>>(),
+            );
+        }
+        for key in ["dst", "first"] {
+            assert_eq!(
+                run(&mut replica, &["ZRANGE", key, "0", "-1"]),
+                run(&mut master, &["ZRANGE", key, "0", "-1"])
+            );
+        }
+    }
+
+    #[test]
+    fn test_xadd_propagates_generated_id() {
+        let mut executor = executor();
+        executor.storage.enable_propagation_log();
+
+        let id = match run(&mut executor, &["XADD", "s", "*", "f", "v"]) {
+            RedisResponse::BulkString(Some(id)) => id,
+            other => panic!("unexpected XADD reply: {:?}", other),
+        };
+        assert_eq!(
+            executor.storage.propagation_log(),
+            [vec!["XADD", "s", &id, "f", "v"]]
+        );
+    }
+
+    #[test]
+    fn test_push_propagates_before_the_pop_it_serves() {
+        let mut executor = executor();
+        executor.storage.enable_propagation_log();
+
+        run(&mut executor, &["HSET", "hash", "f", "v"]);
+        run(&mut executor, &["RPUSH", "hash", "a"]);
+        assert_eq!(
+            run_as(&mut executor, Token(7), &["BLPOP", "queue", "0"]),
+            RedisResponse::Blocked
+        );
+        run(&mut executor, &["RPUSH", "queue", "job"]);
+
+        // The rejected push never reaches replicas
+        assert_eq!(
+            executor.storage.propagation_log(),
+            [
+                vec!["HSET", "hash", "f", "v"],
+                vec!["RPUSH", "queue", "job"],
+                vec!["LPOP", "queue"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transaction_propagates_wrapped_in_multi_exec() {
+        let mut executor = executor();
+        executor.storage.enable_propagation_log();
+
+        executor.begin_exec_propagation();
+        run(&mut executor, &["SET", "a", "1"]);
+        run(&mut executor, &["GET", "a"]);
+        run(&mut executor, &["INCR", "a"]);
+        executor.end_exec_propagation();
+
+        // A single write needs no wrapping
+        executor.begin_exec_propagation();
+        run(&mut executor, &["DEL", "a"]);
+        executor.end_exec_propagation();
+
+        assert_eq!(
+            executor.storage.propagation_log(),
+            [
+                vec!["MULTI"],
+                vec!["SET", "a", "1"],
+                vec!["INCR", "a"],
+                vec!["EXEC"],
+                vec!["DEL", "a"],
+            ]
+        );
+    }
+
+    fn sorted_keys(reply: RedisResponse) -> Vec<String> {
+        let RedisResponse::Array(items) = reply else {
+            panic!("expected an array, got {:?}", reply);
+        };
+        let mut keys: Vec<String> = items
+            .into_iter()
+            .map(|item| match item {
+                RedisResponse::BulkString(Some(key)) => key,
+                other => panic!("expected a bulk string, got {:?}", other),
+            })
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    // Run a SCAN to completion and collect every key it returned
+    fn full_scan(executor: &mut RedisCommandExecutor, pattern: &str) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let reply = run(executor, &["SCAN", &cursor, "MATCH", pattern, "COUNT", "3"]);
+            let RedisResponse::Array(mut parts) = reply else {
+                panic!("unexpected SCAN reply: {:?}", reply);
+            };
+            let page = parts.pop().unwrap();
+            keys.extend(sorted_keys(page));
+            cursor = match parts.pop().unwrap() {
+                RedisResponse::BulkString(Some(next)) => next,
+                other => panic!("unexpected SCAN cursor: {:?}", other),
+            };
+            if cursor == "0" {
+                break;
+            }
+        }
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_keys_and_scan_match_the_same_keys() {
+        let mut executor = executor();
+        let keys = [
+            "hello", "hallo", "hxllo", "hllo", "heeello", "a.b", "axb", "a*b", "user:1", "user:10",
+            "user:[1]", "[", "-", "]",
+        ];
+        for key in keys {
+            run(&mut executor, &["SET", key, "v"]);
+        }
+
+        for pattern in [
+            "*",
+            "h?llo",
+            "h*llo",
+            "h[ae]llo",
+            "h[^e]llo",
+            "h[a-x]llo",
+            "a.b",
+            "a\\*b",
+            "user:?",
+            "user:[0-9]*",
+            "user:\\[1\\]",
+            "[\\[]",
+            "[-]",
+            "[]]",
+            "*[",
+            "nomatch",
+        ] {
+            let from_keys = sorted_keys(run(&mut executor, &["KEYS", pattern]));
+            assert_eq!(
+                full_scan(&mut executor, pattern),
+                from_keys,
+                "pattern {}",
+                pattern
+            );
+        }
+        assert_eq!(
+            sorted_keys(run(&mut executor, &["KEYS", "h[ae]llo"])),
+            ["hallo", "hello"]
+        );
+        assert_eq!(sorted_keys(run(&mut executor, &["KEYS", "a.b"])), ["a.b"]);
+    }
+
+    #[test]
+    fn test_keys_matches_glob_patterns() {
+        let mut executor = executor();
+        for key in ["hello", "hallo", "hxllo", "a.b", "axb", "a*b", "user:[1]"] {
+            run(&mut executor, &["SET", key, "v"]);
+        }
+
+        assert_eq!(
+            sorted_keys(run(&mut executor, &["KEYS", "h[ae]llo"])),
+            ["hallo", "hello"]
+        );
+        assert_eq!(
+            sorted_keys(run(&mut executor, &["KEYS", "h[^e]llo"])),
+            ["hallo", "hxllo"]
+        );
+        assert_eq!(sorted_keys(run(&mut executor, &["KEYS", "a.b"])), ["a.b"]);
+        assert_eq!(sorted_keys(run(&mut executor, &["KEYS", "a\\*b"])), ["a*b"]);
+        assert_eq!(
+            sorted_keys(run(&mut executor, &["KEYS", "user:\\[1\\]"])),
+            ["user:[1]"]
+        );
+    }
+
+    #[test]
+    fn test_config_get_matches_glob_patterns() {
+        let mut executor = executor();
+        let reply = run(&mut executor, &["CONFIG", "GET", "zset-max-listpack-*"]);
+        assert_eq!(
+            reply,
+            RedisResponse::Array(vec![
+                bulk("zset-max-listpack-entries"),
+                bulk("128"),
+                bulk("zset-max-listpack-value"),
+                bulk("64"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_incr_edge_cases() {
+        let mut executor = executor();
+        let not_an_integer = RedisResponse::error("value is not an integer or out of range");
+
+        run(&mut executor, &["SET", "negative", "-7"]);
+        assert_eq!(
+            run(&mut executor, &["INCR", "negative"]),
+            RedisResponse::Integer(-6)
+        );
+
+        for value in [" 5", "5 ", "5.0", "five", "", "+5", "007", "-0"] {
+            run(&mut executor, &["SET", "k", value]);
+            assert_eq!(
+                run(&mut executor, &["INCR", "k"]),
+                not_an_integer,
+                "value {:?}",
+                value
+            );
+            assert_eq!(run(&mut executor, &["GET", "k"]), bulk(value));
+        }
+
+        let max = i64::MAX.to_string();
+        run(&mut executor, &["SET", "max", &max]);
+        assert_eq!(
+            run(&mut executor, &["INCR", "max"]).to_resp(),
+            "-ERR increment or decrement would overflow\r\n"
+        );
+        assert_eq!(run(&mut executor, &["GET", "max"]), bulk(&max));
+    }
+
+    #[test]
+    fn test_invalid_expire_time_errors() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "v"]);
+        let cases: [(&[&str], &str); 8] = [
+            (&["SET", "k", "v", "EX"], "set"),
+            (&["SET", "k", "v", "PX"], "set"),
+            (&["SET", "k", "v", "EXAT"], "set"),
+            (&["SET", "k", "v", "PXAT"], "set"),
+            (&["SETEX", "k"], "setex"),
+            (&["PSETEX", "k"], "psetex"),
+            (&["GETEX", "k", "EX"], "getex"),
+            (&["GETEX", "k", "PX"], "getex"),
+        ];
+        for (prefix, name) in cases {
+            for expiry in ["0", "-5"] {
+                let mut args = prefix.to_vec();
+                // SETEX and PSETEX take the expiry before the value
+                if name.ends_with("setex") {
+                    args.extend([expiry, "v"]);
+                } else {
+                    args.push(expiry);
+                }
+                assert_eq!(
+                    run(&mut executor, &args).to_resp(),
+                    format!("-ERR invalid expire time in '{}' command\r\n", name),
+                    "{:?}",
+                    args
+                );
+            }
+        }
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("v"));
+    }
+
+    #[test]
+    fn test_expire_time_upper_bound() {
+        let mut executor = executor();
+        // A century is well inside the range
+        let century = (100u64 * 365 * 24 * 3600).to_string();
+        assert_eq!(
+            run(&mut executor, &["SET", "k", "v", "EX", &century]),
+            RedisResponse::ok()
+        );
+        assert_eq!(
+            run(&mut executor, &["EXPIRE", "k", &century]),
+            RedisResponse::Integer(1)
+        );
+        // The last second whose milliseconds still fit in an i64
+        let last_second = (i64::MAX / 1000).to_string();
+        assert_eq!(
+            run(&mut executor, &["SET", "k", "v", "EXAT", &last_second]),
+            RedisResponse::ok()
+        );
+
+        let max = i64::MAX.to_string();
+        let past_max = (i64::MAX / 1000 + 1).to_string();
+        let cases: [(&[&str], &str); 5] = [
+            (&["SET", "k", "v", "EX", &max], "set"),
+            (&["SET", "k", "v", "PX", &max], "set"),
+            (&["SET", "k", "v", "EXAT", &past_max], "set"),
+            (&["PSETEX", "k", &max, "v"], "psetex"),
+            (&["EXPIRE", "k", &max], "expire"),
+        ];
+        for (args, name) in cases {
+            assert_eq!(
+                run(&mut executor, args).to_resp(),
+                format!("-ERR invalid expire time in '{}' command\r\n", name),
+                "{:?}",
+                args
+            );
+        }
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("v"));
+    }
+
+    #[test]
+    fn test_setex_and_getex() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["SETEX", "k", "100", "v"]),
+            RedisResponse::ok()
+        );
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("v"));
+
+        run(&mut executor, &["PSETEX", "short", "1", "v"]);
+        run(&mut executor, &["PSETEX", "kept", "100000", "v"]);
+        assert_eq!(run(&mut executor, &["GETEX", "kept", "PERSIST"]), bulk("v"));
+        assert_eq!(
+            run(&mut executor, &["PTTL", "kept"]),
+            RedisResponse::Integer(-1)
+        );
+        assert_eq!(
+            wait_for_reply(&mut executor, &["GET", "short"], RedisResponse::nil()),
+            RedisResponse::nil()
+        );
+        assert_eq!(run(&mut executor, &["GET", "kept"]), bulk("v"));
+
+        assert_eq!(run(&mut executor, &["GETEX", "k", "PX", "1"]), bulk("v"));
+        assert_eq!(
+            wait_for_reply(&mut executor, &["GETEX", "k"], RedisResponse::nil()),
+            RedisResponse::nil()
+        );
+    }
+
+    fn encoding(executor: &mut RedisCommandExecutor, key: &str) -> RedisResponse {
+        run(executor, &["OBJECT", "ENCODING", key])
+    }
+
+    #[test]
+    fn test_geo_keys_report_zset_encoding() {
+        let mut executor = executor();
+        run(
+            &mut executor,
+            &["GEOADD", "geo", "13.361389", "38.115556", "Palermo"],
+        );
+        assert_eq!(run(&mut executor, &["TYPE", "geo"]), simple("zset"));
+        assert_eq!(encoding(&mut executor, "geo"), bulk("listpack"));
+
+        run(
+            &mut executor,
+            &["CONFIG", "SET", "zset-max-listpack-entries", "1"],
+        );
+        run(
+            &mut executor,
+            &["GEOADD", "geo", "15.087269", "37.502669", "Catania"],
+        );
+        assert_eq!(encoding(&mut executor, "geo"), bulk("skiplist"));
+    }
+
+    #[test]
+    fn test_geodist_has_four_decimals() {
+        let mut executor = executor();
+        run(
+            &mut executor,
+            &["GEOADD", "cities", "-86.67", "36.12", "Nashville"],
+        );
+        run(
+            &mut executor,
+            &["GEOADD", "cities", "-118.4", "33.94", "LosAngeles"],
+        );
+        let RedisResponse::BulkString(Some(distance)) = run(
+            &mut executor,
+            &["GEODIST", "cities", "Nashville", "LosAngeles"],
+        ) else {
+            panic!("GEODIST should return a distance");
+        };
+        let (whole, decimals) = distance.split_once('.').unwrap();
+        assert_eq!(decimals.len(), 4, "distance {}", distance);
+        assert_eq!(&whole[..4], "2887", "distance {}", distance);
+    }
+
+    #[test]
+    fn test_geodist_matches_redis_output() {
+        // The GEODIST example from the Redis documentation
+        let mut executor = executor();
+        run(
+            &mut executor,
+            &["GEOADD", "Sicily", "13.361389", "38.115556", "Palermo"],
+        );
+        run(
+            &mut executor,
+            &["GEOADD", "Sicily", "15.087269", "37.502669", "Catania"],
+        );
+        assert_eq!(
+            run(&mut executor, &["GEODIST", "Sicily", "Palermo", "Catania"]),
+            bulk("166274.1516")
+        );
+        assert_eq!(
+            run(&mut executor, &["GEODIST", "Sicily", "Palermo", "Nowhere"]),
+            RedisResponse::nil()
+        );
+    }
+
+    #[test]
+    fn test_zadd_counts_added_and_changed() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["ZADD", "z", "1", "a"]),
+            RedisResponse::Integer(1)
+        );
+
+        // A new score is an update: only CH counts it
+        assert_eq!(
+            run(&mut executor, &["ZADD", "z", "2", "a"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["ZADD", "z", "CH", "3", "a"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(run(&mut executor, &["ZSCORE", "z", "a"]), bulk("3"));
+
+        // The same score changes nothing, with or without CH
+        assert_eq!(
+            run(&mut executor, &["ZADD", "z", "3", "a"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["ZADD", "z", "ch", "3", "a"]),
+            RedisResponse::Integer(0)
+        );
+
+        assert_eq!(
+            run(&mut executor, &["ZADD", "z", "CH", "1", "b"]),
+            RedisResponse::Integer(1)
+        );
+        assert!(matches!(
+            run(&mut executor, &["ZADD", "z", "BOGUS", "1", "c"]),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_zadd_flags() {
+        let mut executor = executor();
+        run(&mut executor, &["ZADD", "z", "5", "a"]);
+        let zadd = |executor: &mut RedisCommandExecutor, args: &[&str]| {
+            run(executor, &[&["ZADD", "z"][..], args].concat())
+        };
+
+        // NX only adds, XX only updates
+        assert_eq!(
+            zadd(&mut executor, &["NX", "1", "a"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            zadd(&mut executor, &["NX", "1", "b"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            zadd(&mut executor, &["XX", "1", "c"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["ZSCORE", "z", "c"]),
+            RedisResponse::nil()
+        );
+        assert_eq!(
+            zadd(&mut executor, &["XX", "CH", "2", "b"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(run(&mut executor, &["ZSCORE", "z", "a"]), bulk("5"));
+
+        // GT and LT only move the score in their direction
+        assert_eq!(
+            zadd(&mut executor, &["GT", "CH", "4", "a"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            zadd(&mut executor, &["GT", "CH", "6", "a"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            zadd(&mut executor, &["LT", "CH", "7", "a"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            zadd(&mut executor, &["LT", "CH", "3", "a"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(run(&mut executor, &["ZSCORE", "z", "a"]), bulk("3"));
+        // but still add new members
+        assert_eq!(
+            zadd(&mut executor, &["GT", "9", "d"]),
+            RedisResponse::Integer(1)
+        );
+
+        // INCR replies with the new score, or nil when a flag blocked it
+        assert_eq!(zadd(&mut executor, &["INCR", "2.5", "a"]), bulk("5.5"));
+        assert_eq!(
+            zadd(&mut executor, &["INCR", "NX", "1", "a"]),
+            RedisResponse::nil()
+        );
+        assert_eq!(
+            zadd(&mut executor, &["INCR", "LT", "1", "a"]),
+            RedisResponse::nil()
+        );
+        assert_eq!(zadd(&mut executor, &["INCR", "XX", "-0.5", "a"]), bulk("5"));
+    }
+
+    #[test]
+    fn test_zincrby() {
+        let mut executor = executor();
+        assert_eq!(run(&mut executor, &["ZINCRBY", "z", "2", "a"]), bulk("2"));
+        assert_eq!(
+            run(&mut executor, &["ZINCRBY", "z", "-3.5", "a"]),
+            bulk("-1.5")
+        );
+        assert_eq!(run(&mut executor, &["ZSCORE", "z", "a"]), bulk("-1.5"));
+        assert!(matches!(
+            run(&mut executor, &["ZINCRBY", "z", "abc", "a"]),
+            RedisResponse::Error(_)
+        ));
+
+        // +inf plus -inf leaves the score as it was
+        let nan = RedisResponse::error("resulting score is not a number (NaN)");
+        run(&mut executor, &["ZADD", "z", "+inf", "b"]);
+        assert_eq!(run(&mut executor, &["ZINCRBY", "z", "-inf", "b"]), nan);
+        assert_eq!(run(&mut executor, &["ZADD", "z", "INCR", "-inf", "b"]), nan);
+        assert_eq!(run(&mut executor, &["ZSCORE", "z", "b"]), bulk("inf"));
+        for args in [
+            &["ZINCRBY", "z", "nan", "c"][..],
+            &["ZADD", "z", "nan", "c"],
+        ] {
+            assert!(matches!(run(&mut executor, args), RedisResponse::Error(_)));
+        }
+        assert_eq!(
+            run(&mut executor, &["ZCARD", "z"]),
+            RedisResponse::Integer(2)
+        );
+    }
+
+    #[test]
+    fn test_zadd_on_wrong_type_leaves_key_untouched() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "v"]);
+        for args in [
+            &["ZADD", "k", "1", "m"][..],
+            &["ZADD", "k", "XX", "1", "m"],
+            &["ZADD", "k", "INCR", "1", "m"],
+            &["ZINCRBY", "k", "1", "m"],
+        ] {
+            assert_eq!(run(&mut executor, args), RedisResponse::wrong_type());
+        }
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("v"));
+
+        // An expired key is replaced like a missing one
+        run(&mut executor, &["SET", "gone", "v", "PX", "1"]);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(
+            run(&mut executor, &["ZADD", "gone", "XX", "1", "m"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["ZADD", "gone", "1", "m"]),
+            RedisResponse::Integer(1)
+        );
+    }
+
+    #[test]
+    fn test_hset_and_hmset_replies() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["HSET", "h", "a", "1"]),
+            RedisResponse::Integer(1)
+        );
+        // Two new fields and one overwrite
+        assert_eq!(
+            run(&mut executor, &["HSET", "h", "b", "2", "a", "10", "c", "3"]),
+            RedisResponse::Integer(2)
+        );
+        assert_eq!(
+            run(&mut executor, &["HMSET", "h", "d", "4", "a", "11"]),
+            RedisResponse::ok()
+        );
+        assert_eq!(run(&mut executor, &["TYPE", "h"]), simple("hash"));
+        assert_eq!(encoding(&mut executor, "h"), bulk("listpack"));
+
+        assert!(matches!(
+            run(&mut executor, &["HSET", "h", "a", "1", "b"]),
+            RedisResponse::Error(_)
+        ));
+        assert!(matches!(
+            run(&mut executor, &["HMSET", "h", "a"]),
+            RedisResponse::Error(_)
+        ));
+
+        run(&mut executor, &["SET", "s", "v"]);
+        assert_eq!(
+            run(&mut executor, &["HSET", "s", "a", "1"]),
+            RedisResponse::wrong_type()
+        );
+    }
+
+    #[test]
+    fn test_resp3_map_replies() {
+        let mut executor = executor();
+        let (resp2, resp3) = (Token(1), Token(2));
+        run(&mut executor, &["HSET", "h", "a", "1", "b", "2"]);
+
+        let hello = run_as(&mut executor, resp3, &["HELLO", "3"]);
+        assert!(hello.to_resp().starts_with("%7\r\n$6\r\nserver\r\n"));
+
+        assert_eq!(
+            run_as(&mut executor, resp3, &["HGETALL", "h"]).to_resp(),
+            "%2\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n"
+        );
+        assert_eq!(
+            run_as(&mut executor, resp2, &["HGETALL", "h"]).to_resp(),
+            "*4\r\n$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n"
+        );
+
+        let config = ["CONFIG", "GET", "hash-max-listpack-entries"];
+        assert_eq!(
+            run_as(&mut executor, resp3, &config).to_resp(),
+            "%1\r\n$25\r\nhash-max-listpack-entries\r\n$3\r\n128\r\n"
+        );
+        assert_eq!(
+            run_as(&mut executor, resp2, &config).to_resp(),
+            "*2\r\n$25\r\nhash-max-listpack-entries\r\n$3\r\n128\r\n"
+        );
+
+        // Switching back to RESP2 restores flat arrays
+        run_as(&mut executor, resp3, &["HELLO", "2"]);
+        assert!(run_as(&mut executor, resp3, &["HGETALL", "h"])
+            .to_resp()
+            .starts_with("*4\r\n"));
+        assert_eq!(
+            run_as(&mut executor, resp3, &["HELLO", "4"]).to_resp(),
+            "-NOPROTO unsupported protocol version\r\n"
+        );
+    }
+
+    #[test]
+    fn test_failover_errors_on_single_node() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["FAILOVER"]).to_resp(),
+            "-ERR FAILOVER requires connected replicas.\r\n"
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["FAILOVER", "TO", "127.0.0.1", "6380", "TIMEOUT", "50"]
+            )
+            .to_resp(),
+            "-ERR FAILOVER requires connected replicas.\r\n"
+        );
+        assert_eq!(
+            run(&mut executor, &["FAILOVER", "ABORT"]).to_resp(),
+            "-ERR No failover in progress.\r\n"
+        );
+        assert!(matches!(
+            run(&mut executor, &["FAILOVER", "ABORT", "FORCE"]),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_geosearch_by_radius_and_box() {
+        // Points from the GEOSEARCH example in the Redis documentation
+        let mut executor = executor();
+        for (lon, lat, member) in [
+            ("13.361389", "38.115556", "Palermo"),
+            ("15.087269", "37.502669", "Catania"),
+            ("12.758489", "38.788135", "edge1"),
+            ("17.241510", "38.788135", "edge2"),
+        ] {
+            run(&mut executor, &["GEOADD", "Sicily", lon, lat, member]);
+        }
+        let mut search = |shape: &[&str]| {
+            let mut args = vec!["GEOSEARCH", "Sicily", "FROMLONLAT", "15", "37"];
+            args.extend(shape);
+            sorted_keys(run(&mut executor, &args))
+        };
+
+        assert_eq!(search(&["BYRADIUS", "100", "km"]), ["Catania"]);
+        assert_eq!(search(&["BYRADIUS", "200", "km"]), ["Catania", "Palermo"]);
+        assert_eq!(
+            search(&["BYRADIUS", "300", "km"]),
+            ["Catania", "Palermo", "edge1", "edge2"]
+        );
+        assert_eq!(search(&["BYRADIUS", "200000", "m"]), ["Catania", "Palermo"]);
+        assert_eq!(search(&["BYRADIUS", "60", "mi"]), ["Catania"]);
+        assert_eq!(
+            search(&["BYRADIUS", "700000", "FT"]),
+            ["Catania", "Palermo"]
+        );
+
+        assert_eq!(
+            search(&["BYBOX", "400", "400", "km"]),
+            ["Catania", "Palermo", "edge1", "edge2"]
+        );
+        assert_eq!(search(&["BYBOX", "200", "200", "km"]), ["Catania"]);
+
+        assert!(matches!(
+            run(
+                &mut executor,
+                &[
+                    "GEOSEARCH",
+                    "Sicily",
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYRADIUS",
+                    "1",
+                    "yd"
+                ]
+            ),
+            RedisResponse::Error(_)
+        ));
+        assert!(matches!(
+            run(
+                &mut executor,
+                &[
+                    "GEOSEARCH",
+                    "Sicily",
+                    "FROMMEMBER",
+                    "x",
+                    "y",
+                    "BYRADIUS",
+                    "1",
+                    "km"
+                ]
+            ),
+            RedisResponse::Error(_)
+        ));
+        assert_eq!(
+            run(
+                &mut executor,
+                &[
+                    "GEOSEARCH",
+                    "nokey",
+                    "FROMLONLAT",
+                    "15",
+                    "37",
+                    "BYRADIUS",
+                    "1",
+                    "km"
+                ]
+            ),
+            RedisResponse::Array(vec![])
+        );
+    }
+
+    #[test]
+    fn test_hash_keeps_insertion_order_across_deletes() {
+        let mut executor = executor();
+        let fields: Vec<String> = (0..10).map(|i| format!("f{}", i)).collect();
+        let mut hset = vec!["HSET", "h"];
+        for field in &fields {
+            hset.extend([field.as_str(), "v"]);
+        }
+        run(&mut executor, &hset);
+        let mut hdel = vec!["HDEL", "h"];
+        hdel.extend(fields[..6].iter().map(String::as_str));
+        assert_eq!(run(&mut executor, &hdel), RedisResponse::Integer(6));
+
+        assert_eq!(
+            run(&mut executor, &["HSET", "h", "f8", "w", "f0", "new"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(run(&mut executor, &["HGET", "h", "f9"]), bulk("v"));
+        assert_eq!(
+            run(&mut executor, &["HGETALL", "h"]),
+            RedisResponse::Array(vec![
+                bulk("f6"),
+                bulk("v"),
+                bulk("f7"),
+                bulk("v"),
+                bulk("f8"),
+                bulk("w"),
+                bulk("f9"),
+                bulk("v"),
+                bulk("f0"),
+                bulk("new"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_hash_commands() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["HSET", "h", "name", "redis", "kind", "db"]),
+            RedisResponse::Integer(2)
+        );
+        // Overwriting a field does not count as new
+        assert_eq!(
+            run(
+                &mut executor,
+                &["HSET", "h", "name", "valkey", "year", "2024"]
+            ),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(run(&mut executor, &["HGET", "h", "name"]), bulk("valkey"));
+        assert_eq!(
+            run(&mut executor, &["HGET", "h", "nope"]),
+            RedisResponse::nil()
+        );
+        assert_eq!(
+            run(&mut executor, &["HLEN", "h"]),
+            RedisResponse::Integer(3)
+        );
+        assert_eq!(
+            run(&mut executor, &["HEXISTS", "h", "kind"]),
+            RedisResponse::Integer(1)
+        );
+
+        // Fields come back in insertion order, overwrites keep their place
+        assert_eq!(
+            run(&mut executor, &["HGETALL", "h"]),
+            RedisResponse::Array(vec![
+                bulk("name"),
+                bulk("valkey"),
+                bulk("kind"),
+                bulk("db"),
+                bulk("year"),
+                bulk("2024"),
+            ])
+        );
+
+        assert_eq!(
+            run(&mut executor, &["HDEL", "h", "kind", "nope"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["HEXISTS", "h", "kind"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["HDEL", "h", "name", "year"]),
+            RedisResponse::Integer(2)
+        );
+        // The last field takes the key with it
+        assert_eq!(run(&mut executor, &["TYPE", "h"]), simple("none"));
+        assert_eq!(
+            run(&mut executor, &["HGETALL", "h"]),
+            RedisResponse::Array(vec![])
+        );
+
+        run(&mut executor, &["SET", "s", "v"]);
+        assert_eq!(
+            run(&mut executor, &["HGET", "s", "f"]),
+            RedisResponse::wrong_type()
+        );
+    }
+
+    #[test]
+    fn test_set_commands() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["SADD", "s", "a", "b", "a"]),
+            RedisResponse::Integer(2)
+        );
+        // Members already present are not counted again
+        assert_eq!(
+            run(&mut executor, &["SADD", "s", "a"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(run(&mut executor, &["TYPE", "s"]), simple("set"));
+        assert_eq!(
+            run(&mut executor, &["SCARD", "s"]),
+            RedisResponse::Integer(2)
+        );
+        assert_eq!(
+            run(&mut executor, &["SISMEMBER", "s", "b"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["SISMEMBER", "s", "z"]),
+            RedisResponse::Integer(0)
+        );
+
+        let RedisResponse::Array(mut members) = run(&mut executor, &["SMEMBERS", "s"]) else {
+            panic!("SMEMBERS should reply with an array");
+        };
+        members.sort_by_key(|member| member.to_resp());
+        assert_eq!(members, vec![bulk("a"), bulk("b")]);
+
+        assert_eq!(
+            run(&mut executor, &["SREM", "s", "a", "z"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(run(&mut executor, &["SPOP", "s"]), bulk("b"));
+        // Emptying the set deletes the key
+        assert_eq!(run(&mut executor, &["TYPE", "s"]), simple("none"));
+        assert_eq!(run(&mut executor, &["SPOP", "s"]), RedisResponse::nil());
+        assert_eq!(
+            run(&mut executor, &["SPOP", "s", "3"]),
+            RedisResponse::Array(vec![])
+        );
+
+        run(&mut executor, &["SADD", "t", "x", "y", "z"]);
+        let RedisResponse::Array(popped) = run(&mut executor, &["SPOP", "t", "2"]) else {
+            panic!("SPOP with a count should reply with an array");
+        };
+        assert_eq!(popped.len(), 2);
+        assert_eq!(
+            run(&mut executor, &["SCARD", "t"]),
+            RedisResponse::Integer(1)
+        );
+        run(&mut executor, &["SREM", "t", "x", "y", "z"]);
+        assert_eq!(
+            run(&mut executor, &["EXISTS", "t"]),
+            RedisResponse::Integer(0)
+        );
+
+        run(&mut executor, &["SET", "str", "v"]);
+        assert_eq!(
+            run(&mut executor, &["SADD", "str", "a"]),
+            RedisResponse::wrong_type()
+        );
+    }
+
+    #[test]
+    fn test_consumer_group_errors() {
+        let mut executor = executor();
+        assert!(matches!(
+            run(&mut executor, &["XGROUP", "CREATE", "s", "g", "$"]),
+            RedisResponse::RawError(msg) if msg.contains("MKSTREAM")
+        ));
+        assert_eq!(
+            run(
+                &mut executor,
+                &["XGROUP", "CREATE", "s", "g", "$", "MKSTREAM"]
+            ),
+            RedisResponse::ok()
+        );
+        assert_eq!(
+            run(&mut executor, &["XGROUP", "CREATE", "s", "g", "0"]),
+            RedisResponse::RawError("BUSYGROUP Consumer Group name already exists".to_string())
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["XREADGROUP", "GROUP", "nope", "c", "STREAMS", "s", ">"]
+            ),
+            RedisResponse::RawError(
+                "NOGROUP No such key 's' or consumer group 'nope' in XREADGROUP with GROUP option"
+                    .to_string()
+            )
+        );
+        // COUNT caps how many entries one read delivers
+        for id in ["1-1", "2-1", "3-1"] {
+            run(&mut executor, &["XADD", "s", id, "f", "v"]);
+        }
+        let RedisResponse::Array(streams) = run(
+            &mut executor,
+            &[
+                "XREADGROUP",
+                "GROUP",
+                "g",
+                "c",
+                "COUNT",
+                "2",
+                "STREAMS",
+                "s",
+                ">",
+            ],
+        ) else {
+            panic!("XREADGROUP should reply with an array");
+        };
+        assert_eq!(
+            streams[0],
+            RedisResponse::Array(vec![
+                bulk("s"),
+                RedisResponse::Array(vec![
+                    RedisCommandExecutor::stream_entry_response(
+                        "1-1".to_string(),
+                        vec![("f".to_string(), "v".to_string())]
+                    ),
+                    RedisCommandExecutor::stream_entry_response(
+                        "2-1".to_string(),
+                        vec![("f".to_string(), "v".to_string())]
+                    ),
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ttl_and_pttl() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["TTL", "missing"]),
+            RedisResponse::Integer(-2)
+        );
+        assert_eq!(
+            run(&mut executor, &["PTTL", "missing"]),
+            RedisResponse::Integer(-2)
+        );
+
+        run(&mut executor, &["SET", "forever", "v"]);
+        assert_eq!(
+            run(&mut executor, &["TTL", "forever"]),
+            RedisResponse::Integer(-1)
+        );
+        assert_eq!(
+            run(&mut executor, &["PTTL", "forever"]),
+            RedisResponse::Integer(-1)
+        );
+
+        run(&mut executor, &["SET", "k", "v", "PX", "10000"]);
+        assert_eq!(
+            run(&mut executor, &["TTL", "k"]),
+            RedisResponse::Integer(10)
+        );
+        let RedisResponse::Integer(pttl) = run(&mut executor, &["PTTL", "k"]) else {
+            panic!("PTTL should reply with an integer");
+        };
+        assert!((9_000..=10_000).contains(&pttl), "unexpected PTTL {}", pttl);
+
+        // Expired keys count as missing
+        run(&mut executor, &["SET", "gone", "v", "PX", "1"]);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(
+            run(&mut executor, &["TTL", "gone"]),
+            RedisResponse::Integer(-2)
+        );
+    }
+
+    #[test]
+    fn test_expire_commands() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["EXPIRE", "missing", "10"]),
+            RedisResponse::Integer(0)
+        );
+
+        run(&mut executor, &["SET", "k", "v"]);
+        assert_eq!(
+            run(&mut executor, &["EXPIRE", "k", "100"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["TTL", "k"]),
+            RedisResponse::Integer(100)
+        );
+
+        let at = (now_ms() / 1000 + 50).to_string();
+        assert_eq!(
+            run(&mut executor, &["EXPIREAT", "k", &at]),
+            RedisResponse::Integer(1)
+        );
+        let RedisResponse::Integer(ttl) = run(&mut executor, &["TTL", "k"]) else {
+            panic!("TTL should reply with an integer");
+        };
+        assert!((49..=50).contains(&ttl), "unexpected TTL {}", ttl);
+
+        assert_eq!(
+            run(&mut executor, &["PEXPIRE", "k", "20"]),
+            RedisResponse::Integer(1)
+        );
+        std::thread::sleep(std::time::Duration::from_millis(30));
+        assert_eq!(run(&mut executor, &["GET", "k"]), RedisResponse::nil());
+        assert_eq!(
+            run(&mut executor, &["PEXPIRE", "k", "20"]),
+            RedisResponse::Integer(0)
+        );
+
+        // A deadline in the past deletes the key
+        run(&mut executor, &["SET", "old", "v"]);
+        assert_eq!(
+            run(&mut executor, &["PEXPIREAT", "old", "1"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["TTL", "old"]),
+            RedisResponse::Integer(-2)
+        );
+
+        assert_eq!(
+            run(&mut executor, &["EXPIRE", "k", "ten"]),
+            RedisResponse::error("value is not an integer or out of range")
+        );
+    }
+
+    #[test]
+    fn test_expire_propagates_as_pexpireat() {
+        let mut executor = executor();
+        executor.storage.enable_propagation_log();
+        run(&mut executor, &["SET", "k", "v"]);
+        run(&mut executor, &["SET", "gone", "v"]);
+
+        let before = now_ms();
+        run(&mut executor, &["EXPIRE", "k", "100"]);
+        let after = now_ms();
+        run(&mut executor, &["EXPIRE", "gone", "-1"]);
+        run(&mut executor, &["EXPIRE", "missing", "100"]);
+
+        let log = executor.storage.propagation_log();
+        assert_eq!(log.len(), 4);
+        assert_eq!(log[2][..2], ["PEXPIREAT", "k"]);
+        let deadline: u128 = log[2][2].parse().unwrap();
+        assert!((before + 100_000..=after + 100_000).contains(&deadline));
+        assert_eq!(log[3], ["DEL", "gone"]);
+    }
+
+    #[test]
+    fn test_commands_rejected_while_loading() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "v"]);
+
+        executor.set_loading(true);
+        let loading =
+            RedisResponse::RawError("LOADING Redis is loading the dataset in memory".to_string());
+        assert_eq!(run(&mut executor, &["GET", "k"]), loading);
+        assert_eq!(run(&mut executor, &["SET", "k", "w"]), loading);
+        assert!(matches!(
+            run(&mut executor, &["INFO", "replication"]),
+            RedisResponse::BulkString(Some(_))
+        ));
+
+        executor.set_loading(false);
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("v"));
+    }
+
+    #[test]
+    fn test_persist() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "v", "PX", "10000"]);
+        assert_eq!(
+            run(&mut executor, &["PERSIST", "k"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["TTL", "k"]),
+            RedisResponse::Integer(-1)
+        );
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("v"));
+
+        // Nothing to remove the second time, nor on keys without a TTL
+        assert_eq!(
+            run(&mut executor, &["PERSIST", "k"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["PERSIST", "missing"]),
+            RedisResponse::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_rename_moves_value_and_ttl() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "v", "PX", "10000"]);
+        assert_eq!(
+            run(&mut executor, &["RENAME", "k", "moved"]),
+            RedisResponse::ok()
+        );
+        assert_eq!(run(&mut executor, &["GET", "moved"]), bulk("v"));
+        assert_eq!(
+            run(&mut executor, &["TTL", "moved"]),
+            RedisResponse::Integer(10)
+        );
+        assert_eq!(
+            run(&mut executor, &["TTL", "k"]),
+            RedisResponse::Integer(-2)
+        );
+
+        // The destination is replaced whatever its type
+        run(&mut executor, &["SET", "dest", "old"]);
+        run(&mut executor, &["RPUSH", "list", "a", "b"]);
+        assert_eq!(
+            run(&mut executor, &["RENAME", "list", "dest"]),
+            RedisResponse::ok()
+        );
+        assert_eq!(run(&mut executor, &["TYPE", "dest"]), simple("list"));
+        assert_eq!(
+            run(&mut executor, &["TTL", "dest"]),
+            RedisResponse::Integer(-1)
+        );
+        assert_eq!(
+            run(&mut executor, &["LRANGE", "dest", "0", "-1"]),
+            RedisResponse::Array(vec![simple("a"), simple("b")])
+        );
+
+        for (key, args) in [
+            ("z", vec!["ZADD", "z", "1", "m"]),
+            ("h", vec!["HSET", "h", "f", "v"]),
+            ("s", vec!["SADD", "s", "m"]),
+            ("x", vec!["XADD", "x", "1-1", "f", "v"]),
+        ] {
+            run(&mut executor, &args);
+            let before = run(&mut executor, &["TYPE", key]);
+            let target = format!("{}-renamed", key);
+            run(&mut executor, &["RENAME", key, &target]);
+            assert_eq!(run(&mut executor, &["TYPE", &target]), before);
+            assert_eq!(run(&mut executor, &["TYPE", key]), simple("none"));
+        }
+
+        assert_eq!(
+            run(&mut executor, &["RENAME", "missing", "other"]),
+            RedisResponse::error("no such key")
+        );
+    }
+
+    #[test]
+    fn test_rpop() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["RPOP", "missing"]),
+            RedisResponse::nil()
+        );
+
+        run(&mut executor, &["RPUSH", "l", "a", "b", "c", "d"]);
+        assert_eq!(run(&mut executor, &["RPOP", "l"]), bulk("d"));
+        assert_eq!(
+            run(&mut executor, &["RPOP", "l", "2"]),
+            RedisResponse::Array(vec![simple("c"), simple("b")])
+        );
+        // Asking for more than is left pops the rest and deletes the key
+        assert_eq!(
+            run(&mut executor, &["RPOP", "l", "10"]),
+            RedisResponse::Array(vec![simple("a")])
+        );
+        assert_eq!(run(&mut executor, &["TYPE", "l"]), simple("none"));
+        assert_eq!(
+            run(&mut executor, &["RPOP", "l", "3"]),
+            RedisResponse::null_array()
+        );
+    }
+
+    #[test]
+    fn test_pop_with_count_zero() {
+        let mut executor = executor();
+        run(&mut executor, &["RPUSH", "l", "a"]);
+        for op in ["LPOP", "RPOP"] {
+            assert_eq!(
+                run(&mut executor, &[op, "l", "0"]),
+                RedisResponse::Array(vec![])
+            );
+            assert_eq!(
+                run(&mut executor, &[op, "missing", "0"]),
+                RedisResponse::null_array()
+            );
+            assert!(matches!(
+                run(&mut executor, &[op, "l", "-1"]),
+                RedisResponse::Error(_)
+            ));
+        }
+        assert_eq!(
+            run(&mut executor, &["LLEN", "l"]),
+            RedisResponse::Integer(1)
+        );
+
+        run(&mut executor, &["PEXPIRE", "l", "1"]);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        assert_eq!(
+            run(&mut executor, &["LPOP", "l", "0"]),
+            RedisResponse::null_array()
+        );
+        assert_eq!(run(&mut executor, &["LPOP", "l"]), RedisResponse::nil());
+    }
+
+    #[test]
+    fn test_lindex_and_lset() {
+        let mut executor = executor();
+        run(&mut executor, &["RPUSH", "l", "a", "b", "c"]);
+        assert_eq!(run(&mut executor, &["LINDEX", "l", "0"]), bulk("a"));
+        assert_eq!(run(&mut executor, &["LINDEX", "l", "-1"]), bulk("c"));
+        assert_eq!(run(&mut executor, &["LINDEX", "l", "-3"]), bulk("a"));
+        assert_eq!(
+            run(&mut executor, &["LINDEX", "l", "-4"]),
+            RedisResponse::nil()
+        );
+        assert_eq!(
+            run(&mut executor, &["LINDEX", "l", "3"]),
+            RedisResponse::nil()
+        );
+
+        assert_eq!(
+            run(&mut executor, &["LSET", "l", "-1", "z"]),
+            RedisResponse::ok()
+        );
+        assert_eq!(run(&mut executor, &["LINDEX", "l", "2"]), bulk("z"));
+        for index in ["3", "-4"] {
+            assert_eq!(
+                run(&mut executor, &["LSET", "l", index, "x"]),
+                RedisResponse::error("index out of range")
+            );
+        }
+        assert_eq!(
+            run(&mut executor, &["LSET", "missing", "0", "x"]),
+            RedisResponse::error("no such key")
+        );
+    }
+
+    #[test]
+    fn test_ltrim() {
+        let mut executor = executor();
+        run(&mut executor, &["RPUSH", "l", "a", "b", "c", "d", "e"]);
+        assert_eq!(
+            run(&mut executor, &["LTRIM", "l", "1", "-2"]),
+            RedisResponse::ok()
+        );
+        assert_eq!(
+            run(&mut executor, &["LRANGE", "l", "0", "-1"]),
+            RedisResponse::Array(vec![simple("b"), simple("c"), simple("d")])
+        );
+        // Out of range bounds are clamped to the list
+        run(&mut executor, &["LTRIM", "l", "-100", "100"]);
+        assert_eq!(
+            run(&mut executor, &["LLEN", "l"]),
+            RedisResponse::Integer(3)
+        );
+
+        // An empty range removes the key
+        run(&mut executor, &["LTRIM", "l", "2", "1"]);
+        assert_eq!(run(&mut executor, &["TYPE", "l"]), simple("none"));
+    }
+
+    #[test]
+    fn test_lrem() {
+        let mut executor = executor();
+        // count, removed, what is left
+        let cases: [(&str, i64, &[&str]); 4] = [
+            ("2", 2, &["a", "b", "c", "x"]),
+            // A negative count removes from the tail
+            ("-2", 2, &["a", "x", "b", "c"]),
+            ("-5", 3, &["a", "b", "c"]),
+            ("0", 3, &["a", "b", "c"]),
+        ];
+        for (count, removed, left) in cases {
+            run(&mut executor, &["DEL", "l"]);
+            run(&mut executor, &["RPUSH", "l", "a", "x", "b", "x", "c", "x"]);
+            assert_eq!(
+                run(&mut executor, &["LREM", "l", count, "x"]),
+                RedisResponse::Integer(removed)
+            );
+            assert_eq!(
+                run(&mut executor, &["LRANGE", "l", "0", "-1"]),
+                RedisResponse::Array(left.iter().map(|item| simple(item)).collect())
+            );
+        }
+        assert_eq!(
+            run(&mut executor, &["LREM", "missing", "0", "x"]),
+            RedisResponse::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_linsert() {
+        let mut executor = executor();
+        run(&mut executor, &["RPUSH", "l", "a", "b", "c"]);
+        assert_eq!(
+            run(&mut executor, &["LINSERT", "l", "BEFORE", "b", "x"]),
+            RedisResponse::Integer(4)
+        );
+        assert_eq!(
+            run(&mut executor, &["LINSERT", "l", "after", "b", "y"]),
+            RedisResponse::Integer(5)
+        );
+        assert_eq!(
+            run(&mut executor, &["LRANGE", "l", "0", "-1"]),
+            RedisResponse::Array(["a", "x", "b", "y", "c"].map(simple).to_vec())
+        );
+        assert_eq!(
+            run(&mut executor, &["LINSERT", "l", "BEFORE", "nope", "z"]),
+            RedisResponse::Integer(-1)
+        );
+        assert_eq!(
+            run(&mut executor, &["LINSERT", "missing", "BEFORE", "a", "z"]),
+            RedisResponse::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_set_store_overwrites_destination() {
+        let mut executor = executor();
+        run(&mut executor, &["SADD", "a", "1", "2", "3"]);
+        run(&mut executor, &["SADD", "b", "2", "3", "4"]);
+        run(&mut executor, &["SET", "dest", "string"]);
+
+        assert_eq!(
+            run(&mut executor, &["SINTERSTORE", "dest", "a", "b"]),
+            RedisResponse::Integer(2)
+        );
+        assert_eq!(run(&mut executor, &["TYPE", "dest"]), simple("set"));
+        let mut members = match run(&mut executor, &["SMEMBERS", "dest"]) {
+            RedisResponse::Array(members) => members,
+            other => panic!("unexpected reply {:?}", other),
+        };
+        members.sort_by_key(|member| format!("{:?}", member));
+        assert_eq!(members, vec![bulk("2"), bulk("3")]);
+
+        // An empty result deletes the destination, even a list
+        run(&mut executor, &["RPUSH", "list", "x"]);
+        assert_eq!(
+            run(&mut executor, &["SINTERSTORE", "list", "a", "missing"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(run(&mut executor, &["TYPE", "list"]), simple("none"));
+
+        assert_eq!(
+            run(&mut executor, &["SUNIONSTORE", "dest", "a", "b"]),
+            RedisResponse::Integer(4)
+        );
+        assert_eq!(
+            run(&mut executor, &["SDIFFSTORE", "dest", "a", "b"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["SMEMBERS", "dest"]),
+            RedisResponse::Array(vec![bulk("1")])
+        );
+
+        // Source keys must still be sets
+        run(&mut executor, &["SET", "s", "v"]);
+        assert_eq!(
+            run(&mut executor, &["SUNIONSTORE", "dest", "a", "s"]),
+            RedisResponse::wrong_type()
+        );
+    }
+
+    #[test]
+    fn test_sintercard() {
+        let mut executor = executor();
+        run(&mut executor, &["SADD", "a", "1", "2", "3", "4"]);
+        run(&mut executor, &["SADD", "b", "2", "3", "4", "5"]);
+
+        assert_eq!(
+            run(&mut executor, &["SINTERCARD", "2", "a", "b"]),
+            RedisResponse::Integer(3)
+        );
+        assert_eq!(
+            run(&mut executor, &["SINTERCARD", "2", "a", "b", "LIMIT", "2"]),
+            RedisResponse::Integer(2)
+        );
+        assert_eq!(
+            run(&mut executor, &["SINTERCARD", "2", "a", "b", "limit", "0"]),
+            RedisResponse::Integer(3)
+        );
+        assert_eq!(
+            run(&mut executor, &["SINTERCARD", "2", "a", "missing"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["SINTERCARD", "3", "a", "b"]),
+            RedisResponse::error("Number of keys can't be greater than number of args")
+        );
+        assert_eq!(
+            run(&mut executor, &["SINTERCARD", "0", "a"]),
+            RedisResponse::error("numkeys should be greater than 0")
+        );
+
+        run(&mut executor, &["SET", "s", "v"]);
+        assert_eq!(
+            run(&mut executor, &["SINTERCARD", "2", "a", "s"]),
+            RedisResponse::wrong_type()
+        );
+    }
+
+    #[test]
+    fn test_lpos() {
+        let mut executor = executor();
+        let ints = |items: &[i64]| {
+            RedisResponse::Array(items.iter().map(|&i| RedisResponse::Integer(i)).collect())
+        };
+        run(
+            &mut executor,
+            &["RPUSH", "mylist", "a", "b", "c", "1", "2", "3", "c", "c"],
+        );
+
+        assert_eq!(
+            run(&mut executor, &["LPOS", "mylist", "c"]),
+            RedisResponse::Integer(2)
+        );
+        assert_eq!(
+            run(&mut executor, &["LPOS", "mylist", "c", "RANK", "2"]),
+            RedisResponse::Integer(6)
+        );
+        assert_eq!(
+            run(&mut executor, &["LPOS", "mylist", "c", "RANK", "-1"]),
+            RedisResponse::Integer(7)
+        );
+        assert_eq!(
+            run(&mut executor, &["LPOS", "mylist", "c", "COUNT", "2"]),
+            ints(&[2, 6])
+        );
+        assert_eq!(
+            run(&mut executor, &["LPOS", "mylist", "c", "COUNT", "0"]),
+            ints(&[2, 6, 7])
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["LPOS", "mylist", "c", "RANK", "-1", "COUNT", "2"]
+            ),
+            ints(&[7, 6])
+        );
+        // MAXLEN bounds the scan, from the tail for a negative rank
+        assert_eq!(
+            run(
+                &mut executor,
+                &["LPOS", "mylist", "c", "COUNT", "0", "MAXLEN", "3"]
+            ),
+            ints(&[2])
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["LPOS", "mylist", "c", "RANK", "-2", "COUNT", "0", "MAXLEN", "2"]
+            ),
+            ints(&[6])
+        );
+
+        assert_eq!(
+            run(&mut executor, &["LPOS", "mylist", "x"]),
+            RedisResponse::nil()
+        );
+        assert_eq!(
+            run(&mut executor, &["LPOS", "mylist", "x", "COUNT", "0"]),
+            ints(&[])
+        );
+        assert!(matches!(
+            run(&mut executor, &["LPOS", "mylist", "c", "RANK", "0"]),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_bitcount_byte_and_bit_ranges() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "foobar"]);
+        let count = |executor: &mut RedisCommandExecutor, range: &[&str]| {
+            run(executor, &[&["BITCOUNT", "k"][..], range].concat())
+        };
+        assert_eq!(count(&mut executor, &[]), RedisResponse::Integer(26));
+        assert_eq!(count(&mut executor, &["0", "0"]), RedisResponse::Integer(4));
+        assert_eq!(
+            count(&mut executor, &["1", "1", "BYTE"]),
+            RedisResponse::Integer(6)
+        );
+        // The same span counted in bits matches the byte range
+        assert_eq!(
+            count(&mut executor, &["8", "15", "BIT"]),
+            RedisResponse::Integer(6)
+        );
+        assert_eq!(
+            count(&mut executor, &["5", "30", "BIT"]),
+            RedisResponse::Integer(17)
+        );
+
+        // Negative indices count back from the last byte or the last bit
+        assert_eq!(
+            count(&mut executor, &["-1", "-1"]),
+            RedisResponse::Integer(4)
+        );
+        assert_eq!(
+            count(&mut executor, &["-8", "-1", "bit"]),
+            RedisResponse::Integer(4)
+        );
+        assert_eq!(
+            count(&mut executor, &["-2", "-1", "BIT"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            count(&mut executor, &["-1", "-1", "BIT"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            count(&mut executor, &["2", "1", "BIT"]),
+            RedisResponse::Integer(0)
+        );
+
+        assert_eq!(
+            run(&mut executor, &["BITCOUNT", "missing"]),
+            RedisResponse::Integer(0)
+        );
+        assert!(matches!(
+            count(&mut executor, &["0", "1", "NIBBLE"]),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_bitpos_byte_and_bit_ranges() {
+        let mut executor = executor();
+        // 'f' is 01100110, 'o' is 01101111 and 'r' is 01110010
+        run(&mut executor, &["SET", "k", "foobar"]);
+        let pos = |executor: &mut RedisCommandExecutor, args: &[&str]| {
+            run(executor, &[&["BITPOS", "k"][..], args].concat())
+        };
+        assert_eq!(pos(&mut executor, &["1"]), RedisResponse::Integer(1));
+        assert_eq!(pos(&mut executor, &["0"]), RedisResponse::Integer(0));
+        assert_eq!(
+            pos(&mut executor, &["1", "2", "-1", "BYTE"]),
+            RedisResponse::Integer(17)
+        );
+        assert_eq!(
+            pos(&mut executor, &["1", "7", "15", "BIT"]),
+            RedisResponse::Integer(9)
+        );
+        assert_eq!(
+            pos(&mut executor, &["0", "-8", "-1", "BIT"]),
+            RedisResponse::Integer(40)
+        );
+        assert_eq!(
+            pos(&mut executor, &["1", "-7", "-1", "BIT"]),
+            RedisResponse::Integer(41)
+        );
+        assert_eq!(
+            pos(&mut executor, &["0", "1", "2", "BIT"]),
+            RedisResponse::Integer(-1)
+        );
+
+        // 0x7f is 01111111, so an explicit end rules out the bit past it
+        run(&mut executor, &["SET", "k", "\u{7f}"]);
+        assert_eq!(
+            pos(&mut executor, &["0", "1", "-1", "BIT"]),
+            RedisResponse::Integer(-1)
+        );
+        assert!(matches!(
+            pos(&mut executor, &["0", "1", "BIT"]),
+            RedisResponse::Error(_)
+        ));
+
+        assert_eq!(
+            run(&mut executor, &["BITPOS", "missing", "0"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["BITPOS", "missing", "1"]),
+            RedisResponse::Integer(-1)
+        );
+    }
+
+    #[test]
+    fn test_zrangebyscore() {
+        let mut executor = executor();
+        for (score, member) in [("1", "a"), ("2", "b"), ("3", "c"), ("4", "d")] {
+            run(&mut executor, &["ZADD", "z", score, member]);
+        }
+        let members =
+            |items: &[&str]| RedisResponse::Array(items.iter().map(|m| bulk(m)).collect());
+
+        assert_eq!(
+            run(&mut executor, &["ZRANGEBYSCORE", "z", "2", "3"]),
+            members(&["b", "c"])
+        );
+        assert_eq!(
+            run(&mut executor, &["ZRANGEBYSCORE", "z", "(2", "3"]),
+            members(&["c"])
+        );
+        assert_eq!(
+            run(&mut executor, &["ZRANGEBYSCORE", "z", "2", "(3"]),
+            members(&["b"])
+        );
+        assert_eq!(
+            run(&mut executor, &["ZRANGEBYSCORE", "z", "(2", "(3"]),
+            members(&[])
+        );
+        assert_eq!(
+            run(&mut executor, &["ZRANGEBYSCORE", "z", "-inf", "+inf"]),
+            members(&["a", "b", "c", "d"])
+        );
+        assert_eq!(
+            run(&mut executor, &["ZRANGEBYSCORE", "z", "(3", "inf"]),
+            members(&["d"])
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["ZRANGEBYSCORE", "z", "-inf", "(1", "WITHSCORES"]
+            ),
+            members(&[])
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["ZRANGEBYSCORE", "z", "-inf", "2", "withscores"]
+            ),
+            members(&["a", "1", "b", "2"])
+        );
+
+        assert_eq!(
+            run(&mut executor, &["ZRANGEBYSCORE", "missing", "-inf", "+inf"]),
+            members(&[])
+        );
+        assert_eq!(
+            run(&mut executor, &["ZRANGEBYSCORE", "z", "low", "3"]),
+            RedisResponse::error("min or max is not a float")
+        );
+    }
+
+    #[test]
+    fn test_score_bounds() {
+        let mut executor = executor();
+        for (score, member) in [("1", "a"), ("5", "b"), ("10", "c")] {
+            run(&mut executor, &["ZADD", "z", score, member]);
+        }
+        let count = |executor: &mut RedisCommandExecutor, min: &str, max: &str| {
+            run(executor, &["ZCOUNT", "z", min, max])
+        };
+        assert_eq!(count(&mut executor, "1", "10"), RedisResponse::Integer(3));
+        assert_eq!(count(&mut executor, "(1", "10"), RedisResponse::Integer(2));
+        assert_eq!(count(&mut executor, "1", "(10"), RedisResponse::Integer(2));
+        assert_eq!(count(&mut executor, "(1", "(10"), RedisResponse::Integer(1));
+        assert_eq!(count(&mut executor, "(5", "(5"), RedisResponse::Integer(0));
+        assert_eq!(
+            count(&mut executor, "-inf", "+inf"),
+            RedisResponse::Integer(3)
+        );
+        assert_eq!(count(&mut executor, "(5", "inf"), RedisResponse::Integer(1));
+        assert_eq!(
+            count(&mut executor, "-inf", "(-inf"),
+            RedisResponse::Integer(0)
+        );
+
+        assert_eq!(
+            run(&mut executor, &["ZRANGE", "z", "(1", "+inf", "BYSCORE"]),
+            RedisResponse::Array(vec![bulk("b"), bulk("c")])
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["ZRANGE", "z", "-inf", "(5", "BYSCORE", "WITHSCORES"]
+            ),
+            RedisResponse::Array(vec![bulk("a"), bulk("1")])
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["ZRANGESTORE", "dst", "z", "(1", "inf", "BYSCORE"]
+            ),
+            RedisResponse::Integer(2)
+        );
+        assert_eq!(run(&mut executor, &["ZSCORE", "dst", "c"]), bulk("10"));
+        assert_eq!(
+            run(&mut executor, &["ZRANGESTORE", "dst", "z", "0", "0"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["ZRANGE", "dst", "0", "-1"]),
+            RedisResponse::Array(vec![simple("a")])
+        );
+
+        for args in [
+            &["ZCOUNT", "z", "(abc", "10"][..],
+            &["ZCOUNT", "z", "1", "nan"],
+            &["ZRANGEBYSCORE", "z", "((1", "2"],
+            &["ZRANGE", "z", "(x", "1", "BYSCORE"],
+            &["ZRANGESTORE", "dst", "z", "1", "(", "BYSCORE"],
+        ] {
+            assert_eq!(
+                run(&mut executor, args).to_resp(),
+                "-ERR min or max is not a float\r\n",
+                "{:?}",
+                args
+            );
+        }
+    }
+
+    #[test]
+    fn test_zmscore() {
+        let mut executor = executor();
+        run(&mut executor, &["ZADD", "z", "1.5", "a"]);
+        run(&mut executor, &["ZADD", "z", "-2", "b"]);
+        assert_eq!(
+            run(&mut executor, &["ZMSCORE", "z", "b", "missing", "a"]),
+            RedisResponse::Array(vec![bulk("-2"), RedisResponse::nil(), bulk("1.5")])
+        );
+        assert_eq!(
+            run(&mut executor, &["ZMSCORE", "nokey", "a"]),
+            RedisResponse::Array(vec![RedisResponse::nil()])
+        );
+        assert!(matches!(
+            run(&mut executor, &["ZMSCORE", "z"]),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_save_writes_a_loadable_snapshot() {
+        let dir = std::env::temp_dir().join(format!("redis-rs-save-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir_arg = dir.to_str().unwrap();
+
+        let mut executor = executor();
+        run(&mut executor, &["CONFIG", "SET", "dir", dir_arg]);
+        run(&mut executor, &["CONFIG", "SET", "dbfilename", "saved.rdb"]);
+        run(&mut executor, &["SET", "a", "1"]);
+        run(&mut executor, &["SET", "b", "two"]);
+        run(&mut executor, &["RPUSH", "list", "x"]);
+        assert_eq!(run(&mut executor, &["SAVE"]), RedisResponse::ok());
+
+        let mut storage = MemoryStorage::new(
+            EventLoopHandle::detached(),
+            ReplConfig::new_master("127.0.0.1".to_string(), 6379),
+        );
+        storage.read_from_persistent_storage(dir_arg, "saved.rdb");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(storage.get("a"), Some("1".to_string()));
+        assert_eq!(storage.get("b"), Some("two".to_string()));
+        assert_eq!(storage.lrange("list", 0, -1), Some(vec!["x".to_string()]));
+        assert!(matches!(
+            run(&mut executor, &["SAVE", "now"]),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_randomkey_skips_expired_keys() {
+        let mut executor = executor();
+        assert_eq!(run(&mut executor, &["RANDOMKEY"]), RedisResponse::nil());
+
+        let keys = ["a", "b", "c"];
+        for key in keys {
+            run(&mut executor, &["SET", key, "v"]);
+        }
+        run(&mut executor, &["PSETEX", "expired", "1", "v"]);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        for _ in 0..20 {
+            let RedisResponse::BulkString(Some(key)) = run(&mut executor, &["RANDOMKEY"]) else {
+                panic!("RANDOMKEY should return a key");
+            };
+            assert!(keys.contains(&key.as_str()), "{}", key);
+        }
+
+        for key in keys {
+            run(&mut executor, &["DEL", key]);
+        }
+        assert_eq!(run(&mut executor, &["RANDOMKEY"]), RedisResponse::nil());
+    }
+
+    #[test]
+    fn test_mget_and_mset() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["MSET", "a", "1", "b", "2"]),
+            RedisResponse::ok()
+        );
+        run(&mut executor, &["RPUSH", "list", "x"]);
+
+        assert_eq!(
+            run(&mut executor, &["MGET", "a", "missing", "b", "list"]),
+            RedisResponse::Array(vec![
+                bulk("1"),
+                RedisResponse::nil(),
+                bulk("2"),
+                RedisResponse::nil(),
+            ])
+        );
+        // Non-string keys are left alone
+        assert_eq!(
+            run(&mut executor, &["LLEN", "list"]),
+            RedisResponse::Integer(1)
+        );
+
+        run(&mut executor, &["MSET", "a", "3", "list", "4"]);
+        assert_eq!(
+            run(&mut executor, &["MGET", "a", "list"]),
+            RedisResponse::Array(vec![bulk("3"), bulk("4")])
+        );
+        assert!(matches!(
+            run(&mut executor, &["MSET", "a", "1", "b"]),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_object_idletime() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "v"]);
+        run(&mut executor, &["SET", "counter", "1"]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "IDLETIME", "k"]),
+            RedisResponse::Integer(0)
+        );
+
+        executor
+            .storage
+            .backdate_access("k", std::time::Duration::from_secs(1));
+        // Introspection doesn't count as an access
+        run(&mut executor, &["TYPE", "k"]);
+        run(&mut executor, &["TTL", "k"]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "IDLETIME", "k"]),
+            RedisResponse::Integer(1)
+        );
+
+        run(&mut executor, &["GET", "k"]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "IDLETIME", "k"]),
+            RedisResponse::Integer(0)
+        );
+        // INCR rebuilds the value in place and must still reset the idle time
+        run(&mut executor, &["INCR", "counter"]);
+        assert_eq!(
+            run(&mut executor, &["OBJECT", "IDLETIME", "counter"]),
+            RedisResponse::Integer(0)
+        );
+        assert!(matches!(
+            run(&mut executor, &["OBJECT", "IDLETIME", "missing"]),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_incrby_and_decrby() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["INCRBY", "n", "10"]),
+            RedisResponse::Integer(10)
+        );
+        assert_eq!(
+            run(&mut executor, &["INCRBY", "n", "-15"]),
+            RedisResponse::Integer(-5)
+        );
+        assert_eq!(
+            run(&mut executor, &["DECR", "n"]),
+            RedisResponse::Integer(-6)
+        );
+        assert_eq!(
+            run(&mut executor, &["DECRBY", "n", "-6"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["DECR", "fresh"]),
+            RedisResponse::Integer(-1)
+        );
+
+        let not_an_integer = RedisResponse::error("value is not an integer or out of range");
+        assert_eq!(run(&mut executor, &["INCRBY", "n", "1.5"]), not_an_integer);
+        run(&mut executor, &["SET", "text", "abc"]);
+        assert_eq!(run(&mut executor, &["DECRBY", "text", "1"]), not_an_integer);
+
+        run(&mut executor, &["SET", "min", &i64::MIN.to_string()]);
+        assert!(matches!(
+            run(&mut executor, &["DECR", "min"]),
+            RedisResponse::Error(_)
+        ));
+        assert!(matches!(
+            run(&mut executor, &["DECRBY", "n", &i64::MIN.to_string()]),
+            RedisResponse::Error(_)
+        ));
+        assert_eq!(run(&mut executor, &["GET", "n"]), bulk("0"));
+    }
+
+    #[test]
+    fn test_incrbyfloat() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "f", "10.50"]);
+        assert_eq!(
+            run(&mut executor, &["INCRBYFLOAT", "f", "0.1"]),
+            bulk("10.6")
+        );
+        assert_eq!(run(&mut executor, &["INCRBYFLOAT", "f", "-5.6"]), bulk("5"));
+        assert_eq!(run(&mut executor, &["GET", "f"]), bulk("5"));
+        assert_eq!(
+            run(&mut executor, &["INCRBYFLOAT", "e", "5.0e3"]),
+            bulk("5000")
+        );
+
+        // The TTL survives the rewrite
+        run(&mut executor, &["SET", "ttl", "1", "EX", "100"]);
+        run(&mut executor, &["INCRBYFLOAT", "ttl", "1.5"]);
+        assert_eq!(run(&mut executor, &["GET", "ttl"]), bulk("2.5"));
+        assert_eq!(
+            run(&mut executor, &["TTL", "ttl"]),
+            RedisResponse::Integer(100)
+        );
+
+        let not_a_float = RedisResponse::error("value is not a valid float");
+        assert_eq!(
+            run(&mut executor, &["INCRBYFLOAT", "f", "abc"]),
+            not_a_float
+        );
+        run(&mut executor, &["SET", "text", "abc"]);
+        assert_eq!(
+            run(&mut executor, &["INCRBYFLOAT", "text", "1"]),
+            not_a_float
+        );
+        assert!(matches!(
+            run(&mut executor, &["INCRBYFLOAT", "f", "1e308"]),
+            RedisResponse::BulkString(Some(_))
+        ));
+        assert!(matches!(
+            run(&mut executor, &["INCRBYFLOAT", "f", "1.7e308"]),
+            RedisResponse::Error(_)
+        ));
+
+        run(&mut executor, &["RPUSH", "list", "1"]);
+        assert_eq!(
+            run(&mut executor, &["INCRBYFLOAT", "list", "1"]),
+            RedisResponse::wrong_type()
+        );
+    }
+
+    #[test]
+    fn test_append_and_strlen() {
+        let mut executor = executor();
+        // APPEND on a missing key behaves like SET
+        assert_eq!(
+            run(&mut executor, &["APPEND", "k", "Hello"]),
+            RedisResponse::Integer(5)
+        );
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("Hello"));
+        assert_eq!(
+            run(&mut executor, &["APPEND", "k", " World"]),
+            RedisResponse::Integer(11)
+        );
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("Hello World"));
+        assert_eq!(
+            run(&mut executor, &["STRLEN", "k"]),
+            RedisResponse::Integer(11)
+        );
+        assert_eq!(
+            run(&mut executor, &["STRLEN", "missing"]),
+            RedisResponse::Integer(0)
+        );
+
+        // The TTL is kept
+        run(&mut executor, &["SET", "ttl", "a", "EX", "100"]);
+        run(&mut executor, &["APPEND", "ttl", "b"]);
+        assert_eq!(
+            run(&mut executor, &["TTL", "ttl"]),
+            RedisResponse::Integer(100)
+        );
+
+        run(&mut executor, &["RPUSH", "list", "a"]);
+        assert_eq!(
+            run(&mut executor, &["APPEND", "list", "b"]),
+            RedisResponse::wrong_type()
+        );
+        assert_eq!(
+            run(&mut executor, &["STRLEN", "list"]),
+            RedisResponse::wrong_type()
+        );
+    }
+
+    #[test]
+    fn test_xinfo_groups_and_consumers() {
+        let mut executor = executor();
+        for id in ["1-1", "2-1", "3-1"] {
+            run(&mut executor, &["XADD", "s", id, "field", "value"]);
+        }
+        run(&mut executor, &["XGROUP", "CREATE", "s", "g", "0"]);
+        run(
+            &mut executor,
+            &[
+                "XREADGROUP",
+                "GROUP",
+                "g",
+                "alice",
+                "COUNT",
+                "2",
+                "STREAMS",
+                "s",
+                ">",
+            ],
+        );
+
+        assert_eq!(
+            run(&mut executor, &["XINFO", "GROUPS", "s"]),
+            RedisResponse::Array(vec![RedisResponse::Array(vec![
+                bulk("name"),
+                bulk("g"),
+                bulk("consumers"),
+                RedisResponse::Integer(1),
+                bulk("pending"),
+                RedisResponse::Integer(2),
+                bulk("last-delivered-id"),
+                bulk("2-1"),
+                bulk("entries-read"),
+                RedisResponse::Integer(2),
+                bulk("lag"),
+                RedisResponse::Integer(1),
+            ])])
+        );
+
+        let RedisResponse::Array(consumers) = run(&mut executor, &["XINFO", "CONSUMERS", "s", "g"])
+        else {
+            panic!("XINFO CONSUMERS should reply with an array");
+        };
+        let RedisResponse::Array(alice) = &consumers[0] else {
+            panic!("each consumer should be an array");
+        };
+        assert_eq!(
+            alice[0..4],
+            [
+                bulk("name"),
+                bulk("alice"),
+                bulk("pending"),
+                RedisResponse::Integer(2)
+            ]
+        );
+        assert_eq!(alice[6], bulk("inactive"));
+        assert!(matches!(alice[7], RedisResponse::Integer(ms) if ms >= 0));
+
+        assert!(matches!(
+            run(&mut executor, &["XINFO", "CONSUMERS", "s", "nope"]),
+            RedisResponse::RawError(_)
+        ));
+        assert!(matches!(
+            run(&mut executor, &["XINFO", "GROUPS", "missing"]),
+            RedisResponse::Error(_)
+        ));
+
+        run(&mut executor, &["SET", "string", "v"]);
+        for subcommand in ["STREAM", "GROUPS"] {
+            assert_eq!(
+                run(&mut executor, &["XINFO", subcommand, "string"]),
+                RedisResponse::wrong_type()
+            );
+        }
+    }
+
+    #[test]
+    fn test_group_reads_propagate_as_xclaim() {
+        let mut master = executor();
+        master.storage.enable_propagation_log();
+        run(
+            &mut master,
+            &["XGROUP", "CREATE", "s", "g", "$", "MKSTREAM"],
+        );
+        for id in ["1-1", "2-1", "3-1"] {
+            run(&mut master, &["XADD", "s", id, "field", "value"]);
+        }
+        let read = [
+            "XREADGROUP",
+            "GROUP",
+            "g",
+            "alice",
+            "COUNT",
+            "2",
+            "STREAMS",
+            "s",
+        ];
+        run(&mut master, &[&read[..], &[">"]].concat());
+        run(&mut master, &[&read[..], &["0"]].concat());
+
+        let log = master.storage.propagation_log().to_vec();
+        assert_eq!(log[0], ["XGROUP", "CREATE", "s", "g", "$", "MKSTREAM"]);
+        let claim = &log[4];
+        assert_eq!(claim[..6], ["XCLAIM", "s", "g", "alice", "0", "1-1"]);
+        assert_eq!(claim[6], "TIME");
+        assert_eq!(
+            claim[8..],
+            ["RETRYCOUNT", "1", "FORCE", "JUSTID", "LASTID", "2-1"]
+        );
+        assert_eq!(
+            log[6],
+            ["XGROUP", "SETID", "s", "g", "2-1", "ENTRIESREAD", "2"]
+        );
+        // Replaying the history read bumps the delivery count
+        assert_eq!(log[7][9], "2");
+
+        let mut replica = executor();
+        for args in &log {
+            run(
+                &mut replica,
+                &args.iter().map(String::as_str).collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(
+            run(&mut replica, &["XINFO", "GROUPS", "s"]),
+            run(&mut master, &["XINFO", "GROUPS", "s"])
+        );
+        assert_eq!(
+            run(&mut replica, &[&read[..], &["0"]].concat()),
+            run(&mut master, &[&read[..], &["0"]].concat())
+        );
+        // Both hand out the same entry next
+        assert_eq!(
+            run(&mut replica, &[&read[..], &[">"]].concat()),
+            run(&mut master, &[&read[..], &[">"]].concat())
+        );
+    }
+
+    #[test]
+    fn test_xclaim_moves_pending_entries() {
+        let mut executor = executor();
+        run(&mut executor, &["XADD", "s", "1-1", "f", "v"]);
+        run(&mut executor, &["XGROUP", "CREATE", "s", "g", "0"]);
+        run(
+            &mut executor,
+            &["XREADGROUP", "GROUP", "g", "alice", "STREAMS", "s", ">"],
+        );
+
+        // Not idle long enough yet
+        assert_eq!(
+            run(&mut executor, &["XCLAIM", "s", "g", "bob", "60000", "1-1"]),
+            RedisResponse::Array(vec![])
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["XCLAIM", "s", "g", "bob", "0", "1-1", "JUSTID"]
+            ),
+            RedisResponse::Array(vec![bulk("1-1")])
+        );
+        assert_eq!(
+            run(
+                &mut executor,
+                &["XREADGROUP", "GROUP", "g", "bob", "STREAMS", "s", "0"]
+            ),
+            RedisResponse::Array(vec![RedisResponse::Array(vec![
+                bulk("s"),
+                RedisResponse::Array(vec![RedisResponse::Array(vec![
+                    bulk("1-1"),
+                    RedisResponse::Array(vec![bulk("f"), bulk("v")]),
+                ])]),
+            ])])
+        );
+        assert!(matches!(
+            run(&mut executor, &["XCLAIM", "s", "nope", "bob", "0", "1-1"]),
+            RedisResponse::RawError(_)
+        ));
+
+        run(&mut executor, &["XGROUP", "SETID", "s", "g", "0"]);
+        assert_eq!(
+            run(
+                &mut executor,
+                &["XREADGROUP", "GROUP", "g", "carol", "STREAMS", "s", ">"]
+            ),
+            RedisResponse::Array(vec![RedisResponse::Array(vec![
+                bulk("s"),
+                RedisResponse::Array(vec![RedisResponse::Array(vec![
+                    bulk("1-1"),
+                    RedisResponse::Array(vec![bulk("f"), bulk("v")]),
+                ])]),
+            ])])
+        );
+    }
+
+    #[test]
+    fn test_zrange_orders_equal_scores_by_member() {
+        let mut executor = executor();
+        for (score, member) in [("1", "c"), ("1", "a"), ("0", "z"), ("1", "b")] {
+            run(&mut executor, &["ZADD", "z", score, member]);
+        }
+        assert_eq!(
+            run(&mut executor, &["ZRANGE", "z", "0", "-1"]),
+            RedisResponse::Array(vec![simple("z"), simple("a"), simple("b"), simple("c")])
+        );
+
+        // Scores closer together than f64::EPSILON are still distinct
+        run(&mut executor, &["ZADD", "tiny", "2e-20", "a"]);
+        run(&mut executor, &["ZADD", "tiny", "1e-20", "b"]);
+        assert_eq!(
+            run(&mut executor, &["ZRANGE", "tiny", "0", "-1"]),
+            RedisResponse::Array(vec![simple("b"), simple("a")])
+        );
+    }
+
+    #[test]
+    fn test_getset_getdel_and_setnx() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["GETSET", "k", "v1"]),
+            RedisResponse::nil()
+        );
+        run(&mut executor, &["EXPIRE", "k", "100"]);
+        assert_eq!(run(&mut executor, &["GETSET", "k", "v2"]), bulk("v1"));
+        // Like SET, GETSET drops the TTL
+        assert_eq!(
+            run(&mut executor, &["TTL", "k"]),
+            RedisResponse::Integer(-1)
+        );
+
+        assert_eq!(run(&mut executor, &["GETDEL", "k"]), bulk("v2"));
+        assert_eq!(
+            run(&mut executor, &["EXISTS", "k"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(run(&mut executor, &["GETDEL", "k"]), RedisResponse::nil());
+
+        assert_eq!(
+            run(&mut executor, &["SETNX", "n", "first"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["SETNX", "n", "second"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(run(&mut executor, &["GET", "n"]), bulk("first"));
+
+        run(&mut executor, &["RPUSH", "list", "a"]);
+        assert_eq!(
+            run(&mut executor, &["GETSET", "list", "v"]),
+            RedisResponse::wrong_type()
+        );
+        assert_eq!(
+            run(&mut executor, &["GETDEL", "list"]),
+            RedisResponse::wrong_type()
+        );
+        assert_eq!(
+            run(&mut executor, &["SETNX", "list", "v"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["LLEN", "list"]),
+            RedisResponse::Integer(1)
+        );
+    }
+
+    #[test]
+    fn test_publish_counts_pattern_subscribers() {
+        let mut executor = executor();
+        executor
+            .storage
+            .subscribe(Token(2), "news.tech".to_string());
+        assert_eq!(
+            executor.storage.psubscribe(Token(3), "news.*".to_string()),
+            1
+        );
+        assert_eq!(
+            run(&mut executor, &["PUBLISH", "news.tech", "hi"]),
+            RedisResponse::Integer(2)
+        );
+
+        // Every matching pattern subscription counts, even for the same client
+        executor.storage.psubscribe(Token(3), "news.t*".to_string());
+        assert_eq!(
+            run(&mut executor, &["PUBLISH", "news.tech", "hi"]),
+            RedisResponse::Integer(3)
+        );
+        assert_eq!(
+            run(&mut executor, &["PUBLISH", "news.sport", "hi"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["PUBLISH", "weather", "hi"]),
+            RedisResponse::Integer(0)
+        );
+    }
+
+    #[test]
+    fn test_forget_client_drops_subscriptions_and_protocol() {
+        let mut executor = executor();
+        executor.storage.subscribe(Token(2), "news".to_string());
+        executor.storage.psubscribe(Token(2), "news.*".to_string());
+        executor.storage.subscribe(Token(3), "news".to_string());
+        run_as(&mut executor, Token(2), &["HELLO", "3"]);
+
+        executor.forget_client(Token(2));
+        assert!(!executor.storage.is_subscribed(Token(2)));
+        assert_eq!(
+            run(&mut executor, &["PUBLISH", "news", "hi"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["PUBLISH", "news.tech", "hi"]),
+            RedisResponse::Integer(0)
+        );
+        // A new connection reusing the token starts over on RESP2
+        assert_eq!(executor.protocol(Token(2)), 2);
+    }
+
+    #[test]
+    fn test_set_nx_xx_and_keepttl() {
+        let mut executor = executor();
+        assert_eq!(
+            run(&mut executor, &["SET", "k", "v1", "XX"]),
+            RedisResponse::nil()
+        );
+        assert_eq!(
+            run(&mut executor, &["EXISTS", "k"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["SET", "k", "v1", "NX"]),
+            RedisResponse::ok()
+        );
+        assert_eq!(
+            run(&mut executor, &["SET", "k", "v2", "NX"]),
+            RedisResponse::nil()
+        );
+        assert_eq!(
+            run(&mut executor, &["SET", "k", "v2", "NX", "GET"]),
+            bulk("v1")
+        );
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("v1"));
+        assert_eq!(
+            run(&mut executor, &["SET", "k", "v2", "XX", "EX", "100"]),
+            RedisResponse::ok()
+        );
+
+        assert_eq!(
+            run(&mut executor, &["SET", "k", "v3", "KEEPTTL"]),
+            RedisResponse::ok()
+        );
+        assert_eq!(run(&mut executor, &["GET", "k"]), bulk("v3"));
+        assert_eq!(
+            run(&mut executor, &["TTL", "k"]),
+            RedisResponse::Integer(100)
+        );
+        run(&mut executor, &["SET", "k", "v4"]);
+        assert_eq!(
+            run(&mut executor, &["TTL", "k"]),
+            RedisResponse::Integer(-1)
+        );
+
+        for args in [
+            &["SET", "k", "v", "NX", "XX"][..],
+            &["SET", "k", "v", "KEEPTTL", "PX", "100"],
+        ] {
+            assert!(matches!(run(&mut executor, args), RedisResponse::Error(_)));
+        }
+    }
+
+    #[test]
+    fn test_replconf_ack_records_each_replica_offset() {
+        let mut executor = executor();
+        let (replica1, replica2) = (Token(10), Token(11));
+        executor.storage.add_replication_client(replica1);
+        executor.storage.add_replication_client(replica2);
+        assert_eq!(executor.storage.acked_replicas(0), 0);
+
+        // The ACK gets no reply; only the offset it carries is kept
+        assert_eq!(
+            run_as(&mut executor, replica1, &["REPLCONF", "ACK", "100"]),
+            RedisResponse::Empty
+        );
+        run_as(&mut executor, replica2, &["REPLCONF", "ACK", "40"]);
+        assert_eq!(executor.storage.acked_replicas(40), 2);
+        assert_eq!(executor.storage.acked_replicas(41), 1);
+        assert_eq!(executor.storage.acked_replicas(101), 0);
+
+        // A later ACK replaces the replica's offset, and a bad one is ignored
+        run_as(&mut executor, replica2, &["REPLCONF", "ACK", "150"]);
+        run_as(&mut executor, replica2, &["REPLCONF", "ACK", "soon"]);
+        assert_eq!(executor.storage.acked_replicas(101), 1);
+    }
+
+    #[test]
+    fn test_wait_counts_replicas_acked_at_call_time() {
+        let (mut executor, messages) = executor_with_messages();
+        let (replica1, replica2, client) = (Token(10), Token(11), Token(1));
+        executor.storage.add_replication_client(replica1);
+        executor.storage.add_replication_client(replica2);
+        run(&mut executor, &["SET", "k", "v"]);
+        let target = executor.storage.repl_config.get_replication_offset();
+        let before = (target - 1).to_string();
+        let target = target.to_string();
+
+        // Only the first replica has caught up
+        fn ack(offset: &str) -> [&str; 3] {
+            ["REPLCONF", "ACK", offset]
+        }
+        assert_eq!(
+            run_as(&mut executor, replica1, &ack(&target)),
+            RedisResponse::Empty
+        );
+        run_as(&mut executor, replica2, &ack(&before));
+        assert_eq!(
+            run(&mut executor, &["WAIT", "1", "0"]),
+            RedisResponse::Integer(1)
+        );
+
+        assert_eq!(
+            run(&mut executor, &["WAIT", "2", "100"]),
+            RedisResponse::Blocked
+        );
+        // A write after the WAIT doesn't raise the offset it waits for
+        run(&mut executor, &["SET", "k", "v2"]);
+        assert_eq!(executor.timeout_response(client), RedisResponse::Integer(1));
+        assert_eq!(
+            executor.timeout_response(client),
+            RedisResponse::null_array()
+        );
+
+        assert_eq!(
+            run(&mut executor, &["WAIT", "2", "0"]),
+            RedisResponse::Blocked
+        );
+        let latest = executor.storage.repl_config.get_replication_offset();
+        let latest = latest.to_string();
+        while messages.try_recv().is_ok() {}
+        run_as(&mut executor, replica1, &ack(&latest));
+        assert!(messages.try_recv().is_err());
+        run_as(&mut executor, replica2, &ack(&latest));
+        let unblocked = messages.try_iter().find_map(|message| match message {
+            EventLoopMessage::UnblockClient { token, response } => Some((token, response)),
+            _ => None,
+        });
+        assert_eq!(unblocked, Some((client, RedisResponse::Integer(2))));
+    }
+
+    #[test]
+    fn test_scan_survives_deleting_returned_keys() {
+        let mut executor = executor();
+        let keys: Vec<String> = (0..20).map(|i| format!("key{}", i)).collect();
+        for key in &keys {
+            run(&mut executor, &["SET", key, "v"]);
+        }
+
+        // Deleting what each page returned must not make the scan skip keys
+        let mut visited = Vec::new();
+        let mut cursor = "0".to_string();
+        loop {
+            let reply = run(&mut executor, &["SCAN", &cursor, "COUNT", "5"]);
+            let RedisResponse::Array(mut parts) = reply else {
+                panic!("unexpected SCAN reply: {:?}", reply);
+            };
+            let page = sorted_keys(parts.pop().unwrap());
+            for key in &page {
+                run(&mut executor, &["DEL", key]);
+            }
+            visited.extend(page);
+            cursor = match parts.pop().unwrap() {
+                RedisResponse::BulkString(Some(next)) => next,
+                other => panic!("unexpected SCAN cursor: {:?}", other),
+            };
+            if cursor == "0" {
+                break;
+            }
+        }
+        visited.sort();
+        let mut expected = keys.clone();
+        expected.sort();
+        assert_eq!(visited, expected);
+    }
+
+    #[test]
+    fn test_scan_visits_each_key_once() {
+        let mut executor = executor();
+        let keys: Vec<String> = (0..10).map(|i| format!("key{}", i)).collect();
+        for key in &keys {
+            run(&mut executor, &["SET", key, "v"]);
+        }
+        assert_eq!(full_scan(&mut executor, "*"), keys);
+
+        // A cursor past the end finishes the iteration
+        assert_eq!(
+            run(&mut executor, &["SCAN", &usize::MAX.to_string()]),
+            RedisResponse::Array(vec![bulk("0"), RedisResponse::Array(vec![])])
+        );
+    }
+
+    #[test]
+    fn test_blpop_asks_the_event_loop_to_block() {
+        let (mut executor, messages) = executor_with_messages();
+        let (waiting, pusher) = (Token(1), Token(2));
+        assert_eq!(
+            run_as(&mut executor, waiting, &["BLPOP", "list", "1.5"]),
+            RedisResponse::Blocked
+        );
+        assert!(matches!(
+            messages.try_recv(),
+            Ok(EventLoopMessage::BlockClient {
+                token,
+                timeout: 1500
+            }) if token == waiting
+        ));
+
+        run_as(&mut executor, pusher, &["RPUSH", "list", "a"]);
+        match messages.try_recv() {
+            Ok(EventLoopMessage::UnblockClient { token, response }) => {
+                assert_eq!(token, waiting);
+                assert_eq!(
+                    response,
+                    RedisResponse::Array(vec![bulk("list"), bulk("a")])
+                );
+            }
+            other => panic!("expected an unblock, got {:?}", other),
+        }
+        assert!(messages.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_copy_makes_an_independent_value() {
+        let mut executor = executor();
+        run(&mut executor, &["RPUSH", "list", "a", "b"]);
+        run(&mut executor, &["EXPIRE", "list", "100"]);
+        assert_eq!(
+            run(&mut executor, &["COPY", "list", "copy"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["TTL", "copy"]),
+            RedisResponse::Integer(100)
+        );
+
+        run(&mut executor, &["RPUSH", "copy", "c"]);
+        assert_eq!(
+            run(&mut executor, &["LRANGE", "list", "0", "-1"]),
+            RedisResponse::Array(vec![simple("a"), simple("b")])
+        );
+        assert_eq!(
+            run(&mut executor, &["LRANGE", "copy", "0", "-1"]),
+            RedisResponse::Array(vec![simple("a"), simple("b"), simple("c")])
+        );
+
+        // An existing destination is only overwritten with REPLACE
+        run(&mut executor, &["SET", "dest", "old"]);
+        assert_eq!(
+            run(&mut executor, &["COPY", "list", "dest"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(run(&mut executor, &["GET", "dest"]), bulk("old"));
+        assert_eq!(
+            run(&mut executor, &["COPY", "list", "dest", "REPLACE"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(run(&mut executor, &["TYPE", "dest"]), simple("list"));
+
+        assert_eq!(
+            run(&mut executor, &["COPY", "missing", "other"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["EXISTS", "other"]),
+            RedisResponse::Integer(0)
+        );
+
+        // Even REPLACE can't copy a key onto itself
+        assert_eq!(
+            run(&mut executor, &["COPY", "list", "list", "REPLACE"]),
+            RedisResponse::error("source and destination objects are the same")
+        );
+        assert_eq!(
+            run(&mut executor, &["LLEN", "list"]),
+            RedisResponse::Integer(2)
+        );
+    }
+
+    #[test]
+    fn test_hstrlen_and_hash_wrong_type() {
+        let mut executor = executor();
+        run(&mut executor, &["HSET", "hash", "field", "hello"]);
+        assert_eq!(
+            run(&mut executor, &["HSTRLEN", "hash", "field"]),
+            RedisResponse::Integer(5)
+        );
+        assert_eq!(
+            run(&mut executor, &["HSTRLEN", "hash", "missing"]),
+            RedisResponse::Integer(0)
+        );
+        assert_eq!(
+            run(&mut executor, &["HSTRLEN", "missing", "field"]),
+            RedisResponse::Integer(0)
+        );
+
+        run(&mut executor, &["SET", "string", "v"]);
+        for args in [
+            &["HSTRLEN", "string", "field"][..],
+            &["HLEN", "string"],
+            &["HGET", "string", "field"],
+            &["HEXISTS", "string", "field"],
+            &["HGETALL", "string"],
+            &["HDEL", "string", "field"],
+            &["HSET", "string", "field", "v"],
+        ] {
+            assert_eq!(run(&mut executor, args), RedisResponse::wrong_type());
+        }
+        assert_eq!(run(&mut executor, &["GET", "string"]), bulk("v"));
+    }
+
+    #[test]
+    fn test_unlink_deletes_multiple_keys() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "a", "1"]);
+        run(&mut executor, &["RPUSH", "b", "x"]);
+        run(&mut executor, &["HSET", "c", "f", "v"]);
+        assert_eq!(
+            run(&mut executor, &["UNLINK", "a", "b", "missing"]),
+            RedisResponse::Integer(2)
+        );
+        assert_eq!(
+            run(&mut executor, &["EXISTS", "a", "b", "c"]),
+            RedisResponse::Integer(1)
+        );
+        assert!(matches!(
+            run(&mut executor, &["UNLINK"]),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_xlen_after_xdel() {
+        let mut executor = executor();
+        for id in ["1-0", "2-0", "3-0"] {
+            run(&mut executor, &["XADD", "s", id, "field", "value"]);
+        }
+        assert_eq!(
+            run(&mut executor, &["XLEN", "s"]),
+            RedisResponse::Integer(3)
+        );
+        assert_eq!(
+            run(&mut executor, &["XDEL", "s", "2-0"]),
+            RedisResponse::Integer(1)
+        );
+        assert_eq!(
+            run(&mut executor, &["XLEN", "s"]),
+            RedisResponse::Integer(2)
+        );
+        // Only an exact ID matches
+        assert_eq!(
+            run(&mut executor, &["XDEL", "s", "2-0", "3-1"]),
+            RedisResponse::Integer(0)
+        );
+
+        assert_eq!(
+            run(&mut executor, &["XLEN", "missing"]),
+            RedisResponse::Integer(0)
+        );
+        run(&mut executor, &["SET", "string", "v"]);
+        assert_eq!(
+            run(&mut executor, &["XLEN", "string"]),
+            RedisResponse::wrong_type()
+        );
+    }
+
+    #[test]
+    fn test_xadd_after_deleting_the_tail_never_reuses_ids() {
+        let mut executor = executor();
+        run(&mut executor, &["XADD", "s", "5-0", "field", "value"]);
+        run(&mut executor, &["XADD", "s", "6-0", "field", "value"]);
+        run(&mut executor, &["XDEL", "s", "6-0"]);
+
+        assert!(matches!(
+            run(&mut executor, &["XADD", "s", "6-0", "field", "value"]),
+            RedisResponse::Error(_)
+        ));
+        let id = match run(&mut executor, &["XADD", "s", "*", "field", "value"]) {
+            RedisResponse::BulkString(Some(id)) => id,
+            other => panic!("unexpected reply: {:?}", other),
+        };
+        let (ms, seq) = id.split_once('-').unwrap();
+        let id: (u64, u64) = (ms.parse().unwrap(), seq.parse().unwrap());
+        assert!(id > (6, 0), "{:?} reuses a deleted ID", id);
+    }
+
+    #[test]
+    fn test_debug_command_can_be_disabled() {
+        let mut executor = executor();
+        run(&mut executor, &["SET", "k", "v"]);
+        executor.set_enable_debug_command(false);
+        assert!(matches!(
+            run(&mut executor, &["DEBUG", "OBJECT", "k"]),
+            RedisResponse::Error(message) if message.starts_with("DEBUG command not allowed")
+        ));
+        executor.set_enable_debug_command(true);
+        assert!(matches!(
+            run(&mut executor, &["DEBUG", "OBJECT", "k"]),
+            RedisResponse::SimpleString(_)
+        ));
+    }
+
+    #[test]
+    fn test_connections_start_with_the_default_protocol() {
+        let mut executor = executor();
+        executor.set_default_protocol(3);
+        run(&mut executor, &["HSET", "h", "f", "v"]);
+        assert_eq!(
+            run(&mut executor, &["HGETALL", "h"]),
+            RedisResponse::Map(vec![(bulk("f"), bulk("v"))])
+        );
+
+        // HELLO still switches a connection back
+        run_as(&mut executor, Token(2), &["HELLO", "2"]);
+        assert_eq!(
+            run_as(&mut executor, Token(2), &["HGETALL", "h"]),
+            RedisResponse::Array(vec![bulk("f"), bulk("v")])
+        );
+    }
+
+    #[test]
+    fn test_xread_count_and_dollar_id() {
+        let (mut executor, messages) = executor_with_messages();
+        for id in ["1-1", "2-1", "3-1"] {
+            run(&mut executor, &["XADD", "s", id, "f", "v"]);
+        }
+        let reply = run(&mut executor, &["XREAD", "COUNT", "2", "STREAMS", "s", "0"]);
+        let resp = reply.to_resp();
+        assert!(resp.contains("1-1") && resp.contains("2-1"), "{}", resp);
+        assert!(!resp.contains("3-1"), "{}", resp);
+
+        // `$` only sees entries added after the call
+        let reply = run(&mut executor, &["XREAD", "STREAMS", "s", "$"]);
+        assert_eq!(reply.to_resp(), "*-1\r\n");
+
+        let (reader, writer) = (Token(1), Token(2));
+        assert_eq!(
+            run_as(
+                &mut executor,
+                reader,
+                &["XREAD", "BLOCK", "0", "COUNT", "1", "STREAMS", "s", "$"]
+            ),
+            RedisResponse::Blocked
+        );
+        run_as(&mut executor, writer, &["XADD", "s", "4-1", "f", "v"]);
+        let unblocked = messages.try_iter().find_map(|message| match message {
+            EventLoopMessage::UnblockClient { token, response } => Some((token, response)),
+            _ => None,
+        });
+        let Some((token, response)) = unblocked else {
+            panic!("the blocked XREAD was not woken");
+        };
+        assert_eq!(token, reader);
+        let resp = response.to_resp();
+        assert!(resp.contains("4-1") && !resp.contains("3-1"), "{}", resp);
+    }
+
+    #[test]
+    fn test_xadd_wakes_every_blocked_reader() {
+        let (mut executor, messages) = executor_with_messages();
+        let (first, second, writer) = (Token(1), Token(2), Token(3));
+        for reader in [first, second] {
+            assert_eq!(
+                run_as(
+                    &mut executor,
+                    reader,
+                    &["XREAD", "BLOCK", "0", "STREAMS", "s", "$"]
+                ),
+                RedisResponse::Blocked
+            );
+        }
+
+        run_as(&mut executor, writer, &["XADD", "s", "1-1", "f", "v"]);
+        let mut woken: Vec<(Token, String)> = messages
+            .try_iter()
+            .filter_map(|message| match message {
+                EventLoopMessage::UnblockClient { token, response } => {
+                    Some((token, response.to_resp()))
+                }
+                _ => None,
+            })
+            .collect();
+        woken.sort_by_key(|(token, _)| token.0);
+        assert_eq!(
+            woken.iter().map(|(token, _)| *token).collect::<Vec<_>>(),
+            vec![first, second]
+        );
+        for (_, resp) in &woken {
+            assert!(
+                resp.contains("1-1") && resp.contains("$1\r\nf\r\n"),
+                "{}",
+                resp
+            );
+        }
+    }
+
+    #[test]
+    fn test_xadd_maxlen_trims_the_oldest_entries() {
+        let mut executor = executor();
+        for i in 1..=3 {
+            run(&mut executor, &["XADD", "s", &format!("{}-0", i), "f", "v"]);
+        }
+        // Above the current length nothing is removed
+        run(
+            &mut executor,
+            &["XADD", "s", "MAXLEN", "10", "4-0", "f", "v"],
+        );
+        assert_eq!(
+            run(&mut executor, &["XLEN", "s"]),
+            RedisResponse::Integer(4)
+        );
+
+        assert_eq!(
+            run(
+                &mut executor,
+                &["XADD", "s", "MAXLEN", "~", "2", "5-0", "f", "v"]
+            ),
+            bulk("5-0")
+        );
+        let ids = |reply: RedisResponse| -> Vec<String> {
+            let RedisResponse::Array(entries) = reply else {
+                panic!("unexpected XRANGE reply: {:?}", reply);
+            };
+            entries
+                .into_iter()
+                .map(|entry| match entry {
+                    RedisResponse::Array(mut parts) => match parts.remove(0) {
+                        RedisResponse::BulkString(Some(id)) => id,
+                        other => panic!("unexpected entry id: {:?}", other),
+                    },
+                    other => panic!("unexpected entry: {:?}", other),
+                })
+                .collect()
+        };
+        assert_eq!(
+            ids(run(&mut executor, &["XRANGE", "s", "-", "+"])),
+            vec!["4-0", "5-0"]
+        );
+        assert!(matches!(
+            run(
+                &mut executor,
+                &["XADD", "s", "MAXLEN", "-1", "6-0", "f", "v"]
+            ),
+            RedisResponse::Error(_)
+        ));
+    }
+
+    #[test]
+    fn test_object_encoding_lists() {
+        let mut executor = executor();
+        run(&mut executor, &["RPUSH", "short", "a", "b"]);
+        assert_eq!(encoding(&mut executor, "short"), bulk("listpack"));
+
+        let items: Vec<String> = (0..129).map(|i| i.to_string()).collect();
+        let mut command = vec!["RPUSH", "long"];
+        command.extend(items.iter().map(|item| item.as_str()));
+        run(&mut executor, &command);
+        assert_eq!(encoding(&mut executor, "long"), bulk("quicklist"));
+
+        // A single large element is enough to leave listpack
+        run(&mut executor, &["RPUSH", "wide", &"x".repeat(65)]);
+        assert_eq!(encoding(&mut executor, "wide"), bulk("quicklist"));
+    }
+
+    #[test]
+    fn test_subscribe_confirms_each_channel() {
+        let mut executor = executor();
+        let confirmation = |kind: &str, channel: Option<&str>, count: i64| {
+            RedisResponse::Array(vec![
+                bulk(kind),
+                RedisResponse::BulkString(channel.map(|channel| channel.to_string())),
+                RedisResponse::Integer(count),
+            ])
+        };
+        assert_eq!(
+            run(&mut executor, &["SUBSCRIBE", "a", "b", "a"]),
+            RedisResponse::Multiple(vec![
+                confirmation("subscribe", Some("a"), 1),
+                confirmation("subscribe", Some("b"), 2),
+                confirmation("subscribe", Some("a"), 2),
+            ])
+        );
+        assert_eq!(
+            run(&mut executor, &["PSUBSCRIBE", "c*"]),
+            RedisResponse::Multiple(vec![confirmation("psubscribe", Some("c*"), 3)])
+        );
+
+        // Without arguments every channel is dropped, patterns stay
+        let RedisResponse::Multiple(mut replies) = run(&mut executor, &["UNSUBSCRIBE"]) else {
+            panic!("UNSUBSCRIBE should confirm each channel");
+        };
+        replies.sort_by_key(|reply| reply.to_resp());
+        assert_eq!(replies.len(), 2);
+        assert!(replies[0].to_resp().contains("$1\r\na\r\n"));
+        assert!(replies[1].to_resp().contains("$1\r\nb\r\n"));
+        assert_eq!(
+            run(&mut executor, &["UNSUBSCRIBE"]),
+            confirmation("unsubscribe", None, 1)
+        );
+        assert_eq!(
+            run(&mut executor, &["PUNSUBSCRIBE"]),
+            RedisResponse::Multiple(vec![confirmation("punsubscribe", Some("c*"), 0)])
+        );
+        // Out of subscribed mode again
+        assert_eq!(run(&mut executor, &["SET", "k", "v"]), RedisResponse::ok());
+    }
 }
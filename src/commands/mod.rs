@@ -6,60 +6,218 @@ pub use executor::{CommandExecutor, RedisCommandExecutor};
 pub use parser::CommandParser;
 pub use response::RedisResponse;
 
+use crate::storage::geo_shape::GeoShape;
+use crate::storage::stream_member::XClaimOptions;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SetOptions {
+    pub expiry: Option<Expiry>,
+    pub get: bool,     // reply with the previous value
+    pub nx: bool,      // only set a missing key
+    pub xx: bool,      // only set an existing key
+    pub keepttl: bool, // keep the key's current expiry
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ZAddOptions {
+    pub ch: bool,   // count changed members as well as added ones
+    pub nx: bool,   // only add new members
+    pub xx: bool,   // only update existing members
+    pub gt: bool,   // only update when the new score is greater
+    pub lt: bool,   // only update when the new score is less
+    pub incr: bool, // add to the score, replying with the result like ZINCRBY
+}
+
+// BITCOUNT/BITPOS range, in bytes unless `bit` is set
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitRange {
+    pub start: i64,
+    pub end: Option<i64>, // BITPOS may leave the end open
+    pub bit: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LPosOptions {
+    pub rank: Option<i64>,     // which match to start from, negative from the tail
+    pub count: Option<usize>,  // reply with an array of up to this many, 0 for all
+    pub maxlen: Option<usize>, // only compare this many elements
+}
+
+// An EX/PX/EXAT/PXAT argument. A relative one is only turned into a
+// deadline when the command runs, and an absolute one is kept as given,
+// so a command queued in MULTI expires the key when it asked to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Expiry {
+    In(u128), // milliseconds from now
+    At(u128), // Unix time in milliseconds
+}
+
+impl Expiry {
+    // The Unix time in milliseconds the key expires at
+    pub fn deadline(&self) -> u128 {
+        match *self {
+            Expiry::In(millis) => millis.saturating_add(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis(),
+            ),
+            Expiry::At(deadline) => deadline,
+        }
+    }
+}
+
+// How GETEX changes the key's time to live
+#[derive(Debug, Clone, PartialEq)]
+pub enum GetExOption {
+    Expire(Expiry),
+    Persist,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum RedisCommand {
     Ping(Option<String>),
     Echo(String),
     Get(String),
-    Set(String, String),
-    SetWithExpiry(String, String, u128),
+    Set(String, String, SetOptions),
+    GETEX(String, Option<GetExOption>),
+    GETSET(String, String),
+    GETDEL(String),
+    SETNX(String, String),
     Del(Vec<String>),
+    UNLINK(Vec<String>), // DEL under the name clients use for lazy freeing
     Exists(Vec<String>),
+    MGET(Vec<String>),
+    MSET(Vec<(String, String)>),
+    EXPIRE(String, Expiry), // a deadline that has passed deletes the key
+    PERSIST(String),
+    RENAME(String, String),     // key, new key
+    COPY(String, String, bool), // source, destination, REPLACE
+    TTL(String),
+    PTTL(String),
     RPUSH(String, Vec<String>),
     LRANGE(String, i64, i64),
     LPUSH(String, Vec<String>),
     LLEN(String),
     LPOP(String, Option<i64>),
+    RPOP(String, Option<i64>),
+    LINDEX(String, i64),
+    LSET(String, i64, String), // key, index, value
+    LTRIM(String, i64, i64),
+    LREM(String, i64, String),             // key, count, value
+    LINSERT(String, bool, String, String), // key, before, pivot, value
+    LPOS(String, String, LPosOptions),     // key, element, options
     BLPOP(Vec<String>, u64),
     BRPOP(Vec<String>, u64),
     INCR(String),
+    INCRBY(String, i64),
+    DECR(String),
+    DECRBY(String, i64),
+    INCRBYFLOAT(String, f64),
+    STRLEN(String),
+    GETRANGE(String, i64, i64),
+    SETRANGE(String, usize, String),
+    APPEND(String, String),
+    BITCOUNT(String, Option<BitRange>),
+    BITPOS(String, bool, Option<BitRange>), // key, bit, range
     MULTI,
     EXEC,
     DISCARD,
+    WATCH(Vec<String>),
+    UNWATCH,
 
     // Sorted Set Commands
-    ZADD(String, f64, String),
+    ZADD(String, f64, String, ZAddOptions),
+    ZINCRBY(String, f64, String), // key, increment, member
+    ZRANGEBYSCORE(String, String, String, bool), // key, min, max, WITHSCORES
+    ZCOUNT(String, String, String), // key, min, max
+    ZMSCORE(String, Vec<String>), // key, members
+    ZRANGESTORE(String, String, String, String, bool), // destination, source, min, max, BYSCORE
     ZRANK(String, String),
     ZRANGE(String, i64, i64),
     ZCARD(String),
     ZSCORE(String, String),
     ZREM(String, String),
 
+    // Hash Commands
+    HSET(String, Vec<(String, String)>),
+    HMSET(String, Vec<(String, String)>),
+    HGET(String, String),
+    HDEL(String, Vec<String>),
+    HGETALL(String),
+    HLEN(String),
+    HEXISTS(String, String),
+    HSTRLEN(String, String), // key, field
+
+    // Set Commands
+    SADD(String, Vec<String>),
+    SREM(String, Vec<String>),
+    SMEMBERS(String),
+    SISMEMBER(String, String),
+    SCARD(String),
+    SPOP(String, Option<usize>),
+    SINTER(Vec<String>),
+    SUNION(Vec<String>),
+    SDIFF(Vec<String>),
+    SINTERSTORE(String, Vec<String>), // destination, keys
+    SUNIONSTORE(String, Vec<String>),
+    SDIFFSTORE(String, Vec<String>),
+    SINTERCARD(Vec<String>, usize), // keys, limit (0 for no limit)
+
     // Stream commands
     TYPE(String),
-    XADD(String, Option<String>, Vec<(String, String)>),
+    OBJECT(String, String),
+    DEBUG(String, String),
+    XADD(String, Option<String>, Vec<(String, String)>, Option<usize>), // key, id, fields, MAXLEN
     XRANGE(String, String, String),
-    XREAD(Option<u64>, Vec<(String, String)>),
+    XREAD(Option<u64>, Option<usize>, Vec<(String, String)>), // BLOCK, COUNT, key-id pairs
+    XLEN(String),
+    XDEL(String, Vec<String>),
+    XTRIM(String, String, String), // key, MAXLEN or MINID, threshold
+    XINFO(String, String, Option<String>), // subcommand, key, CONSUMERS group
+    XGROUPCREATE(String, String, String, bool), // key, group, id, MKSTREAM
+    XGROUPSETID(String, String, String, Option<u64>), // key, group, id, ENTRIESREAD
+    // key, group, consumer, min-idle-time, ids, options
+    XCLAIM(String, String, String, u64, Vec<String>, XClaimOptions),
+    // group, consumer, COUNT, BLOCK, key-id pairs
+    XREADGROUP(
+        String,
+        String,
+        Option<usize>,
+        Option<u64>,
+        Vec<(String, String)>,
+    ),
 
     // Geo Spatial Commands
     GEOADD(String, f64, f64, String),
     GEOPOS(String, Vec<String>),
     GEODIST(String, String, String),
-    GEOSEARCH(String, f64, f64, bool, f64, String), // bool: use_radius, last parameter is unit
+    GEOSEARCH(String, f64, f64, GeoShape, String), // key, longitude, latitude, shape, unit
 
     // Replication Commands
-    CONFIG(String, String),
+    CONFIG(String, Vec<String>), // subcommand, arguments
     KEYS(String),
+    SCAN(usize, Option<String>, usize), // cursor, MATCH pattern, COUNT
+    RANDOMKEY,
     INFO(String),
+    SAVE,
 
     // Pub/Sub Commands
-    SUBSCRIBE(String),
+    SUBSCRIBE(Vec<String>),
     PUBLISH(String, String),
-    UNSUBSCRIBE(String),
+    UNSUBSCRIBE(Vec<String>), // no channels unsubscribes from all of them
+    PSUBSCRIBE(Vec<String>),
+    PUNSUBSCRIBE(Vec<String>),
 
     // Replication Commands
     REPLCONF(String, String),
-    PSYNC(String, String)
+    PSYNC(String, String),
+    FAILOVER(bool),   // ABORT
+    WAIT(usize, u64), // numreplicas, timeout in milliseconds (0 blocks forever)
+
+    // Connection Commands
+    CLIENT(String, Vec<String>), // subcommand (uppercased), arguments
+    HELLO(Option<u8>),           // protocol version
 }
 
 impl RedisCommand {
@@ -68,43 +226,372 @@ impl RedisCommand {
             RedisCommand::Ping(_) => "ping".to_string(),
             RedisCommand::Echo(_) => "echo".to_string(),
             RedisCommand::Get(_) => "get".to_string(),
-            RedisCommand::Set(_, _) => "set".to_string(),
-            RedisCommand::SetWithExpiry(_, _, _) => "set".to_string(),
+            RedisCommand::Set(_, _, _) => "set".to_string(),
+            RedisCommand::GETEX(_, _) => "getex".to_string(),
+            RedisCommand::GETSET(_, _) => "getset".to_string(),
+            RedisCommand::GETDEL(_) => "getdel".to_string(),
+            RedisCommand::SETNX(_, _) => "setnx".to_string(),
             RedisCommand::Del(_) => "del".to_string(),
+            RedisCommand::UNLINK(_) => "unlink".to_string(),
             RedisCommand::Exists(_) => "exists".to_string(),
+            RedisCommand::MGET(_) => "mget".to_string(),
+            RedisCommand::MSET(_) => "mset".to_string(),
+            RedisCommand::EXPIRE(_, _) => "expire".to_string(),
+            RedisCommand::PERSIST(_) => "persist".to_string(),
+            RedisCommand::RENAME(_, _) => "rename".to_string(),
+            RedisCommand::COPY(_, _, _) => "copy".to_string(),
+            RedisCommand::TTL(_) => "ttl".to_string(),
+            RedisCommand::PTTL(_) => "pttl".to_string(),
             RedisCommand::RPUSH(_, _) => "rpush".to_string(),
             RedisCommand::LRANGE(_, _, _) => "lrange".to_string(),
             RedisCommand::LPUSH(_, _) => "lpush".to_string(),
             RedisCommand::LLEN(_) => "llen".to_string(),
             RedisCommand::LPOP(_, _) => "lpop".to_string(),
+            RedisCommand::RPOP(_, _) => "rpop".to_string(),
+            RedisCommand::LINDEX(_, _) => "lindex".to_string(),
+            RedisCommand::LSET(_, _, _) => "lset".to_string(),
+            RedisCommand::LTRIM(_, _, _) => "ltrim".to_string(),
+            RedisCommand::LREM(_, _, _) => "lrem".to_string(),
+            RedisCommand::LINSERT(_, _, _, _) => "linsert".to_string(),
+            RedisCommand::LPOS(_, _, _) => "lpos".to_string(),
             RedisCommand::BLPOP(_, _) => "blpop".to_string(),
             RedisCommand::BRPOP(_, _) => "brpop".to_string(),
             RedisCommand::INCR(_) => "incr".to_string(),
+            RedisCommand::INCRBY(_, _) => "incrby".to_string(),
+            RedisCommand::DECR(_) => "decr".to_string(),
+            RedisCommand::DECRBY(_, _) => "decrby".to_string(),
+            RedisCommand::INCRBYFLOAT(_, _) => "incrbyfloat".to_string(),
+            RedisCommand::STRLEN(_) => "strlen".to_string(),
+            RedisCommand::GETRANGE(_, _, _) => "getrange".to_string(),
+            RedisCommand::SETRANGE(_, _, _) => "setrange".to_string(),
+            RedisCommand::APPEND(_, _) => "append".to_string(),
+            RedisCommand::BITCOUNT(_, _) => "bitcount".to_string(),
+            RedisCommand::BITPOS(_, _, _) => "bitpos".to_string(),
             RedisCommand::MULTI => "multi".to_string(),
             RedisCommand::EXEC => "exec".to_string(),
             RedisCommand::DISCARD => "discard".to_string(),
-            RedisCommand::ZADD(_, _, _) => "zadd".to_string(),
+            RedisCommand::WATCH(_) => "watch".to_string(),
+            RedisCommand::UNWATCH => "unwatch".to_string(),
+            RedisCommand::ZADD(_, _, _, _) => "zadd".to_string(),
+            RedisCommand::ZINCRBY(_, _, _) => "zincrby".to_string(),
+            RedisCommand::ZRANGEBYSCORE(_, _, _, _) => "zrangebyscore".to_string(),
+            RedisCommand::ZCOUNT(_, _, _) => "zcount".to_string(),
+            RedisCommand::ZMSCORE(_, _) => "zmscore".to_string(),
+            RedisCommand::ZRANGESTORE(_, _, _, _, _) => "zrangestore".to_string(),
+            RedisCommand::HSET(_, _) => "hset".to_string(),
+            RedisCommand::HMSET(_, _) => "hmset".to_string(),
+            RedisCommand::HGET(_, _) => "hget".to_string(),
+            RedisCommand::HDEL(_, _) => "hdel".to_string(),
+            RedisCommand::HGETALL(_) => "hgetall".to_string(),
+            RedisCommand::HLEN(_) => "hlen".to_string(),
+            RedisCommand::HEXISTS(_, _) => "hexists".to_string(),
+            RedisCommand::HSTRLEN(_, _) => "hstrlen".to_string(),
+            RedisCommand::SADD(_, _) => "sadd".to_string(),
+            RedisCommand::SREM(_, _) => "srem".to_string(),
+            RedisCommand::SMEMBERS(_) => "smembers".to_string(),
+            RedisCommand::SISMEMBER(_, _) => "sismember".to_string(),
+            RedisCommand::SCARD(_) => "scard".to_string(),
+            RedisCommand::SPOP(_, _) => "spop".to_string(),
+            RedisCommand::SINTER(_) => "sinter".to_string(),
+            RedisCommand::SUNION(_) => "sunion".to_string(),
+            RedisCommand::SDIFF(_) => "sdiff".to_string(),
+            RedisCommand::SINTERSTORE(_, _) => "sinterstore".to_string(),
+            RedisCommand::SUNIONSTORE(_, _) => "sunionstore".to_string(),
+            RedisCommand::SDIFFSTORE(_, _) => "sdiffstore".to_string(),
+            RedisCommand::SINTERCARD(_, _) => "sintercard".to_string(),
             RedisCommand::ZRANK(_, _) => "zrank".to_string(),
             RedisCommand::ZRANGE(_, _, _) => "zrange".to_string(),
             RedisCommand::ZCARD(_) => "zcard".to_string(),
             RedisCommand::ZSCORE(_, _) => "zscore".to_string(),
             RedisCommand::ZREM(_, _) => "zrem".to_string(),
             RedisCommand::TYPE(_) => "type".to_string(),
-            RedisCommand::XADD(_, _, _) => "xadd".to_string(),
+            RedisCommand::OBJECT(_, _) => "object".to_string(),
+            RedisCommand::DEBUG(_, _) => "debug".to_string(),
+            RedisCommand::XADD(_, _, _, _) => "xadd".to_string(),
             RedisCommand::XRANGE(_, _, _) => "xrange".to_string(),
-            RedisCommand::XREAD(_, _) => "xread".to_string(),
+            RedisCommand::XREAD(_, _, _) => "xread".to_string(),
+            RedisCommand::XLEN(_) => "xlen".to_string(),
+            RedisCommand::XDEL(_, _) => "xdel".to_string(),
+            RedisCommand::XTRIM(_, _, _) => "xtrim".to_string(),
+            RedisCommand::XINFO(_, _, _) => "xinfo".to_string(),
+            RedisCommand::XGROUPCREATE(_, _, _, _) => "xgroup".to_string(),
+            RedisCommand::XGROUPSETID(_, _, _, _) => "xgroup".to_string(),
+            RedisCommand::XCLAIM(_, _, _, _, _, _) => "xclaim".to_string(),
+            RedisCommand::XREADGROUP(_, _, _, _, _) => "xreadgroup".to_string(),
             RedisCommand::GEOADD(_, _, _, _) => "geoadd".to_string(),
             RedisCommand::GEOPOS(_, _) => "geopos".to_string(),
             RedisCommand::GEODIST(_, _, _) => "geodist".to_string(),
-            RedisCommand::GEOSEARCH(_, _, _, _, _, _) => "geosearch".to_string(),
+            RedisCommand::GEOSEARCH(_, _, _, _, _) => "geosearch".to_string(),
             RedisCommand::CONFIG(_, _) => "config".to_string(),
             RedisCommand::KEYS(_) => "keys".to_string(),
+            RedisCommand::SCAN(_, _, _) => "scan".to_string(),
+            RedisCommand::RANDOMKEY => "randomkey".to_string(),
             RedisCommand::INFO(_) => "info".to_string(),
+            RedisCommand::SAVE => "save".to_string(),
             RedisCommand::SUBSCRIBE(_) => "subscribe".to_string(),
             RedisCommand::PUBLISH(_, _) => "publish".to_string(),
             RedisCommand::UNSUBSCRIBE(_) => "unsubscribe".to_string(),
+            RedisCommand::PSUBSCRIBE(_) => "psubscribe".to_string(),
+            RedisCommand::PUNSUBSCRIBE(_) => "punsubscribe".to_string(),
             RedisCommand::REPLCONF(_, _) => "replconf".to_string(),
             RedisCommand::PSYNC(_, _) => "psync".to_string(),
+            RedisCommand::FAILOVER(_) => "failover".to_string(),
+            RedisCommand::WAIT(_, _) => "wait".to_string(),
+            RedisCommand::CLIENT(_, _) => "client".to_string(),
+            RedisCommand::HELLO(_) => "hello".to_string(),
+        }
+    }
+
+    // Keys the command looks up, each counting as an access for OBJECT
+    // IDLETIME. Like Redis, introspection (TYPE, TTL, OBJECT...) leaves the
+    // access time alone.
+    pub fn keys(&self) -> Vec<&str> {
+        match self {
+            RedisCommand::Get(key)
+            | RedisCommand::Set(key, _, _)
+            | RedisCommand::GETEX(key, _)
+            | RedisCommand::GETSET(key, _)
+            | RedisCommand::GETDEL(key)
+            | RedisCommand::SETNX(key, _)
+            | RedisCommand::EXPIRE(key, _)
+            | RedisCommand::PERSIST(key)
+            | RedisCommand::RPUSH(key, _)
+            | RedisCommand::LRANGE(key, _, _)
+            | RedisCommand::LPUSH(key, _)
+            | RedisCommand::LLEN(key)
+            | RedisCommand::LPOP(key, _)
+            | RedisCommand::RPOP(key, _)
+            | RedisCommand::LINDEX(key, _)
+            | RedisCommand::LSET(key, _, _)
+            | RedisCommand::LTRIM(key, _, _)
+            | RedisCommand::LREM(key, _, _)
+            | RedisCommand::LINSERT(key, _, _, _)
+            | RedisCommand::LPOS(key, _, _)
+            | RedisCommand::INCR(key)
+            | RedisCommand::INCRBY(key, _)
+            | RedisCommand::DECR(key)
+            | RedisCommand::DECRBY(key, _)
+            | RedisCommand::INCRBYFLOAT(key, _)
+            | RedisCommand::STRLEN(key)
+            | RedisCommand::GETRANGE(key, _, _)
+            | RedisCommand::SETRANGE(key, _, _)
+            | RedisCommand::APPEND(key, _)
+            | RedisCommand::BITCOUNT(key, _)
+            | RedisCommand::BITPOS(key, _, _)
+            | RedisCommand::ZADD(key, _, _, _)
+            | RedisCommand::ZINCRBY(key, _, _)
+            | RedisCommand::ZRANGEBYSCORE(key, _, _, _)
+            | RedisCommand::ZCOUNT(key, _, _)
+            | RedisCommand::ZMSCORE(key, _)
+            | RedisCommand::ZRANK(key, _)
+            | RedisCommand::ZRANGE(key, _, _)
+            | RedisCommand::ZCARD(key)
+            | RedisCommand::ZSCORE(key, _)
+            | RedisCommand::ZREM(key, _)
+            | RedisCommand::HSET(key, _)
+            | RedisCommand::HMSET(key, _)
+            | RedisCommand::HGET(key, _)
+            | RedisCommand::HDEL(key, _)
+            | RedisCommand::HGETALL(key)
+            | RedisCommand::HLEN(key)
+            | RedisCommand::HEXISTS(key, _)
+            | RedisCommand::HSTRLEN(key, _)
+            | RedisCommand::SADD(key, _)
+            | RedisCommand::SREM(key, _)
+            | RedisCommand::SMEMBERS(key)
+            | RedisCommand::SISMEMBER(key, _)
+            | RedisCommand::SCARD(key)
+            | RedisCommand::SPOP(key, _)
+            | RedisCommand::XADD(key, _, _, _)
+            | RedisCommand::XRANGE(key, _, _)
+            | RedisCommand::XLEN(key)
+            | RedisCommand::XDEL(key, _)
+            | RedisCommand::XTRIM(key, _, _)
+            | RedisCommand::XGROUPCREATE(key, _, _, _)
+            | RedisCommand::XGROUPSETID(key, _, _, _)
+            | RedisCommand::XCLAIM(key, _, _, _, _, _)
+            | RedisCommand::GEOADD(key, _, _, _)
+            | RedisCommand::GEOPOS(key, _)
+            | RedisCommand::GEODIST(key, _, _)
+            | RedisCommand::GEOSEARCH(key, _, _, _, _) => vec![key],
+            RedisCommand::RENAME(key, destination)
+            | RedisCommand::COPY(key, destination, _)
+            | RedisCommand::ZRANGESTORE(destination, key, _, _, _) => vec![key, destination],
+            RedisCommand::Del(keys)
+            | RedisCommand::UNLINK(keys)
+            | RedisCommand::MGET(keys)
+            | RedisCommand::BLPOP(keys, _)
+            | RedisCommand::BRPOP(keys, _)
+            | RedisCommand::SINTER(keys)
+            | RedisCommand::SUNION(keys)
+            | RedisCommand::SDIFF(keys)
+            | RedisCommand::SINTERCARD(keys, _) => keys.iter().map(String::as_str).collect(),
+            RedisCommand::SINTERSTORE(destination, keys)
+            | RedisCommand::SUNIONSTORE(destination, keys)
+            | RedisCommand::SDIFFSTORE(destination, keys) => std::iter::once(destination)
+                .chain(keys)
+                .map(String::as_str)
+                .collect(),
+            RedisCommand::MSET(pairs) => pairs.iter().map(|(key, _)| key.as_str()).collect(),
+            RedisCommand::XREAD(_, _, streams) | RedisCommand::XREADGROUP(_, _, _, _, streams) => {
+                streams.iter().map(|(key, _)| key.as_str()).collect()
+            }
+            // Introspection, and WATCH which only remembers the keys
+            RedisCommand::Exists(_)
+            | RedisCommand::TTL(_)
+            | RedisCommand::PTTL(_)
+            | RedisCommand::TYPE(_)
+            | RedisCommand::OBJECT(_, _)
+            | RedisCommand::DEBUG(_, _)
+            | RedisCommand::XINFO(_, _, _)
+            | RedisCommand::WATCH(_) => Vec::new(),
+            // Commands that name no keys; PUBLISH and SUBSCRIBE take channels
+            RedisCommand::Ping(_)
+            | RedisCommand::Echo(_)
+            | RedisCommand::MULTI
+            | RedisCommand::EXEC
+            | RedisCommand::DISCARD
+            | RedisCommand::UNWATCH
+            | RedisCommand::CONFIG(_, _)
+            | RedisCommand::KEYS(_)
+            | RedisCommand::SCAN(_, _, _)
+            | RedisCommand::RANDOMKEY
+            | RedisCommand::INFO(_)
+            | RedisCommand::SAVE
+            | RedisCommand::SUBSCRIBE(_)
+            | RedisCommand::PUBLISH(_, _)
+            | RedisCommand::UNSUBSCRIBE(_)
+            | RedisCommand::PSUBSCRIBE(_)
+            | RedisCommand::PUNSUBSCRIBE(_)
+            | RedisCommand::REPLCONF(_, _)
+            | RedisCommand::PSYNC(_, _)
+            | RedisCommand::FAILOVER(_)
+            | RedisCommand::WAIT(_, _)
+            | RedisCommand::CLIENT(_, _)
+            | RedisCommand::HELLO(_) => Vec::new(),
+        }
+    }
+
+    // Commands that may change the dataset, held back by CLIENT PAUSE WRITE.
+    // PUBLISH counts too since it is propagated, as in Redis.
+    pub fn is_write(&self) -> bool {
+        match self {
+            RedisCommand::Set(_, _, _)
+            | RedisCommand::GETEX(_, _)
+            | RedisCommand::GETSET(_, _)
+            | RedisCommand::GETDEL(_)
+            | RedisCommand::SETNX(_, _)
+            | RedisCommand::Del(_)
+            | RedisCommand::UNLINK(_)
+            | RedisCommand::MSET(_)
+            | RedisCommand::EXPIRE(_, _)
+            | RedisCommand::PERSIST(_)
+            | RedisCommand::RENAME(_, _)
+            | RedisCommand::COPY(_, _, _)
+            | RedisCommand::RPUSH(_, _)
+            | RedisCommand::LPUSH(_, _)
+            | RedisCommand::LPOP(_, _)
+            | RedisCommand::RPOP(_, _)
+            | RedisCommand::LSET(_, _, _)
+            | RedisCommand::LTRIM(_, _, _)
+            | RedisCommand::LREM(_, _, _)
+            | RedisCommand::LINSERT(_, _, _, _)
+            | RedisCommand::BLPOP(_, _)
+            | RedisCommand::BRPOP(_, _)
+            | RedisCommand::INCR(_)
+            | RedisCommand::INCRBY(_, _)
+            | RedisCommand::DECR(_)
+            | RedisCommand::DECRBY(_, _)
+            | RedisCommand::INCRBYFLOAT(_, _)
+            | RedisCommand::SETRANGE(_, _, _)
+            | RedisCommand::APPEND(_, _)
+            | RedisCommand::ZADD(_, _, _, _)
+            | RedisCommand::ZINCRBY(_, _, _)
+            | RedisCommand::ZRANGESTORE(_, _, _, _, _)
+            | RedisCommand::ZREM(_, _)
+            | RedisCommand::HSET(_, _)
+            | RedisCommand::HMSET(_, _)
+            | RedisCommand::HDEL(_, _)
+            | RedisCommand::SADD(_, _)
+            | RedisCommand::SREM(_, _)
+            | RedisCommand::SPOP(_, _)
+            | RedisCommand::SINTERSTORE(_, _)
+            | RedisCommand::SUNIONSTORE(_, _)
+            | RedisCommand::SDIFFSTORE(_, _)
+            | RedisCommand::XADD(_, _, _, _)
+            | RedisCommand::XDEL(_, _)
+            | RedisCommand::XTRIM(_, _, _)
+            | RedisCommand::XGROUPCREATE(_, _, _, _)
+            | RedisCommand::XGROUPSETID(_, _, _, _)
+            | RedisCommand::XCLAIM(_, _, _, _, _, _)
+            | RedisCommand::XREADGROUP(_, _, _, _, _)
+            | RedisCommand::GEOADD(_, _, _, _)
+            | RedisCommand::PUBLISH(_, _) => true,
+            RedisCommand::Ping(_)
+            | RedisCommand::Echo(_)
+            | RedisCommand::Get(_)
+            | RedisCommand::Exists(_)
+            | RedisCommand::MGET(_)
+            | RedisCommand::TTL(_)
+            | RedisCommand::PTTL(_)
+            | RedisCommand::LRANGE(_, _, _)
+            | RedisCommand::LLEN(_)
+            | RedisCommand::LINDEX(_, _)
+            | RedisCommand::LPOS(_, _, _)
+            | RedisCommand::STRLEN(_)
+            | RedisCommand::GETRANGE(_, _, _)
+            | RedisCommand::BITCOUNT(_, _)
+            | RedisCommand::BITPOS(_, _, _)
+            | RedisCommand::MULTI
+            | RedisCommand::EXEC
+            | RedisCommand::DISCARD
+            | RedisCommand::WATCH(_)
+            | RedisCommand::UNWATCH
+            | RedisCommand::ZRANGEBYSCORE(_, _, _, _)
+            | RedisCommand::ZCOUNT(_, _, _)
+            | RedisCommand::ZMSCORE(_, _)
+            | RedisCommand::ZRANK(_, _)
+            | RedisCommand::ZRANGE(_, _, _)
+            | RedisCommand::ZCARD(_)
+            | RedisCommand::ZSCORE(_, _)
+            | RedisCommand::HGET(_, _)
+            | RedisCommand::HGETALL(_)
+            | RedisCommand::HLEN(_)
+            | RedisCommand::HEXISTS(_, _)
+            | RedisCommand::HSTRLEN(_, _)
+            | RedisCommand::SMEMBERS(_)
+            | RedisCommand::SISMEMBER(_, _)
+            | RedisCommand::SCARD(_)
+            | RedisCommand::SINTER(_)
+            | RedisCommand::SUNION(_)
+            | RedisCommand::SDIFF(_)
+            | RedisCommand::SINTERCARD(_, _)
+            | RedisCommand::TYPE(_)
+            | RedisCommand::OBJECT(_, _)
+            | RedisCommand::DEBUG(_, _)
+            | RedisCommand::XRANGE(_, _, _)
+            | RedisCommand::XREAD(_, _, _)
+            | RedisCommand::XLEN(_)
+            | RedisCommand::XINFO(_, _, _)
+            | RedisCommand::GEOPOS(_, _)
+            | RedisCommand::GEODIST(_, _, _)
+            | RedisCommand::GEOSEARCH(_, _, _, _, _)
+            | RedisCommand::CONFIG(_, _)
+            | RedisCommand::KEYS(_)
+            | RedisCommand::SCAN(_, _, _)
+            | RedisCommand::RANDOMKEY
+            | RedisCommand::INFO(_)
+            | RedisCommand::SAVE
+            | RedisCommand::SUBSCRIBE(_)
+            | RedisCommand::UNSUBSCRIBE(_)
+            | RedisCommand::PSUBSCRIBE(_)
+            | RedisCommand::PUNSUBSCRIBE(_)
+            | RedisCommand::REPLCONF(_, _)
+            | RedisCommand::PSYNC(_, _)
+            | RedisCommand::FAILOVER(_)
+            | RedisCommand::WAIT(_, _)
+            | RedisCommand::CLIENT(_, _)
+            | RedisCommand::HELLO(_) => false,
         }
     }
 }
@@ -1,8 +1,38 @@
-use super::RedisCommand;
+use super::{BitRange, Expiry, GetExOption, LPosOptions, RedisCommand, SetOptions, ZAddOptions};
+use crate::storage::geo_shape::GeoShape;
+use crate::storage::stream_member::XClaimOptions;
+use crate::storage::zset_member::ScoreBound;
+use std::collections::HashMap;
+
+// Redis keeps deadlines as signed 64-bit Unix milliseconds, so no expiry may
+// land past this
+const MAX_EXPIRE_DEADLINE_MS: u128 = i64::MAX as u128;
 
 pub struct CommandParser;
 
 impl CommandParser {
+    // Parse with rename-command applied. `renames` maps an uppercased command
+    // name to its new name, an empty new name disables the command entirely.
+    pub fn parse_with_renames(
+        mut args: Vec<String>,
+        renames: &HashMap<String, String>,
+    ) -> Result<RedisCommand, String> {
+        if let Some(name) = args.first() {
+            let name = name.to_uppercase();
+            let original = renames
+                .iter()
+                .find(|(_, new_name)| !new_name.is_empty() && new_name.to_uppercase() == name)
+                .map(|(original, _)| original.clone());
+
+            if let Some(original) = original {
+                args[0] = original;
+            } else if renames.contains_key(&name) {
+                return Err(format!("Unknown command: {}", name));
+            }
+        }
+        Self::parse(args)
+    }
+
     pub fn parse(args: Vec<String>) -> Result<RedisCommand, String> {
         if args.is_empty() {
             return Err("Empty command".to_string());
@@ -15,41 +45,118 @@ impl CommandParser {
             "ECHO" => Self::parse_echo(&args),
             "GET" => Self::parse_get(&args),
             "SET" => Self::parse_set(&args),
-            "DEL" => Self::parse_del(&args),
+            "GETEX" => Self::parse_getex(&args),
+            "GETSET" => Self::parse_getset(&args, "GETSET"),
+            "GETDEL" => Self::parse_getdel(&args),
+            "SETNX" => Self::parse_getset(&args, "SETNX"),
+            "SETEX" => Self::parse_setex(&args, "EX"),
+            "PSETEX" => Self::parse_setex(&args, "PX"),
+            "DEL" => Self::parse_del(&args, "DEL"),
+            "UNLINK" => Self::parse_del(&args, "UNLINK"),
             "EXISTS" => Self::parse_exists(&args),
+            "MGET" => Self::parse_mget(&args),
+            "MSET" => Self::parse_mset(&args),
+            "EXPIRE" => Self::parse_expire(&args, "EX"),
+            "PEXPIRE" => Self::parse_expire(&args, "PX"),
+            "EXPIREAT" => Self::parse_expire(&args, "EXAT"),
+            "PEXPIREAT" => Self::parse_expire(&args, "PXAT"),
+            "PERSIST" => Self::parse_persist(&args),
+            "RENAME" => Self::parse_rename(&args),
+            "COPY" => Self::parse_copy(&args),
+            "TTL" => Self::parse_ttl(&args),
+            "PTTL" => Self::parse_ttl(&args),
             "RPUSH" => Self::parse_rpush(&args),
             "LRANGE" => Self::parse_lrange(&args),
             "LPUSH" => Self::parse_lpush(&args),
             "LLEN" => Self::parse_llen(&args),
             "LPOP" => Self::parse_lpop(&args),
+            "RPOP" => Self::parse_lpop(&args),
+            "LINDEX" => Self::parse_lindex(&args),
+            "LSET" => Self::parse_lset(&args),
+            "LTRIM" => Self::parse_ltrim(&args),
+            "LREM" => Self::parse_lrem(&args),
+            "LINSERT" => Self::parse_linsert(&args),
+            "LPOS" => Self::parse_lpos(&args),
             "BLPOP" => Self::parse_blpop(&args),
             "BRPOP" => Self::parse_brpop(&args),
             "INCR" => Self::parse_incr(&args),
+            "INCRBY" => Self::parse_incr_by(&args, "INCRBY"),
+            "DECR" => Self::parse_decr(&args),
+            "DECRBY" => Self::parse_incr_by(&args, "DECRBY"),
+            "INCRBYFLOAT" => Self::parse_incrbyfloat(&args),
+            "STRLEN" => Self::parse_strlen(&args),
+            "GETRANGE" => Self::parse_getrange(&args),
+            "BITCOUNT" => Self::parse_bitcount(&args),
+            "BITPOS" => Self::parse_bitpos(&args),
+            "SETRANGE" => Self::parse_setrange(&args),
+            "APPEND" => Self::parse_append(&args),
             "MULTI" => Self::parse_multi(&args),
             "EXEC" => Self::parse_exec(&args),
             "DISCARD" => Self::parse_discard(&args),
+            "WATCH" => Self::parse_watch(&args),
+            "UNWATCH" => Self::parse_unwatch(&args),
             "ZADD" => Self::parse_zadd(&args),
+            "ZINCRBY" => Self::parse_zincrby(&args),
+            "ZRANGEBYSCORE" => Self::parse_zrangebyscore(&args),
+            "ZCOUNT" => Self::parse_zcount(&args),
+            "ZMSCORE" => Self::parse_zmscore(&args),
+            "ZRANGESTORE" => Self::parse_zrangestore(&args),
+            "HSET" => Self::parse_hset(&args),
+            "HMSET" => Self::parse_hset(&args),
+            "HGET" => Self::parse_hget(&args),
+            "HDEL" => Self::parse_hdel(&args),
+            "HGETALL" => Self::parse_hgetall(&args),
+            "HLEN" => Self::parse_hlen(&args),
+            "HEXISTS" => Self::parse_hexists(&args),
+            "HSTRLEN" => Self::parse_hstrlen(&args),
+            "SADD" => Self::parse_sadd(&args),
+            "SREM" => Self::parse_srem(&args),
+            "SMEMBERS" => Self::parse_smembers(&args),
+            "SISMEMBER" => Self::parse_sismember(&args),
+            "SCARD" => Self::parse_scard(&args),
+            "SPOP" => Self::parse_spop(&args),
+            "SINTER" | "SUNION" | "SDIFF" => Self::parse_set_operation(&args),
+            "SINTERSTORE" | "SUNIONSTORE" | "SDIFFSTORE" => Self::parse_set_operation_store(&args),
+            "SINTERCARD" => Self::parse_sintercard(&args),
             "ZRANK" => Self::parse_zrank(&args),
             "ZRANGE" => Self::parse_zrange(&args),
             "ZCARD" => Self::parse_zcard(&args),
             "ZSCORE" => Self::parse_zscore(&args),
             "ZREM" => Self::parse_zrem(&args),
             "TYPE" => Self::parse_type(&args),
+            "OBJECT" => Self::parse_object(&args),
+            "DEBUG" => Self::parse_debug(&args),
             "XADD" => Self::parse_xadd(&args),
             "XRANGE" => Self::parse_xrange(&args),
             "XREAD" => Self::parse_xread(&args),
+            "XLEN" => Self::parse_xlen(&args),
+            "XDEL" => Self::parse_xdel(&args),
+            "XTRIM" => Self::parse_xtrim(&args),
+            "XINFO" => Self::parse_xinfo(&args),
+            "XGROUP" => Self::parse_xgroup(&args),
+            "XREADGROUP" => Self::parse_xreadgroup(&args),
+            "XCLAIM" => Self::parse_xclaim(&args),
             "GEOADD" => Self::parse_geoadd(&args),
             "GEOPOS" => Self::parse_geopos(&args),
             "GEODIST" => Self::parse_geodist(&args),
             "GEOSEARCH" => Self::parse_geosearch(&args),
             "CONFIG" => Self::parse_config(&args),
             "KEYS" => Self::parse_keys(&args),
+            "SCAN" => Self::parse_scan(&args),
+            "RANDOMKEY" => Self::parse_randomkey(&args),
             "INFO" => Self::parse_info(&args),
+            "SAVE" => Self::parse_save(&args),
             "SUBSCRIBE" => Self::parse_subscribe(&args),
             "PUBLISH" => Self::parse_publish(&args),
             "UNSUBSCRIBE" => Self::parse_unsubscribe(&args),
+            "PSUBSCRIBE" => Self::parse_psubscribe(&args),
+            "PUNSUBSCRIBE" => Self::parse_punsubscribe(&args),
             "REPLCONF" => Self::parse_replconf(&args),
             "PSYNC" => Self::parse_psync(&args),
+            "FAILOVER" => Self::parse_failover(&args),
+            "WAIT" => Self::parse_wait(&args),
+            "CLIENT" => Self::parse_client(&args),
+            "HELLO" => Self::parse_hello(&args),
             _ => Err(format!("Unknown command: {}", command)),
         }
     }
@@ -77,40 +184,136 @@ impl CommandParser {
     }
 
     fn parse_set(args: &[String]) -> Result<RedisCommand, String> {
-        if args.len() == 3 {
-            Ok(RedisCommand::Set(args[1].clone(), args[2].clone()))
-        } else if args.len() == 5 {
-            if args[3].to_ascii_uppercase() == "EX" {
-                let expiry: u128 = args[4]
-                    .parse()
-                    .map_err(|_| "Invalid expiry value".to_string())?;
-                Ok(RedisCommand::SetWithExpiry(
-                    args[1].clone(),
-                    args[2].clone(),
-                    expiry * 1000,
-                )) // Convert seconds to milliseconds
-            } else if args[3].to_ascii_uppercase() == "PX" {
-                let expiry: u128 = args[4]
-                    .parse()
-                    .map_err(|_| "Invalid expiry value".to_string())?;
-                Ok(RedisCommand::SetWithExpiry(
-                    args[1].clone(),
-                    args[2].clone(),
-                    expiry,
-                ))
-            } else {
-                return Err("Invalid SET command format".to_string());
+        if args.len() < 3 {
+            return Err("Wrong number of arguments for SET".to_string());
+        }
+
+        let mut options = SetOptions::default();
+        let mut i = 3;
+        while i < args.len() {
+            match args[i].to_ascii_uppercase().as_str() {
+                "EX" | "PX" | "EXAT" | "PXAT" if options.expiry.is_none() && i + 1 < args.len() => {
+                    options.expiry =
+                        Some(Self::parse_expiry_option(&args[i], &args[i + 1], "set")?);
+                    i += 2;
+                }
+                "GET" => {
+                    options.get = true;
+                    i += 1;
+                }
+                "NX" => {
+                    options.nx = true;
+                    i += 1;
+                }
+                "XX" => {
+                    options.xx = true;
+                    i += 1;
+                }
+                "KEEPTTL" => {
+                    options.keepttl = true;
+                    i += 1;
+                }
+                _ => return Err("Invalid SET command format".to_string()),
             }
+        }
+        if (options.nx && options.xx) || (options.keepttl && options.expiry.is_some()) {
+            return Err("syntax error".to_string());
+        }
+
+        Ok(RedisCommand::Set(args[1].clone(), args[2].clone(), options))
+    }
+
+    // SETEX and PSETEX are SET with a mandatory expiry
+    fn parse_setex(args: &[String], unit: &str) -> Result<RedisCommand, String> {
+        let command = args[0].to_lowercase();
+        if args.len() != 4 {
+            return Err(format!(
+                "Wrong number of arguments for {}",
+                command.to_uppercase()
+            ));
+        }
+        let options = SetOptions {
+            expiry: Some(Self::parse_expiry_option(unit, &args[2], &command)?),
+            ..SetOptions::default()
+        };
+        Ok(RedisCommand::Set(args[1].clone(), args[3].clone(), options))
+    }
+
+    fn parse_getex(args: &[String]) -> Result<RedisCommand, String> {
+        let option = match args.len() {
+            2 => None,
+            3 if args[2].eq_ignore_ascii_case("PERSIST") => Some(GetExOption::Persist),
+            4 => match args[2].to_ascii_uppercase().as_str() {
+                "EX" | "PX" | "EXAT" | "PXAT" => Some(GetExOption::Expire(
+                    Self::parse_expiry_option(&args[2], &args[3], "getex")?,
+                )),
+                _ => return Err("syntax error".to_string()),
+            },
+            1 => return Err("Wrong number of arguments for GETEX".to_string()),
+            _ => return Err("syntax error".to_string()),
+        };
+        Ok(RedisCommand::GETEX(args[1].clone(), option))
+    }
+
+    // Read an EX/PX/EXAT/PXAT argument in milliseconds. Every expiry-setting
+    // command validates through here so they share Redis'
+    // "invalid expire time in '<command>' command" error.
+    fn parse_expiry_option(unit: &str, value: &str, command: &str) -> Result<Expiry, String> {
+        let invalid = || format!("invalid expire time in '{}' command", command);
+        let value = value
+            .parse::<i64>()
+            .map_err(|_| "value is not an integer or out of range".to_string())?;
+        if value <= 0 {
+            return Err(invalid());
+        }
+
+        let unit = unit.to_ascii_uppercase();
+        let multiplier = if unit.starts_with("EX") { 1000 } else { 1 };
+        let millis = value.checked_mul(multiplier).ok_or_else(invalid)? as u128;
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        if unit.ends_with("AT") {
+            // Kept absolute; a past deadline expires the key when it runs
+            Ok(Expiry::At(millis))
+        } else if millis + now > MAX_EXPIRE_DEADLINE_MS {
+            Err(invalid())
         } else {
-            return Err("Wrong number of arguments for SET".to_string());
+            Ok(Expiry::In(millis))
+        }
+    }
+
+    // GETSET and SETNX, which both take a key and a value
+    fn parse_getset(args: &[String], name: &str) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err(format!("Wrong number of arguments for {}", name));
+        }
+        let (key, value) = (args[1].clone(), args[2].clone());
+        Ok(match name {
+            "GETSET" => RedisCommand::GETSET(key, value),
+            _ => RedisCommand::SETNX(key, value),
+        })
+    }
+
+    fn parse_getdel(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 2 {
+            return Err("Wrong number of arguments for GETDEL".to_string());
         }
+        Ok(RedisCommand::GETDEL(args[1].clone()))
     }
 
-    fn parse_del(args: &[String]) -> Result<RedisCommand, String> {
+    // UNLINK frees memory in the background in Redis; here it is just DEL
+    fn parse_del(args: &[String], name: &str) -> Result<RedisCommand, String> {
         if args.len() < 2 {
-            return Err("Wrong number of arguments for DEL".to_string());
+            return Err(format!("Wrong number of arguments for {}", name));
+        }
+        let keys = args[1..].to_vec();
+        match name {
+            "UNLINK" => Ok(RedisCommand::UNLINK(keys)),
+            _ => Ok(RedisCommand::Del(keys)),
         }
-        Ok(RedisCommand::Del(args[1..].to_vec()))
     }
 
     fn parse_exists(args: &[String]) -> Result<RedisCommand, String> {
@@ -120,6 +323,88 @@ impl CommandParser {
         Ok(RedisCommand::Exists(args[1..].to_vec()))
     }
 
+    fn parse_mget(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 2 {
+            return Err("Wrong number of arguments for MGET".to_string());
+        }
+        Ok(RedisCommand::MGET(args[1..].to_vec()))
+    }
+
+    fn parse_mset(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 3 || args.len().is_multiple_of(2) {
+            return Err("Wrong number of arguments for MSET".to_string());
+        }
+        let pairs = args[1..]
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+        Ok(RedisCommand::MSET(pairs))
+    }
+
+    // EXPIRE, PEXPIRE, EXPIREAT and PEXPIREAT, told apart by their SET-style unit
+    fn parse_expire(args: &[String], unit: &str) -> Result<RedisCommand, String> {
+        let command = args[0].to_lowercase();
+        if args.len() != 3 {
+            return Err(format!(
+                "Wrong number of arguments for {}",
+                command.to_uppercase()
+            ));
+        }
+        let value = args[2]
+            .parse::<i64>()
+            .map_err(|_| "value is not an integer or out of range".to_string())?;
+        // Unlike SET, a deadline that already passed is allowed and deletes the key
+        let expiry = if value <= 0 {
+            Expiry::In(0)
+        } else {
+            Self::parse_expiry_option(unit, &args[2], &command)?
+        };
+        Ok(RedisCommand::EXPIRE(args[1].clone(), expiry))
+    }
+
+    fn parse_persist(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 2 {
+            return Err("Wrong number of arguments for PERSIST".to_string());
+        }
+        Ok(RedisCommand::PERSIST(args[1].clone()))
+    }
+
+    fn parse_rename(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for RENAME".to_string());
+        }
+        Ok(RedisCommand::RENAME(args[1].clone(), args[2].clone()))
+    }
+
+    fn parse_copy(args: &[String]) -> Result<RedisCommand, String> {
+        let replace = match args.len() {
+            3 => false,
+            4 if args[3].eq_ignore_ascii_case("REPLACE") => true,
+            4 => return Err("syntax error".to_string()),
+            _ => return Err("Wrong number of arguments for COPY".to_string()),
+        };
+        Ok(RedisCommand::COPY(
+            args[1].clone(),
+            args[2].clone(),
+            replace,
+        ))
+    }
+
+    // TTL and PTTL only differ in the unit of the reply
+    fn parse_ttl(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 2 {
+            return Err(format!(
+                "Wrong number of arguments for {}",
+                args[0].to_uppercase()
+            ));
+        }
+        if args[0].eq_ignore_ascii_case("PTTL") {
+            Ok(RedisCommand::PTTL(args[1].clone()))
+        } else {
+            Ok(RedisCommand::TTL(args[1].clone()))
+        }
+    }
+
     fn parse_rpush(args: &[String]) -> Result<RedisCommand, String> {
         if args.len() < 3 {
             return Err("Wrong number of arguments for RPUSH".to_string());
@@ -140,6 +425,96 @@ impl CommandParser {
         Ok(RedisCommand::LRANGE(args[1].clone(), start, end))
     }
 
+    fn parse_lindex(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for LINDEX".to_string());
+        }
+        let index: i64 = args[2]
+            .parse()
+            .map_err(|_| "value is not an integer or out of range".to_string())?;
+        Ok(RedisCommand::LINDEX(args[1].clone(), index))
+    }
+
+    fn parse_lset(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 4 {
+            return Err("Wrong number of arguments for LSET".to_string());
+        }
+        let index: i64 = args[2]
+            .parse()
+            .map_err(|_| "value is not an integer or out of range".to_string())?;
+        Ok(RedisCommand::LSET(args[1].clone(), index, args[3].clone()))
+    }
+
+    fn parse_ltrim(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 4 {
+            return Err("Wrong number of arguments for LTRIM".to_string());
+        }
+        let start: i64 = args[2]
+            .parse()
+            .map_err(|_| "Invalid start index".to_string())?;
+        let end: i64 = args[3]
+            .parse()
+            .map_err(|_| "Invalid end index".to_string())?;
+        Ok(RedisCommand::LTRIM(args[1].clone(), start, end))
+    }
+
+    fn parse_lrem(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 4 {
+            return Err("Wrong number of arguments for LREM".to_string());
+        }
+        let count: i64 = args[2]
+            .parse()
+            .map_err(|_| "value is not an integer or out of range".to_string())?;
+        Ok(RedisCommand::LREM(args[1].clone(), count, args[3].clone()))
+    }
+
+    fn parse_linsert(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 5 {
+            return Err("Wrong number of arguments for LINSERT".to_string());
+        }
+        let before = match args[2].to_uppercase().as_str() {
+            "BEFORE" => true,
+            "AFTER" => false,
+            _ => return Err("syntax error".to_string()),
+        };
+        Ok(RedisCommand::LINSERT(
+            args[1].clone(),
+            before,
+            args[3].clone(),
+            args[4].clone(),
+        ))
+    }
+
+    fn parse_lpos(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 3 || args.len().is_multiple_of(2) {
+            return Err("Wrong number of arguments for LPOS".to_string());
+        }
+        let mut options = LPosOptions::default();
+        for pair in args[3..].chunks(2) {
+            let value = pair[1]
+                .parse::<i64>()
+                .map_err(|_| "value is not an integer or out of range".to_string())?;
+            match pair[0].to_uppercase().as_str() {
+                "RANK" if value == 0 => {
+                    return Err("RANK can't be zero: use 1 to start from the first match, \
+                        2 from the second ... or use negative to start from the end of the list"
+                        .to_string())
+                }
+                "RANK" => options.rank = Some(value),
+                "COUNT" if value < 0 => return Err("COUNT can't be negative".to_string()),
+                "COUNT" => options.count = Some(value as usize),
+                "MAXLEN" if value < 0 => return Err("MAXLEN can't be negative".to_string()),
+                "MAXLEN" => options.maxlen = Some(value as usize),
+                _ => return Err("syntax error".to_string()),
+            }
+        }
+        Ok(RedisCommand::LPOS(
+            args[1].clone(),
+            args[2].clone(),
+            options,
+        ))
+    }
+
     fn parse_lpush(args: &[String]) -> Result<RedisCommand, String> {
         if args.len() < 3 {
             return Err("Wrong number of arguments for LPUSH".to_string());
@@ -154,20 +529,28 @@ impl CommandParser {
         Ok(RedisCommand::LLEN(args[1].clone()))
     }
 
+    // LPOP and RPOP share the `key [count]` form
     fn parse_lpop(args: &[String]) -> Result<RedisCommand, String> {
+        let command = args[0].to_uppercase();
         if args.len() < 2 || args.len() > 3 {
-            return Err("Wrong number of arguments for LPOP".to_string());
+            return Err(format!("Wrong number of arguments for {}", command));
         }
         let count = if args.len() == 3 {
-            Some(
-                args[2]
-                    .parse::<i64>()
-                    .map_err(|_| "Invalid count value".to_string())?,
-            )
+            let count = args[2]
+                .parse::<i64>()
+                .map_err(|_| "Invalid count value".to_string())?;
+            if count < 0 {
+                return Err("value is out of range, must be positive".to_string());
+            }
+            Some(count)
         } else {
             None
         };
-        Ok(RedisCommand::LPOP(args[1].clone(), count))
+        if command == "RPOP" {
+            Ok(RedisCommand::RPOP(args[1].clone(), count))
+        } else {
+            Ok(RedisCommand::LPOP(args[1].clone(), count))
+        }
     }
 
     fn parse_blpop(args: &[String]) -> Result<RedisCommand, String> {
@@ -207,6 +590,127 @@ impl CommandParser {
         Ok(RedisCommand::INCR(args[1].clone()))
     }
 
+    fn parse_decr(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 2 {
+            return Err("Wrong number of arguments for DECR".to_string());
+        }
+        Ok(RedisCommand::DECR(args[1].clone()))
+    }
+
+    // INCRBY and DECRBY
+    fn parse_incr_by(args: &[String], name: &str) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err(format!("Wrong number of arguments for {}", name));
+        }
+        let amount = args[2]
+            .parse::<i64>()
+            .map_err(|_| "value is not an integer or out of range".to_string())?;
+        Ok(match name {
+            "INCRBY" => RedisCommand::INCRBY(args[1].clone(), amount),
+            _ => RedisCommand::DECRBY(args[1].clone(), amount),
+        })
+    }
+
+    fn parse_incrbyfloat(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for INCRBYFLOAT".to_string());
+        }
+        let amount = args[2]
+            .parse::<f64>()
+            .ok()
+            .filter(|amount| amount.is_finite())
+            .ok_or_else(|| "value is not a valid float".to_string())?;
+        Ok(RedisCommand::INCRBYFLOAT(args[1].clone(), amount))
+    }
+
+    fn parse_strlen(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 2 {
+            return Err("Wrong number of arguments for STRLEN".to_string());
+        }
+        Ok(RedisCommand::STRLEN(args[1].clone()))
+    }
+
+    fn parse_getrange(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 4 {
+            return Err("Wrong number of arguments for GETRANGE".to_string());
+        }
+        let start = args[2]
+            .parse::<i64>()
+            .map_err(|_| "value is not an integer or out of range".to_string())?;
+        let end = args[3]
+            .parse::<i64>()
+            .map_err(|_| "value is not an integer or out of range".to_string())?;
+        Ok(RedisCommand::GETRANGE(args[1].clone(), start, end))
+    }
+
+    // BITCOUNT key [start end [BYTE|BIT]]
+    fn parse_bitcount(args: &[String]) -> Result<RedisCommand, String> {
+        let range = match args.len() {
+            2 => None,
+            4 | 5 => Some(Self::parse_bit_range(&args[2..])?),
+            3 => return Err("syntax error".to_string()),
+            _ => return Err("Wrong number of arguments for BITCOUNT".to_string()),
+        };
+        Ok(RedisCommand::BITCOUNT(args[1].clone(), range))
+    }
+
+    // BITPOS key bit [start [end [BYTE|BIT]]]
+    fn parse_bitpos(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 3 || args.len() > 6 {
+            return Err("Wrong number of arguments for BITPOS".to_string());
+        }
+        let bit = match args[2].as_str() {
+            "0" => false,
+            "1" => true,
+            _ => return Err("The bit argument must be 1 or 0.".to_string()),
+        };
+        let range = match args.len() {
+            3 => None,
+            _ => Some(Self::parse_bit_range(&args[3..])?),
+        };
+        Ok(RedisCommand::BITPOS(args[1].clone(), bit, range))
+    }
+
+    // `start [end [BYTE|BIT]]`
+    fn parse_bit_range(args: &[String]) -> Result<BitRange, String> {
+        let parse_index = |arg: &String| {
+            arg.parse::<i64>()
+                .map_err(|_| "value is not an integer or out of range".to_string())
+        };
+        let bit = match args.get(2).map(|unit| unit.to_uppercase()) {
+            None => false,
+            Some(unit) if unit == "BYTE" => false,
+            Some(unit) if unit == "BIT" => true,
+            Some(_) => return Err("syntax error".to_string()),
+        };
+        Ok(BitRange {
+            start: parse_index(&args[0])?,
+            end: args.get(1).map(parse_index).transpose()?,
+            bit,
+        })
+    }
+
+    fn parse_setrange(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 4 {
+            return Err("Wrong number of arguments for SETRANGE".to_string());
+        }
+        let offset = args[2]
+            .parse::<usize>()
+            .map_err(|_| "offset is out of range".to_string())?;
+        Ok(RedisCommand::SETRANGE(
+            args[1].clone(),
+            offset,
+            args[3].clone(),
+        ))
+    }
+
+    fn parse_append(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for APPEND".to_string());
+        }
+        Ok(RedisCommand::APPEND(args[1].clone(), args[2].clone()))
+    }
+
     fn parse_multi(args: &[String]) -> Result<RedisCommand, String> {
         if args.len() != 1 {
             return Err("Wrong number of arguments for MULTI".to_string());
@@ -228,15 +732,300 @@ impl CommandParser {
         Ok(RedisCommand::DISCARD)
     }
 
+    fn parse_watch(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 2 {
+            return Err("Wrong number of arguments for WATCH".to_string());
+        }
+        Ok(RedisCommand::WATCH(args[1..].to_vec()))
+    }
+
+    fn parse_unwatch(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 1 {
+            return Err("Wrong number of arguments for UNWATCH".to_string());
+        }
+        Ok(RedisCommand::UNWATCH)
+    }
+
+    // HSET and HMSET share the variadic `key field value [field value ...]` form
+    fn parse_hset(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 4 || !args.len().is_multiple_of(2) {
+            return Err(format!(
+                "Wrong number of arguments for {}",
+                args[0].to_uppercase()
+            ));
+        }
+        let key = args[1].clone();
+        let pairs = args[2..]
+            .chunks(2)
+            .map(|pair| (pair[0].clone(), pair[1].clone()))
+            .collect();
+        if args[0].eq_ignore_ascii_case("HMSET") {
+            Ok(RedisCommand::HMSET(key, pairs))
+        } else {
+            Ok(RedisCommand::HSET(key, pairs))
+        }
+    }
+
+    fn parse_hget(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for HGET".to_string());
+        }
+        Ok(RedisCommand::HGET(args[1].clone(), args[2].clone()))
+    }
+
+    fn parse_hdel(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 3 {
+            return Err("Wrong number of arguments for HDEL".to_string());
+        }
+        Ok(RedisCommand::HDEL(args[1].clone(), args[2..].to_vec()))
+    }
+
+    fn parse_hlen(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 2 {
+            return Err("Wrong number of arguments for HLEN".to_string());
+        }
+        Ok(RedisCommand::HLEN(args[1].clone()))
+    }
+
+    fn parse_hexists(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for HEXISTS".to_string());
+        }
+        Ok(RedisCommand::HEXISTS(args[1].clone(), args[2].clone()))
+    }
+
+    fn parse_hstrlen(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for HSTRLEN".to_string());
+        }
+        Ok(RedisCommand::HSTRLEN(args[1].clone(), args[2].clone()))
+    }
+
+    fn parse_hgetall(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 2 {
+            return Err("Wrong number of arguments for HGETALL".to_string());
+        }
+        Ok(RedisCommand::HGETALL(args[1].clone()))
+    }
+
+    fn parse_sadd(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 3 {
+            return Err("Wrong number of arguments for SADD".to_string());
+        }
+        Ok(RedisCommand::SADD(args[1].clone(), args[2..].to_vec()))
+    }
+
+    fn parse_srem(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 3 {
+            return Err("Wrong number of arguments for SREM".to_string());
+        }
+        Ok(RedisCommand::SREM(args[1].clone(), args[2..].to_vec()))
+    }
+
+    fn parse_smembers(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 2 {
+            return Err("Wrong number of arguments for SMEMBERS".to_string());
+        }
+        Ok(RedisCommand::SMEMBERS(args[1].clone()))
+    }
+
+    fn parse_sismember(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for SISMEMBER".to_string());
+        }
+        Ok(RedisCommand::SISMEMBER(args[1].clone(), args[2].clone()))
+    }
+
+    fn parse_scard(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 2 {
+            return Err("Wrong number of arguments for SCARD".to_string());
+        }
+        Ok(RedisCommand::SCARD(args[1].clone()))
+    }
+
+    // SINTER, SUNION and SDIFF all take `key [key ...]`
+    fn parse_set_operation(args: &[String]) -> Result<RedisCommand, String> {
+        let command = args[0].to_uppercase();
+        if args.len() < 2 {
+            return Err(format!("Wrong number of arguments for {}", command));
+        }
+        let keys = args[1..].to_vec();
+        Ok(match command.as_str() {
+            "SINTER" => RedisCommand::SINTER(keys),
+            "SUNION" => RedisCommand::SUNION(keys),
+            _ => RedisCommand::SDIFF(keys),
+        })
+    }
+
+    // The STORE forms take `destination key [key ...]`
+    fn parse_set_operation_store(args: &[String]) -> Result<RedisCommand, String> {
+        let command = args[0].to_uppercase();
+        if args.len() < 3 {
+            return Err(format!("Wrong number of arguments for {}", command));
+        }
+        let destination = args[1].clone();
+        let keys = args[2..].to_vec();
+        Ok(match command.as_str() {
+            "SINTERSTORE" => RedisCommand::SINTERSTORE(destination, keys),
+            "SUNIONSTORE" => RedisCommand::SUNIONSTORE(destination, keys),
+            _ => RedisCommand::SDIFFSTORE(destination, keys),
+        })
+    }
+
+    // SINTERCARD numkeys key [key ...] [LIMIT limit]
+    fn parse_sintercard(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 3 {
+            return Err("Wrong number of arguments for SINTERCARD".to_string());
+        }
+        let numkeys = args[1]
+            .parse::<usize>()
+            .ok()
+            .filter(|&numkeys| numkeys > 0)
+            .ok_or_else(|| "numkeys should be greater than 0".to_string())?;
+        if numkeys > args.len() - 2 {
+            return Err("Number of keys can't be greater than number of args".to_string());
+        }
+        let keys = args[2..2 + numkeys].to_vec();
+
+        let mut limit = 0;
+        match &args[2 + numkeys..] {
+            [] => {}
+            [option, value] if option.eq_ignore_ascii_case("LIMIT") => {
+                limit = value
+                    .parse::<usize>()
+                    .map_err(|_| "LIMIT can't be negative".to_string())?;
+            }
+            _ => return Err("syntax error".to_string()),
+        }
+        Ok(RedisCommand::SINTERCARD(keys, limit))
+    }
+
+    fn parse_spop(args: &[String]) -> Result<RedisCommand, String> {
+        match args.len() {
+            2 => Ok(RedisCommand::SPOP(args[1].clone(), None)),
+            3 => {
+                let count = args[2]
+                    .parse::<i64>()
+                    .map_err(|_| "value is not an integer or out of range".to_string())?;
+                if count < 0 {
+                    return Err("value is out of range, must be positive".to_string());
+                }
+                Ok(RedisCommand::SPOP(args[1].clone(), Some(count as usize)))
+            }
+            _ => Err("Wrong number of arguments for SPOP".to_string()),
+        }
+    }
+
     fn parse_zadd(args: &[String]) -> Result<RedisCommand, String> {
-        if args.len() != 4 {
+        if args.len() < 4 {
             return Err("Wrong number of arguments for ZADD".to_string());
         }
-        let score = args[2]
+        // Flags sit between the key and the score/member pair
+        let mut options = ZAddOptions::default();
+        for flag in &args[2..args.len() - 2] {
+            match flag.to_uppercase().as_str() {
+                "CH" => options.ch = true,
+                "NX" => options.nx = true,
+                "XX" => options.xx = true,
+                "GT" => options.gt = true,
+                "LT" => options.lt = true,
+                "INCR" => options.incr = true,
+                _ => return Err("syntax error".to_string()),
+            }
+        }
+        if options.nx && options.xx {
+            return Err("XX and NX options at the same time are not compatible".to_string());
+        }
+        if (options.gt && options.lt) || (options.nx && (options.gt || options.lt)) {
+            return Err(
+                "GT, LT, and/or NX options at the same time are not compatible".to_string(),
+            );
+        }
+        let score = args[args.len() - 2]
             .parse::<f64>()
-            .map_err(|_| "Invalid score value".to_string())?;
-        let member = args[3].clone();
-        Ok(RedisCommand::ZADD(args[1].clone(), score, member))
+            .ok()
+            .filter(|score| !score.is_nan())
+            .ok_or_else(|| "Invalid score value".to_string())?;
+        let member = args[args.len() - 1].clone();
+        Ok(RedisCommand::ZADD(args[1].clone(), score, member, options))
+    }
+
+    fn parse_zincrby(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 4 {
+            return Err("Wrong number of arguments for ZINCRBY".to_string());
+        }
+        let increment = args[2]
+            .parse::<f64>()
+            .ok()
+            .filter(|increment| !increment.is_nan())
+            .ok_or_else(|| "value is not a valid float".to_string())?;
+        Ok(RedisCommand::ZINCRBY(
+            args[1].clone(),
+            increment,
+            args[3].clone(),
+        ))
+    }
+
+    fn parse_zrangebyscore(args: &[String]) -> Result<RedisCommand, String> {
+        let with_scores = match args.len() {
+            4 => false,
+            5 if args[4].eq_ignore_ascii_case("WITHSCORES") => true,
+            5 => return Err("syntax error".to_string()),
+            _ => return Err("Wrong number of arguments for ZRANGEBYSCORE".to_string()),
+        };
+        // Validate the bounds now so a bad one is a parse error
+        ScoreBound::parse(&args[2])?;
+        ScoreBound::parse(&args[3])?;
+        Ok(RedisCommand::ZRANGEBYSCORE(
+            args[1].clone(),
+            args[2].clone(),
+            args[3].clone(),
+            with_scores,
+        ))
+    }
+
+    fn parse_zcount(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 4 {
+            return Err("Wrong number of arguments for ZCOUNT".to_string());
+        }
+        ScoreBound::parse(&args[2])?;
+        ScoreBound::parse(&args[3])?;
+        Ok(RedisCommand::ZCOUNT(
+            args[1].clone(),
+            args[2].clone(),
+            args[3].clone(),
+        ))
+    }
+
+    fn parse_zmscore(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 3 {
+            return Err("Wrong number of arguments for ZMSCORE".to_string());
+        }
+        Ok(RedisCommand::ZMSCORE(args[1].clone(), args[2..].to_vec()))
+    }
+
+    // ZRANGESTORE dst src min max [BYSCORE], where min and max are ranks
+    // unless BYSCORE is given
+    fn parse_zrangestore(args: &[String]) -> Result<RedisCommand, String> {
+        let by_score = match args.len() {
+            5 => false,
+            6 if args[5].eq_ignore_ascii_case("BYSCORE") => true,
+            6 => return Err("syntax error".to_string()),
+            _ => return Err("Wrong number of arguments for ZRANGESTORE".to_string()),
+        };
+        if by_score {
+            ScoreBound::parse(&args[3])?;
+            ScoreBound::parse(&args[4])?;
+        } else if args[3].parse::<i64>().is_err() || args[4].parse::<i64>().is_err() {
+            return Err("value is not an integer or out of range".to_string());
+        }
+        Ok(RedisCommand::ZRANGESTORE(
+            args[1].clone(),
+            args[2].clone(),
+            args[3].clone(),
+            args[4].clone(),
+            by_score,
+        ))
     }
 
     fn parse_zrank(args: &[String]) -> Result<RedisCommand, String> {
@@ -247,9 +1036,32 @@ impl CommandParser {
     }
 
     fn parse_zrange(args: &[String]) -> Result<RedisCommand, String> {
-        if args.len() != 4 {
+        if args.len() < 4 {
             return Err("Wrong number of arguments for ZRANGE".to_string());
         }
+        // ZRANGE key min max BYSCORE [WITHSCORES] is ZRANGEBYSCORE
+        if args.len() > 4 {
+            let mut by_score = false;
+            let mut with_scores = false;
+            for flag in &args[4..] {
+                match flag.to_uppercase().as_str() {
+                    "BYSCORE" => by_score = true,
+                    "WITHSCORES" => with_scores = true,
+                    _ => return Err("syntax error".to_string()),
+                }
+            }
+            if !by_score {
+                return Err("syntax error".to_string());
+            }
+            ScoreBound::parse(&args[2])?;
+            ScoreBound::parse(&args[3])?;
+            return Ok(RedisCommand::ZRANGEBYSCORE(
+                args[1].clone(),
+                args[2].clone(),
+                args[3].clone(),
+                with_scores,
+            ));
+        }
         let start = args[2]
             .parse::<i64>()
             .map_err(|_| "Invalid start index".to_string())?;
@@ -287,17 +1099,51 @@ impl CommandParser {
         Ok(RedisCommand::TYPE(args[1].clone()))
     }
 
+    fn parse_object(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for OBJECT".to_string());
+        }
+        Ok(RedisCommand::OBJECT(args[1].clone(), args[2].clone()))
+    }
+
+    fn parse_debug(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for DEBUG".to_string());
+        }
+        Ok(RedisCommand::DEBUG(args[1].clone(), args[2].clone()))
+    }
+
     fn parse_xadd(args: &[String]) -> Result<RedisCommand, String> {
-        if args.len() < 4 || args.len() % 2 == 0 {
+        // XADD key [MAXLEN [=|~] threshold] id field value [field value ...]
+        if args.len() < 4 {
             return Err("Wrong number of arguments for XADD".to_string());
         }
         let key = args[1].clone();
-        let id = args[2].clone();
+        let mut idx = 2;
+        let mut maxlen = None;
+        if args[idx].eq_ignore_ascii_case("MAXLEN") {
+            idx += 1;
+            if matches!(args[idx].as_str(), "=" | "~") {
+                idx += 1;
+            }
+            let threshold = args.get(idx).ok_or("syntax error".to_string())?;
+            maxlen = Some(
+                threshold
+                    .parse::<usize>()
+                    .map_err(|_| "value is not an integer or out of range".to_string())?,
+            );
+            idx += 1;
+        }
+        let rest = &args[idx.min(args.len())..];
+        if rest.len() < 3 || rest.len().is_multiple_of(2) {
+            return Err("Wrong number of arguments for XADD".to_string());
+        }
+        let id = rest[0].clone();
         let mut fields = Vec::new();
-        for i in (3..args.len()).step_by(2) {
-            fields.push((args[i].clone(), args[i + 1].clone()));
+        for i in (1..rest.len()).step_by(2) {
+            fields.push((rest[i].clone(), rest[i + 1].clone()));
         }
-        Ok(RedisCommand::XADD(key, Some(id), fields))
+        Ok(RedisCommand::XADD(key, Some(id), fields, maxlen))
     }
 
     fn parse_xrange(args: &[String]) -> Result<RedisCommand, String> {
@@ -316,43 +1162,246 @@ impl CommandParser {
         }
 
         let mut block_time: Option<u64> = None;
+        let mut count = None;
         let mut idx = 1;
 
-        // Check for BLOCK option
-        if args[idx].to_uppercase() == "BLOCK" {
-            if args.len() < 6 {
-                return Err("Wrong number of arguments for XREAD with BLOCK".to_string());
+        // COUNT and BLOCK may come in either order before STREAMS
+        while idx < args.len() && !args[idx].eq_ignore_ascii_case("STREAMS") {
+            let value = args.get(idx + 1).ok_or("syntax error".to_string())?;
+            match args[idx].to_uppercase().as_str() {
+                "BLOCK" => {
+                    block_time = Some(
+                        value
+                            .parse::<u64>()
+                            .map_err(|_| "Invalid BLOCK time value".to_string())?,
+                    )
+                }
+                "COUNT" => {
+                    count = Some(
+                        value
+                            .parse::<usize>()
+                            .map_err(|_| "value is not an integer or out of range".to_string())?,
+                    )
+                }
+                _ => return Err("syntax error".to_string()),
             }
-            block_time = Some(
-                args[idx + 1]
-                    .parse::<u64>()
-                    .map_err(|_| "Invalid BLOCK time value".to_string())?,
-            );
             idx += 2;
         }
 
-        if args[idx].to_uppercase() != "STREAMS" {
+        if idx >= args.len() {
             return Err("Expected 'STREAMS' keyword in XREAD".to_string());
         }
 
-        idx += 1;
+        // All the keys come first, followed by one ID per key
+        let streams = &args[idx + 1..];
+        if streams.is_empty() || !streams.len().is_multiple_of(2) {
+            return Err("Unbalanced 'xread' list of streams: for each stream key an ID or '$' must be specified.".to_string());
+        }
+        let (keys, ids) = streams.split_at(streams.len() / 2);
+        let key_id_pairs = keys.iter().cloned().zip(ids.iter().cloned()).collect();
+        Ok(RedisCommand::XREAD(block_time, count, key_id_pairs))
+    }
+
+    fn parse_xlen(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 2 {
+            return Err("Wrong number of arguments for XLEN".to_string());
+        }
+        Ok(RedisCommand::XLEN(args[1].clone()))
+    }
+
+    fn parse_xdel(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 3 {
+            return Err("Wrong number of arguments for XDEL".to_string());
+        }
+        Ok(RedisCommand::XDEL(args[1].clone(), args[2..].to_vec()))
+    }
+
+    fn parse_xtrim(args: &[String]) -> Result<RedisCommand, String> {
+        // XTRIM key MAXLEN|MINID [=|~] threshold, trimming is always exact
+        let threshold_idx = match args.get(3).map(|arg| arg.as_str()) {
+            Some("=") | Some("~") => 4,
+            _ => 3,
+        };
+        if args.len() != threshold_idx + 1 {
+            return Err("Wrong number of arguments for XTRIM".to_string());
+        }
+        let strategy = args[2].to_uppercase();
+        if strategy != "MAXLEN" && strategy != "MINID" {
+            return Err("syntax error".to_string());
+        }
+        Ok(RedisCommand::XTRIM(
+            args[1].clone(),
+            strategy,
+            args[threshold_idx].clone(),
+        ))
+    }
+
+    fn parse_xinfo(args: &[String]) -> Result<RedisCommand, String> {
+        // XINFO CONSUMERS also names the group
+        let expected = if args.len() > 1 && args[1].eq_ignore_ascii_case("CONSUMERS") {
+            4
+        } else {
+            3
+        };
+        if args.len() != expected {
+            return Err("Wrong number of arguments for XINFO".to_string());
+        }
+        Ok(RedisCommand::XINFO(
+            args[1].clone(),
+            args[2].clone(),
+            args.get(3).cloned(),
+        ))
+    }
+
+    // XGROUP CREATE key group id [MKSTREAM] and XGROUP SETID key group id [ENTRIESREAD n]
+    fn parse_xgroup(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() >= 2 && args[1].eq_ignore_ascii_case("SETID") {
+            return Self::parse_xgroup_setid(args);
+        }
+        if args.len() < 2 || !args[1].eq_ignore_ascii_case("CREATE") {
+            return Err("Unsupported XGROUP subcommand".to_string());
+        }
+        let mkstream = match args.len() {
+            5 => false,
+            6 if args[5].eq_ignore_ascii_case("MKSTREAM") => true,
+            6 => return Err("syntax error".to_string()),
+            _ => return Err("Wrong number of arguments for XGROUP CREATE".to_string()),
+        };
+        Ok(RedisCommand::XGROUPCREATE(
+            args[2].clone(),
+            args[3].clone(),
+            args[4].clone(),
+            mkstream,
+        ))
+    }
+
+    fn parse_xgroup_setid(args: &[String]) -> Result<RedisCommand, String> {
+        let entries_read = match args.len() {
+            5 => None,
+            7 if args[5].eq_ignore_ascii_case("ENTRIESREAD") => Some(
+                args[6]
+                    .parse::<u64>()
+                    .map_err(|_| "value is not an integer or out of range".to_string())?,
+            ),
+            7 => return Err("syntax error".to_string()),
+            _ => return Err("Wrong number of arguments for XGROUP SETID".to_string()),
+        };
+        Ok(RedisCommand::XGROUPSETID(
+            args[2].clone(),
+            args[3].clone(),
+            args[4].clone(),
+            entries_read,
+        ))
+    }
 
-        // Remaining arguments should be key-id pairs
-        if (args.len() - idx) % 2 != 0 {
-            return Err("Wrong number of arguments for XREAD key-id pairs".to_string());
+    // XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-ms]
+    // [RETRYCOUNT count] [FORCE] [JUSTID] [LASTID id]
+    fn parse_xclaim(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 6 {
+            return Err("Wrong number of arguments for XCLAIM".to_string());
         }
+        let parse_integer = |value: Option<&String>| {
+            value
+                .ok_or("syntax error".to_string())?
+                .parse::<u64>()
+                .map_err(|_| "value is not an integer or out of range".to_string())
+        };
+        let min_idle_time = parse_integer(args.get(4))?;
 
-        let mut id_idx = (idx + args.len()) / 2;
+        let is_option = |arg: &String| {
+            ["IDLE", "TIME", "RETRYCOUNT", "FORCE", "JUSTID", "LASTID"]
+                .iter()
+                .any(|option| arg.eq_ignore_ascii_case(option))
+        };
+        let mut idx = 5;
+        let mut ids = Vec::new();
+        while idx < args.len() && !is_option(&args[idx]) {
+            ids.push(args[idx].clone());
+            idx += 1;
+        }
+        if ids.is_empty() {
+            return Err("Wrong number of arguments for XCLAIM".to_string());
+        }
 
-        let mut key_id_pairs = Vec::new();
-        while idx < id_idx && id_idx < args.len() {
-            let key = args[idx].clone();
-            let id = args[id_idx].clone();
-            key_id_pairs.push((key, id));
+        let mut options = XClaimOptions::default();
+        while idx < args.len() {
+            match args[idx].to_uppercase().as_str() {
+                "IDLE" => {
+                    options.idle = Some(parse_integer(args.get(idx + 1))?);
+                    idx += 1;
+                }
+                "TIME" => {
+                    options.time = Some(parse_integer(args.get(idx + 1))?);
+                    idx += 1;
+                }
+                "RETRYCOUNT" => {
+                    options.retrycount = Some(parse_integer(args.get(idx + 1))?);
+                    idx += 1;
+                }
+                "LASTID" => {
+                    let id = args.get(idx + 1).ok_or("syntax error".to_string())?;
+                    options.lastid = Some(id.clone());
+                    idx += 1;
+                }
+                "FORCE" => options.force = true,
+                "JUSTID" => options.justid = true,
+                _ => return Err("syntax error".to_string()),
+            }
             idx += 1;
-            id_idx += 1;
         }
-        Ok(RedisCommand::XREAD(block_time, key_id_pairs))
+        Ok(RedisCommand::XCLAIM(
+            args[1].clone(),
+            args[2].clone(),
+            args[3].clone(),
+            min_idle_time,
+            ids,
+            options,
+        ))
+    }
+
+    // XREADGROUP GROUP group consumer [COUNT n] [BLOCK ms] STREAMS key [key ...] id [id ...]
+    fn parse_xreadgroup(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 7 || !args[1].eq_ignore_ascii_case("GROUP") {
+            return Err("Wrong number of arguments for XREADGROUP".to_string());
+        }
+        let group = args[2].clone();
+        let consumer = args[3].clone();
+
+        let mut count = None;
+        let mut block = None;
+        let mut idx = 4;
+        while idx < args.len() && !args[idx].eq_ignore_ascii_case("STREAMS") {
+            let value = args.get(idx + 1).ok_or("syntax error".to_string())?;
+            match args[idx].to_uppercase().as_str() {
+                "COUNT" => {
+                    count = Some(
+                        value
+                            .parse::<usize>()
+                            .map_err(|_| "value is not an integer or out of range".to_string())?,
+                    )
+                }
+                "BLOCK" => {
+                    block = Some(
+                        value
+                            .parse::<u64>()
+                            .map_err(|_| "timeout is not an integer or out of range".to_string())?,
+                    )
+                }
+                _ => return Err("syntax error".to_string()),
+            }
+            idx += 2;
+        }
+
+        // Skip STREAMS, then split the rest into keys and their IDs
+        let rest = args.get(idx + 1..).unwrap_or_default();
+        if rest.is_empty() || !rest.len().is_multiple_of(2) {
+            return Err("Unbalanced 'xreadgroup' list of streams: for each stream key an ID or '>' must be specified.".to_string());
+        }
+        let (keys, ids) = rest.split_at(rest.len() / 2);
+        let streams = keys.iter().cloned().zip(ids.iter().cloned()).collect();
+        Ok(RedisCommand::XREADGROUP(
+            group, consumer, count, block, streams,
+        ))
     }
 
     fn parse_geoadd(args: &[String]) -> Result<RedisCommand, String> {
@@ -393,13 +1442,21 @@ impl CommandParser {
     }
 
     fn parse_config(args: &[String]) -> Result<RedisCommand, String> {
-        if args.len() != 3 {
-            return Err("Wrong number of arguments for CONFIG GET".to_string());
+        if args.len() < 2 {
+            return Err("Wrong number of arguments for CONFIG".to_string());
         }
-        if args[1].to_uppercase() != "GET" {
-            return Err("Unsupported CONFIG subcommand".to_string());
+        let expected = match args[1].to_uppercase().as_str() {
+            "GET" => 3,
+            "SET" => 4,
+            _ => return Err("Unsupported CONFIG subcommand".to_string()),
+        };
+        if args.len() != expected {
+            return Err(format!(
+                "Wrong number of arguments for CONFIG {}",
+                args[1].to_uppercase()
+            ));
         }
-        Ok(RedisCommand::CONFIG(args[1].clone(), args[2].clone()))
+        Ok(RedisCommand::CONFIG(args[1].clone(), args[2..].to_vec()))
     }
 
     fn parse_keys(args: &[String]) -> Result<RedisCommand, String> {
@@ -416,12 +1473,14 @@ impl CommandParser {
         Ok(RedisCommand::KEYS(args[1].clone()))
     }
 
+    // GEOSEARCH key FROMLONLAT lon lat <BYRADIUS radius | BYBOX width height> unit
     fn parse_geosearch(args: &[String]) -> Result<RedisCommand, String> {
-        if args.len() != 8 {
+        if args.len() < 8 {
             return Err("Wrong number of arguments for GEOSEARCH".to_string());
         }
-
-        assert_eq!(args[2].to_uppercase(), "FROMLONLAT");
+        if !args[2].eq_ignore_ascii_case("FROMLONLAT") {
+            return Err("Invalid GEOSEARCH origin, expected FROMLONLAT".to_string());
+        }
 
         let longitude = args[3]
             .parse::<f64>()
@@ -429,25 +1488,69 @@ impl CommandParser {
         let latitude = args[4]
             .parse::<f64>()
             .map_err(|_| "Invalid latitude value".to_string())?;
-        let use_radius = match args[5].to_uppercase().as_str() {
-            "BYRADIUS" => true,
-            "BYBOX" => false,
-            _ => return Err("Invalid GEOSEARCH option, expected RADIUS or BOX".to_string()),
+        let distance = |arg: &String| {
+            arg.parse::<f64>()
+                .ok()
+                .filter(|distance| *distance >= 0.0)
+                .ok_or_else(|| "Invalid distance value".to_string())
         };
-        let distance = args[6]
-            .parse::<f64>()
-            .map_err(|_| "Invalid distance value".to_string())?;
-        let unit = args[7].clone();
+        let (shape, unit) = match (args[5].to_uppercase().as_str(), args.len()) {
+            ("BYRADIUS", 8) => (GeoShape::Radius(distance(&args[6])?), &args[7]),
+            ("BYBOX", 9) => (
+                GeoShape::Box(distance(&args[6])?, distance(&args[7])?),
+                &args[8],
+            ),
+            ("BYRADIUS", _) | ("BYBOX", _) => {
+                return Err("Wrong number of arguments for GEOSEARCH".to_string())
+            }
+            _ => return Err("Invalid GEOSEARCH option, expected BYRADIUS or BYBOX".to_string()),
+        };
+        if !["m", "km", "mi", "ft"].contains(&unit.to_lowercase().as_str()) {
+            return Err("unsupported unit provided. please use M, KM, FT, MI".to_string());
+        }
+
         Ok(RedisCommand::GEOSEARCH(
             args[1].clone(),
             longitude,
             latitude,
-            use_radius,
-            distance,
-            unit,
+            shape,
+            unit.clone(),
         ))
     }
 
+    fn parse_scan(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 2 || !args.len().is_multiple_of(2) {
+            return Err("Wrong number of arguments for SCAN".to_string());
+        }
+        let cursor = args[1]
+            .parse::<usize>()
+            .map_err(|_| "invalid cursor".to_string())?;
+
+        let mut pattern = None;
+        let mut count = 10;
+        for option in args[2..].chunks(2) {
+            match option[0].to_uppercase().as_str() {
+                "MATCH" => pattern = Some(option[1].clone()),
+                "COUNT" => {
+                    count = option[1]
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|&count| count > 0)
+                        .ok_or_else(|| "syntax error".to_string())?;
+                }
+                _ => return Err("syntax error".to_string()),
+            }
+        }
+        Ok(RedisCommand::SCAN(cursor, pattern, count))
+    }
+
+    fn parse_randomkey(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 1 {
+            return Err("Wrong number of arguments for RANDOMKEY".to_string());
+        }
+        Ok(RedisCommand::RANDOMKEY)
+    }
+
     fn parse_info(args: &[String]) -> Result<RedisCommand, String> {
         if args.len() > 2 {
             return Err("Wrong number of arguments for INFO".to_string());
@@ -460,11 +1563,18 @@ impl CommandParser {
         Ok(RedisCommand::INFO(section))
     }
 
+    fn parse_save(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 1 {
+            return Err("Wrong number of arguments for SAVE".to_string());
+        }
+        Ok(RedisCommand::SAVE)
+    }
+
     fn parse_subscribe(args: &[String]) -> Result<RedisCommand, String> {
         if args.len() < 2 {
             return Err("Wrong number of arguments for SUBSCRIBE".to_string());
         }
-        Ok(RedisCommand::SUBSCRIBE(args[1].clone()))
+        Ok(RedisCommand::SUBSCRIBE(args[1..].to_vec()))
     }
 
     fn parse_publish(args: &[String]) -> Result<RedisCommand, String> {
@@ -475,10 +1585,18 @@ impl CommandParser {
     }
 
     fn parse_unsubscribe(args: &[String]) -> Result<RedisCommand, String> {
+        Ok(RedisCommand::UNSUBSCRIBE(args[1..].to_vec()))
+    }
+
+    fn parse_psubscribe(args: &[String]) -> Result<RedisCommand, String> {
         if args.len() < 2 {
-            return Err("Wrong number of arguments for UNSUBSCRIBE".to_string());
+            return Err("Wrong number of arguments for PSUBSCRIBE".to_string());
         }
-        Ok(RedisCommand::UNSUBSCRIBE(args[1].clone()))
+        Ok(RedisCommand::PSUBSCRIBE(args[1..].to_vec()))
+    }
+
+    fn parse_punsubscribe(args: &[String]) -> Result<RedisCommand, String> {
+        Ok(RedisCommand::PUNSUBSCRIBE(args[1..].to_vec()))
     }
 
     fn parse_replconf(args: &[String]) -> Result<RedisCommand, String> {
@@ -494,4 +1612,217 @@ impl CommandParser {
         }
         Ok(RedisCommand::PSYNC(args[1].clone(), args[2].clone()))
     }
+
+    // FAILOVER [TO host port [FORCE]] [ABORT] [TIMEOUT milliseconds]
+    fn parse_failover(args: &[String]) -> Result<RedisCommand, String> {
+        let mut abort = false;
+        let mut i = 1;
+        while i < args.len() {
+            match args[i].to_ascii_uppercase().as_str() {
+                "ABORT" => {
+                    abort = true;
+                    i += 1;
+                }
+                "TO" if i + 2 < args.len() => {
+                    args[i + 2]
+                        .parse::<u16>()
+                        .map_err(|_| "Invalid port".to_string())?;
+                    i += 3;
+                }
+                "FORCE" => i += 1,
+                "TIMEOUT" if i + 1 < args.len() => {
+                    args[i + 1]
+                        .parse::<u64>()
+                        .map_err(|_| "FAILOVER timeout must be greater than 0".to_string())?;
+                    i += 2;
+                }
+                _ => return Err("syntax error".to_string()),
+            }
+        }
+        // ABORT cancels a failover and takes no other options
+        if abort && args.len() > 2 {
+            return Err("syntax error".to_string());
+        }
+        Ok(RedisCommand::FAILOVER(abort))
+    }
+
+    fn parse_wait(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() != 3 {
+            return Err("Wrong number of arguments for WAIT".to_string());
+        }
+        let numreplicas = args[1]
+            .parse::<usize>()
+            .map_err(|_| "value is not an integer or out of range".to_string())?;
+        let timeout = args[2]
+            .parse::<u64>()
+            .map_err(|_| "timeout is not an integer or out of range".to_string())?;
+        Ok(RedisCommand::WAIT(numreplicas, timeout))
+    }
+
+    // Subcommand arity is checked here rather than when the subcommand runs,
+    // so a malformed CLIENT inside MULTI is refused as it is queued
+    fn parse_client(args: &[String]) -> Result<RedisCommand, String> {
+        if args.len() < 2 {
+            return Err("Wrong number of arguments for CLIENT".to_string());
+        }
+        let subcommand = args[1].to_ascii_uppercase();
+        let rest = &args[2..];
+        let arity_ok = match subcommand.as_str() {
+            "ID" | "INFO" | "UNPAUSE" => rest.is_empty(),
+            "PAUSE" => (1..=2).contains(&rest.len()),
+            "KILL" => !rest.is_empty(),
+            "NO-EVICT" | "NO-TOUCH" => rest.len() == 1,
+            _ => return Err(format!("Unknown subcommand '{}' for CLIENT", args[1])),
+        };
+        if !arity_ok {
+            return Err(format!(
+                "Wrong number of arguments for CLIENT {}",
+                subcommand
+            ));
+        }
+        Ok(RedisCommand::CLIENT(subcommand, rest.to_vec()))
+    }
+
+    // Only the protocol version is supported, there is no AUTH or SETNAME
+    fn parse_hello(args: &[String]) -> Result<RedisCommand, String> {
+        match args.len() {
+            1 => Ok(RedisCommand::HELLO(None)),
+            2 => args[1]
+                .parse::<u8>()
+                .map(|version| RedisCommand::HELLO(Some(version)))
+                .map_err(|_| "Protocol version is not an integer or out of range".to_string()),
+            _ => Err("Unsupported HELLO option".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &[&str]) -> Vec<String> {
+        command.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_disabled_command_is_unknown() {
+        let renames = HashMap::from([
+            ("FLUSHALL".to_string(), String::new()),
+            ("GET".to_string(), String::new()),
+        ]);
+        assert_eq!(
+            CommandParser::parse_with_renames(args(&["get", "k"]), &renames),
+            Err("Unknown command: GET".to_string())
+        );
+        assert!(CommandParser::parse_with_renames(args(&["SET", "k", "v"]), &renames).is_ok());
+    }
+
+    #[test]
+    fn test_renamed_command_only_answers_to_new_name() {
+        let renames = HashMap::from([("CONFIG".to_string(), "s3cr3t".to_string())]);
+        assert_eq!(
+            CommandParser::parse_with_renames(args(&["S3CR3T", "GET", "dir"]), &renames),
+            Ok(RedisCommand::CONFIG("GET".to_string(), args(&["dir"])))
+        );
+        assert!(
+            CommandParser::parse_with_renames(args(&["CONFIG", "GET", "dir"]), &renames).is_err()
+        );
+    }
+
+    #[test]
+    fn test_client_subcommand_arity() {
+        assert_eq!(
+            CommandParser::parse(args(&["client", "pause", "100", "write"])),
+            Ok(RedisCommand::CLIENT(
+                "PAUSE".to_string(),
+                args(&["100", "write"])
+            ))
+        );
+        for command in [
+            &["CLIENT", "PAUSE"][..],
+            &["CLIENT", "PAUSE", "100", "WRITE", "ALL"],
+            &["CLIENT", "ID", "1"],
+            &["CLIENT", "UNPAUSE", "now"],
+            &["CLIENT", "KILL"],
+            &["CLIENT", "NO-EVICT"],
+            &["CLIENT", "NOSUCH"],
+        ] {
+            assert!(
+                CommandParser::parse(args(command)).is_err(),
+                "{:?}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn test_zadd_rejects_conflicting_flags() {
+        for flags in [
+            &["NX", "XX"][..],
+            &["GT", "LT"],
+            &["NX", "GT"],
+            &["lt", "nx"],
+        ] {
+            let command = [&["ZADD", "z"][..], flags, &["1", "a"]].concat();
+            assert!(CommandParser::parse(args(&command)).is_err(), "{:?}", flags);
+        }
+        assert_eq!(
+            CommandParser::parse(args(&["ZADD", "z", "XX", "GT", "CH", "INCR", "1", "a"])),
+            Ok(RedisCommand::ZADD(
+                "z".to_string(),
+                1.0,
+                "a".to_string(),
+                ZAddOptions {
+                    ch: true,
+                    xx: true,
+                    gt: true,
+                    incr: true,
+                    ..ZAddOptions::default()
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_xread_pairs_each_key_with_its_id() {
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(key, id)| (key.to_string(), id.to_string()))
+                .collect()
+        };
+        assert_eq!(
+            CommandParser::parse(args(&["XREAD", "STREAMS", "s1", "0"])),
+            Ok(RedisCommand::XREAD(None, None, pairs(&[("s1", "0")])))
+        );
+        assert_eq!(
+            CommandParser::parse(args(&["XREAD", "STREAMS", "s1", "s2", "1-0", "$"])),
+            Ok(RedisCommand::XREAD(
+                None,
+                None,
+                pairs(&[("s1", "1-0"), ("s2", "$")])
+            ))
+        );
+        assert_eq!(
+            CommandParser::parse(args(&[
+                "XREAD", "COUNT", "5", "BLOCK", "10", "STREAMS", "a", "b", "c", "1", "2", "3"
+            ])),
+            Ok(RedisCommand::XREAD(
+                Some(10),
+                Some(5),
+                pairs(&[("a", "1"), ("b", "2"), ("c", "3")])
+            ))
+        );
+        // A key without an ID is rejected rather than paired with the wrong one
+        for command in [
+            &["XREAD", "STREAMS", "s1", "s2", "0"][..],
+            &["XREAD", "COUNT", "1", "STREAMS"],
+        ] {
+            assert!(
+                CommandParser::parse(args(command)).is_err(),
+                "{:?}",
+                command
+            );
+        }
+    }
 }
@@ -6,11 +6,14 @@ pub enum RedisResponse {
     BulkString(Option<String>), // None represents null
     Integer(i64),
     Array(Vec<RedisResponse>),
+    Map(Vec<(RedisResponse, RedisResponse)>), // RESP3 only
     Error(String),
-    Blocked, // Timeout in seconds
+    RawError(String), // Sent without the "ERR" prefix
+    Blocked,          // Timeout in seconds
     Empty,
     NullArray,
-    FileContents(Vec<u8>)
+    FileContents(Vec<u8>),
+    Multiple(Vec<RedisResponse>), // several replies sent back to back
 }
 
 impl RedisResponse {
@@ -27,12 +30,24 @@ impl RedisResponse {
                 }
                 result
             }
+            RedisResponse::Map(pairs) => {
+                let mut result = format!("%{}\r\n", pairs.len());
+                for (key, value) in pairs {
+                    result.push_str(&key.to_resp());
+                    result.push_str(&value.to_resp());
+                }
+                result
+            }
             RedisResponse::Error(e) => format!("-ERR {}\r\n", e),
+            RedisResponse::RawError(e) => format!("-{}\r\n", e),
             RedisResponse::Empty | RedisResponse::Blocked => "".to_string(),
             RedisResponse::NullArray => "*-1\r\n".to_string(),
             RedisResponse::FileContents(contents) => {
                 format!("${}\r\n", contents.len())
             }
+            RedisResponse::Multiple(replies) => {
+                replies.iter().map(|reply| reply.to_resp()).collect()
+            }
         }
     }
 
@@ -52,6 +67,12 @@ impl RedisResponse {
         RedisResponse::Error(msg.to_string())
     }
 
+    pub fn wrong_type() -> Self {
+        RedisResponse::RawError(
+            "WRONGTYPE Operation against a key holding the wrong kind of value".to_string(),
+        )
+    }
+
     pub fn queued() -> Self {
         RedisResponse::SimpleString("QUEUED".to_string())
     }
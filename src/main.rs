@@ -1,10 +1,12 @@
 #![allow(unused_imports)]
-use redis_rs::{server::RedisServer, storage::repl_config::ReplConfig};
+use redis_rs::{
+    server::{logging, RedisServer},
+    storage::repl_config::ReplConfig,
+};
+use std::collections::HashMap;
 use std::io;
 
 fn main() -> io::Result<()> {
-    env_logger::init();
-
     // Parse command line arguments
     let args: Vec<String> = std::env::args().collect();
     let mut port = 6379;
@@ -13,6 +15,12 @@ fn main() -> io::Result<()> {
 
     let mut slave_of_host = None;
     let mut slave_of_port = None;
+    let mut command_renames = HashMap::new();
+    let mut log_level = log::LevelFilter::Info;
+    let mut logfile = None;
+    let mut max_queued_commands = None;
+    let mut proto_default = None;
+    let mut enable_debug_command = None;
 
     let mut i = 1;
     while i < args.len() {
@@ -41,10 +49,53 @@ fn main() -> io::Result<()> {
                 }
                 i += 2;
             }
+            "--rename-command" if i + 2 < args.len() => {
+                // An empty new name disables the command
+                command_renames.insert(args[i + 1].to_uppercase(), args[i + 2].clone());
+                i += 3;
+            }
+            "--loglevel" if i + 1 < args.len() => {
+                match logging::parse_log_level(&args[i + 1]) {
+                    Some(level) => log_level = level,
+                    None => eprintln!("Invalid loglevel: {}", args[i + 1]),
+                }
+                i += 2;
+            }
+            "--logfile" if i + 1 < args.len() => {
+                // An empty path keeps logging on stderr, as in Redis
+                if !args[i + 1].is_empty() {
+                    logfile = Some(args[i + 1].clone());
+                }
+                i += 2;
+            }
+            "--max-queued-commands" if i + 1 < args.len() => {
+                match args[i + 1].parse::<usize>() {
+                    Ok(max) => max_queued_commands = Some(max),
+                    Err(_) => eprintln!("Invalid max-queued-commands: {}", args[i + 1]),
+                }
+                i += 2;
+            }
+            "--proto-default" if i + 1 < args.len() => {
+                match args[i + 1].parse::<u8>() {
+                    Ok(version @ (2 | 3)) => proto_default = Some(version),
+                    _ => eprintln!("Invalid proto-default: {}", args[i + 1]),
+                }
+                i += 2;
+            }
+            "--enable-debug-command" if i + 1 < args.len() => {
+                match args[i + 1].to_lowercase().as_str() {
+                    "yes" => enable_debug_command = Some(true),
+                    "no" => enable_debug_command = Some(false),
+                    _ => eprintln!("Invalid enable-debug-command: {}", args[i + 1]),
+                }
+                i += 2;
+            }
             _ => i += 1,
         }
     }
 
+    logging::init(log_level, logfile.as_deref())?;
+
     let repl_config = if slave_of_host.is_some() && slave_of_port.is_some() {
         let master_port = slave_of_port.unwrap();
         let mut master_host = slave_of_host.unwrap();
@@ -59,5 +110,15 @@ fn main() -> io::Result<()> {
     println!("Starting Redis server on port {}", port);
 
     let mut server = RedisServer::new(dir, dbfilename, repl_config)?;
+    server.set_command_renames(command_renames);
+    if let Some(max) = max_queued_commands {
+        server.set_max_queued_commands(max);
+    }
+    if let Some(version) = proto_default {
+        server.set_default_protocol(version);
+    }
+    if let Some(enabled) = enable_debug_command {
+        server.set_enable_debug_command(enabled);
+    }
     server.run()
 }
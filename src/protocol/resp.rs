@@ -15,6 +15,8 @@ pub struct RespParser {
     expecting_rdb: bool,
     /// Size of RDB data we're expecting (if known)
     expected_rdb_size: Option<usize>,
+    /// The last RDB payload received, until the caller takes it
+    rdb_payload: Option<Vec<u8>>,
 }
 
 impl Default for RespParser {
@@ -26,8 +28,9 @@ impl Default for RespParser {
 impl RespParser {
     pub fn new() -> Self {
         Self {
-            expecting_rdb: true,
+            expecting_rdb: false,
             expected_rdb_size: None,
+            rdb_payload: None,
         }
     }
     
@@ -36,48 +39,63 @@ impl RespParser {
         self.expecting_rdb = true;
         self.expected_rdb_size = size;
     }
+
+    /// Take the RDB payload behind the last __RDB_DATA__ command
+    pub fn take_rdb_payload(&mut self) -> Option<Vec<u8>> {
+        self.rdb_payload.take()
+    }
     
     /// Parse RESP commands from a buffer
     /// Returns (parsed_commands, bytes_consumed)
     pub fn parse_commands(&mut self, buffer: &[u8]) -> (Vec<Vec<String>>, usize) {
         let mut commands = Vec::new();
         let mut pos = 0;
-        
-        // Handle RDB data if we're expecting it
-        if self.expecting_rdb {
-            if let Some((rdb_data, consumed)) = self.try_parse_rdb_data(&buffer[pos..]) {
-                // We got the RDB data, now continue with normal parsing
-                self.expecting_rdb = false;
-                self.expected_rdb_size = None;
-                pos += consumed;
-                
-                // Store RDB data as a special command type
-                commands.push(vec!["__RDB_DATA__".to_string(), 
-                                 format!("({} bytes)", rdb_data.len())]);
-            } else {
-                // Still waiting for complete RDB data
-                return (commands, 0);
-            }
-        }
-        
+
         while pos < buffer.len() {
-            match self.parse_single_command(&buffer[pos..]) {
+            match self.parse_next_command(&buffer[pos..]) {
                 Some((command, consumed)) => {
-                    // Check if this is a FULLRESYNC command that will be followed by RDB data
-                    if command.len() >= 1 && command[0].to_uppercase() == "FULLRESYNC" {
-                        self.expecting_rdb = true;
-                    }
-                    
                     commands.push(command);
                     pos += consumed;
                 }
                 None => break, // Incomplete command, wait for more data
             }
         }
-        
+
         (commands, pos)
     }
-    
+
+    /// Parse the first complete command at the start of the buffer
+    /// Returns (command_args, bytes_consumed), or None if more data is needed
+    pub fn parse_next_command(&mut self, buffer: &[u8]) -> Option<(Vec<String>, usize)> {
+        // Handle RDB data if we're expecting it
+        if self.expecting_rdb {
+            // Still waiting for complete RDB data if this fails
+            let (rdb_data, consumed) = self.try_parse_rdb_data(buffer)?;
+
+            // We got the RDB data, now continue with normal parsing
+            self.expecting_rdb = false;
+            self.expected_rdb_size = None;
+
+            // Store RDB data as a special command type
+            let summary = format!("({} bytes)", rdb_data.len());
+            self.rdb_payload = Some(rdb_data);
+            return Some((vec!["__RDB_DATA__".to_string(), summary], consumed));
+        }
+
+        if buffer.is_empty() {
+            return None;
+        }
+
+        let (command, consumed) = self.parse_single_command(buffer)?;
+
+        // Check if this is a FULLRESYNC command that will be followed by RDB data
+        if !command.is_empty() && command[0].to_uppercase() == "FULLRESYNC" {
+            self.expecting_rdb = true;
+        }
+
+        Some((command, consumed))
+    }
+
     /// Try to parse RDB data
     fn try_parse_rdb_data(&self, buffer: &[u8]) -> Option<(Vec<u8>, usize)> {
         if buffer.is_empty() {
@@ -203,11 +221,12 @@ impl RespParser {
         let string = String::from_utf8_lossy(string_data).to_string();
         pos += length;
         
-        // Skip \r\n (bulk strings should always end with \r\n)
-        if pos + 1 < buffer.len() && buffer[pos] == b'\r' && buffer[pos + 1] == b'\n' {
+        // Skip \r\n (bulk strings should always end with \r\n), which may be
+        // the last two bytes of the buffer
+        if &buffer[pos..pos + 2] == b"\r\n" {
             pos += 2;
         } else {
-            // This might be malformed or incomplete
+            // The length doesn't match the data
             return None;
         }
         
@@ -299,4 +318,36 @@ impl RespParser {
         
         None // Incomplete line
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bulk_string_ending_at_the_buffer_boundary() {
+        assert_eq!(
+            RespParser::parse_bulk_string_value(b"$3\r\nfoo\r\n"),
+            Some((Some("foo".to_string()), 9))
+        );
+        // Missing either byte of the terminator is still incomplete
+        assert_eq!(RespParser::parse_bulk_string_value(b"$3\r\nfoo\r"), None);
+        assert_eq!(RespParser::parse_bulk_string_value(b"$3\r\nfoo"), None);
+
+        let command = b"*2\r\n$4\r\nECHO\r\n$3\r\nfoo\r\n";
+        let (commands, consumed) = RespParser::new().parse_commands(command);
+        assert_eq!(commands, vec![vec!["ECHO".to_string(), "foo".to_string()]]);
+        assert_eq!(consumed, command.len());
+    }
+
+    #[test]
+    fn test_new_parser_reads_an_inline_command_first() {
+        // Only a replica expects an RDB payload, and only after FULLRESYNC
+        let mut parser = RespParser::new();
+        assert_eq!(
+            parser.parse_next_command(b"PING\r\n"),
+            Some((vec!["PING".to_string()], 6))
+        );
+        assert_eq!(parser.take_rdb_payload(), None);
+    }
+}
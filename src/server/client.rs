@@ -1,6 +1,8 @@
+use crate::protocol::RespParser;
 use crate::RedisCommand;
 use mio::{net::TcpStream, Token};
 use std::io::{self, ErrorKind, Read, Write};
+use std::net::SocketAddr;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ClientState {
@@ -13,16 +15,21 @@ pub enum ClientState {
 pub struct Client {
     pub socket: TcpStream,
     pub token: Token,
+    pub addr: Option<SocketAddr>,
     pub read_buffer: Vec<u8>,
     pub write_buffer: Vec<u8>,
     pub write_pos: usize,
     pub state: ClientState,
     pub execution_queue: Vec<RedisCommand>,
+    pub multi_dirty: bool, // a command was refused while queueing, EXEC aborts
+    pub parser: RespParser, // Per connection, so RDB transfer state stays with the master link
+    pub close_after_write: bool,
 }
 
 impl Client {
     pub fn new(socket: TcpStream, token: Token) -> Self {
         Self {
+            addr: socket.peer_addr().ok(),
             socket,
             token,
             read_buffer: Vec::with_capacity(4096),
@@ -30,11 +37,15 @@ impl Client {
             write_pos: 0,
             state: ClientState::Reading,
             execution_queue: Vec::new(),
+            multi_dirty: false,
+            parser: RespParser::new(),
+            close_after_write: false,
         }
     }
 
     pub fn read_data(&mut self) -> io::Result<usize> {
-        if self.state != ClientState::Reading {
+        // Blocked clients keep buffering input; it is parsed once they are unblocked
+        if self.state != ClientState::Reading && self.state != ClientState::Blocked {
             return Ok(0);
         }
 
@@ -91,7 +102,11 @@ impl Client {
                     if self.write_pos >= self.write_buffer.len() {
                         // Finished writing all data
                         self.clear_write_buffer();
-                        self.state = ClientState::Reading;
+                        self.state = if self.close_after_write {
+                            ClientState::Closed
+                        } else {
+                            ClientState::Reading
+                        };
                         break;
                     }
                 }
@@ -149,4 +164,13 @@ impl Client {
     pub fn is_blocked(&self) -> bool {
         matches!(self.state, ClientState::Blocked)
     }
+
+    // Single-line description of the connection, as returned by CLIENT INFO
+    pub fn info(&self, subscriptions: usize) -> String {
+        let addr = self.addr.map(|addr| addr.to_string()).unwrap_or_default();
+        format!(
+            "id={} addr={} name= db=0 sub={}\n",
+            self.token.0, addr, subscriptions
+        )
+    }
 }
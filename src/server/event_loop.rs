@@ -1,8 +1,7 @@
 use super::client::Client;
 use super::event_loop_handle::{EventLoopHandle, EventLoopMessage};
 use crate::commands::executor::Transactions;
-use crate::commands::{CommandExecutor, CommandParser, RedisCommandExecutor};
-use crate::protocol::RespParser;
+use crate::commands::{CommandExecutor, CommandParser, RedisCommand, RedisCommandExecutor};
 use crate::storage::comm_utils::CommunicationUtils;
 use crate::storage::repl_config::ReplConfig;
 use crate::RedisResponse;
@@ -17,6 +16,61 @@ const SERVER_TOKEN: Token = Token(0);
 const WAKER_TOKEN: Token = Token(usize::MAX);
 const MASTER_TOKEN: Token = Token(usize::MAX - 1); // Reserved token for master connection
 
+// Commands a single MULTI may queue before the transaction is refused
+const DEFAULT_MAX_QUEUED_COMMANDS: usize = 100_000;
+// How often a replica reports its offset to the master unprompted
+const DEFAULT_REPLICA_ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+// CLIENT PAUSE: commands from clients are held back until `until`
+#[derive(Debug, Clone, Copy)]
+struct ClientPause {
+    until: Instant,
+    writes_only: bool, // WRITE mode, reads still go through
+}
+
+// Replica side of the sync handshake. Each step has sent its command and
+// waits for the master's reply before the next one goes out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MasterHandshake {
+    Ping,
+    ListeningPort,
+    Capabilities,
+    Psync,
+    Done, // FULLRESYNC received, the snapshot and the write stream follow
+}
+
+impl MasterHandshake {
+    fn next(self) -> Self {
+        match self {
+            MasterHandshake::Ping => MasterHandshake::ListeningPort,
+            MasterHandshake::ListeningPort => MasterHandshake::Capabilities,
+            MasterHandshake::Capabilities => MasterHandshake::Psync,
+            MasterHandshake::Psync | MasterHandshake::Done => MasterHandshake::Done,
+        }
+    }
+
+    fn command(self, listening_port: u16) -> Option<RedisResponse> {
+        let args = match self {
+            MasterHandshake::Ping => vec!["PING".to_string()],
+            MasterHandshake::ListeningPort => vec![
+                "REPLCONF".to_string(),
+                "listening-port".to_string(),
+                listening_port.to_string(),
+            ],
+            MasterHandshake::Capabilities => {
+                vec!["REPLCONF".into(), "capa".into(), "psync2".into()]
+            }
+            MasterHandshake::Psync => vec!["PSYNC".into(), "?".into(), "-1".into()],
+            MasterHandshake::Done => return None,
+        };
+        Some(RedisResponse::Array(
+            args.into_iter()
+                .map(|arg| RedisResponse::BulkString(Some(arg)))
+                .collect(),
+        ))
+    }
+}
+
 pub struct EventLoop {
     poll: Poll,
     events: Events,
@@ -37,9 +91,21 @@ pub struct EventLoop {
 
     // Multi operation tracking
     multi_clients: HashSet<Token>,
+    max_queued_commands: usize,
+
+    // rename-command mappings, original name -> new name ("" disables)
+    command_renames: HashMap<String, String>,
+
+    // Clients with commands held back by CLIENT PAUSE, resumed when it ends
+    client_pause: Option<ClientPause>,
+    paused_clients: HashSet<Token>,
 
-    // Parser
-    resp_parser: RespParser,
+    // Periodic REPLCONF ACK over the master link, only set on a replica
+    replica_ack_interval: Duration,
+    next_replica_ack: Option<Instant>,
+
+    // How far the master link has got, only set on a replica
+    master_handshake: Option<MasterHandshake>,
 }
 
 impl EventLoop {
@@ -85,18 +151,21 @@ impl EventLoop {
             RedisCommandExecutor::new(handle.clone(), repl_config.clone())
         };
 
-        // If we are a slave, attempt initial connection to master
+        // If we are a slave, start connecting to the master. The PING goes
+        // out as soon as the connection is writable and the master's reply
+        // to each handshake step triggers the next.
         let mut clients = HashMap::new();
+        let mut master_handshake = None;
         if repl_config.is_slave() {
-            if let Some(mut master_stream) = CommunicationUtils::setup_replication(&repl_config)? {
-                log::info!("Setting up initial master connection");
-
-                // Register master connection for read events
+            if let Some(mut master_stream) = CommunicationUtils::connect_to_master(&repl_config)? {
                 poll.registry()
-                    .register(&mut master_stream, MASTER_TOKEN, Interest::READABLE)?;
+                    .register(&mut master_stream, MASTER_TOKEN, Interest::WRITABLE)?;
 
-                let master_client = Client::new(master_stream, MASTER_TOKEN);
+                let mut master_client = Client::new(master_stream, MASTER_TOKEN);
+                let ping = MasterHandshake::Ping.command(0).unwrap();
+                master_client.add_response(ping.to_resp());
                 clients.insert(MASTER_TOKEN, master_client);
+                master_handshake = Some(MasterHandshake::Ping);
             } else {
                 log::warn!("Could not connect to master during startup");
             }
@@ -114,10 +183,39 @@ impl EventLoop {
             event_loop_handle: handle,
             blocked_clients_timeout: HashMap::new(),
             multi_clients: HashSet::new(),
-            resp_parser: RespParser::new(),
+            max_queued_commands: DEFAULT_MAX_QUEUED_COMMANDS,
+            command_renames: HashMap::new(),
+            client_pause: None,
+            paused_clients: HashSet::new(),
+            replica_ack_interval: DEFAULT_REPLICA_ACK_INTERVAL,
+            next_replica_ack: None,
+            master_handshake,
         })
     }
 
+    pub fn set_command_renames(&mut self, renames: HashMap<String, String>) {
+        self.command_renames = renames;
+    }
+
+    pub fn set_max_queued_commands(&mut self, max: usize) {
+        self.max_queued_commands = max;
+    }
+
+    pub fn set_default_protocol(&mut self, version: u8) {
+        self.command_executor.set_default_protocol(version);
+    }
+
+    pub fn set_enable_debug_command(&mut self, enabled: bool) {
+        self.command_executor.set_enable_debug_command(enabled);
+    }
+
+    pub fn set_replica_ack_interval(&mut self, interval: Duration) {
+        self.replica_ack_interval = interval;
+        if let Some(next) = self.next_replica_ack.as_mut() {
+            *next = Instant::now() + interval;
+        }
+    }
+
     pub fn get_handle(&self) -> EventLoopHandle {
         self.event_loop_handle.clone()
     }
@@ -134,6 +232,8 @@ impl EventLoop {
 
             // Check for timed out blocked clients first
             self.handle_blocked_client_timeouts()?;
+            self.send_replica_ack_if_due()?;
+            self.resume_paused_clients_if_due()?;
 
             // Process messages from other modules
             self.process_messages()?;
@@ -165,8 +265,9 @@ impl EventLoop {
                         continue;
                     }
                     token => {
+                        // The client may have been closed earlier in this batch
                         if !self.clients.contains_key(&token) {
-                            return Ok(());
+                            continue;
                         }
 
                         let mut should_close = false;
@@ -219,8 +320,7 @@ impl EventLoop {
         loop {
             match self.server.accept() {
                 Ok((socket, addr)) => {
-                    let token = Token(self.next_token);
-                    self.next_token += 1;
+                    let token = self.allocate_token();
 
                     log::info!("New client connection from {} with token {}", addr, token.0);
 
@@ -248,6 +348,19 @@ impl EventLoop {
         Ok(())
     }
 
+    // Hand out the next client token, skipping the reserved server, waker and
+    // master tokens and, once the counter has wrapped, tokens still in use
+    fn allocate_token(&mut self) -> Token {
+        loop {
+            let token = Token(self.next_token);
+            self.next_token = self.next_token.wrapping_add(1);
+            let reserved = [SERVER_TOKEN, WAKER_TOKEN, MASTER_TOKEN].contains(&token);
+            if !reserved && !self.clients.contains_key(&token) {
+                return token;
+            }
+        }
+    }
+
     fn process_messages(&mut self) -> io::Result<()> {
         while let Ok(message) = self.message_receiver.try_recv() {
             match message {
@@ -280,6 +393,22 @@ impl EventLoop {
                         ]),
                     )?;
                 }
+                EventLoopMessage::SendPatternMessage {
+                    token,
+                    pattern,
+                    channel,
+                    message,
+                } => {
+                    self.write_response(
+                        token,
+                        RedisResponse::Array(vec![
+                            RedisResponse::BulkString(Some("pmessage".to_string())),
+                            RedisResponse::BulkString(Some(pattern)),
+                            RedisResponse::BulkString(Some(channel)),
+                            RedisResponse::BulkString(Some(message)),
+                        ]),
+                    )?;
+                }
                 EventLoopMessage::SendFile { token, contents } => {
                     if let Some(client) = self.clients.get_mut(&token) {
                         // Send RESP bulk string: $<length>\r\n<data>\r\n
@@ -321,6 +450,12 @@ impl EventLoop {
             return Ok(());
         }
 
+        // Input from a blocked client stays buffered until it is unblocked,
+        // and from a paused one until the pause ends
+        if self.clients.get(&token).unwrap().is_blocked() || self.paused_clients.contains(&token) {
+            return Ok(());
+        }
+
         // Process any complete commands
         self.process_client_commands(token)?;
 
@@ -346,33 +481,48 @@ impl EventLoop {
     }
 
     fn process_client_commands(&mut self, token: Token) -> io::Result<()> {
-        let commands_and_consumed = {
-            let client = self.clients.get(&token).unwrap();
-            self.resp_parser.parse_commands(&client.read_buffer)
-        };
+        loop {
+            let parsed = {
+                let client = self.clients.get_mut(&token).unwrap();
+                // Stop at a blocking command, the rest of the input waits for the unblock
+                if client.is_blocked() {
+                    break;
+                }
+                client.parser.parse_next_command(&client.read_buffer)
+            };
+
+            let (command_args, bytes_consumed) = match parsed {
+                Some(parsed) => parsed,
+                None => break, // Incomplete command, wait for more data
+            };
 
-        let (commands, bytes_consumed) = commands_and_consumed;
+            // A paused command stays in the buffer, and everything after it
+            if token != MASTER_TOKEN && self.is_paused(token, &command_args) {
+                self.paused_clients.insert(token);
+                break;
+            }
 
-        if bytes_consumed > 0 {
             // Remove processed bytes from buffer
             let client = self.clients.get_mut(&token).unwrap();
             client.extract_read_data(bytes_consumed);
-        }
 
-        // Process each command
-        for command_args in commands {
             if command_args.is_empty() {
                 continue;
             }
 
+            if token == MASTER_TOKEN {
+                self.process_master_command(command_args, bytes_consumed)?;
+                continue;
+            }
+
             log::debug!(
                 "Processing command from client {}: {:?}",
                 token.0,
                 command_args
             );
 
-            let client = self.clients.get_mut(&token).unwrap();
-            let response = match CommandParser::parse(command_args) {
+            let parsed = CommandParser::parse_with_renames(command_args, &self.command_renames);
+            let response = match parsed {
                 Ok(command) => {
                     if self.multi_clients.contains(&token)
                         && !RedisCommandExecutor::is_transaction_command(
@@ -380,40 +530,299 @@ impl EventLoop {
                             &command,
                         )
                     {
-                        client.execution_queue.push(command);
-                        RedisResponse::queued()
+                        if matches!(command, RedisCommand::WATCH(_)) {
+                            RedisResponse::error("WATCH inside MULTI is not allowed")
+                        } else if client.execution_queue.len() >= self.max_queued_commands {
+                            // Past the cap nothing more is kept and EXEC will abort,
+                            // like Redis refusing commands under memory pressure
+                            client.multi_dirty = true;
+                            RedisResponse::error(&format!(
+                                "Transaction exceeds the limit of {} queued commands",
+                                self.max_queued_commands
+                            ))
+                        } else {
+                            client.execution_queue.push(command);
+                            RedisResponse::queued()
+                        }
                     } else {
-                        self.command_executor.execute(command, token)
+                        self.execute_command(command, token)?
                     }
                 }
-                Err(error) => crate::commands::RedisResponse::error(&error),
+                Err(error) => {
+                    // A command that can't even be queued dooms the transaction
+                    if self.multi_clients.contains(&token) {
+                        client.multi_dirty = true;
+                    }
+                    crate::commands::RedisResponse::error(&error)
+                }
             };
 
-            if token == MASTER_TOKEN {
-                // The command has been executed on the slave, no need to send a response
-                println!("Command executed on slave from master: {}", response);
-                return Ok(());
-            }
+            let client = self.clients.get_mut(&token).unwrap();
 
             // For blocking commands, the executor will handle the blocking via the handle
             // We only send responses for non-blocking commands here
-            if !matches!(response, RedisResponse::Blocked) {
-                client.add_response(response.to_resp());
+            if matches!(response, RedisResponse::Blocked) {
+                client.block();
+                continue;
+            }
 
-                // Switch to write mode if we have data to send
-                if client.has_pending_writes() {
-                    self.poll.registry().reregister(
-                        &mut client.socket,
-                        token,
-                        Interest::WRITABLE,
-                    )?;
+            client.add_response(response.to_resp());
+
+            // Switch to write mode if we have data to send
+            if client.has_pending_writes() {
+                self.poll
+                    .registry()
+                    .reregister(&mut client.socket, token, Interest::WRITABLE)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn process_master_command(
+        &mut self,
+        command_args: Vec<String>,
+        bytes: usize,
+    ) -> io::Result<()> {
+        // The sync handshake and snapshot don't count towards the replication offset
+        if let Some(step) = self
+            .master_handshake
+            .filter(|step| *step != MasterHandshake::Done)
+        {
+            return self.advance_master_handshake(step, command_args);
+        }
+        if command_args[0] == "__RDB_DATA__" {
+            log::debug!("Received {:?} from master", command_args);
+            let payload = self
+                .clients
+                .get_mut(&MASTER_TOKEN)
+                .and_then(|master| master.parser.take_rdb_payload());
+            if let Some(payload) = payload {
+                self.command_executor.load_snapshot(&payload);
+            }
+            return Ok(());
+        }
+
+        match CommandParser::parse(command_args) {
+            Ok(command) => {
+                let response =
+                    self.command_executor
+                        .execute_replicated(command, MASTER_TOKEN, bytes);
+                if let Some(response) = response {
+                    self.write_response(MASTER_TOKEN, response)?;
+                }
+            }
+            Err(error) => log::error!("Failed to parse command from master: {}", error),
+        }
+        Ok(())
+    }
+
+    fn advance_master_handshake(
+        &mut self,
+        step: MasterHandshake,
+        reply: Vec<String>,
+    ) -> io::Result<()> {
+        log::info!("Received {:?} from master during {:?}", reply, step);
+        if step == MasterHandshake::Psync {
+            if reply[0].to_uppercase().starts_with("FULLRESYNC") {
+                // The master follows FULLRESYNC with a snapshot of its dataset
+                if let Some(master) = self.clients.get_mut(&MASTER_TOKEN) {
+                    master.parser.set_expecting_rdb(None);
+                }
+            } else {
+                log::error!("Master refused PSYNC: {:?}", reply);
+            }
+        }
+
+        let next = step.next();
+        self.master_handshake = Some(next);
+        if next == MasterHandshake::Done {
+            self.next_replica_ack = Some(Instant::now() + self.replica_ack_interval);
+            return Ok(());
+        }
+        let port = self.server.local_addr()?.port();
+        match next.command(port) {
+            Some(command) => self.write_response(MASTER_TOKEN, command),
+            None => Ok(()),
+        }
+    }
+
+    fn execute_command(
+        &mut self,
+        command: RedisCommand,
+        token: Token,
+    ) -> io::Result<RedisResponse> {
+        if let RedisCommand::CLIENT(subcommand, args) = &command {
+            if let Some(response) = self.execute_client_command(token, subcommand, args)? {
+                return Ok(response);
+            }
+        }
+        Ok(self.command_executor.execute(command, token))
+    }
+
+    // CLIENT subcommands that need the connection table, others go to the executor
+    fn execute_client_command(
+        &mut self,
+        token: Token,
+        subcommand: &str,
+        args: &[String],
+    ) -> io::Result<Option<RedisResponse>> {
+        let response = match subcommand {
+            "ID" => RedisResponse::Integer(token.0 as i64),
+            "INFO" => {
+                let subscriptions = self.command_executor.subscription_count(token);
+                match self.clients.get(&token) {
+                    Some(client) => RedisResponse::BulkString(Some(client.info(subscriptions))),
+                    None => return Ok(None),
+                }
+            }
+            "KILL" => self.kill_clients(token, args)?,
+            "PAUSE" => self.pause_clients(args),
+            "UNPAUSE" => {
+                // Expiring the pause lets the loop resume clients as usual
+                if let Some(pause) = self.client_pause.as_mut() {
+                    pause.until = Instant::now();
                 }
+                RedisResponse::ok()
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(response))
+    }
+
+    // CLIENT KILL <addr>, or CLIENT KILL [ID <id>] [ADDR <addr>] [SKIPME yes|no]
+    fn kill_clients(&mut self, token: Token, args: &[String]) -> io::Result<RedisResponse> {
+        let matches_addr =
+            |client: &Client, addr: &str| client.addr.is_some_and(|a| a.to_string() == addr);
+
+        if args.len() == 1 {
+            // The legacy form may target the calling connection too
+            let target = self
+                .clients
+                .values()
+                .find(|client| matches_addr(client, &args[0]))
+                .map(|client| client.token);
+            return match target {
+                Some(target) => {
+                    self.kill_client(token, target)?;
+                    Ok(RedisResponse::ok())
+                }
+                None => Ok(RedisResponse::error("No such client")),
+            };
+        }
+
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Ok(RedisResponse::error("syntax error"));
+        }
+
+        let mut id = None;
+        let mut addr = None;
+        let mut skip_me = true;
+        for pair in args.chunks(2) {
+            match pair[0].to_ascii_uppercase().as_str() {
+                "ID" => match pair[1].parse::<usize>() {
+                    Ok(value) => id = Some(value),
+                    Err(_) => {
+                        return Ok(RedisResponse::error("client-id should be greater than 0"))
+                    }
+                },
+                "ADDR" => addr = Some(pair[1].clone()),
+                "SKIPME" => match pair[1].to_ascii_lowercase().as_str() {
+                    "yes" => skip_me = true,
+                    "no" => skip_me = false,
+                    _ => return Ok(RedisResponse::error("syntax error")),
+                },
+                _ => return Ok(RedisResponse::error("syntax error")),
             }
         }
 
+        let targets: Vec<Token> = self
+            .clients
+            .values()
+            .filter(|client| client.token != MASTER_TOKEN)
+            .filter(|client| !(skip_me && client.token == token))
+            .filter(|client| id.is_none_or(|id| client.token.0 == id))
+            .filter(|client| addr.as_ref().is_none_or(|addr| matches_addr(client, addr)))
+            .map(|client| client.token)
+            .collect();
+
+        for &target in &targets {
+            self.kill_client(token, target)?;
+        }
+        Ok(RedisResponse::Integer(targets.len() as i64))
+    }
+
+    // CLIENT PAUSE timeout [WRITE|ALL]
+    fn pause_clients(&mut self, args: &[String]) -> RedisResponse {
+        let Ok(timeout) = args[0].parse::<u64>() else {
+            return RedisResponse::error("timeout is not an integer or out of range");
+        };
+        let writes_only = match args.get(1).map(|mode| mode.to_ascii_uppercase()) {
+            None => false,
+            Some(mode) if mode == "ALL" => false,
+            Some(mode) if mode == "WRITE" => true,
+            Some(_) => return RedisResponse::error("syntax error"),
+        };
+        self.client_pause = Some(ClientPause {
+            until: Instant::now() + Duration::from_millis(timeout),
+            writes_only,
+        });
+        RedisResponse::ok()
+    }
+
+    fn is_paused(&self, token: Token, command_args: &[String]) -> bool {
+        let Some(pause) = self.client_pause else {
+            return false;
+        };
+        if pause.until <= Instant::now() {
+            return false;
+        }
+        let Ok(command) =
+            CommandParser::parse_with_renames(command_args.to_vec(), &self.command_renames)
+        else {
+            return false;
+        };
+        match command {
+            // Left through so the pause can be lifted
+            RedisCommand::CLIENT(_, _) => false,
+            _ if !pause.writes_only => true,
+            // EXEC applies whatever writes were queued, queueing them writes nothing
+            RedisCommand::EXEC => self
+                .clients
+                .get(&token)
+                .is_some_and(|client| client.execution_queue.iter().any(RedisCommand::is_write)),
+            _ if self.multi_clients.contains(&token) => false,
+            command => command.is_write(),
+        }
+    }
+
+    fn resume_paused_clients_if_due(&mut self) -> io::Result<()> {
+        if self
+            .client_pause
+            .is_some_and(|pause| pause.until > Instant::now())
+        {
+            return Ok(());
+        }
+        self.client_pause = None;
+        for token in std::mem::take(&mut self.paused_clients) {
+            if self.clients.contains_key(&token) {
+                self.process_client_commands(token)?;
+            }
+        }
         Ok(())
     }
 
+    fn kill_client(&mut self, token: Token, target: Token) -> io::Result<()> {
+        if target == token {
+            // Let the reply reach the caller before its connection goes away
+            if let Some(client) = self.clients.get_mut(&token) {
+                client.close_after_write = true;
+            }
+            return Ok(());
+        }
+        self.close_client(target)
+    }
+
     fn write_response(&mut self, token: Token, response: RedisResponse) -> io::Result<()> {
         if let Some(client) = self.clients.get_mut(&token) {
             client.add_response(response.to_resp());
@@ -453,7 +862,10 @@ impl EventLoop {
             if client.is_blocked() {
                 client.unblock();
                 self.blocked_clients_timeout.remove(&token);
-                return self.write_response(token, response);
+                self.write_response(token, response)?;
+
+                // Resume with any commands that arrived while the client was blocked
+                return self.process_client_commands(token);
             }
         }
 
@@ -486,7 +898,9 @@ impl EventLoop {
             }
 
             client.execution_queue.clear();
+            client.multi_dirty = false;
             self.multi_clients.remove(&token);
+            self.command_executor.end_watch(token);
             self.write_response(token, RedisResponse::ok())?;
         }
 
@@ -502,11 +916,34 @@ impl EventLoop {
                 );
             }
 
+            let queue: Vec<RedisCommand> = client.execution_queue.drain(..).collect();
+            let watch_broken = self.command_executor.end_watch(token);
+            if std::mem::take(&mut client.multi_dirty) {
+                self.multi_clients.remove(&token);
+                return self.write_response(
+                    token,
+                    RedisResponse::RawError(
+                        "EXECABORT Transaction discarded because of previous errors.".to_string(),
+                    ),
+                );
+            }
+            // A watched key changed since WATCH, so nothing runs
+            if watch_broken {
+                self.multi_clients.remove(&token);
+                return self.write_response(token, RedisResponse::null_array());
+            }
             let mut responses = Vec::new();
-            for command in client.execution_queue.drain(..) {
-                let response = self.command_executor.execute(command, token);
-                responses.push(response);
+            self.command_executor.begin_exec_propagation();
+            for command in queue {
+                match self.execute_command(command, token) {
+                    Ok(response) => responses.push(response),
+                    Err(e) => {
+                        self.command_executor.end_exec_propagation();
+                        return Err(e);
+                    }
+                }
             }
+            self.command_executor.end_exec_propagation();
 
             self.multi_clients.remove(&token);
             self.write_response(token, RedisResponse::Array(responses))?;
@@ -515,13 +952,33 @@ impl EventLoop {
         Ok(())
     }
 
-    fn calculate_poll_timeout(&self) -> Option<Duration> {
-        if self.blocked_clients_timeout.is_empty() {
-            return None;
+    // Lets the master's WAIT see how far this replica has got
+    fn send_replica_ack_if_due(&mut self) -> io::Result<()> {
+        let Some(next) = self.next_replica_ack else {
+            return Ok(());
+        };
+        if next > Instant::now() {
+            return Ok(());
+        }
+        if !self.clients.contains_key(&MASTER_TOKEN) {
+            // The master link is gone, there is nobody left to report to
+            self.next_replica_ack = None;
+            return Ok(());
         }
+        self.next_replica_ack = Some(Instant::now() + self.replica_ack_interval);
+        let ack = self.command_executor.replica_ack();
+        self.write_response(MASTER_TOKEN, ack)
+    }
 
+    fn calculate_poll_timeout(&self) -> Option<Duration> {
         let now = Instant::now();
-        let next_timeout = self.blocked_clients_timeout.values().min().copied()?;
+        let next_timeout = self
+            .blocked_clients_timeout
+            .values()
+            .copied()
+            .chain(self.next_replica_ack)
+            .chain(self.client_pause.map(|pause| pause.until))
+            .min()?;
 
         if next_timeout <= now {
             Some(Duration::from_millis(0))
@@ -545,7 +1002,8 @@ impl EventLoop {
             if let Some(client) = self.clients.get_mut(&token) {
                 if client.is_blocked() {
                     log::debug!("Client {} has timed out", token.0);
-                    self.unblock_client_internal(token, RedisResponse::null_array())?;
+                    let response = self.command_executor.timeout_response(token);
+                    self.unblock_client_internal(token, response)?;
                 }
             }
         }
@@ -554,6 +1012,8 @@ impl EventLoop {
     }
 
     fn close_client(&mut self, token: Token) -> io::Result<()> {
+        self.command_executor.end_watch(token);
+        self.command_executor.forget_client(token);
         if let Some(mut client) = self.clients.remove(&token) {
             log::info!("Closing client connection {}", token.0);
             let _ = self.poll.registry().deregister(&mut client.socket);
@@ -576,3 +1036,1013 @@ impl EventLoop {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::thread;
+
+    fn start_server() -> SocketAddr {
+        start_configured_server(|_| {})
+    }
+
+    // Start a master with settings applied before it accepts connections
+    fn start_configured_server(configure: fn(&mut EventLoop)) -> SocketAddr {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let addr = listener.local_addr().unwrap();
+            let repl_config = ReplConfig::new_master("127.0.0.1".to_string(), addr.port());
+            let mut event_loop = EventLoop::new(listener, repl_config).unwrap();
+            configure(&mut event_loop);
+            sender.send(addr).unwrap();
+            event_loop.run().unwrap();
+        });
+        receiver.recv().unwrap()
+    }
+
+    // Start a replica of `master_addr`; the handshake runs once the loop does
+    fn start_replica(master_addr: SocketAddr) -> SocketAddr {
+        start_configured_replica(master_addr, |_| {})
+    }
+
+    fn start_configured_replica(
+        master_addr: SocketAddr,
+        configure: fn(&mut EventLoop),
+    ) -> SocketAddr {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+            let addr = listener.local_addr().unwrap();
+            let repl_config = ReplConfig::new_slave(
+                "127.0.0.1".to_string(),
+                addr.port(),
+                "127.0.0.1".to_string(),
+                master_addr.port(),
+            );
+            let mut event_loop = EventLoop::new(listener, repl_config).unwrap();
+            configure(&mut event_loop);
+            sender.send(addr).unwrap();
+            event_loop.run().unwrap();
+        });
+        receiver.recv().unwrap()
+    }
+
+    fn connect(addr: SocketAddr) -> TcpStream {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        stream
+    }
+
+    fn encode(args: &[&str]) -> String {
+        let mut command = format!("*{}\r\n", args.len());
+        for arg in args {
+            command.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+        }
+        command
+    }
+
+    // Read until `expected` bytes have arrived. With `expected` 0, collect whatever
+    // arrives until the socket goes quiet for a read timeout.
+    fn read_reply(stream: &mut TcpStream, expected: usize) -> String {
+        let mut reply = Vec::new();
+        let mut buffer = [0u8; 1024];
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while (expected == 0 || reply.len() < expected) && Instant::now() < deadline {
+            match stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => reply.extend_from_slice(&buffer[..n]),
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    if expected == 0 {
+                        break;
+                    }
+                }
+                Err(e) => panic!("read failed: {}", e),
+            }
+        }
+        String::from_utf8(reply).unwrap()
+    }
+
+    #[test]
+    fn test_commands_after_blpop_wait_for_unblock() {
+        let addr = start_server();
+        let mut blocked = connect(addr);
+        let mut pusher = connect(addr);
+
+        let pipeline = encode(&["BLPOP", "queue", "0"]) + &encode(&["PING"]);
+        blocked.write_all(pipeline.as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut blocked, 0), "");
+
+        pusher
+            .write_all(encode(&["RPUSH", "queue", "job"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut pusher, 4), ":1\r\n");
+
+        let expected = "*2\r\n$5\r\nqueue\r\n$3\r\njob\r\n+PONG\r\n";
+        assert_eq!(read_reply(&mut blocked, expected.len()), expected);
+    }
+
+    #[test]
+    fn test_blpop_timeout_replies_with_null_array() {
+        let addr = start_server();
+        let mut client = connect(addr);
+        client
+            .write_all(encode(&["BLPOP", "queue", "0.1"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 5), "*-1\r\n");
+    }
+
+    #[test]
+    fn test_xreadgroup_block_wakes_on_xadd() {
+        let addr = start_server();
+        let mut consumer = connect(addr);
+        let mut producer = connect(addr);
+
+        producer
+            .write_all(encode(&["XGROUP", "CREATE", "jobs", "workers", "$", "MKSTREAM"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut producer, 5), "+OK\r\n");
+
+        let read = encode(&[
+            "XREADGROUP",
+            "GROUP",
+            "workers",
+            "alice",
+            "BLOCK",
+            "0",
+            "STREAMS",
+            "jobs",
+            ">",
+        ]);
+        consumer.write_all(read.as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut consumer, 0), "");
+
+        producer
+            .write_all(encode(&["XADD", "jobs", "1-1", "task", "build"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut producer, 9), "$3\r\n1-1\r\n");
+
+        let expected =
+            "*1\r\n*2\r\n$4\r\njobs\r\n*1\r\n*2\r\n$3\r\n1-1\r\n*2\r\n$4\r\ntask\r\n$5\r\nbuild\r\n";
+        assert_eq!(read_reply(&mut consumer, expected.len()), expected);
+
+        // The delivered entry is now pending for the consumer
+        let history = encode(&[
+            "XREADGROUP",
+            "GROUP",
+            "workers",
+            "alice",
+            "STREAMS",
+            "jobs",
+            "0",
+        ]);
+        consumer.write_all(history.as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut consumer, expected.len()), expected);
+
+        // And the group does not hand it out again
+        let again = encode(&[
+            "XREADGROUP",
+            "GROUP",
+            "workers",
+            "bob",
+            "STREAMS",
+            "jobs",
+            ">",
+        ]);
+        producer.write_all(again.as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut producer, 5), "*-1\r\n");
+    }
+
+    #[test]
+    fn test_xreadgroup_block_on_several_streams_is_served_once() {
+        let addr = start_server();
+        let mut consumer = connect(addr);
+        let mut producer = connect(addr);
+
+        for key in ["m1", "m2"] {
+            let create = encode(&["XGROUP", "CREATE", key, "workers", "$", "MKSTREAM"]);
+            producer.write_all(create.as_bytes()).unwrap();
+            assert_eq!(read_reply(&mut producer, 5), "+OK\r\n");
+        }
+
+        let read = encode(&[
+            "XREADGROUP",
+            "GROUP",
+            "workers",
+            "alice",
+            "BLOCK",
+            "0",
+            "STREAMS",
+            "m1",
+            "m2",
+            ">",
+            ">",
+        ]);
+        consumer.write_all(read.as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut consumer, 0), "");
+
+        producer
+            .write_all(encode(&["XADD", "m1", "1-1", "f", "v"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut producer, 9), "$3\r\n1-1\r\n");
+        let expected =
+            "*1\r\n*2\r\n$2\r\nm1\r\n*1\r\n*2\r\n$3\r\n1-1\r\n*2\r\n$1\r\nf\r\n$1\r\nv\r\n";
+        assert_eq!(read_reply(&mut consumer, expected.len()), expected);
+
+        // The woken client no longer waits on m2, so its entry stays undelivered
+        producer
+            .write_all(encode(&["XADD", "m2", "1-1", "f", "v"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut producer, 9), "$3\r\n1-1\r\n");
+        assert_eq!(read_reply(&mut consumer, 0), "");
+        let again = encode(&[
+            "XREADGROUP",
+            "GROUP",
+            "workers",
+            "bob",
+            "STREAMS",
+            "m2",
+            ">",
+        ]);
+        producer.write_all(again.as_bytes()).unwrap();
+        let expected = expected.replace("m1", "m2");
+        assert_eq!(read_reply(&mut producer, expected.len()), expected);
+    }
+
+    #[test]
+    fn test_exec_aborts_past_queued_command_cap() {
+        let addr = start_configured_server(|event_loop| event_loop.set_max_queued_commands(2));
+        let mut client = connect(addr);
+
+        client.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        for value in ["1", "2"] {
+            client
+                .write_all(encode(&["SET", "k", value]).as_bytes())
+                .unwrap();
+            assert_eq!(read_reply(&mut client, 9), "+QUEUED\r\n");
+        }
+        client
+            .write_all(encode(&["SET", "k", "3"]).as_bytes())
+            .unwrap();
+        assert!(read_reply(&mut client, 1).starts_with("-ERR "));
+
+        client.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+        let abort = "-EXECABORT Transaction discarded because of previous errors.\r\n";
+        assert_eq!(read_reply(&mut client, abort.len()), abort);
+
+        // Nothing ran, and the next transaction starts clean
+        client.write_all(encode(&["GET", "k"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "$-1\r\n");
+        client.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        client
+            .write_all(encode(&["SET", "k", "v"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 9), "+QUEUED\r\n");
+        client.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 9), "*1\r\n+OK\r\n");
+    }
+
+    #[test]
+    fn test_exec_aborts_when_a_watched_key_changes() {
+        let addr = start_server();
+        let mut client = connect(addr);
+        let mut other = connect(addr);
+
+        client
+            .write_all(encode(&["WATCH", "k"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        other
+            .write_all(encode(&["SET", "k", "theirs"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut other, 5), "+OK\r\n");
+
+        client.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        client
+            .write_all(encode(&["SET", "k", "mine"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 9), "+QUEUED\r\n");
+        client.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "*-1\r\n");
+        client.write_all(encode(&["GET", "k"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 12), "$6\r\ntheirs\r\n");
+
+        // EXEC cleared the watch, and UNWATCH drops one that was never used
+        for watch in [&["WATCH", "k"][..], &["UNWATCH"]] {
+            client.write_all(encode(watch).as_bytes()).unwrap();
+            assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        }
+        other
+            .write_all(encode(&["SET", "k", "again"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut other, 5), "+OK\r\n");
+        client.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        client
+            .write_all(encode(&["WATCH", "k"]).as_bytes())
+            .unwrap();
+        assert!(read_reply(&mut client, 1).starts_with("-ERR WATCH inside MULTI"));
+        client
+            .write_all(encode(&["SET", "k", "mine"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 9), "+QUEUED\r\n");
+        client.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 9), "*1\r\n+OK\r\n");
+    }
+
+    #[test]
+    fn test_exec_aborts_when_a_watched_key_is_deleted() {
+        let addr = start_server();
+        let mut client = connect(addr);
+        let mut other = connect(addr);
+
+        client
+            .write_all(encode(&["SET", "k", "v"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        client
+            .write_all(encode(&["WATCH", "k"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        other.write_all(encode(&["DEL", "k"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut other, 4), ":1\r\n");
+
+        client.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        client
+            .write_all(encode(&["SET", "k", "mine"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 9), "+QUEUED\r\n");
+        client.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "*-1\r\n");
+        client
+            .write_all(encode(&["EXISTS", "k"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 4), ":0\r\n");
+    }
+
+    #[test]
+    fn test_exec_runs_when_a_write_leaves_the_watched_key_alone() {
+        let addr = start_server();
+        let mut client = connect(addr);
+        let mut other = connect(addr);
+
+        client
+            .write_all(encode(&["SET", "k", "x"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        // A refused SET NX, a DEL of nothing and a WRONGTYPE push change nothing
+        let writes: [(&[&str], &str); 3] = [
+            (&["SET", "k", "y", "NX"], "$-1\r\n"),
+            (&["DEL", "nokey"], ":0\r\n"),
+            (&["LPUSH", "k", "a"], "-WRONGTYPE"),
+        ];
+        for (write, reply) in writes {
+            let watched = write[1];
+            client
+                .write_all(encode(&["WATCH", watched]).as_bytes())
+                .unwrap();
+            assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+            other.write_all(encode(write).as_bytes()).unwrap();
+            assert!(read_reply(&mut other, reply.len()).starts_with(reply));
+
+            client.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+            assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+            client
+                .write_all(encode(&["EXISTS", watched]).as_bytes())
+                .unwrap();
+            assert_eq!(read_reply(&mut client, 9), "+QUEUED\r\n");
+            client.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+            let exists = if watched == "k" { 1 } else { 0 };
+            assert_eq!(read_reply(&mut client, 8), format!("*1\r\n:{}\r\n", exists));
+        }
+    }
+
+    #[test]
+    fn test_exec_aborts_after_a_command_fails_to_parse() {
+        let addr = start_server();
+        let mut client = connect(addr);
+
+        client.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        client
+            .write_all(encode(&["SET", "k", "v"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 9), "+QUEUED\r\n");
+        client.write_all(encode(&["SET", "k"]).as_bytes()).unwrap();
+        assert!(read_reply(&mut client, 1).starts_with("-ERR "));
+
+        client.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+        let abort = "-EXECABORT Transaction discarded because of previous errors.\r\n";
+        assert_eq!(read_reply(&mut client, abort.len()), abort);
+        client.write_all(encode(&["GET", "k"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "$-1\r\n");
+
+        // Outside a transaction a parse error leaves nothing behind
+        client.write_all(encode(&["SET", "k"]).as_bytes()).unwrap();
+        assert!(read_reply(&mut client, 1).starts_with("-ERR "));
+        client.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        client.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 4), "*0\r\n");
+    }
+
+    #[test]
+    fn test_multi_reports_arity_and_unknown_commands_when_queued() {
+        let addr = start_server();
+        let mut client = connect(addr);
+
+        let malformed = [
+            &["GET", "a", "b"][..],
+            &["NOSUCHCOMMAND"],
+            &["LPUSH", "l"],
+            &["CLIENT", "PAUSE"],
+            &["CLIENT", "NOSUCH"],
+        ];
+        for malformed in malformed {
+            client.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+            assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+            client
+                .write_all(encode(&["INCR", "counter"]).as_bytes())
+                .unwrap();
+            assert_eq!(read_reply(&mut client, 9), "+QUEUED\r\n");
+            // The error arrives right away, not at EXEC
+            client.write_all(encode(malformed).as_bytes()).unwrap();
+            assert!(
+                read_reply(&mut client, 1).starts_with("-ERR "),
+                "{:?}",
+                malformed
+            );
+            client.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+            assert!(read_reply(&mut client, 0).starts_with("-EXECABORT "));
+        }
+        client
+            .write_all(encode(&["GET", "counter"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 5), "$-1\r\n");
+    }
+
+    #[test]
+    fn test_psubscribe_receives_matching_channels() {
+        let addr = start_server();
+        let mut subscriber = connect(addr);
+        let mut publisher = connect(addr);
+
+        subscriber
+            .write_all(encode(&["PSUBSCRIBE", "news.*"]).as_bytes())
+            .unwrap();
+        let confirmation = "*3\r\n$10\r\npsubscribe\r\n$6\r\nnews.*\r\n:1\r\n";
+        assert_eq!(
+            read_reply(&mut subscriber, confirmation.len()),
+            confirmation
+        );
+
+        for (channel, receivers) in [("news.tech", ":1\r\n"), ("weather", ":0\r\n")] {
+            publisher
+                .write_all(encode(&["PUBLISH", channel, "hello"]).as_bytes())
+                .unwrap();
+            assert_eq!(read_reply(&mut publisher, 4), receivers);
+        }
+        let pmessage = "*4\r\n$8\r\npmessage\r\n$6\r\nnews.*\r\n$9\r\nnews.tech\r\n$5\r\nhello\r\n";
+        assert_eq!(read_reply(&mut subscriber, 0), pmessage);
+
+        // A client with only pattern subscriptions is in subscribed mode too
+        subscriber
+            .write_all(encode(&["GET", "k"]).as_bytes())
+            .unwrap();
+        assert!(read_reply(&mut subscriber, 1).starts_with("-ERR Can't execute 'get'"));
+
+        subscriber
+            .write_all(encode(&["PUNSUBSCRIBE", "news.*"]).as_bytes())
+            .unwrap();
+        let confirmation = "*3\r\n$12\r\npunsubscribe\r\n$6\r\nnews.*\r\n:0\r\n";
+        assert_eq!(
+            read_reply(&mut subscriber, confirmation.len()),
+            confirmation
+        );
+        publisher
+            .write_all(encode(&["PUBLISH", "news.tech", "hello"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut publisher, 4), ":0\r\n");
+    }
+
+    #[test]
+    fn test_inline_ping() {
+        let addr = start_server();
+        let mut client = connect(addr);
+
+        client.write_all(b"PING\r\n").unwrap();
+        assert_eq!(read_reply(&mut client, 7), "+PONG\r\n");
+
+        client.write_all(b"PING hello\r\n").unwrap();
+        assert_eq!(read_reply(&mut client, 11), "$5\r\nhello\r\n");
+    }
+
+    #[test]
+    fn test_client_setup_subcommands() {
+        let addr = start_server();
+        let mut client = connect(addr);
+
+        client
+            .write_all(encode(&["CLIENT", "NO-EVICT", "ON"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+
+        client
+            .write_all(encode(&["CLIENT", "INFO"]).as_bytes())
+            .unwrap();
+        let reply = read_reply(&mut client, 1);
+        let local_addr = client.local_addr().unwrap().to_string();
+        assert!(reply.starts_with('$'), "unexpected reply: {}", reply);
+        assert!(reply.contains(&format!("addr={} ", local_addr)));
+        assert!(reply.contains(" sub=0"));
+    }
+
+    #[test]
+    fn test_client_kill_by_id() {
+        let addr = start_server();
+        let mut victim = connect(addr);
+        let mut admin = connect(addr);
+
+        victim
+            .write_all(encode(&["CLIENT", "ID"]).as_bytes())
+            .unwrap();
+        let reply = read_reply(&mut victim, 1);
+        let id = reply.trim_start_matches(':').trim_end();
+
+        admin
+            .write_all(encode(&["CLIENT", "KILL", "ID", id]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut admin, 4), ":1\r\n");
+
+        let mut buffer = [0u8; 16];
+        assert_eq!(victim.read(&mut buffer).unwrap(), 0);
+
+        // The killer's own connection is unaffected
+        admin.write_all(encode(&["PING"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut admin, 7), "+PONG\r\n");
+    }
+
+    // Play the master's side of the sync handshake, then send a snapshot of
+    // an empty dataset. Returns the replica and the master end of its link.
+    fn start_replica_of_mock_master(configure: fn(&mut EventLoop)) -> (SocketAddr, TcpStream) {
+        let master = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let master_addr = master.local_addr().unwrap();
+        let handshake = thread::spawn(move || {
+            let (mut link, _) = master.accept().unwrap();
+            link.set_read_timeout(Some(Duration::from_millis(200)))
+                .unwrap();
+            // PING, REPLCONF listening-port, REPLCONF capa, PSYNC
+            for reply in ["+PONG\r\n", "+OK\r\n", "+OK\r\n", "+FULLRESYNC abc 0\r\n"] {
+                read_reply(&mut link, 1);
+                link.write_all(reply.as_bytes()).unwrap();
+            }
+            link
+        });
+        let replica_addr = start_configured_replica(master_addr, configure);
+        let mut link = handshake.join().unwrap();
+
+        // An empty RDB file: header, EOF marker and a disabled checksum
+        let snapshot = b"REDIS0011\xff\0\0\0\0\0\0\0\0";
+        link.write_all(format!("${}\r\n", snapshot.len()).as_bytes())
+            .unwrap();
+        link.write_all(snapshot).unwrap();
+        (replica_addr, link)
+    }
+
+    #[test]
+    fn test_replica_serves_clients_while_its_master_is_silent() {
+        let master = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let replica_addr = start_replica(master.local_addr().unwrap());
+        let (mut link, _) = master.accept().unwrap();
+        link.set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        let ping = encode(&["PING"]);
+        assert_eq!(read_reply(&mut link, ping.len()), ping);
+
+        // The handshake is parked on the master's reply, not the whole replica
+        let mut client = connect(replica_addr);
+        client.write_all(encode(&["PING"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 7), "+PONG\r\n");
+
+        link.write_all(b"+PONG\r\n").unwrap();
+        let port = replica_addr.port().to_string();
+        let replconf = encode(&["REPLCONF", "listening-port", &port]);
+        assert_eq!(read_reply(&mut link, replconf.len()), replconf);
+    }
+
+    #[test]
+    fn test_replica_applies_master_commands_silently() {
+        let (replica_addr, mut link) = start_replica_of_mock_master(|_| {});
+        let set = encode(&["SET", "k", "v"]);
+        link.write_all(set.as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut link, 0), "");
+
+        let mut client = connect(replica_addr);
+        client.write_all(encode(&["GET", "k"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 7), "$1\r\nv\r\n");
+
+        link.write_all(encode(&["REPLCONF", "GETACK", "*"]).as_bytes())
+            .unwrap();
+        let offset = set.len().to_string();
+        let ack = encode(&["REPLCONF", "ACK", &offset]);
+        assert_eq!(read_reply(&mut link, ack.len()), ack);
+    }
+
+    #[test]
+    fn test_replica_offset_counts_every_propagated_byte() {
+        let (_, mut link) = start_replica_of_mock_master(|_| {});
+        let writes = [
+            encode(&["SET", "a", "1"]),
+            encode(&["RPUSH", "list", "x", "y"]),
+            encode(&["INCR", "a"]),
+            encode(&["DEL", "list"]),
+        ];
+        link.write_all(writes.concat().as_bytes()).unwrap();
+        let getack = encode(&["REPLCONF", "GETACK", "*"]);
+        link.write_all(getack.as_bytes()).unwrap();
+        let offset: usize = writes.iter().map(|write| write.len()).sum();
+        let ack = encode(&["REPLCONF", "ACK", &offset.to_string()]);
+        assert_eq!(read_reply(&mut link, ack.len()), ack);
+
+        // The next ACK includes the previous GETACK
+        link.write_all(getack.as_bytes()).unwrap();
+        let offset = offset + getack.len();
+        let ack = encode(&["REPLCONF", "ACK", &offset.to_string()]);
+        assert_eq!(read_reply(&mut link, ack.len()), ack);
+    }
+
+    #[test]
+    fn test_replica_acks_its_offset_periodically() {
+        let (_, mut link) = start_replica_of_mock_master(|event_loop| {
+            event_loop.set_replica_ack_interval(Duration::from_millis(50))
+        });
+        let ack = |offset: usize| encode(&["REPLCONF", "ACK", &offset.to_string()]);
+        assert_eq!(read_reply(&mut link, ack(0).len()), ack(0));
+
+        let mut offset = 0;
+        for i in 0..3 {
+            let set = encode(&["SET", &format!("key{}", i), "v"]);
+            link.write_all(set.as_bytes()).unwrap();
+            offset += set.len();
+
+            // ACKs already on their way may still carry the previous offset
+            let expected = ack(offset);
+            let deadline = Instant::now() + Duration::from_secs(2);
+            let mut received = String::new();
+            while !received.ends_with(&expected) && Instant::now() < deadline {
+                received.push_str(&read_reply(&mut link, 1));
+            }
+            assert!(received.ends_with(&expected), "got {:?}", received);
+        }
+    }
+
+    #[test]
+    fn test_client_pause_write_defers_writes_only() {
+        let addr = start_server();
+        let mut admin = connect(addr);
+        let mut writer = connect(addr);
+        let mut reader = connect(addr);
+
+        admin
+            .write_all(encode(&["CLIENT", "PAUSE", "500", "WRITE"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut admin, 5), "+OK\r\n");
+
+        let paused_at = Instant::now();
+        writer
+            .write_all(encode(&["SET", "k", "v"]).as_bytes())
+            .unwrap();
+        reader.write_all(encode(&["GET", "k"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut reader, 5), "$-1\r\n");
+
+        assert_eq!(read_reply(&mut writer, 5), "+OK\r\n");
+        assert!(paused_at.elapsed() >= Duration::from_millis(400));
+        reader.write_all(encode(&["GET", "k"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut reader, 7), "$1\r\nv\r\n");
+    }
+
+    #[test]
+    fn test_client_unpause_resumes_clients_early() {
+        let addr = start_server();
+        let mut admin = connect(addr);
+        let mut client = connect(addr);
+
+        admin
+            .write_all(encode(&["CLIENT", "PAUSE", "10000"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut admin, 5), "+OK\r\n");
+        client.write_all(encode(&["PING"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut client, 0), "");
+
+        admin
+            .write_all(encode(&["CLIENT", "UNPAUSE"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut admin, 5), "+OK\r\n");
+        assert_eq!(read_reply(&mut client, 7), "+PONG\r\n");
+    }
+
+    #[test]
+    fn test_client_pause_write_holds_exec_not_queueing() {
+        let addr = start_server();
+        let mut admin = connect(addr);
+        let mut writer = connect(addr);
+
+        admin
+            .write_all(encode(&["CLIENT", "PAUSE", "300", "WRITE"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut admin, 5), "+OK\r\n");
+
+        let paused_at = Instant::now();
+        writer.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut writer, 5), "+OK\r\n");
+        writer
+            .write_all(encode(&["SET", "k", "v"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut writer, 9), "+QUEUED\r\n");
+        assert!(paused_at.elapsed() < Duration::from_millis(200));
+
+        writer.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut writer, 9), "*1\r\n+OK\r\n");
+        assert!(paused_at.elapsed() >= Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_client_unpause_inside_multi_resumes_clients() {
+        let addr = start_server();
+        let mut admin = connect(addr);
+        let mut client = connect(addr);
+
+        admin
+            .write_all(encode(&["CLIENT", "PAUSE", "10000", "WRITE"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut admin, 5), "+OK\r\n");
+        client
+            .write_all(encode(&["SET", "k", "v"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 0), "");
+
+        admin.write_all(encode(&["MULTI"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut admin, 5), "+OK\r\n");
+        admin
+            .write_all(encode(&["CLIENT", "UNPAUSE"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut admin, 9), "+QUEUED\r\n");
+        admin.write_all(encode(&["EXEC"]).as_bytes()).unwrap();
+        assert_eq!(read_reply(&mut admin, 9), "*1\r\n+OK\r\n");
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+    }
+
+    // A field from an INFO reply, polled until `ready` accepts it
+    fn wait_for_info_field(addr: SocketAddr, field: &str, ready: impl Fn(&str) -> bool) -> String {
+        let mut client = connect(addr);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            client
+                .write_all(encode(&["INFO", "replication"]).as_bytes())
+                .unwrap();
+            let info = read_reply(&mut client, 0);
+            let value = info
+                .lines()
+                .find_map(|line| line.strip_prefix(&format!("{}:", field)))
+                .unwrap_or_default()
+                .to_string();
+            if ready(&value) || Instant::now() > deadline {
+                return value;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn test_replica_offset_converges_with_master() {
+        let master_addr = start_server();
+        let replica_addr = start_replica(master_addr);
+        wait_for_info_field(master_addr, "connected_slaves", |value| value == "1");
+
+        let mut client = connect(master_addr);
+        for i in 0..20 {
+            let key = format!("key{}", i);
+            client
+                .write_all(encode(&["SET", &key, "value"]).as_bytes())
+                .unwrap();
+            assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        }
+
+        let master_offset = wait_for_info_field(master_addr, "master_repl_offset", |_| true);
+        assert_ne!(master_offset, "0");
+        let replica_offset = wait_for_info_field(replica_addr, "slave_repl_offset", |value| {
+            value == master_offset
+        });
+        assert_eq!(replica_offset, master_offset);
+
+        let mut replica = connect(replica_addr);
+        for i in 0..20 {
+            let key = format!("key{}", i);
+            replica
+                .write_all(encode(&["GET", &key]).as_bytes())
+                .unwrap();
+            assert_eq!(read_reply(&mut replica, 11), "$5\r\nvalue\r\n");
+        }
+    }
+
+    #[test]
+    fn test_replica_loads_the_masters_existing_keys() {
+        let master_addr = start_server();
+        let mut client = connect(master_addr);
+        for (command, reply) in [
+            (&["SET", "greeting", "hello"][..], "+OK\r\n"),
+            (&["RPUSH", "list", "a", "b"], ":2\r\n"),
+            (&["HSET", "hash", "field", "1"], ":1\r\n"),
+        ] {
+            client.write_all(encode(command).as_bytes()).unwrap();
+            assert_eq!(read_reply(&mut client, reply.len()), reply);
+        }
+
+        let replica_addr = start_replica(master_addr);
+        wait_for_info_field(master_addr, "connected_slaves", |value| value == "1");
+
+        // The snapshot arrives some time after the replica counts as connected
+        let mut replica = connect(replica_addr);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        loop {
+            replica
+                .write_all(encode(&["EXISTS", "greeting"]).as_bytes())
+                .unwrap();
+            if read_reply(&mut replica, 4) == ":1\r\n" || Instant::now() > deadline {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        for (command, reply) in [
+            (&["GET", "greeting"][..], "$5\r\nhello\r\n"),
+            (&["LRANGE", "list", "0", "-1"], "*2\r\n+a\r\n+b\r\n"),
+            (&["HGET", "hash", "field"], "$1\r\n1\r\n"),
+        ] {
+            replica.write_all(encode(command).as_bytes()).unwrap();
+            assert_eq!(
+                read_reply(&mut replica, reply.len()),
+                reply,
+                "{:?}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn test_wait_returns_once_the_replica_acks() {
+        let master_addr = start_server();
+        start_replica(master_addr);
+        wait_for_info_field(master_addr, "connected_slaves", |value| value == "1");
+
+        let mut client = connect(master_addr);
+        client
+            .write_all(encode(&["SET", "k", "v"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+
+        client
+            .set_read_timeout(Some(Duration::from_millis(1500)))
+            .unwrap();
+        let started = Instant::now();
+        client
+            .write_all(encode(&["WAIT", "1", "1000"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 4), ":1\r\n");
+        assert!(started.elapsed() < Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_wait_stops_counting_a_disconnected_replica() {
+        let addr = start_server();
+        let mut replica = connect(addr);
+        replica
+            .write_all(encode(&["REPLCONF", "listening-port", "6380"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut replica, 5), "+OK\r\n");
+        replica
+            .write_all(encode(&["REPLCONF", "ACK", "1000000"]).as_bytes())
+            .unwrap();
+
+        let mut client = connect(addr);
+        client
+            .write_all(encode(&["SET", "k", "v"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 5), "+OK\r\n");
+        client
+            .write_all(encode(&["WAIT", "1", "0"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut client, 4), ":1\r\n");
+
+        // Once the server notices the replica is gone, WAIT times out without it
+        drop(replica);
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut reply = String::new();
+        while Instant::now() < deadline {
+            client
+                .write_all(encode(&["WAIT", "1", "50"]).as_bytes())
+                .unwrap();
+            reply = read_reply(&mut client, 4);
+            if reply == ":0\r\n" {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert_eq!(reply, ":0\r\n");
+    }
+
+    #[test]
+    fn test_connected_slaves_drops_when_a_replica_disconnects() {
+        let addr = start_server();
+        let mut replica = connect(addr);
+        replica
+            .write_all(encode(&["REPLCONF", "listening-port", "6380"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut replica, 5), "+OK\r\n");
+        assert_eq!(
+            wait_for_info_field(addr, "connected_slaves", |value| value == "1"),
+            "1"
+        );
+
+        drop(replica);
+        assert_eq!(
+            wait_for_info_field(addr, "connected_slaves", |value| value == "0"),
+            "0"
+        );
+    }
+
+    #[test]
+    fn test_replica_receives_every_kind_of_write() {
+        let master_addr = start_server();
+        let replica_addr = start_replica(master_addr);
+        wait_for_info_field(master_addr, "connected_slaves", |value| value == "1");
+
+        let mut waiter = connect(master_addr);
+        waiter
+            .write_all(encode(&["BLPOP", "queue", "0"]).as_bytes())
+            .unwrap();
+        assert_eq!(read_reply(&mut waiter, 0), "");
+
+        let mut client = connect(master_addr);
+        for (command, reply) in [
+            (&["RPUSH", "queue", "a", "b"][..], ":2\r\n"),
+            (&["ZADD", "z", "1", "m"], ":1\r\n"),
+            (&["ZINCRBY", "z", "2", "m"], "$1\r\n3\r\n"),
+            (&["ZADD", "z", "5", "n"], ":1\r\n"),
+            (&["ZREM", "z", "n"], ":1\r\n"),
+            (&["RPUSH", "list", "x", "y"], ":2\r\n"),
+            (&["LPOP", "list"], "$1\r\nx\r\n"),
+        ] {
+            client.write_all(encode(command).as_bytes()).unwrap();
+            assert_eq!(read_reply(&mut client, reply.len()), reply, "{:?}", command);
+        }
+        let served = "*2\r\n$5\r\nqueue\r\n$1\r\na\r\n";
+        assert_eq!(read_reply(&mut waiter, served.len()), served);
+
+        let master_offset = wait_for_info_field(master_addr, "master_repl_offset", |_| true);
+        wait_for_info_field(replica_addr, "slave_repl_offset", |value| {
+            value == master_offset
+        });
+        let mut replica = connect(replica_addr);
+        for (command, reply) in [
+            // The element handed to the blocked client is gone there too
+            (&["LLEN", "queue"][..], ":1\r\n"),
+            (&["LINDEX", "queue", "0"], "$1\r\nb\r\n"),
+            (&["ZSCORE", "z", "m"], "$1\r\n3\r\n"),
+            (&["ZCARD", "z"], ":1\r\n"),
+            (&["LLEN", "list"], ":1\r\n"),
+        ] {
+            replica.write_all(encode(command).as_bytes()).unwrap();
+            assert_eq!(
+                read_reply(&mut replica, reply.len()),
+                reply,
+                "{:?}",
+                command
+            );
+        }
+    }
+
+    #[test]
+    fn test_reserved_tokens_are_never_allocated() {
+        let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let repl_config = ReplConfig::new_master("127.0.0.1".to_string(), port);
+        let mut event_loop = EventLoop::new(listener, repl_config).unwrap();
+
+        event_loop.next_token = usize::MAX - 2;
+        assert_eq!(event_loop.allocate_token(), Token(usize::MAX - 2));
+        // MASTER_TOKEN, WAKER_TOKEN and, after wrapping, SERVER_TOKEN are skipped
+        assert_eq!(event_loop.allocate_token(), Token(1));
+        assert_eq!(event_loop.allocate_token(), Token(2));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::RedisResponse;
 use mio::{Token, Waker};
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::{self, Receiver, SendError, Sender};
 
 #[derive(Debug)]
 pub enum EventLoopMessage {
@@ -26,6 +26,12 @@ pub enum EventLoopMessage {
         channel: String,
         message: String,
     },
+    SendPatternMessage {
+        token: Token,
+        pattern: String,
+        channel: String,
+        message: String,
+    },
     SendFile {
         token: Token,
         contents: Vec<u8>,
@@ -38,78 +44,100 @@ pub enum EventLoopMessage {
 
 #[derive(Debug, Clone)]
 pub struct EventLoopHandle {
-    sender: Sender<EventLoopMessage>,
-    waker: std::sync::Arc<Waker>,
+    sender: Option<Sender<EventLoopMessage>>, // None when detached
+    waker: Option<std::sync::Arc<Waker>>,     // None when no event loop is polling
 }
 
 impl EventLoopHandle {
     pub fn new(sender: Sender<EventLoopMessage>, waker: std::sync::Arc<Waker>) -> Self {
-        EventLoopHandle { sender, waker }
+        EventLoopHandle {
+            sender: Some(sender),
+            waker: Some(waker),
+        }
+    }
+
+    // A handle with no event loop behind it, for driving an executor
+    // directly. Whatever it is asked to send is dropped.
+    pub fn detached() -> Self {
+        EventLoopHandle {
+            sender: None,
+            waker: None,
+        }
+    }
+
+    // Like `detached`, but the messages are kept for the caller to inspect
+    pub fn channel() -> (Self, Receiver<EventLoopMessage>) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = EventLoopHandle {
+            sender: Some(sender),
+            waker: None,
+        };
+        (handle, receiver)
+    }
+
+    // Without an event loop to send to, the message is dropped
+    fn send(&self, message: EventLoopMessage) -> Result<(), SendError<EventLoopMessage>> {
+        match &self.sender {
+            Some(sender) => sender.send(message),
+            None => Ok(()),
+        }
+    }
+
+    fn wake(&self) {
+        if let Some(waker) = &self.waker {
+            if let Err(e) = waker.wake() {
+                log::error!("Failed to wake event loop: {}", e);
+            }
+        }
     }
 
     pub fn unblock_client(&self, token: Token, response: RedisResponse) {
-        if let Err(e) = self
-            .sender
-            .send(EventLoopMessage::UnblockClient { token, response })
-        {
+        if let Err(e) = self.send(EventLoopMessage::UnblockClient { token, response }) {
             log::error!("Failed to send UnblockClient message: {}", e);
             return;
         }
 
-        if let Err(e) = self.waker.wake() {
-            log::error!("Failed to wake event loop: {}", e);
-        }
+        self.wake();
     }
 
     pub fn block_client(&self, token: Token, timeout: u64) {
-        if let Err(e) = self
-            .sender
-            .send(EventLoopMessage::BlockClient { token, timeout })
-        {
+        if let Err(e) = self.send(EventLoopMessage::BlockClient { token, timeout }) {
             log::error!("Failed to send BlockClient message: {}", e);
             return;
         }
 
-        if let Err(e) = self.waker.wake() {
-            log::error!("Failed to wake event loop: {}", e);
-        }
+        self.wake();
     }
 
     pub fn execute_queue(&self, token: Token) {
-        if let Err(e) = self.sender.send(EventLoopMessage::ExecuteQueue { token }) {
+        if let Err(e) = self.send(EventLoopMessage::ExecuteQueue { token }) {
             log::error!("Failed to send ExecuteQueue message: {}", e);
             return;
         }
 
-        if let Err(e) = self.waker.wake() {
-            log::error!("Failed to wake event loop: {}", e);
-        }
+        self.wake();
     }
 
     pub fn discard_queue(&self, token: Token) {
-        if let Err(e) = self.sender.send(EventLoopMessage::DiscardQueue { token }) {
+        if let Err(e) = self.send(EventLoopMessage::DiscardQueue { token }) {
             log::error!("Failed to send DiscardQueue message: {}", e);
             return;
         }
 
-        if let Err(e) = self.waker.wake() {
-            log::error!("Failed to wake event loop: {}", e);
-        }
+        self.wake();
     }
 
     pub fn start_multi(&self, token: Token) {
-        if let Err(e) = self.sender.send(EventLoopMessage::StartMulti { token }) {
+        if let Err(e) = self.send(EventLoopMessage::StartMulti { token }) {
             log::error!("Failed to send StartMulti message: {}", e);
             return;
         }
 
-        if let Err(e) = self.waker.wake() {
-            log::error!("Failed to wake event loop: {}", e);
-        }
+        self.wake();
     }
 
     pub fn send_message(&self, token: Token, channel: String, message: String) {
-        if let Err(e) = self.sender.send(EventLoopMessage::SendMessage {
+        if let Err(e) = self.send(EventLoopMessage::SendMessage {
             token,
             channel,
             message,
@@ -118,36 +146,44 @@ impl EventLoopHandle {
             return;
         }
 
-        if let Err(e) = self.waker.wake() {
-            log::error!("Failed to wake event loop: {}", e);
+        self.wake();
+    }
+
+    pub fn send_pattern_message(
+        &self,
+        token: Token,
+        pattern: String,
+        channel: String,
+        message: String,
+    ) {
+        if let Err(e) = self.send(EventLoopMessage::SendPatternMessage {
+            token,
+            pattern,
+            channel,
+            message,
+        }) {
+            log::error!("Failed to send SendPatternMessage message: {}", e);
+            return;
         }
+
+        self.wake();
     }
 
     pub fn send_file(&self, token: Token, contents: Vec<u8>) {
-        if let Err(e) = self
-            .sender
-            .send(EventLoopMessage::SendFile { token, contents })
-        {
+        if let Err(e) = self.send(EventLoopMessage::SendFile { token, contents }) {
             log::error!("Failed to send SendFile message: {}", e);
             return;
         }
 
-        if let Err(e) = self.waker.wake() {
-            log::error!("Failed to wake event loop: {}", e);
-        }
+        self.wake();
     }
 
     pub fn send_command(&self, token: Token, command: RedisResponse) {
-        if let Err(e) = self
-            .sender
-            .send(EventLoopMessage::SendCommand { token, command })
-        {
+        if let Err(e) = self.send(EventLoopMessage::SendCommand { token, command }) {
             log::error!("Failed to send SendCommand message: {}", e);
             return;
         }
 
-        if let Err(e) = self.waker.wake() {
-            log::error!("Failed to wake event loop: {}", e);
-        }
+        self.wake();
     }
 }
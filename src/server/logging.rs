@@ -0,0 +1,47 @@
+use env_logger::{Builder, Target, WriteStyle};
+use log::LevelFilter;
+use std::fs::OpenOptions;
+use std::io;
+
+// Map a Redis `loglevel` name onto the `log` crate's levels
+pub fn parse_log_level(level: &str) -> Option<LevelFilter> {
+    match level.to_lowercase().as_str() {
+        "debug" => Some(LevelFilter::Trace),
+        "verbose" => Some(LevelFilter::Debug),
+        "notice" => Some(LevelFilter::Info),
+        "warning" => Some(LevelFilter::Warn),
+        "nothing" => Some(LevelFilter::Off),
+        _ => None,
+    }
+}
+
+// Install the global logger at `level`, writing to `logfile` when given and to
+// stderr otherwise. RUST_LOG, when set, overrides the configured level.
+pub fn init(level: LevelFilter, logfile: Option<&str>) -> io::Result<()> {
+    let mut builder = Builder::new();
+    builder.filter_level(level).parse_default_env();
+
+    if let Some(path) = logfile {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        builder
+            .target(Target::Pipe(Box::new(file)))
+            .write_style(WriteStyle::Never);
+    }
+
+    builder.try_init().map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_level() {
+        assert_eq!(parse_log_level("debug"), Some(LevelFilter::Trace));
+        assert_eq!(parse_log_level("verbose"), Some(LevelFilter::Debug));
+        assert_eq!(parse_log_level("NOTICE"), Some(LevelFilter::Info));
+        assert_eq!(parse_log_level("warning"), Some(LevelFilter::Warn));
+        assert_eq!(parse_log_level("nothing"), Some(LevelFilter::Off));
+        assert_eq!(parse_log_level("loud"), None);
+    }
+}
@@ -1,9 +1,11 @@
 pub mod client;
 pub mod event_loop;
 pub mod event_loop_handle;
+pub mod logging;
 
 use event_loop::EventLoop;
 use mio::net::TcpListener;
+use std::collections::HashMap;
 use std::io;
 
 use crate::storage::repl_config::ReplConfig;
@@ -35,6 +37,22 @@ impl RedisServer {
         Ok(Self { event_loop })
     }
 
+    pub fn set_command_renames(&mut self, renames: HashMap<String, String>) {
+        self.event_loop.set_command_renames(renames);
+    }
+
+    pub fn set_max_queued_commands(&mut self, max: usize) {
+        self.event_loop.set_max_queued_commands(max);
+    }
+
+    pub fn set_default_protocol(&mut self, version: u8) {
+        self.event_loop.set_default_protocol(version);
+    }
+
+    pub fn set_enable_debug_command(&mut self, enabled: bool) {
+        self.event_loop.set_enable_debug_command(enabled);
+    }
+
     pub fn run(&mut self) -> io::Result<()> {
         log::info!("Starting Redis server event loop");
         self.event_loop.run()
@@ -0,0 +1,62 @@
+// Thresholds below which hashes and sorted sets report the compact "listpack"
+// encoding, settable through CONFIG SET like their Redis counterparts
+#[derive(Debug, Clone)]
+pub struct EncodingConfig {
+    pub hash_max_listpack_entries: usize,
+    pub hash_max_listpack_value: usize,
+    pub zset_max_listpack_entries: usize,
+    pub zset_max_listpack_value: usize,
+}
+
+impl Default for EncodingConfig {
+    fn default() -> Self {
+        EncodingConfig {
+            hash_max_listpack_entries: 128,
+            hash_max_listpack_value: 64,
+            zset_max_listpack_entries: 128,
+            zset_max_listpack_value: 64,
+        }
+    }
+}
+
+impl EncodingConfig {
+    pub const PARAMETERS: [&'static str; 4] = [
+        "hash-max-listpack-entries",
+        "hash-max-listpack-value",
+        "zset-max-listpack-entries",
+        "zset-max-listpack-value",
+    ];
+
+    pub fn get(&self, parameter: &str) -> Option<String> {
+        let value = match parameter {
+            "hash-max-listpack-entries" => self.hash_max_listpack_entries,
+            "hash-max-listpack-value" => self.hash_max_listpack_value,
+            "zset-max-listpack-entries" => self.zset_max_listpack_entries,
+            "zset-max-listpack-value" => self.zset_max_listpack_value,
+            _ => return None,
+        };
+        Some(value.to_string())
+    }
+
+    pub fn set(&mut self, parameter: &str, value: &str) -> Result<(), String> {
+        let field = match parameter {
+            "hash-max-listpack-entries" => &mut self.hash_max_listpack_entries,
+            "hash-max-listpack-value" => &mut self.hash_max_listpack_value,
+            "zset-max-listpack-entries" => &mut self.zset_max_listpack_entries,
+            "zset-max-listpack-value" => &mut self.zset_max_listpack_value,
+            _ => {
+                return Err(format!(
+                    "Unknown option or number of arguments for CONFIG SET - '{}'",
+                    parameter
+                ))
+            }
+        };
+        *field = value.parse().map_err(|_| {
+            format!(
+                "CONFIG SET failed (possibly related to argument '{}') - argument couldn't be parsed into an integer",
+                parameter
+            )
+        })?;
+        Ok(())
+    }
+}
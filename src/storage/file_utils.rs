@@ -1,12 +1,98 @@
 use std::{
+    borrow::Cow,
     collections::HashMap,
     fs::File,
-    io::{BufReader, Read},
-    time::{SystemTime, UNIX_EPOCH},
+    io::{self, BufReader, Read, Write},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
+use crate::storage::hash_member::OrderedHash;
+use crate::storage::stream_member::{
+    Consumer, ConsumerGroup, PendingEntry, Stream, StreamId, StreamMember, EMPTY_STREAM_ID,
+};
+use crate::storage::unit::Implementation;
+use crate::storage::zset_member::ZSetMember;
 use crate::storage::Unit;
 
+// Value type bytes that precede each key in the RDB data section
+const RDB_TYPE_STRING: u8 = 0x00;
+const RDB_TYPE_LIST: u8 = 0x01;
+const RDB_TYPE_SET: u8 = 0x02;
+const RDB_TYPE_ZSET: u8 = 0x03; // scores stored as strings
+const RDB_TYPE_HASH: u8 = 0x04;
+const RDB_TYPE_ZSET_2: u8 = 0x05; // scores stored as binary doubles
+const RDB_TYPE_SET_INTSET: u8 = 0x0B;
+const RDB_TYPE_HASH_LISTPACK: u8 = 0x10;
+const RDB_TYPE_ZSET_LISTPACK: u8 = 0x11;
+const RDB_TYPE_LIST_QUICKLIST_2: u8 = 0x12;
+const RDB_TYPE_SET_LISTPACK: u8 = 0x14;
+const RDB_TYPE_STREAM_LISTPACKS_2: u8 = 0x13;
+const RDB_TYPE_STREAM_LISTPACKS_3: u8 = 0x15; // adds the consumers' active time
+
+// SELECTDB indexes past this are refused, like Redis with its default
+// `databases` setting
+const DATABASES: usize = 16;
+
+// Quicklist nodes hold either a single element or a listpack of them
+const QUICKLIST_NODE_PLAIN: usize = 1;
+
+// Stream entries are stored in listpacks of at most this many, keyed by the
+// ID of their first entry, which the others are stored relative to
+const STREAM_NODE_MAX_ENTRIES: usize = 100;
+const STREAM_ITEM_FLAG_DELETED: i64 = 1;
+const STREAM_ITEM_FLAG_SAMEFIELDS: i64 = 2;
+
+// Reflected form of the Jones polynomial Redis checksums RDB files with
+const CRC64_POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+fn crc64(bytes: &[u8]) -> u64 {
+    let mut crc = 0u64;
+    for &byte in bytes {
+        crc ^= byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ CRC64_POLY
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+// LZF, which Redis compresses longer strings with. Each control byte starts
+// either a run of up to 32 literal bytes or a back reference into the output
+fn lzf_decompress(input: &[u8], len: usize) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        let control = input[pos] as usize;
+        pos += 1;
+        if control < 32 {
+            let literal = input.get(pos..pos + control + 1)?;
+            output.extend_from_slice(literal);
+            pos += control + 1;
+        } else {
+            let mut run = control >> 5;
+            if run == 7 {
+                run += *input.get(pos)? as usize;
+                pos += 1;
+            }
+            let offset = (((control & 0x1F) << 8) | *input.get(pos)? as usize) + 1;
+            pos += 1;
+            // The reference may overlap the bytes it is copying out
+            let start = output.len().checked_sub(offset)?;
+            for i in start..start + run + 2 {
+                output.push(output[i]);
+            }
+        }
+        if output.len() > len {
+            return None;
+        }
+    }
+    (output.len() == len).then_some(output)
+}
+
 #[derive(Debug)]
 enum FileStage {
     Header,
@@ -41,17 +127,15 @@ impl FileUtils {
                 *pos += 1;
                 Some((((first_byte & 0x3F) as usize) << 8) | (next_byte as usize))
             }
+            0b10 if first_byte == 0x81 => {
+                // The length is the next 8 bytes, big-endian
+                let bytes = Self::read_bytes(buffer, pos, 8)?;
+                usize::try_from(u64::from_be_bytes(bytes.try_into().ok()?)).ok()
+            }
             0b10 => {
                 // The length is the next 4 bytes, big-endian
-                if *pos + 4 > buffer.len() {
-                    return None;
-                }
-                let len = ((buffer[*pos] as usize) << 24)
-                    | ((buffer[*pos + 1] as usize) << 16)
-                    | ((buffer[*pos + 2] as usize) << 8)
-                    | (buffer[*pos + 3] as usize);
-                *pos += 4;
-                Some(len)
+                let bytes = Self::read_bytes(buffer, pos, 4)?;
+                Some(u32::from_be_bytes(bytes.try_into().ok()?) as usize)
             }
             0b11 => {
                 // Special string encoding - should not be handled here
@@ -94,24 +178,17 @@ impl FileUtils {
                 }
                 0x01 => {
                     // 16-bit integer (little-endian)
-                    if *pos + 2 > buffer.len() {
-                        return None;
-                    }
-                    let value = (buffer[*pos] as u16) | ((buffer[*pos + 1] as u16) << 8);
-                    *pos += 2;
-                    Some((value as i16).to_string())
+                    let bytes = Self::read_bytes(buffer, pos, 2)?;
+                    Some(i16::from_le_bytes(bytes.try_into().ok()?).to_string())
                 }
                 0x02 => {
                     // 32-bit integer (little-endian)
-                    if *pos + 4 > buffer.len() {
-                        return None;
-                    }
-                    let value = (buffer[*pos] as u32)
-                        | ((buffer[*pos + 1] as u32) << 8)
-                        | ((buffer[*pos + 2] as u32) << 16)
-                        | ((buffer[*pos + 3] as u32) << 24);
-                    *pos += 4;
-                    Some((value as i32).to_string())
+                    let bytes = Self::read_bytes(buffer, pos, 4)?;
+                    Some(i32::from_le_bytes(bytes.try_into().ok()?).to_string())
+                }
+                0x03 => {
+                    let bytes = Self::read_lzf(buffer, pos)?;
+                    Some(String::from_utf8_lossy(&bytes).to_string())
                 }
                 _ => {
                     log::error!(
@@ -125,16 +202,294 @@ impl FileUtils {
             // Regular string with length encoding
             let len = Self::read_length_encoded(buffer, pos)?;
 
-            if *pos + len > buffer.len() {
+            let Some(bytes) = Self::read_bytes(buffer, pos, len) else {
                 log::error!("String length {} exceeds buffer bounds", len);
                 return None;
+            };
+
+            Some(String::from_utf8_lossy(bytes).to_string())
+        }
+    }
+
+    // Read a length-prefixed string as raw bytes, for the packed encodings
+    fn read_blob<'a>(buffer: &'a [u8], pos: &mut usize) -> Option<Cow<'a, [u8]>> {
+        if buffer.get(*pos) == Some(&0xC3) {
+            *pos += 1;
+            return Self::read_lzf(buffer, pos).map(Cow::Owned);
+        }
+        let Some(len) = Self::read_length_encoded(buffer, pos) else {
+            log::error!("Unsupported blob encoding at position {}", pos);
+            return None;
+        };
+        Self::read_bytes(buffer, pos, len).map(Cow::Borrowed)
+    }
+
+    // An LZF compressed string: its compressed length, its plain length, then
+    // the compressed bytes
+    fn read_lzf(buffer: &[u8], pos: &mut usize) -> Option<Vec<u8>> {
+        let compressed_len = Self::read_length_encoded(buffer, pos)?;
+        let len = Self::read_length_encoded(buffer, pos)?;
+        let compressed = Self::read_bytes(buffer, pos, compressed_len)?;
+        lzf_decompress(compressed, len)
+    }
+
+    // Take the next `len` bytes, or None if the buffer ends first. Lengths
+    // come straight from the file, so the end is computed without overflow
+    fn read_bytes<'a>(buffer: &'a [u8], pos: &mut usize, len: usize) -> Option<&'a [u8]> {
+        let bytes = buffer.get(*pos..pos.checked_add(len)?)?;
+        *pos += len;
+        Some(bytes)
+    }
+
+    // Sign-extend a little-endian integer of up to 8 bytes
+    fn read_le_int(bytes: &[u8]) -> i64 {
+        let mut value = 0i64;
+        for (i, &byte) in bytes.iter().enumerate() {
+            value |= (byte as i64) << (i * 8);
+        }
+        let shift = 64 - bytes.len() * 8;
+        (value << shift) >> shift
+    }
+
+    // Every listpack entry is followed by its own length, in 1 to 5 bytes
+    fn listpack_backlen_size(len: usize) -> usize {
+        match len {
+            0..=127 => 1,
+            128..=16382 => 2,
+            16383..=2097150 => 3,
+            2097151..=268435454 => 4,
+            _ => 5,
+        }
+    }
+
+    // A listpack: total bytes (u32), element count (u16), entries, then 0xFF
+    fn read_listpack(blob: &[u8]) -> Option<Vec<String>> {
+        let string = |entry: &[u8], start: usize, len: usize| {
+            entry
+                .get(start..start.checked_add(len)?)
+                .map(|bytes| String::from_utf8_lossy(bytes).to_string())
+        };
+        let mut entries = Vec::new();
+        let mut pos = 6;
+        loop {
+            let entry = blob.get(pos..)?;
+            let first = *entry.first()?;
+            let (value, len) = match first {
+                0xFF => return Some(entries),
+                0x00..=0x7F => (first.to_string(), 1),
+                0x80..=0xBF => {
+                    let len = (first & 0x3F) as usize;
+                    (string(entry, 1, len)?, 1 + len)
+                }
+                0xC0..=0xDF => {
+                    let raw = ((first as i64 & 0x1F) << 8) | *entry.get(1)? as i64;
+                    let value = if raw >= 1 << 12 { raw - (1 << 13) } else { raw };
+                    (value.to_string(), 2)
+                }
+                0xE0..=0xEF => {
+                    let len = (((first & 0x0F) as usize) << 8) | *entry.get(1)? as usize;
+                    (string(entry, 2, len)?, 2 + len)
+                }
+                0xF0 => {
+                    let len = u32::from_le_bytes(entry.get(1..5)?.try_into().ok()?) as usize;
+                    (string(entry, 5, len)?, 5 + len)
+                }
+                0xF1..=0xF4 => {
+                    let width = [2, 3, 4, 8][(first - 0xF1) as usize];
+                    let value = Self::read_le_int(entry.get(1..1 + width)?);
+                    (value.to_string(), 1 + width)
+                }
+                _ => {
+                    log::error!("Unsupported listpack entry encoding: {:#x}", first);
+                    return None;
+                }
+            };
+            entries.push(value);
+            pos += len + Self::listpack_backlen_size(len);
+        }
+    }
+
+    // An intset: integer width (u32), element count (u32), then the integers
+    fn read_intset(blob: &[u8]) -> Option<Vec<String>> {
+        let width = u32::from_le_bytes(blob.get(0..4)?.try_into().ok()?) as usize;
+        let len = u32::from_le_bytes(blob.get(4..8)?.try_into().ok()?) as usize;
+        if !matches!(width, 2 | 4 | 8) {
+            log::error!("Unsupported intset width: {}", width);
+            return None;
+        }
+        (0..len)
+            .map(|i| {
+                let start = 8 + i * width;
+                let bytes = blob.get(start..start + width)?;
+                Some(Self::read_le_int(bytes).to_string())
+            })
+            .collect()
+    }
+
+    // Scores of the original zset type are strings with a one byte length,
+    // where 253 to 255 stand for NaN and the two infinities
+    fn read_zset_score(buffer: &[u8], pos: &mut usize) -> Option<f64> {
+        let len = *buffer.get(*pos)? as usize;
+        *pos += 1;
+        match len {
+            253 => Some(f64::NAN),
+            254 => Some(f64::INFINITY),
+            255 => Some(f64::NEG_INFINITY),
+            _ => {
+                let bytes = Self::read_bytes(buffer, pos, len)?;
+                std::str::from_utf8(bytes).ok()?.parse().ok()
             }
+        }
+    }
 
-            let result = String::from_utf8_lossy(&buffer[*pos..*pos + len]).to_string();
-            *pos += len;
+    // A stream ID stored as 16 raw bytes, milliseconds then sequence
+    fn read_raw_stream_id(buffer: &[u8], pos: &mut usize) -> Option<StreamId> {
+        let bytes = Self::read_bytes(buffer, pos, 16)?;
+        Some(StreamId {
+            timestamp: u64::from_be_bytes(bytes[..8].try_into().ok()?),
+            sequence: u64::from_be_bytes(bytes[8..].try_into().ok()?),
+        })
+    }
+
+    fn read_length_stream_id(buffer: &[u8], pos: &mut usize) -> Option<StreamId> {
+        Some(StreamId {
+            timestamp: Self::read_length_encoded(buffer, pos)? as u64,
+            sequence: Self::read_length_encoded(buffer, pos)? as u64,
+        })
+    }
 
-            Some(result)
+    fn read_millisecond_time(buffer: &[u8], pos: &mut usize) -> Option<i64> {
+        let bytes = Self::read_bytes(buffer, pos, 8)?;
+        Some(i64::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    fn next_number<T: std::str::FromStr>(elements: &mut impl Iterator<Item = String>) -> Option<T> {
+        elements.next()?.parse().ok()
+    }
+
+    // The entries of one stream listpack: a master entry with the node's
+    // count, deleted count and field names, then each entry as flags, ID
+    // deltas and either its values alone (same fields as the master) or its
+    // own field/value pairs, closed by its element count
+    fn read_stream_node(
+        master_id: &StreamId,
+        listpack: &[u8],
+        entries: &mut Vec<StreamMember>,
+    ) -> Option<()> {
+        let mut elements = Self::read_listpack(listpack)?.into_iter();
+        let count: usize = Self::next_number(&mut elements)?;
+        let deleted: usize = Self::next_number(&mut elements)?;
+        let master_fields_count = Self::next_number(&mut elements)?;
+        let master_fields: Vec<String> = elements.by_ref().take(master_fields_count).collect();
+        elements.next()?; // the master entry terminator
+
+        for _ in 0..count + deleted {
+            let flags: i64 = Self::next_number(&mut elements)?;
+            let ms_delta: i64 = Self::next_number(&mut elements)?;
+            let seq_delta: i64 = Self::next_number(&mut elements)?;
+            let fields = if flags & STREAM_ITEM_FLAG_SAMEFIELDS != 0 {
+                master_fields
+                    .iter()
+                    .map(|field| Some((field.clone(), elements.next()?)))
+                    .collect::<Option<Vec<_>>>()?
+            } else {
+                let fields_count = Self::next_number(&mut elements)?;
+                (0..fields_count)
+                    .map(|_| Some((elements.next()?, elements.next()?)))
+                    .collect::<Option<Vec<_>>>()?
+            };
+            elements.next()?; // the entry's element count
+            if flags & STREAM_ITEM_FLAG_DELETED == 0 {
+                entries.push(StreamMember {
+                    id: StreamId {
+                        timestamp: master_id.timestamp.wrapping_add(ms_delta as u64),
+                        sequence: master_id.sequence.wrapping_add(seq_delta as u64),
+                    },
+                    fields,
+                });
+            }
         }
+        Some(())
+    }
+
+    fn read_stream(buffer: &[u8], pos: &mut usize, with_active_time: bool) -> Option<Stream> {
+        let mut stream = Stream::new();
+        let nodes = Self::read_length_encoded(buffer, pos)?;
+        for _ in 0..nodes {
+            let key = Self::read_blob(buffer, pos)?;
+            let master_id = Self::read_raw_stream_id(&key, &mut 0)?;
+            let listpack = Self::read_blob(buffer, pos)?;
+            Self::read_stream_node(&master_id, &listpack, &mut stream.entries)?;
+        }
+
+        Self::read_length_encoded(buffer, pos)?; // length, known from the entries
+        stream.last_id = Self::read_length_stream_id(buffer, pos)?;
+        Self::read_length_stream_id(buffer, pos)?; // first ID, likewise
+        stream.max_deleted_id = Self::read_length_stream_id(buffer, pos)?;
+        stream.entries_added = Self::read_length_encoded(buffer, pos)? as u64;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as i64);
+        let instant_at = |millis: i64| {
+            Instant::now().checked_sub(Duration::from_millis(
+                now.saturating_sub(millis).max(0) as u64
+            ))
+        };
+
+        let groups = Self::read_length_encoded(buffer, pos)?;
+        for _ in 0..groups {
+            let name = Self::read_string_encoded(buffer, pos)?;
+            let last_delivered_id = Self::read_length_stream_id(buffer, pos)?;
+            // Unknown is stored as -1
+            let entries_read = Some(Self::read_length_encoded(buffer, pos)? as u64)
+                .filter(|&entries_read| entries_read != u64::MAX);
+            let mut group = ConsumerGroup::new(last_delivered_id, entries_read);
+
+            let pending = Self::read_length_encoded(buffer, pos)?;
+            for _ in 0..pending {
+                let id = Self::read_raw_stream_id(buffer, pos)?;
+                let delivery_time = Self::read_millisecond_time(buffer, pos)?.max(0) as u64;
+                let delivery_count = Self::read_length_encoded(buffer, pos)? as u64;
+                group.pending.insert(
+                    id,
+                    PendingEntry {
+                        consumer: String::new(),
+                        delivery_count,
+                        delivery_time,
+                    },
+                );
+            }
+
+            // The group's pending entries name their consumer through the
+            // consumers' own lists
+            let consumers = Self::read_length_encoded(buffer, pos)?;
+            for _ in 0..consumers {
+                let consumer = Self::read_string_encoded(buffer, pos)?;
+                let seen_time = Self::read_millisecond_time(buffer, pos)?;
+                let active_time = if with_active_time {
+                    Self::read_millisecond_time(buffer, pos)?
+                } else {
+                    seen_time
+                };
+                let owned = Self::read_length_encoded(buffer, pos)?;
+                for _ in 0..owned {
+                    let id = Self::read_raw_stream_id(buffer, pos)?;
+                    group.pending.get_mut(&id)?.consumer = consumer.clone();
+                }
+                group.consumers.insert(
+                    consumer,
+                    Consumer {
+                        seen_time: instant_at(seen_time).unwrap_or_else(Instant::now),
+                        active_time: (active_time >= 0)
+                            .then(|| instant_at(active_time))
+                            .flatten(),
+                    },
+                );
+            }
+            stream.groups.insert(name, group);
+        }
+        Some(stream)
     }
 
     // Parse a key-value pair with optional expiry
@@ -150,12 +505,102 @@ impl FileUtils {
 
         // Parse value based on the value type
         let value = match value_type {
-            0x00 => {
-                // String type
-                let string_value = Self::read_string_encoded(buffer, pos)?;
-                string_value
+            RDB_TYPE_STRING => Implementation::STRING(Self::read_string_encoded(buffer, pos)?),
+            RDB_TYPE_LIST => {
+                let len = Self::read_length_encoded(buffer, pos)?;
+                let mut list = Vec::new();
+                for _ in 0..len {
+                    list.push(Self::read_string_encoded(buffer, pos)?);
+                }
+                Implementation::LIST(list)
+            }
+            RDB_TYPE_LIST_QUICKLIST_2 => {
+                let nodes = Self::read_length_encoded(buffer, pos)?;
+                let mut list = Vec::new();
+                for _ in 0..nodes {
+                    let container = Self::read_length_encoded(buffer, pos)?;
+                    let blob = Self::read_blob(buffer, pos)?;
+                    if container == QUICKLIST_NODE_PLAIN {
+                        list.push(String::from_utf8_lossy(&blob).to_string());
+                    } else {
+                        list.extend(Self::read_listpack(&blob)?);
+                    }
+                }
+                Implementation::LIST(list)
+            }
+            RDB_TYPE_SET => {
+                let len = Self::read_length_encoded(buffer, pos)?;
+                let mut set = std::collections::HashSet::new();
+                for _ in 0..len {
+                    set.insert(Self::read_string_encoded(buffer, pos)?);
+                }
+                Implementation::SET(set)
+            }
+            RDB_TYPE_SET_INTSET => {
+                let blob = Self::read_blob(buffer, pos)?;
+                Implementation::SET(Self::read_intset(&blob)?.into_iter().collect())
+            }
+            RDB_TYPE_SET_LISTPACK => {
+                let blob = Self::read_blob(buffer, pos)?;
+                Implementation::SET(Self::read_listpack(&blob)?.into_iter().collect())
+            }
+            RDB_TYPE_HASH => {
+                let len = Self::read_length_encoded(buffer, pos)?;
+                let mut hash = OrderedHash::new();
+                for _ in 0..len {
+                    let field = Self::read_string_encoded(buffer, pos)?;
+                    let value = Self::read_string_encoded(buffer, pos)?;
+                    hash.insert(field, value);
+                }
+                Implementation::HASH(hash)
+            }
+            RDB_TYPE_HASH_LISTPACK => {
+                let blob = Self::read_blob(buffer, pos)?;
+                let entries = Self::read_listpack(&blob)?;
+                let hash = entries
+                    .chunks_exact(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect();
+                Implementation::HASH(hash)
+            }
+            RDB_TYPE_ZSET => {
+                let len = Self::read_length_encoded(buffer, pos)?;
+                let mut zset = std::collections::BTreeSet::new();
+                for _ in 0..len {
+                    let member = Self::read_string_encoded(buffer, pos)?;
+                    let score = Self::read_zset_score(buffer, pos)?;
+                    zset.insert(ZSetMember { score, member });
+                }
+                Implementation::ZSET(zset)
+            }
+            RDB_TYPE_ZSET_LISTPACK => {
+                let blob = Self::read_blob(buffer, pos)?;
+                let entries = Self::read_listpack(&blob)?;
+                let mut zset = std::collections::BTreeSet::new();
+                for pair in entries.chunks_exact(2) {
+                    let score = pair[1].parse().ok()?;
+                    zset.insert(ZSetMember {
+                        score,
+                        member: pair[0].clone(),
+                    });
+                }
+                Implementation::ZSET(zset)
+            }
+            RDB_TYPE_ZSET_2 => {
+                let len = Self::read_length_encoded(buffer, pos)?;
+                let mut zset = std::collections::BTreeSet::new();
+                for _ in 0..len {
+                    let member = Self::read_string_encoded(buffer, pos)?;
+                    let bytes = Self::read_bytes(buffer, pos, 8)?;
+                    let score = f64::from_le_bytes(bytes.try_into().ok()?);
+                    zset.insert(ZSetMember { score, member });
+                }
+                Implementation::ZSET(zset)
+            }
+            RDB_TYPE_STREAM_LISTPACKS_2 | RDB_TYPE_STREAM_LISTPACKS_3 => {
+                let with_active_time = value_type == RDB_TYPE_STREAM_LISTPACKS_3;
+                Implementation::STREAM(Self::read_stream(buffer, pos, with_active_time)?)
             }
-            // Add other value types as needed (lists, sets, etc.)
             _ => {
                 log::warn!("Unsupported value type: {:#x}, skipping entry", value_type);
                 return None;
@@ -178,9 +623,16 @@ impl FileUtils {
             }
         }
 
-        // Convert SystemTime to u128 (milliseconds since epoch) for Unit::new_string
+        // Convert SystemTime to u128 (milliseconds since epoch) for the Unit
         let expiry_u128 = expiry_timestamp.map(|ts| ts as u128);
-        db.insert(key.clone(), Unit::new_string(value, expiry_u128));
+        db.insert(
+            key.clone(),
+            Unit {
+                implementation: value,
+                expiry: expiry_u128,
+                last_access: std::time::Instant::now(),
+            },
+        );
         log::debug!(
             "Parsed key-value pair: '{}' with expiry: {:?}",
             key,
@@ -213,11 +665,15 @@ impl FileUtils {
         true
     }
 
-    pub fn construct_db_from_file(dir: &str, dbfilename: &str) -> Option<HashMap<String, Unit>> {
+    // Load every database in the file, indexed by its SELECTDB number
+    pub fn construct_db_from_file(
+        dir: &str,
+        dbfilename: &str,
+    ) -> Option<Vec<HashMap<String, Unit>>> {
         let file_path = std::path::Path::new(dir).join(dbfilename);
         if !file_path.exists() || !file_path.is_file() {
             log::warn!("RDB file does not exist: {}", file_path.display());
-            return Some(HashMap::new()); // Return empty database if file doesn't exist
+            return Some(vec![HashMap::new()]); // Return empty database if file doesn't exist
         }
 
         // Read file into buffer
@@ -229,7 +685,14 @@ impl FileUtils {
             return None;
         }
 
-        let mut db = HashMap::new();
+        Self::parse_db(&buffer)
+    }
+
+    // Parse an in-memory RDB payload into one keyspace per database. Keys
+    // before any SELECTDB marker belong to database 0.
+    pub fn parse_db(buffer: &[u8]) -> Option<Vec<HashMap<String, Unit>>> {
+        let mut dbs = vec![HashMap::new()];
+        let mut current_db = 0;
         let mut position = 0;
         let mut stage = FileStage::Header;
         let mut pending_expiry: Option<u64> = None;
@@ -275,9 +738,9 @@ impl FileUtils {
                             position += 1;
 
                             // Read metadata name
-                            let name = Self::read_string_encoded(&buffer, &mut position)?;
+                            let name = Self::read_string_encoded(buffer, &mut position)?;
                             // Read metadata value
-                            let value = Self::read_string_encoded(&buffer, &mut position)?;
+                            let value = Self::read_string_encoded(buffer, &mut position)?;
                             log::debug!("Metadata: {} = {}", name, value);
                         }
                         0xFE => {
@@ -286,6 +749,7 @@ impl FileUtils {
                         }
                         0xFF => {
                             // End of file
+                            position += 1;
                             stage = FileStage::End;
                         }
                         _ => {
@@ -312,15 +776,27 @@ impl FileUtils {
                         }
                         0xFE => {
                             // Start of database subsection
-                            let db_index = Self::read_length_encoded(&buffer, &mut position)?;
+                            let db_index = Self::read_length_encoded(buffer, &mut position)?;
+                            if db_index >= DATABASES {
+                                log::error!(
+                                    "RDB selects database {} out of {}",
+                                    db_index,
+                                    DATABASES
+                                );
+                                return None;
+                            }
                             log::debug!("Switching to database: {}", db_index);
+                            if dbs.len() <= db_index {
+                                dbs.resize_with(db_index + 1, HashMap::new);
+                            }
+                            current_db = db_index;
                         }
                         0xFB => {
                             // Hash table size information
                             let hash_table_size =
-                                Self::read_length_encoded(&buffer, &mut position)?;
+                                Self::read_length_encoded(buffer, &mut position)?;
                             let expire_hash_size =
-                                Self::read_length_encoded(&buffer, &mut position)?;
+                                Self::read_length_encoded(buffer, &mut position)?;
                             log::debug!(
                                 "Hash table size: {}, expire hash table size: {}",
                                 hash_table_size,
@@ -367,49 +843,777 @@ impl FileUtils {
                         _ => {
                             // Regular key-value pair (marker is the value type)
                             Self::parse_key_value_pair(
-                                &buffer,
+                                buffer,
                                 &mut position,
                                 marker,
                                 pending_expiry.take(), // Use and clear pending expiry
-                                &mut db,
+                                &mut dbs[current_db],
                             )?;
                         }
                     }
                 }
                 FileStage::End => {
-                    // Handle checksum if present
-                    if position + 8 <= buffer.len() {
+                    // Handle checksum if present, a zero one means it is disabled
+                    if let Some(bytes) = buffer.get(position..position + 8) {
+                        let checksum = u64::from_le_bytes(bytes.try_into().ok()?);
+                        if checksum != 0 && checksum != crc64(&buffer[..position]) {
+                            log::error!("RDB checksum mismatch");
+                            return None;
+                        }
                         log::debug!("Found CRC64 checksum at end of file");
                     }
-                    log::debug!("RDB parsing complete. Loaded {} keys", db.len());
+                    log::debug!("RDB parsing complete");
                     break;
                 }
             }
         }
 
-        log::info!("Successfully loaded {} keys from RDB file", db.len());
-        Some(db)
+        log::info!(
+            "Successfully loaded {} keys from RDB file",
+            dbs.iter().map(HashMap::len).sum::<usize>()
+        );
+        Some(dbs)
     }
 
-    pub fn get_db_as_file() -> Vec<u8> {
-        // Convert hex string to Vec<u8>
-        let hex_string = "524544495330303131fa0972656469732d76657205372e322e30fa0a72656469732d62697473c040fa056374696d65c26d08bc65fa08757365642d6d656dc2b0c41000fa08616f662d62617365c000fff06e3bfec0ff5aa2";
-        let mut bytes = Vec::with_capacity(hex_string.len() / 2);
-        
-        for i in (0..hex_string.len()).step_by(2) {
-            if i + 2 > hex_string.len() {
-            break;
+    // Write a length-encoded value, the inverse of read_length_encoded
+    fn write_length_encoded(buffer: &mut Vec<u8>, len: usize) {
+        if len < 1 << 6 {
+            buffer.push(len as u8);
+        } else if len < 1 << 14 {
+            buffer.push(0x40 | (len >> 8) as u8);
+            buffer.push(len as u8);
+        } else if let Ok(len) = u32::try_from(len) {
+            buffer.push(0x80);
+            buffer.extend_from_slice(&len.to_be_bytes());
+        } else {
+            buffer.push(0x81);
+            buffer.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+
+    // Write a string, the inverse of read_string_encoded. Integers that
+    // round-trip exactly use the compact integer encodings like Redis does.
+    fn write_string_encoded(buffer: &mut Vec<u8>, value: &str) {
+        if let Some(int) = value.parse::<i32>().ok().filter(|int| int.to_string() == value) {
+            if let Ok(int) = i8::try_from(int) {
+                buffer.push(0xC0);
+                buffer.extend_from_slice(&int.to_le_bytes());
+            } else if let Ok(int) = i16::try_from(int) {
+                buffer.push(0xC1);
+                buffer.extend_from_slice(&int.to_le_bytes());
+            } else {
+                buffer.push(0xC2);
+                buffer.extend_from_slice(&int.to_le_bytes());
             }
-            let byte_str = &hex_string[i..i+2];
-            match u8::from_str_radix(byte_str, 16) {
-            Ok(byte) => bytes.push(byte),
-            Err(_) => {
-                log::error!("Invalid hex string at position {}: {}", i, byte_str);
-                return Vec::new();
+            return;
+        }
+        Self::write_length_encoded(buffer, value.len());
+        buffer.extend_from_slice(value.as_bytes());
+    }
+
+    // One listpack element: small unsigned integers fit in the type byte,
+    // other integers take 8 bytes and strings are prefixed with their length
+    fn listpack_int(value: i64) -> Vec<u8> {
+        match value {
+            0..=127 => vec![value as u8],
+            _ => [&[0xF4][..], &value.to_le_bytes()].concat(),
+        }
+    }
+
+    fn listpack_string(value: &str) -> Vec<u8> {
+        let len = value.len();
+        let mut element = match len {
+            0..=63 => vec![0x80 | len as u8],
+            64..=4095 => vec![0xE0 | (len >> 8) as u8, len as u8],
+            _ => [&[0xF0][..], &(len as u32).to_le_bytes()].concat(),
+        };
+        element.extend_from_slice(value.as_bytes());
+        element
+    }
+
+    // The inverse of read_listpack. Each element is followed by its length,
+    // seven bits per byte, so that the listpack can be walked backwards.
+    fn write_listpack(elements: &[Vec<u8>]) -> Vec<u8> {
+        let mut blob = vec![0; 6];
+        for element in elements {
+            blob.extend_from_slice(element);
+            let len = element.len();
+            let backlen_size = Self::listpack_backlen_size(len);
+            for i in (0..backlen_size).rev() {
+                let continuation = if i + 1 < backlen_size { 0x80 } else { 0 };
+                blob.push(((len >> (7 * i)) & 0x7F) as u8 | continuation);
             }
+        }
+        blob.push(0xFF);
+        let total = blob.len() as u32;
+        blob[0..4].copy_from_slice(&total.to_le_bytes());
+        let count = elements.len().min(u16::MAX as usize) as u16;
+        blob[4..6].copy_from_slice(&count.to_le_bytes());
+        blob
+    }
+
+    fn write_raw_stream_id(buffer: &mut Vec<u8>, id: &StreamId) {
+        buffer.extend_from_slice(&id.timestamp.to_be_bytes());
+        buffer.extend_from_slice(&id.sequence.to_be_bytes());
+    }
+
+    fn write_length_stream_id(buffer: &mut Vec<u8>, id: &StreamId) {
+        Self::write_length_encoded(buffer, id.timestamp as usize);
+        Self::write_length_encoded(buffer, id.sequence as usize);
+    }
+
+    // The node layout read_stream_node expects, with the first entry's
+    // fields as the master fields
+    fn write_stream_node(entries: &[StreamMember]) -> Vec<u8> {
+        let master = &entries[0];
+        let mut elements = vec![
+            Self::listpack_int(entries.len() as i64),
+            Self::listpack_int(0),
+            Self::listpack_int(master.fields.len() as i64),
+        ];
+        elements.extend(master.fields.iter().map(|(field, _)| Self::listpack_string(field)));
+        elements.push(Self::listpack_int(0));
+
+        for entry in entries {
+            let same_fields = entry.fields.len() == master.fields.len()
+                && entry
+                    .fields
+                    .iter()
+                    .zip(&master.fields)
+                    .all(|((field, _), (master_field, _))| field == master_field);
+            let start = elements.len();
+            let flags = if same_fields { STREAM_ITEM_FLAG_SAMEFIELDS } else { 0 };
+            elements.push(Self::listpack_int(flags));
+            elements.push(Self::listpack_int(
+                entry.id.timestamp.wrapping_sub(master.id.timestamp) as i64,
+            ));
+            elements.push(Self::listpack_int(
+                entry.id.sequence.wrapping_sub(master.id.sequence) as i64,
+            ));
+            if same_fields {
+                elements.extend(entry.fields.iter().map(|(_, value)| Self::listpack_string(value)));
+            } else {
+                elements.push(Self::listpack_int(entry.fields.len() as i64));
+                for (field, value) in &entry.fields {
+                    elements.push(Self::listpack_string(field));
+                    elements.push(Self::listpack_string(value));
+                }
+            }
+            elements.push(Self::listpack_int((elements.len() - start) as i64));
+        }
+        Self::write_listpack(&elements)
+    }
+
+    fn write_stream(buffer: &mut Vec<u8>, stream: &Stream) {
+        let nodes = stream.entries.chunks(STREAM_NODE_MAX_ENTRIES);
+        Self::write_length_encoded(buffer, nodes.len());
+        for node in nodes {
+            Self::write_length_encoded(buffer, 16);
+            Self::write_raw_stream_id(buffer, &node[0].id);
+            let listpack = Self::write_stream_node(node);
+            Self::write_length_encoded(buffer, listpack.len());
+            buffer.extend_from_slice(&listpack);
+        }
+
+        let first_id = stream
+            .entries
+            .first()
+            .map_or(EMPTY_STREAM_ID, |entry| entry.id.clone());
+        Self::write_length_encoded(buffer, stream.len());
+        Self::write_length_stream_id(buffer, &stream.last_id);
+        Self::write_length_stream_id(buffer, &first_id);
+        Self::write_length_stream_id(buffer, &stream.max_deleted_id);
+        Self::write_length_encoded(buffer, stream.entries_added as usize);
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_millis() as i64);
+        let millis_at = |instant: Instant| now - instant.elapsed().as_millis() as i64;
+
+        Self::write_length_encoded(buffer, stream.groups.len());
+        for (name, group) in &stream.groups {
+            Self::write_string_encoded(buffer, name);
+            Self::write_length_stream_id(buffer, &group.last_delivered_id);
+            Self::write_length_encoded(buffer, group.entries_read.unwrap_or(u64::MAX) as usize);
+
+            Self::write_length_encoded(buffer, group.pending.len());
+            for (id, entry) in &group.pending {
+                Self::write_raw_stream_id(buffer, id);
+                buffer.extend_from_slice(&(entry.delivery_time as i64).to_le_bytes());
+                Self::write_length_encoded(buffer, entry.delivery_count as usize);
+            }
+
+            Self::write_length_encoded(buffer, group.consumers.len());
+            for (name, consumer) in &group.consumers {
+                Self::write_string_encoded(buffer, name);
+                buffer.extend_from_slice(&millis_at(consumer.seen_time).to_le_bytes());
+                let active_time = consumer.active_time.map_or(-1, millis_at);
+                buffer.extend_from_slice(&active_time.to_le_bytes());
+                let owned: Vec<&StreamId> = group
+                    .pending
+                    .iter()
+                    .filter(|(_, entry)| &entry.consumer == name)
+                    .map(|(id, _)| id)
+                    .collect();
+                Self::write_length_encoded(buffer, owned.len());
+                for id in owned {
+                    Self::write_raw_stream_id(buffer, id);
+                }
+            }
+        }
+    }
+
+    // Write a value in its RDB encoding and return the type byte that must
+    // precede its key
+    fn write_value(buffer: &mut Vec<u8>, implementation: &Implementation) -> u8 {
+        match implementation {
+            Implementation::STRING(value) => {
+                Self::write_string_encoded(buffer, value);
+                RDB_TYPE_STRING
+            }
+            Implementation::LIST(list) => {
+                Self::write_length_encoded(buffer, list.len());
+                for item in list {
+                    Self::write_string_encoded(buffer, item);
+                }
+                RDB_TYPE_LIST
+            }
+            Implementation::SET(set) => {
+                Self::write_length_encoded(buffer, set.len());
+                for member in set {
+                    Self::write_string_encoded(buffer, member);
+                }
+                RDB_TYPE_SET
+            }
+            Implementation::HASH(hash) => {
+                Self::write_length_encoded(buffer, hash.len());
+                for (field, value) in hash.iter() {
+                    Self::write_string_encoded(buffer, field);
+                    Self::write_string_encoded(buffer, value);
+                }
+                RDB_TYPE_HASH
+            }
+            Implementation::ZSET(zset) => {
+                Self::write_length_encoded(buffer, zset.len());
+                for member in zset {
+                    Self::write_string_encoded(buffer, &member.member);
+                    buffer.extend_from_slice(&member.score.to_le_bytes());
+                }
+                RDB_TYPE_ZSET_2
+            }
+            Implementation::STREAM(stream) => {
+                Self::write_stream(buffer, stream);
+                RDB_TYPE_STREAM_LISTPACKS_3
+            }
+        }
+    }
+
+    // Serialize a single key, preceded by its millisecond expiry marker if it has one
+    fn write_key_value_pair(buffer: &mut Vec<u8>, key: &str, unit: &Unit) {
+        if let Some(expiry) = unit.expiry {
+            buffer.push(0xFC);
+            buffer.extend_from_slice(&(expiry as u64).to_le_bytes());
+        }
+        let mut value = Vec::new();
+        buffer.push(Self::write_value(&mut value, &unit.implementation));
+        Self::write_string_encoded(buffer, key);
+        buffer.extend_from_slice(&value);
+    }
+
+    // Size of a value in its RDB encoding, as DEBUG OBJECT reports it
+    pub fn serialized_length(implementation: &Implementation) -> usize {
+        let mut buffer = Vec::new();
+        Self::write_value(&mut buffer, implementation);
+        buffer.len()
+    }
+
+    // Serialize keyspaces into an RDB payload readable by parse_db, with a
+    // SELECTDB section for every database that has keys
+    pub fn serialize_db(dbs: &[HashMap<String, Unit>]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        buffer.extend_from_slice(b"REDIS0011");
+
+        buffer.push(0xFA);
+        Self::write_string_encoded(&mut buffer, "redis-ver");
+        Self::write_string_encoded(&mut buffer, "7.2.0");
+
+        for (index, db) in dbs.iter().enumerate() {
+            let mut entries = Vec::new();
+            for (key, unit) in db {
+                Self::write_key_value_pair(&mut entries, key, unit);
+            }
+            let expires = db.values().filter(|unit| unit.expiry.is_some()).count();
+
+            if !db.is_empty() {
+                buffer.push(0xFE);
+                Self::write_length_encoded(&mut buffer, index);
+                buffer.push(0xFB);
+                Self::write_length_encoded(&mut buffer, db.len());
+                Self::write_length_encoded(&mut buffer, expires);
+                buffer.extend_from_slice(&entries);
             }
         }
-        
-        bytes
+
+        buffer.push(0xFF);
+        let checksum = crc64(&buffer);
+        buffer.extend_from_slice(&checksum.to_le_bytes());
+        buffer
+    }
+
+    // Write through a temporary file so a failed save leaves the old one intact
+    pub fn write_db_to_file(
+        dir: &str,
+        dbfilename: &str,
+        storage: &HashMap<String, Unit>,
+    ) -> io::Result<()> {
+        let dir_path = std::path::Path::new(dir);
+        let temp_path = dir_path.join(format!("temp-{}.rdb", std::process::id()));
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&Self::serialize_db(std::slice::from_ref(storage)))?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, dir_path.join(dbfilename))?;
+        log::info!("Saved {} keys to {}/{}", storage.len(), dir, dbfilename);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now_millis() -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis()
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("redis-rs-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_round_trip_preserves_ttl() {
+        let live_expiry = now_millis() + 100_000;
+        let mut db = HashMap::new();
+        db.insert(
+            "plain".to_string(),
+            Unit::new_string("value".to_string(), None),
+        );
+        db.insert(
+            "live".to_string(),
+            Unit::new_string("soon".to_string(), Some(live_expiry)),
+        );
+        db.insert(
+            "stale".to_string(),
+            Unit::new_string("gone".to_string(), Some(now_millis() - 1_000)),
+        );
+
+        let dir = temp_dir("ttl-round-trip");
+        std::fs::write(dir.join("dump.rdb"), FileUtils::serialize_db(&[db])).unwrap();
+        let mut loaded =
+            FileUtils::construct_db_from_file(dir.to_str().unwrap(), "dump.rdb").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.len(), 1);
+        let loaded = loaded.remove(0);
+        assert_eq!(loaded.len(), 2);
+        let plain = loaded.get("plain").unwrap();
+        assert_eq!(plain.implementation.as_string().unwrap(), "value");
+        assert_eq!(plain.expiry, None);
+
+        let live = loaded.get("live").unwrap();
+        assert_eq!(live.implementation.as_string().unwrap(), "soon");
+        assert_eq!(live.expiry, Some(live_expiry));
+
+        assert!(!loaded.contains_key("stale"));
+    }
+
+    #[test]
+    fn test_round_trip_keeps_databases_apart() {
+        let mut dbs = vec![HashMap::new(), HashMap::new(), HashMap::new()];
+        dbs[0].insert("zero".to_string(), Unit::new_string("a".to_string(), None));
+        dbs[2].insert("two".to_string(), Unit::new_string("b".to_string(), None));
+
+        let payload = FileUtils::serialize_db(&dbs);
+        // Empty databases get no SELECTDB section
+        assert_eq!(payload.iter().filter(|&&byte| byte == 0xFE).count(), 2);
+
+        let dir = temp_dir("multi-db-round-trip");
+        std::fs::write(dir.join("dump.rdb"), payload).unwrap();
+        let loaded = FileUtils::construct_db_from_file(dir.to_str().unwrap(), "dump.rdb").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(loaded.len(), 3);
+        assert_eq!(loaded[0].len(), 1);
+        assert_eq!(
+            loaded[0].get("zero").unwrap().implementation.as_string().unwrap(),
+            "a"
+        );
+        assert!(loaded[1].is_empty());
+        assert_eq!(loaded[2].len(), 1);
+        assert_eq!(
+            loaded[2].get("two").unwrap().implementation.as_string().unwrap(),
+            "b"
+        );
+    }
+
+    #[test]
+    fn test_serialized_length_matches_codec() {
+        let string = Implementation::STRING("hello".to_string());
+        assert_eq!(FileUtils::serialized_length(&string), 6);
+        // Small integers take a type byte plus their little-endian bytes
+        let int = Implementation::STRING("12345".to_string());
+        assert_eq!(FileUtils::serialized_length(&int), 3);
+        let padded = Implementation::STRING("007".to_string());
+        assert_eq!(FileUtils::serialized_length(&padded), 4);
+
+        let list = Implementation::LIST(vec!["a".to_string(), "bb".to_string()]);
+        assert_eq!(FileUtils::serialized_length(&list), 6);
+        let mut zset = std::collections::BTreeSet::new();
+        zset.insert(ZSetMember {
+            score: 1.5,
+            member: "m".to_string(),
+        });
+        assert_eq!(
+            FileUtils::serialized_length(&Implementation::ZSET(zset)),
+            1 + 2 + 8
+        );
+        assert_eq!(FileUtils::serialized_length(&string), 6);
+    }
+
+    #[test]
+    fn test_round_trip_collections() {
+        let mut zset = std::collections::BTreeSet::new();
+        zset.insert(ZSetMember {
+            score: -2.25,
+            member: "low".to_string(),
+        });
+        let mut db = HashMap::new();
+        db.insert(
+            "list".to_string(),
+            Unit::new_list(vec!["x".to_string(), "70000".to_string()], None),
+        );
+        db.insert(
+            "set".to_string(),
+            Unit::new_set(["a".to_string()].into_iter().collect(), None),
+        );
+        db.insert(
+            "hash".to_string(),
+            Unit::new_hash([("f".to_string(), "-12".to_string())].into_iter().collect(), None),
+        );
+        db.insert("zset".to_string(), Unit::new_zset(zset, None));
+
+        let loaded = FileUtils::parse_db(&FileUtils::serialize_db(&[db])).unwrap();
+        let loaded = &loaded[0];
+        assert_eq!(
+            loaded.get("list").unwrap().implementation.as_list().unwrap(),
+            &vec!["x".to_string(), "70000".to_string()]
+        );
+        assert!(loaded
+            .get("set")
+            .unwrap()
+            .implementation
+            .as_set()
+            .unwrap()
+            .contains("a"));
+        assert_eq!(
+            loaded.get("hash").unwrap().implementation.as_hash().unwrap(),
+            &[("f".to_string(), "-12".to_string())].into_iter().collect()
+        );
+        let zset = loaded.get("zset").unwrap().implementation.as_zset().unwrap();
+        let member = zset.iter().next().unwrap();
+        assert_eq!((member.member.as_str(), member.score), ("low", -2.25));
+    }
+
+    #[test]
+    fn test_round_trip_streams_with_groups() {
+        let id = |timestamp, sequence| StreamId {
+            timestamp,
+            sequence,
+        };
+        let fields = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(field, value)| (field.to_string(), value.to_string()))
+                .collect()
+        };
+        let mut stream = Stream::new();
+        // More entries than fit in one node, with a change of fields midway
+        for i in 0..150 {
+            let entry_fields = if i == 120 {
+                fields(&[("other", "x")])
+            } else {
+                fields(&[("n", &i.to_string()), ("blob", &"v".repeat(i))])
+            };
+            stream.push(StreamMember {
+                id: id(1_700_000_000_000 + i as u64, i as u64 % 3),
+                fields: entry_fields,
+            });
+        }
+        stream.remove_where(|entry| entry.id == id(1_700_000_000_001, 1));
+
+        let mut group = ConsumerGroup::new(id(1_700_000_000_002, 2), Some(2));
+        group.seen("alice").active_time = Some(Instant::now());
+        group.seen("bob");
+        group.pending.insert(
+            id(1_700_000_000_002, 2),
+            PendingEntry {
+                consumer: "alice".to_string(),
+                delivery_count: 3,
+                delivery_time: 1_700_000_000_500,
+            },
+        );
+        stream.groups.insert("g".to_string(), group);
+        stream
+            .groups
+            .insert("unknown".to_string(), ConsumerGroup::new(id(5, 0), None));
+
+        let mut db = HashMap::new();
+        db.insert("s".to_string(), Unit::new_stream(stream.clone(), None));
+        let loaded = FileUtils::parse_db(&FileUtils::serialize_db(&[db])).unwrap();
+        let loaded = loaded[0].get("s").unwrap().implementation.as_stream().unwrap();
+
+        assert_eq!(loaded.len(), 149);
+        for (loaded, original) in loaded.entries.iter().zip(&stream.entries) {
+            assert_eq!(loaded.id, original.id);
+            assert_eq!(loaded.fields, original.fields);
+        }
+        assert_eq!(loaded.last_id, stream.last_id);
+        assert_eq!(loaded.entries_added, 150);
+        assert_eq!(loaded.max_deleted_id, id(1_700_000_000_001, 1));
+
+        let group = loaded.groups.get("g").unwrap();
+        assert_eq!(group.last_delivered_id, id(1_700_000_000_002, 2));
+        assert_eq!(group.entries_read, Some(2));
+        let pending = group.pending.get(&id(1_700_000_000_002, 2)).unwrap();
+        assert_eq!(pending.consumer, "alice");
+        assert_eq!(pending.delivery_count, 3);
+        assert_eq!(pending.delivery_time, 1_700_000_000_500);
+        assert!(group.consumers.get("alice").unwrap().active_time.is_some());
+        assert!(group.consumers.get("bob").unwrap().active_time.is_none());
+        assert_eq!(loaded.groups.get("unknown").unwrap().entries_read, None);
+    }
+
+    #[test]
+    fn test_length_encoding_round_trip() {
+        for len in [0, 63, 64, 16383, 16384, 1 << 20, 1 << 40] {
+            let mut buffer = Vec::new();
+            FileUtils::write_length_encoded(&mut buffer, len);
+            let mut pos = 0;
+            assert_eq!(FileUtils::read_length_encoded(&buffer, &mut pos), Some(len));
+            assert_eq!(pos, buffer.len());
+        }
+    }
+
+    #[test]
+    fn test_lengths_past_the_buffer_are_rejected() {
+        // A 64 bit length near usize::MAX must not overflow the end offset
+        let mut buffer = vec![0x81];
+        buffer.extend_from_slice(&u64::MAX.to_be_bytes());
+        buffer.extend_from_slice(b"abc");
+        let mut pos = 0;
+        assert_eq!(FileUtils::read_blob(&buffer, &mut pos), None);
+        let mut pos = 0;
+        assert_eq!(FileUtils::read_string_encoded(&buffer, &mut pos), None);
+
+        // Nor may a length that runs off the end of a listpack entry
+        let mut listpack = vec![0; 6];
+        listpack.extend_from_slice(&[0xF0, 0xFF, 0xFF, 0xFF, 0xFF, b'a']);
+        assert_eq!(FileUtils::read_listpack(&listpack), None);
+    }
+
+    #[test]
+    fn test_crc64_matches_redis() {
+        assert_eq!(crc64(b"123456789"), 0xe9c6_d914_c4b8_d9ca);
+    }
+
+    #[test]
+    fn test_checksum_mismatch_is_rejected() {
+        let mut db = HashMap::new();
+        db.insert("key".to_string(), Unit::new_string("value".to_string(), None));
+        let mut payload = FileUtils::serialize_db(&[db]);
+        assert_eq!(FileUtils::parse_db(&payload).unwrap()[0].len(), 1);
+
+        // Flip a byte of the value, leaving the structure intact
+        let value = payload.windows(5).position(|window| window == b"value").unwrap();
+        payload[value] = b'V';
+        assert!(FileUtils::parse_db(&payload).is_none());
+    }
+
+    #[test]
+    fn test_out_of_range_database_is_rejected() {
+        let mut payload = b"REDIS0011\xfe\x0f".to_vec();
+        payload.extend_from_slice(&[0xFF, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(FileUtils::parse_db(&payload).unwrap().len(), 16);
+
+        // A huge index must not size the database list
+        let mut payload = b"REDIS0011\xfe\x80\xff\xff\xff\xff".to_vec();
+        payload.extend_from_slice(&[0xFF, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(FileUtils::parse_db(&payload).is_none());
+        let mut payload = b"REDIS0011\xfe\x10".to_vec();
+        payload.extend_from_slice(&[0xFF, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert!(FileUtils::parse_db(&payload).is_none());
+    }
+
+    // A database 0 section holding `entries`, with the checksum disabled
+    fn rdb_with(entries: &[u8]) -> Vec<u8> {
+        let mut buffer = b"REDIS0011\xfe\x00".to_vec();
+        buffer.extend_from_slice(entries);
+        buffer.push(0xFF);
+        buffer.extend_from_slice(&[0u8; 8]);
+        buffer
+    }
+
+    // A short string entry with its one byte backlen
+    fn lp_string(value: &str) -> Vec<u8> {
+        let mut entry = vec![0x80 | value.len() as u8];
+        entry.extend_from_slice(value.as_bytes());
+        entry.push(entry.len() as u8);
+        entry
+    }
+
+    // A listpack wrapped in the length prefix of an RDB string
+    fn listpack_blob(entries: &[Vec<u8>]) -> Vec<u8> {
+        let body = entries.concat();
+        let total = 6 + body.len() + 1;
+        let mut blob = vec![total as u8];
+        blob.extend_from_slice(&(total as u32).to_le_bytes());
+        blob.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        blob.extend_from_slice(&body);
+        blob.push(0xFF);
+        blob
+    }
+
+    #[test]
+    fn test_loads_quicklist_lists() {
+        let mut entries = vec![RDB_TYPE_LIST_QUICKLIST_2, 4];
+        entries.extend_from_slice(b"list");
+        entries.push(2); // nodes
+        entries.push(2); // packed node: a listpack
+        entries.extend(listpack_blob(&[lp_string("a"), vec![0x05, 0x01]]));
+        entries.push(1); // plain node: one element
+        entries.extend_from_slice(b"\x03big");
+
+        let loaded = FileUtils::parse_db(&rdb_with(&entries)).unwrap();
+        assert_eq!(
+            loaded[0].get("list").unwrap().implementation.as_list().unwrap(),
+            &vec!["a".to_string(), "5".to_string(), "big".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_loads_lzf_compressed_values() {
+        // "abc" as a literal, then a 9 byte reference 3 bytes back
+        let mut entries = vec![RDB_TYPE_STRING, 1, b's', 0xC3, 7, 12];
+        entries.extend_from_slice(&[0x02, b'a', b'b', b'c', 0xE0, 0x00, 0x02]);
+        // A 35 byte listpack of four "redis" entries: the header and first
+        // entry as 13 literal bytes, a 21 byte reference 7 bytes back that
+        // repeats the entry, then the terminator
+        entries.extend_from_slice(&[RDB_TYPE_LIST_QUICKLIST_2, 4]);
+        entries.extend_from_slice(b"list");
+        entries.extend_from_slice(&[1, 2, 0xC3, 19, 35]);
+        entries.extend_from_slice(&[0x0C, 0x23, 0x00, 0x00, 0x00, 0x04, 0x00]);
+        entries.extend_from_slice(&[0x85, b'r', b'e', b'd', b'i', b's', 0x06]);
+        entries.extend_from_slice(&[0xE0, 0x0C, 0x06, 0x00, 0xFF]);
+
+        let loaded = FileUtils::parse_db(&rdb_with(&entries)).unwrap();
+        assert_eq!(
+            loaded[0].get("s").unwrap().implementation.as_string().unwrap(),
+            "abcabcabcabc"
+        );
+        assert_eq!(
+            loaded[0].get("list").unwrap().implementation.as_list().unwrap(),
+            &vec!["redis".to_string(); 4]
+        );
+
+        // A reference before the start of the output is corrupt
+        let entries = [RDB_TYPE_STRING, 1, b's', 0xC3, 3, 3, 0x20, 0x05, 0x00];
+        assert!(FileUtils::parse_db(&rdb_with(&entries)).is_none());
+    }
+
+    #[test]
+    fn test_loads_packed_sets_and_hashes() {
+        let mut entries = vec![RDB_TYPE_SET_LISTPACK, 1, b's'];
+        entries.extend(listpack_blob(&[lp_string("x"), lp_string("y")]));
+        // Two 16 bit integers, 1 and -2
+        entries.extend_from_slice(&[RDB_TYPE_SET_INTSET, 1, b'i', 12]);
+        entries.extend_from_slice(&[2, 0, 0, 0, 2, 0, 0, 0, 0x01, 0x00, 0xFE, 0xFF]);
+        entries.extend_from_slice(&[RDB_TYPE_HASH_LISTPACK, 1, b'h']);
+        entries.extend(listpack_blob(&[
+            lp_string("f"),
+            lp_string("v"),
+            lp_string("n"),
+            vec![0xC3, 0xE8, 0x02],       // 13 bit integer 1000
+            lp_string("neg"),
+            vec![0xF1, 0xFF, 0xFF, 0x03], // 16 bit integer -1
+        ]));
+
+        let loaded = FileUtils::parse_db(&rdb_with(&entries)).unwrap();
+        let set = |key: &str| {
+            let mut members: Vec<String> = loaded[0]
+                .get(key)
+                .unwrap()
+                .implementation
+                .as_set()
+                .unwrap()
+                .iter()
+                .cloned()
+                .collect();
+            members.sort();
+            members
+        };
+        assert_eq!(set("s"), vec!["x".to_string(), "y".to_string()]);
+        assert_eq!(set("i"), vec!["-2".to_string(), "1".to_string()]);
+        assert_eq!(
+            loaded[0].get("h").unwrap().implementation.as_hash().unwrap(),
+            &[
+                ("f".to_string(), "v".to_string()),
+                ("n".to_string(), "1000".to_string()),
+                ("neg".to_string(), "-1".to_string()),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn test_loads_zsets_with_string_and_packed_scores() {
+        let mut entries = vec![RDB_TYPE_ZSET, 1, b'z', 2];
+        entries.extend_from_slice(b"\x01m\x031.5");
+        entries.extend_from_slice(b"\x03top\xfe"); // +inf
+        entries.extend_from_slice(&[RDB_TYPE_ZSET_LISTPACK, 1, b'p']);
+        entries.extend(listpack_blob(&[lp_string("a"), lp_string("2.5")]));
+
+        let loaded = FileUtils::parse_db(&rdb_with(&entries)).unwrap();
+        let members = |key: &str| -> Vec<(String, f64)> {
+            loaded[0]
+                .get(key)
+                .unwrap()
+                .implementation
+                .as_zset()
+                .unwrap()
+                .iter()
+                .map(|member| (member.member.clone(), member.score))
+                .collect()
+        };
+        assert_eq!(
+            members("z"),
+            vec![("m".to_string(), 1.5), ("top".to_string(), f64::INFINITY)]
+        );
+        assert_eq!(members("p"), vec![("a".to_string(), 2.5)]);
+    }
+
+    #[test]
+    fn test_truncated_collections_with_huge_lengths_are_rejected() {
+        // The declared lengths must not size anything before the elements are read
+        for value_type in [RDB_TYPE_LIST, RDB_TYPE_SET] {
+            let mut entries = vec![value_type, 1, b'k', 0x81];
+            entries.extend_from_slice(&0x0C_0000_0000u64.to_be_bytes());
+            entries.extend_from_slice(b"\x01a");
+            assert!(FileUtils::parse_db(&rdb_with(&entries)).is_none());
+        }
     }
 }
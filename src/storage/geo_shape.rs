@@ -0,0 +1,6 @@
+// GEOSEARCH area, in the command's distance unit
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeoShape {
+    Radius(f64),
+    Box(f64, f64), // width, height
+}
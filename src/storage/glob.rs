@@ -0,0 +1,151 @@
+// Redis-style glob matching, shared by every command that takes a pattern
+// (KEYS, SCAN MATCH, CONFIG GET) so they all agree on what matches.
+//
+// Supports `*`, `?`, `[abc]`, `[^abc]`, `[a-z]` and `\` to escape the next
+// character, compared byte by byte like Redis' stringmatchlen.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.as_bytes();
+    let text = text.as_bytes();
+
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after the last `*`: pattern index past it, and text index
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    // Consecutive stars behave like one
+                    while p < pattern.len() && pattern[p] == b'*' {
+                        p += 1;
+                    }
+                    if p == pattern.len() {
+                        return true;
+                    }
+                    star = Some((p, t));
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                b'[' => {
+                    let (matched, next) = match_class(pattern, p + 1, text[t]);
+                    if matched {
+                        p = next;
+                        t += 1;
+                        continue;
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == text[t] {
+                        p += 2;
+                        t += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == text[t] {
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        // Mismatch: let the last `*` swallow one more character
+        match star {
+            Some((star_p, star_t)) => {
+                p = star_p;
+                t = star_t + 1;
+                star = Some((star_p, star_t + 1));
+            }
+            None => return false,
+        }
+    }
+
+    // Only trailing stars may remain
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+// Match `c` against the class starting just after `[`. Returns whether it
+// matched and the pattern index after the closing `]` (or the end of the
+// pattern when the class is unterminated).
+fn match_class(pattern: &[u8], mut p: usize, c: u8) -> (bool, usize) {
+    let negate = p < pattern.len() && pattern[p] == b'^';
+    if negate {
+        p += 1;
+    }
+
+    let mut matched = false;
+    while p < pattern.len() && pattern[p] != b']' {
+        if pattern[p] == b'\\' && p + 1 < pattern.len() {
+            matched |= pattern[p + 1] == c;
+            p += 2;
+        } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' && pattern[p + 2] != b']' {
+            let (start, end) = if pattern[p] <= pattern[p + 2] {
+                (pattern[p], pattern[p + 2])
+            } else {
+                (pattern[p + 2], pattern[p])
+            };
+            matched |= (start..=end).contains(&c);
+            p += 3;
+        } else {
+            matched |= pattern[p] == c;
+            p += 1;
+        }
+    }
+
+    // Skip the closing bracket
+    if p < pattern.len() {
+        p += 1;
+    }
+    (matched != negate, p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("h?llo", "hello"));
+        assert!(!glob_match("h?llo", "hllo"));
+        assert!(glob_match("h*llo", "heeeello"));
+        assert!(glob_match("h*llo", "hllo"));
+        assert!(glob_match("a**b", "axxb"));
+        assert!(glob_match("*b*", "abc"));
+        assert!(!glob_match("*b", "abc"));
+        assert!(glob_match("user:*:name", "user:42:name"));
+        assert!(!glob_match("user:*:name", "user:42:age"));
+    }
+
+    #[test]
+    fn test_classes() {
+        assert!(glob_match("h[ae]llo", "hallo"));
+        assert!(!glob_match("h[ae]llo", "hillo"));
+        assert!(glob_match("h[^e]llo", "hallo"));
+        assert!(!glob_match("h[^e]llo", "hello"));
+        assert!(glob_match("h[a-b]llo", "hbllo"));
+        assert!(glob_match("h[b-a]llo", "hallo"));
+        assert!(!glob_match("h[a-b]llo", "hcllo"));
+        assert!(glob_match("[a-]", "-"));
+        assert!(glob_match("[\\]]", "]"));
+    }
+
+    #[test]
+    fn test_escapes_and_regex_characters() {
+        assert!(glob_match("a\\*b", "a*b"));
+        assert!(!glob_match("a\\*b", "axb"));
+        assert!(glob_match("a\\?", "a?"));
+        // Characters special to regexes are literal in globs
+        assert!(glob_match("a.b", "a.b"));
+        assert!(!glob_match("a.b", "axb"));
+        assert!(glob_match("(x)+", "(x)+"));
+        assert!(glob_match("$^|{}", "$^|{}"));
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+// A hash's fields in insertion order, like a listpack-encoded Redis hash
+// replies with them, with an index so lookups don't walk the fields.
+// Removed fields leave a hole that is compacted away once holes make up
+// half of the entries.
+#[derive(Debug, Clone, Default)]
+pub struct OrderedHash {
+    entries: Vec<Option<(String, String)>>,
+    positions: HashMap<String, usize>, // field -> index in `entries`
+}
+
+impl OrderedHash {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn get(&self, field: &str) -> Option<&String> {
+        let position = *self.positions.get(field)?;
+        self.entries[position].as_ref().map(|(_, value)| value)
+    }
+
+    // Overwriting a field keeps its place. Returns the previous value.
+    pub fn insert(&mut self, field: String, value: String) -> Option<String> {
+        if let Some(&position) = self.positions.get(&field) {
+            let (_, existing) = self.entries[position].as_mut()?;
+            return Some(std::mem::replace(existing, value));
+        }
+        self.positions.insert(field.clone(), self.entries.len());
+        self.entries.push(Some((field, value)));
+        None
+    }
+
+    pub fn remove(&mut self, field: &str) -> Option<String> {
+        let position = self.positions.remove(field)?;
+        let (_, value) = self.entries[position].take()?;
+        if self.entries.len() >= 2 * self.positions.len() {
+            self.compact();
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.entries
+            .iter()
+            .flatten()
+            .map(|(field, value)| (field, value))
+    }
+
+    fn compact(&mut self) {
+        self.entries.retain(Option::is_some);
+        for (position, (field, _)) in self.entries.iter().flatten().enumerate() {
+            if let Some(index) = self.positions.get_mut(field) {
+                *index = position;
+            }
+        }
+    }
+}
+
+impl FromIterator<(String, String)> for OrderedHash {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        let mut hash = OrderedHash::new();
+        for (field, value) in iter {
+            hash.insert(field, value);
+        }
+        hash
+    }
+}
+
+impl PartialEq for OrderedHash {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
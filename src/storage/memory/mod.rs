@@ -3,26 +3,47 @@ use std::collections::{HashMap, HashSet};
 use std::time::Instant;
 
 use crate::commands::response::RedisResponse;
+use crate::commands::RedisCommand;
 use crate::server::event_loop_handle::EventLoopHandle;
+use crate::storage::encoding_config::EncodingConfig;
 use crate::storage::file_utils::FileUtils;
 use crate::storage::repl_config::ReplConfig;
 use crate::storage::stream_member::StreamId;
+use crate::storage::unit::Implementation;
 use crate::storage::{
-    Replication, Storage, StorageGeo, StorageList, StoragePubSub, StorageStream, StorageZSet, Unit,
+    Replication, Storage, StorageGeo, StorageHash, StorageList, StoragePubSub, StorageSet,
+    StorageStream, StorageZSet, Unit,
 };
 
 mod replication;
 mod storage;
 mod storage_geo;
+mod storage_hash;
 mod storage_list;
 mod storage_pub_sub;
+mod storage_set;
 mod storage_stream;
 mod storage_zset;
 
+// Strings up to this many bytes are reported as "embstr", longer ones as "raw"
+const EMBSTR_SIZE_LIMIT: usize = 44;
+// Small lists are reported with the compact "listpack" encoding
+const LIST_LISTPACK_MAX_ENTRIES: usize = 128;
+const LIST_LISTPACK_MAX_VALUE: usize = 64;
+// Sets of integers use "intset", other small sets "listpack"
+const SET_MAX_INTSET_ENTRIES: usize = 512;
+const SET_LISTPACK_MAX_ENTRIES: usize = 128;
+const SET_LISTPACK_MAX_VALUE: usize = 64;
+
 #[derive(Debug, Clone)]
 enum BlockedType {
     List(bool),       // true for BLPOP, false for BRPOP
-    Stream(StreamId), // ID to read after
+    Stream(StreamId, Option<usize>), // ID to read after, COUNT
+    Group {
+        group: String,
+        consumer: String,
+        count: Option<usize>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -41,11 +62,34 @@ impl BlockedClient {
         }
     }
 
-    pub fn new_stream(token: Token, timeout: Option<Instant>, id: StreamId) -> Self {
+    pub fn new_stream(
+        token: Token,
+        timeout: Option<Instant>,
+        id: StreamId,
+        count: Option<usize>,
+    ) -> Self {
+        Self {
+            token,
+            timeout,
+            blocked_type: BlockedType::Stream(id, count),
+        }
+    }
+
+    pub fn new_group(
+        token: Token,
+        timeout: Option<Instant>,
+        group: String,
+        consumer: String,
+        count: Option<usize>,
+    ) -> Self {
         Self {
             token,
             timeout,
-            blocked_type: BlockedType::Stream(id),
+            blocked_type: BlockedType::Group {
+                group,
+                consumer,
+                count,
+            },
         }
     }
 
@@ -62,7 +106,10 @@ impl BlockedClient {
     }
 
     pub fn by_stream(&self) -> bool {
-        matches!(self.blocked_type, BlockedType::Stream(_))
+        matches!(
+            self.blocked_type,
+            BlockedType::Stream(_, _) | BlockedType::Group { .. }
+        )
     }
 
     pub fn left_blocked(&self) -> bool {
@@ -72,14 +119,13 @@ impl BlockedClient {
             false
         }
     }
+}
 
-    pub fn stream_id(&self) -> Option<&StreamId> {
-        if let BlockedType::Stream(ref id) = self.blocked_type {
-            Some(id)
-        } else {
-            None
-        }
-    }
+// A WAIT counts replicas that acked the master's offset at the time it was called
+#[derive(Debug, Clone, Copy)]
+struct ReplicaWait {
+    offset: u64,
+    numreplicas: usize,
 }
 
 #[derive(Debug)]
@@ -91,7 +137,15 @@ pub struct MemoryStorage {
     dbfilename: Option<String>,
     pub repl_config: ReplConfig,
     pubsub: HashMap<String, Vec<mio::Token>>, // channel -> subscribers
+    pattern_pubsub: HashMap<String, Vec<mio::Token>>, // pattern -> subscribers
     replication_clients: HashSet<mio::Token>,
+    replica_ack_offsets: HashMap<mio::Token, u64>, // last offset each replica acked
+    replica_waiters: HashMap<mio::Token, ReplicaWait>, // clients blocked in WAIT
+    watched_keys: HashMap<String, HashSet<mio::Token>>, // key -> clients WATCHing it
+    dirty_watchers: HashSet<mio::Token>, // watched a key that has since been written
+    encoding_config: EncodingConfig,
+    propagation_log: Option<Vec<Vec<String>>>,
+    transaction_propagation: Option<Vec<RedisResponse>>,
 }
 
 impl MemoryStorage {
@@ -104,7 +158,15 @@ impl MemoryStorage {
             dbfilename: None,
             repl_config,
             pubsub: HashMap::new(),
+            pattern_pubsub: HashMap::new(),
             replication_clients: HashSet::new(),
+            replica_ack_offsets: HashMap::new(),
+            replica_waiters: HashMap::new(),
+            watched_keys: HashMap::new(),
+            dirty_watchers: HashSet::new(),
+            encoding_config: EncodingConfig::default(),
+            propagation_log: None,
+            transaction_propagation: None,
         }
     }
 
@@ -124,6 +186,17 @@ impl MemoryStorage {
         self.storage.keys()
     }
 
+    // Drop the key if removing elements left behind an empty collection
+    fn remove_if_empty(&mut self, key: &str) {
+        if self
+            .storage
+            .get(key)
+            .is_some_and(|unit| unit.implementation.is_empty_collection())
+        {
+            self.storage.remove(key);
+        }
+    }
+
     // Helper method to unblock clients waiting on a specific key
     fn unblock_clients_for_key(&mut self, key: &str, blocked_on_list: bool) {
         if let Some(blocked_clients) = self.blocked_clients.remove(key) {
@@ -133,6 +206,12 @@ impl MemoryStorage {
                 key
             );
 
+            if !blocked_on_list {
+                self.unblock_stream_readers(key, blocked_clients);
+                return;
+            }
+
+            let mut served = Vec::new();
             for blocked_client in blocked_clients.clone() {
                 if blocked_client.is_timed_out() {
                     log::debug!(
@@ -143,18 +222,19 @@ impl MemoryStorage {
                     continue;
                 }
 
-                let response = if blocked_on_list {
-                    self.response_blocked_on_list(blocked_client.clone(), key)
-                } else {
-                    self.response_blocked_on_stream(blocked_client.clone(), key)
-                };
-
-                if response.is_none() {
+                let Some(response) = self.response_blocked_on_list(blocked_client.clone(), key)
+                else {
                     continue;
-                }
-
-                let response = response.unwrap();
+                };
                 self.handle.unblock_client(blocked_client.token, response);
+                served.push(blocked_client.token);
+                // Replicas see the served client's pop as a plain LPOP/RPOP
+                let pop = if blocked_client.left_blocked() {
+                    RedisCommand::LPOP(key.to_string(), None)
+                } else {
+                    RedisCommand::RPOP(key.to_string(), None)
+                };
+                self.replicate_command(pop);
 
                 // If we successfully unblocked a client, we might have consumed the only element
                 // Check if there are more elements for remaining clients
@@ -172,9 +252,46 @@ impl MemoryStorage {
                     break;
                 }
             }
+            for token in served {
+                self.forget_blocked_client(token);
+            }
         }
     }
 
+    // An XADD doesn't consume anything, so every reader with new entries is
+    // woken while the others keep waiting
+    fn unblock_stream_readers(&mut self, key: &str, blocked_clients: Vec<BlockedClient>) {
+        let mut still_blocked = Vec::new();
+        let mut served = Vec::new();
+        for blocked_client in blocked_clients {
+            if blocked_client.is_timed_out() {
+                continue;
+            }
+            match self.response_blocked_on_stream(blocked_client.clone(), key) {
+                Some(response) => {
+                    self.handle.unblock_client(blocked_client.token, response);
+                    served.push(blocked_client.token);
+                }
+                None => still_blocked.push(blocked_client),
+            }
+        }
+        if !still_blocked.is_empty() {
+            self.blocked_clients.insert(key.to_string(), still_blocked);
+        }
+        for token in served {
+            self.forget_blocked_client(token);
+        }
+    }
+
+    // A client blocked on several keys waits on all of them until one serves
+    // it, it times out or it disconnects, after which none may serve it again
+    pub fn forget_blocked_client(&mut self, token: Token) {
+        self.blocked_clients.retain(|_, clients| {
+            clients.retain(|client| client.token != token);
+            !clients.is_empty()
+        });
+    }
+
     fn response_blocked_on_list(
         &mut self,
         blocked_client: BlockedClient,
@@ -207,9 +324,7 @@ impl MemoryStorage {
             {
                 if !list.is_empty() {
                     let item = list.remove(list.len() - 1);
-                    if list.is_empty() {
-                        self.storage.remove(key);
-                    }
+                    self.remove_if_empty(key);
                     Some(RedisResponse::Array(vec![
                         RedisResponse::BulkString(Some(key.to_string())),
                         RedisResponse::BulkString(Some(item)),
@@ -232,9 +347,25 @@ impl MemoryStorage {
             return None;
         }
 
-        let last_id = blocked_client.stream_id()?;
+        // Group reads take the entries over for the consumer instead of peeking
+        if let BlockedType::Group {
+            group,
+            consumer,
+            count,
+        } = &blocked_client.blocked_type
+        {
+            let entries = self.deliver_new_entries(key, group, consumer, *count);
+            if entries.is_empty() {
+                return None;
+            }
+            return Some(stream_read_response(key, entries));
+        }
 
-        if let Some(entries) = self.xrange(
+        let BlockedType::Stream(last_id, count) = &blocked_client.blocked_type else {
+            return None;
+        };
+
+        if let Some(mut entries) = self.xrange(
             key,
             StreamId::next(last_id).to_string(),
             StreamId {
@@ -244,22 +375,8 @@ impl MemoryStorage {
             .to_string(),
         ) {
             if !entries.is_empty() {
-                let mut response_entries = Vec::new();
-                for (entry_id, fields) in entries {
-                    let mut field_responses = Vec::new();
-                    for (field, value) in fields {
-                        field_responses.push(RedisResponse::BulkString(Some(field)));
-                        field_responses.push(RedisResponse::BulkString(Some(value)));
-                    }
-                    response_entries.push(RedisResponse::Array(vec![
-                        RedisResponse::BulkString(Some(entry_id)),
-                        RedisResponse::Array(field_responses),
-                    ]));
-                }
-                return Some(RedisResponse::Array(vec![RedisResponse::Array(vec![
-                    RedisResponse::BulkString(Some(key.to_string())),
-                    RedisResponse::Array(response_entries),
-                ])]));
+                entries.truncate(count.unwrap_or(usize::MAX));
+                return Some(stream_read_response(key, entries));
             }
         }
 
@@ -291,22 +408,170 @@ impl MemoryStorage {
                 log::debug!("Key '{}' has expired", key);
                 return "none".to_string();
             }
-            if unit.implementation.is_string() {
-                "string".to_string()
-            } else if unit.implementation.is_list() {
-                "list".to_string()
-            } else if unit.implementation.is_zset() {
-                "zset".to_string()
-            } else if unit.implementation.is_stream() {
-                "stream".to_string()
-            } else {
-                "unknown".to_string()
+            // Geo sets are sorted sets, so they report "zset" like in Redis
+            match unit.implementation {
+                Implementation::STRING(_) => "string",
+                Implementation::LIST(_) => "list",
+                Implementation::SET(_) => "set",
+                Implementation::HASH(_) => "hash",
+                Implementation::ZSET(_) => "zset",
+                Implementation::STREAM(_) => "stream",
             }
+            .to_string()
         } else {
             "none".to_string()
         }
     }
 
+    pub fn object_encoding(&self, key: &str) -> Option<String> {
+        let unit = self.storage.get(key)?;
+        if unit.is_expired() {
+            return None;
+        }
+
+        let encoding = match &unit.implementation {
+            Implementation::STRING(value) => {
+                if value.parse::<i64>().is_ok() {
+                    "int"
+                } else if value.len() <= EMBSTR_SIZE_LIMIT {
+                    "embstr"
+                } else {
+                    "raw"
+                }
+            }
+            Implementation::LIST(list) => {
+                if list.len() <= LIST_LISTPACK_MAX_ENTRIES
+                    && list.iter().all(|item| item.len() <= LIST_LISTPACK_MAX_VALUE)
+                {
+                    "listpack"
+                } else {
+                    "quicklist"
+                }
+            }
+            Implementation::ZSET(zset) => {
+                let config = &self.encoding_config;
+                if zset.len() <= config.zset_max_listpack_entries
+                    && zset
+                        .iter()
+                        .all(|m| m.member.len() <= config.zset_max_listpack_value)
+                {
+                    "listpack"
+                } else {
+                    "skiplist"
+                }
+            }
+            Implementation::STREAM(_) => "stream",
+            Implementation::HASH(hash) => {
+                let config = &self.encoding_config;
+                if hash.len() <= config.hash_max_listpack_entries
+                    && hash.iter().all(|(field, value)| {
+                        field.len() <= config.hash_max_listpack_value
+                            && value.len() <= config.hash_max_listpack_value
+                    })
+                {
+                    "listpack"
+                } else {
+                    "hashtable"
+                }
+            }
+            Implementation::SET(set) => {
+                if set.len() <= SET_MAX_INTSET_ENTRIES
+                    && set.iter().all(|member| member.parse::<i64>().is_ok())
+                {
+                    "intset"
+                } else if set.len() <= SET_LISTPACK_MAX_ENTRIES
+                    && set.iter().all(|member| member.len() <= SET_LISTPACK_MAX_VALUE)
+                {
+                    "listpack"
+                } else {
+                    "hashtable"
+                }
+            }
+        };
+        Some(encoding.to_string())
+    }
+
+    pub fn object_idletime(&self, key: &str) -> Option<u64> {
+        self.storage
+            .get(key)
+            .filter(|unit| !unit.is_expired())
+            .map(|unit| unit.idle_seconds())
+    }
+
+    // Lets tests age a key instead of sleeping through IDLETIME's seconds
+    #[cfg(test)]
+    pub fn backdate_access(&mut self, key: &str, idle: std::time::Duration) {
+        if let Some(unit) = self.storage.get_mut(key) {
+            unit.last_access = Instant::now() - idle;
+        }
+    }
+
+    pub fn touch(&mut self, keys: &[&str]) {
+        for key in keys {
+            if let Some(unit) = self.storage.get_mut(*key) {
+                unit.touch();
+            }
+        }
+    }
+
+    pub fn watch(&mut self, token: mio::Token, keys: Vec<String>) {
+        for key in keys {
+            self.watched_keys.entry(key).or_default().insert(token);
+        }
+    }
+
+    // Called as a write is propagated, so an EXEC after it finds its WATCH broken
+    pub fn signal_modified_keys(&mut self, keys: &[&str]) {
+        for key in keys {
+            if let Some(watchers) = self.watched_keys.get(*key) {
+                self.dirty_watchers.extend(watchers);
+            }
+        }
+    }
+
+    // Forget everything the client watched, telling whether any of it was written to
+    pub fn unwatch(&mut self, token: mio::Token) -> bool {
+        self.watched_keys.retain(|_, watchers| {
+            watchers.remove(&token);
+            !watchers.is_empty()
+        });
+        self.dirty_watchers.remove(&token)
+    }
+
+    // Fields reported by DEBUG OBJECT, in output order. Redis also prints the
+    // value's address, which is left out so the output is stable across runs.
+    fn debug_object_fields(&self, key: &str) -> Option<Vec<(&'static str, String)>> {
+        let encoding = self.object_encoding(key)?;
+        let unit = self.storage.get(key)?;
+        let idle = unit.idle_seconds();
+        let mut fields = vec![
+            ("refcount", "1".to_string()),
+            ("encoding", encoding),
+            (
+                "serializedlength",
+                FileUtils::serialized_length(&unit.implementation).to_string(),
+            ),
+            ("lru", "0".to_string()),
+            ("lru_seconds_idle", idle.to_string()),
+        ];
+        if let Some(stream) = unit.implementation.as_stream() {
+            fields.push(("entries_added", stream.entries_added.to_string()));
+            fields.push(("max_deleted_id", stream.max_deleted_id.to_string()));
+        }
+        Some(fields)
+    }
+
+    pub fn debug_object(&self, key: &str) -> Option<String> {
+        let fields = self.debug_object_fields(key)?;
+        Some(
+            fields
+                .iter()
+                .map(|(name, value)| format!("{}:{}", name, value))
+                .collect::<Vec<_>>()
+                .join(" "),
+        )
+    }
+
     pub fn read_from_persistent_storage(&mut self, dir: &str, dbfilename: &str) {
         self.dir = Some(dir.to_string());
         self.dbfilename = Some(dbfilename.to_string());
@@ -318,8 +583,8 @@ impl MemoryStorage {
                 dbfilename
             );
             match FileUtils::construct_db_from_file(dir, dbfilename) {
-                Some(loaded_storage) => {
-                    self.storage = loaded_storage;
+                Some(loaded_dbs) => {
+                    self.replace_keyspace(loaded_dbs);
                     log::info!(
                         "Successfully loaded {} keys from persistent storage",
                         self.storage.len()
@@ -334,15 +599,53 @@ impl MemoryStorage {
         log::info!("Persistent storage file not found or invalid. Starting with empty storage.");
     }
 
+    // Replace the keyspace with the snapshot a master sent after FULLRESYNC
+    pub fn load_snapshot(&mut self, payload: &[u8]) -> bool {
+        match FileUtils::parse_db(payload) {
+            Some(loaded_dbs) => {
+                self.replace_keyspace(loaded_dbs);
+                log::info!("Loaded {} keys from the master", self.storage.len());
+                true
+            }
+            None => {
+                log::error!("Error parsing the snapshot from the master");
+                false
+            }
+        }
+    }
+
+    fn replace_keyspace(&mut self, mut loaded_dbs: Vec<HashMap<String, Unit>>) {
+        // Only database 0 is served; keys in others are dropped
+        for (index, db) in loaded_dbs.iter().enumerate().skip(1) {
+            if !db.is_empty() {
+                log::warn!("Ignoring {} keys in database {}", db.len(), index);
+            }
+        }
+        self.storage = loaded_dbs.swap_remove(0);
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        FileUtils::serialize_db(std::slice::from_ref(&self.storage))
+    }
+
+    // Write the keyspace to `dir`/`dbfilename`, defaulting like Redis does
+    pub fn save(&self) -> std::io::Result<()> {
+        FileUtils::write_db_to_file(
+            self.dir.as_deref().unwrap_or("."),
+            self.dbfilename.as_deref().unwrap_or("dump.rdb"),
+            &self.storage,
+        )
+    }
+
     pub fn get_info_replication(&self) -> String {
-        return self.repl_config.to_string();
+        return self.repl_config.to_string(self.replication_clients.len());
     }
 
     pub fn add_subscriber(&mut self, token: mio::Token, channel: String) {
-        self.pubsub
-            .entry(channel)
-            .or_insert_with(Vec::new)
-            .push(token);
+        let subscribers = self.pubsub.entry(channel).or_default();
+        if !subscribers.contains(&token) {
+            subscribers.push(token);
+        }
     }
 
     pub fn get_subscriptions(&self, token: mio::Token) -> Vec<String> {
@@ -355,6 +658,29 @@ impl MemoryStorage {
         channels
     }
 
+    // Subscribed clients may only run the pub/sub commands
+    pub fn is_subscribed(&self, token: mio::Token) -> bool {
+        self.pubsub
+            .values()
+            .chain(self.pattern_pubsub.values())
+            .any(|subscribers| subscribers.contains(&token))
+    }
+
+    pub fn add_pattern_subscriber(&mut self, token: mio::Token, pattern: String) {
+        let subscribers = self.pattern_pubsub.entry(pattern).or_default();
+        if !subscribers.contains(&token) {
+            subscribers.push(token);
+        }
+    }
+
+    pub fn get_pattern_subscriptions(&self, token: mio::Token) -> Vec<String> {
+        self.pattern_pubsub
+            .iter()
+            .filter(|(_, subscribers)| subscribers.contains(&token))
+            .map(|(pattern, _)| pattern.clone())
+            .collect()
+    }
+
     pub fn get_channel_subscriptions(&self, channel: &str) -> Vec<mio::Token> {
         self.pubsub.get(channel).cloned().unwrap_or_else(Vec::new)
     }
@@ -370,4 +696,37 @@ impl MemoryStorage {
         }
         0
     }
+
+    // Channel and pattern subscriptions die with the connection
+    pub fn forget_subscriber(&mut self, token: mio::Token) {
+        for subscriptions in [&mut self.pubsub, &mut self.pattern_pubsub] {
+            subscriptions.retain(|_, subscribers| {
+                subscribers.retain(|&t| t != token);
+                !subscribers.is_empty()
+            });
+        }
+    }
+}
+
+// Reply delivered to a client woken up by new entries on a single stream
+fn stream_read_response(
+    key: &str,
+    entries: Vec<(String, Vec<(String, String)>)>,
+) -> RedisResponse {
+    let mut response_entries = Vec::new();
+    for (entry_id, fields) in entries {
+        let mut field_responses = Vec::new();
+        for (field, value) in fields {
+            field_responses.push(RedisResponse::BulkString(Some(field)));
+            field_responses.push(RedisResponse::BulkString(Some(value)));
+        }
+        response_entries.push(RedisResponse::Array(vec![
+            RedisResponse::BulkString(Some(entry_id)),
+            RedisResponse::Array(field_responses),
+        ]));
+    }
+    RedisResponse::Array(vec![RedisResponse::Array(vec![
+        RedisResponse::BulkString(Some(key.to_string())),
+        RedisResponse::Array(response_entries),
+    ])])
 }
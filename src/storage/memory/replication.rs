@@ -1,46 +1,265 @@
-use super::{MemoryStorage, Replication};
+use super::{MemoryStorage, ReplicaWait, Replication};
 use crate::commands::{RedisCommand, RedisResponse};
-use crate::storage::file_utils::FileUtils;
+use crate::storage::repl_config::ReplConfig;
 
 impl Replication for MemoryStorage {
     fn add_replication_client(&mut self, token: mio::Token) {
         self.replication_clients.insert(token);
     }
 
+    fn remove_replication_client(&mut self, token: mio::Token) {
+        self.replication_clients.remove(&token);
+        self.replica_ack_offsets.remove(&token);
+        self.replica_waiters.remove(&token);
+    }
+
+    fn replica_count(&self) -> usize {
+        self.replication_clients.len()
+    }
+
     fn send_file(&self, token: mio::Token) {
         if self.replication_clients.contains(&token) {
-            let contents = FileUtils::get_db_as_file();
-            self.handle.send_file(token, contents);
+            self.handle.send_file(token, self.serialize());
+        }
+    }
+
+    fn replicate_command(&mut self, command: crate::RedisCommand) {
+        // Only writes that changed the dataset are propagated, which is also
+        // when they break a WATCH on the keys they wrote
+        self.signal_modified_keys(&written_keys(&command));
+        let Some(resp) = self.command_to_response(command) else {
+            return;
+        };
+        match self.transaction_propagation.as_mut() {
+            Some(pending) => pending.push(resp),
+            None => self.propagate(resp),
         }
     }
 
-    fn replicate_command(&self, command: crate::RedisCommand) {
-        let resp = command_to_response(command).unwrap();
+    fn start_transaction_propagation(&mut self) {
+        self.transaction_propagation = Some(Vec::new());
+    }
+
+    fn end_transaction_propagation(&mut self) {
+        let Some(pending) = self.transaction_propagation.take() else {
+            return;
+        };
+        // Like Redis, only wrap when there is more than one write to keep atomic
+        let wrapped = pending.len() > 1;
+        if wrapped {
+            self.propagate(command_response("MULTI"));
+        }
+        for resp in pending {
+            self.propagate(resp);
+        }
+        if wrapped {
+            self.propagate(command_response("EXEC"));
+        }
+    }
+
+    fn ack_replica(&mut self, token: mio::Token, offset: u64) {
+        self.replica_ack_offsets.insert(token, offset);
+        let satisfied: Vec<(mio::Token, usize)> = self
+            .replica_waiters
+            .iter()
+            .map(|(&waiter, wait)| (waiter, wait, self.acked_replicas(wait.offset)))
+            .filter(|(_, wait, acked)| *acked >= wait.numreplicas)
+            .map(|(waiter, _, acked)| (waiter, acked))
+            .collect();
+        for (waiter, acked) in satisfied {
+            self.replica_waiters.remove(&waiter);
+            self.handle
+                .unblock_client(waiter, RedisResponse::Integer(acked as i64));
+        }
+    }
+
+    fn acked_replicas(&self, offset: u64) -> usize {
+        self.replication_clients
+            .iter()
+            .filter(|token| {
+                self.replica_ack_offsets
+                    .get(token)
+                    .is_some_and(|&acked| acked >= offset)
+            })
+            .count()
+    }
+
+    fn wait_for_replicas(
+        &mut self,
+        token: mio::Token,
+        offset: u64,
+        numreplicas: usize,
+        timeout: u64,
+    ) {
+        self.replica_waiters.insert(
+            token,
+            ReplicaWait {
+                offset,
+                numreplicas,
+            },
+        );
+        self.handle.block_client(token, timeout);
+        let getack = RedisResponse::Array(
+            ["REPLCONF", "GETACK", "*"]
+                .iter()
+                .map(|arg| RedisResponse::BulkString(Some(arg.to_string())))
+                .collect(),
+        );
+        self.send_to_replicas(getack);
+    }
+
+    fn take_wait_timeout(&mut self, token: mio::Token) -> Option<usize> {
+        let wait = self.replica_waiters.remove(&token)?;
+        Some(self.acked_replicas(wait.offset))
+    }
+}
+
+impl MemoryStorage {
+    fn propagate(&mut self, resp: RedisResponse) {
+        if let Some(log) = self.propagation_log.as_mut() {
+            log.push(response_args(&resp));
+        }
+        self.send_to_replicas(resp);
+    }
+
+    // Straight to replicas, keeping GETACK out of the propagation log
+    fn send_to_replicas(&mut self, resp: RedisResponse) {
+        // The master's offset counts every byte of the stream sent to
+        // replicas; a replica's offset only advances with what it applies
+        if let ReplConfig::Master(config) = &mut self.repl_config {
+            config.replication_offset += resp.to_resp().len() as u64;
+        }
         for &token in &self.replication_clients {
             self.handle.send_command(token, resp.clone());
         }
     }
+
+    // Record every propagated command from now on, for tests asserting on the
+    // replication stream
+    pub fn enable_propagation_log(&mut self) {
+        self.propagation_log.get_or_insert_with(Vec::new);
+    }
+
+    pub fn propagation_log(&self) -> &[Vec<String>] {
+        self.propagation_log.as_deref().unwrap_or_default()
+    }
+
+    fn command_to_response(&self, command: RedisCommand) -> Option<RedisResponse> {
+        match command {
+            // Relative expiries are propagated as the absolute deadline so
+            // replicas expire the key at the same moment as the master
+            RedisCommand::Set(key, value, options) => {
+                let deadline = (options.expiry.is_some() || options.keepttl)
+                    .then(|| self.storage.get(&key).and_then(|unit| unit.expiry))
+                    .flatten();
+                let mut array = vec![
+                    RedisResponse::SimpleString("SET".to_string()),
+                    RedisResponse::BulkString(Some(key)),
+                    RedisResponse::BulkString(Some(value)),
+                ];
+                if let Some(deadline) = deadline {
+                    array.push(RedisResponse::SimpleString("PXAT".to_string()));
+                    array.push(RedisResponse::SimpleString(deadline.to_string()));
+                }
+                Some(RedisResponse::Array(array))
+            }
+            // Every EXPIRE form reaches replicas as the resulting deadline,
+            // or as a DEL when it removed the key
+            RedisCommand::EXPIRE(key, _) => {
+                let array = match self.storage.get(&key).and_then(|unit| unit.expiry) {
+                    Some(deadline) => vec![
+                        RedisResponse::SimpleString("PEXPIREAT".to_string()),
+                        RedisResponse::BulkString(Some(key)),
+                        RedisResponse::SimpleString(deadline.to_string()),
+                    ],
+                    None => vec![
+                        RedisResponse::SimpleString("DEL".to_string()),
+                        RedisResponse::BulkString(Some(key)),
+                    ],
+                };
+                Some(RedisResponse::Array(array))
+            }
+            // Replicas store the resulting text instead of redoing the float
+            // addition, as Redis does
+            RedisCommand::INCRBYFLOAT(key, _) => {
+                let value = self.storage.get(&key)?.implementation.as_string()?.clone();
+                Some(RedisResponse::Array(vec![
+                    RedisResponse::SimpleString("SET".to_string()),
+                    RedisResponse::BulkString(Some(key)),
+                    RedisResponse::BulkString(Some(value)),
+                    RedisResponse::SimpleString("KEEPTTL".to_string()),
+                ]))
+            }
+            command => command_to_response(command),
+        }
+    }
+}
+
+// `NAME destination key [key ...]`
+fn store_response(name: &str, destination: String, keys: Vec<String>) -> RedisResponse {
+    RedisResponse::Array(
+        [name.to_string(), destination]
+            .into_iter()
+            .chain(keys)
+            .map(|arg| RedisResponse::BulkString(Some(arg)))
+            .collect(),
+    )
+}
+
+// The keys a propagated write changed. Stores and COPY leave their sources alone
+fn written_keys(command: &RedisCommand) -> Vec<&str> {
+    match command {
+        RedisCommand::COPY(_, destination, _)
+        | RedisCommand::ZRANGESTORE(destination, _, _, _, _)
+        | RedisCommand::SINTERSTORE(destination, _)
+        | RedisCommand::SUNIONSTORE(destination, _)
+        | RedisCommand::SDIFFSTORE(destination, _) => vec![destination],
+        _ => command.keys(),
+    }
+}
+
+fn command_response(name: &str) -> RedisResponse {
+    RedisResponse::Array(vec![RedisResponse::SimpleString(name.to_string())])
+}
+
+// The plain arguments of a propagated command
+fn response_args(resp: &RedisResponse) -> Vec<String> {
+    match resp {
+        RedisResponse::Array(items) => items
+            .iter()
+            .filter_map(|item| match item {
+                RedisResponse::SimpleString(s) | RedisResponse::BulkString(Some(s)) => {
+                    Some(s.clone())
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
 }
 
 fn command_to_response(command: RedisCommand) -> Option<RedisResponse> {
     match command {
-        RedisCommand::Set(key, value) => Some(RedisResponse::Array(vec![
-            RedisResponse::SimpleString("SET".to_string()),
-            RedisResponse::BulkString(Some(key)),
-            RedisResponse::BulkString(Some(value)),
-        ])),
-        RedisCommand::SetWithExpiry(key, value, expiry) => Some(RedisResponse::Array(vec![
-            RedisResponse::SimpleString("SET".to_string()),
-            RedisResponse::BulkString(Some(key)),
-            RedisResponse::BulkString(Some(value)),
-            RedisResponse::SimpleString("PX".to_string()),
-            RedisResponse::SimpleString(expiry.to_string()),
-        ])),
         RedisCommand::Del(keys) => Some(RedisResponse::Array(
             std::iter::once(RedisResponse::SimpleString("DEL".to_string()))
                 .chain(keys.into_iter().map(|k| RedisResponse::BulkString(Some(k))))
                 .collect(),
         )),
+        RedisCommand::UNLINK(keys) => Some(RedisResponse::Array(
+            std::iter::once(RedisResponse::SimpleString("UNLINK".to_string()))
+                .chain(keys.into_iter().map(|k| RedisResponse::BulkString(Some(k))))
+                .collect(),
+        )),
+        RedisCommand::MSET(pairs) => Some(RedisResponse::Array(
+            std::iter::once(RedisResponse::SimpleString("MSET".to_string()))
+                .chain(pairs.into_iter().flat_map(|(key, value)| {
+                    [
+                        RedisResponse::BulkString(Some(key)),
+                        RedisResponse::BulkString(Some(value)),
+                    ]
+                }))
+                .collect(),
+        )),
         RedisCommand::RPUSH(key, values) => Some(RedisResponse::Array(
             std::iter::once(RedisResponse::SimpleString("RPUSH".to_string()))
                 .chain(std::iter::once(RedisResponse::BulkString(Some(key))))
@@ -51,6 +270,51 @@ fn command_to_response(command: RedisCommand) -> Option<RedisResponse> {
                 )
                 .collect(),
         )),
+        RedisCommand::LPOP(key, count) => {
+            let mut array = vec![
+                RedisResponse::SimpleString("LPOP".to_string()),
+                RedisResponse::BulkString(Some(key)),
+            ];
+            if let Some(count) = count {
+                array.push(RedisResponse::SimpleString(count.to_string()));
+            }
+            Some(RedisResponse::Array(array))
+        }
+        RedisCommand::RPOP(key, count) => {
+            let mut array = vec![
+                RedisResponse::SimpleString("RPOP".to_string()),
+                RedisResponse::BulkString(Some(key)),
+            ];
+            if let Some(count) = count {
+                array.push(RedisResponse::SimpleString(count.to_string()));
+            }
+            Some(RedisResponse::Array(array))
+        }
+        RedisCommand::LSET(key, index, value) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("LSET".to_string()),
+            RedisResponse::BulkString(Some(key)),
+            RedisResponse::SimpleString(index.to_string()),
+            RedisResponse::BulkString(Some(value)),
+        ])),
+        RedisCommand::LTRIM(key, start, end) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("LTRIM".to_string()),
+            RedisResponse::BulkString(Some(key)),
+            RedisResponse::SimpleString(start.to_string()),
+            RedisResponse::SimpleString(end.to_string()),
+        ])),
+        RedisCommand::LREM(key, count, value) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("LREM".to_string()),
+            RedisResponse::BulkString(Some(key)),
+            RedisResponse::SimpleString(count.to_string()),
+            RedisResponse::BulkString(Some(value)),
+        ])),
+        RedisCommand::LINSERT(key, before, pivot, value) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("LINSERT".to_string()),
+            RedisResponse::BulkString(Some(key)),
+            RedisResponse::SimpleString(if before { "BEFORE" } else { "AFTER" }.to_string()),
+            RedisResponse::BulkString(Some(pivot)),
+            RedisResponse::BulkString(Some(value)),
+        ])),
         RedisCommand::LPUSH(key, values) => Some(RedisResponse::Array(
             std::iter::once(RedisResponse::SimpleString("LPUSH".to_string()))
                 .chain(std::iter::once(RedisResponse::BulkString(Some(key))))
@@ -65,23 +329,150 @@ fn command_to_response(command: RedisCommand) -> Option<RedisResponse> {
             RedisResponse::SimpleString("INCR".to_string()),
             RedisResponse::BulkString(Some(key)),
         ])),
-        RedisCommand::ZADD(key, score, value) => Some(RedisResponse::Array(vec![
+        RedisCommand::DECR(key) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("DECR".to_string()),
+            RedisResponse::BulkString(Some(key)),
+        ])),
+        RedisCommand::INCRBY(key, amount) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("INCRBY".to_string()),
+            RedisResponse::BulkString(Some(key)),
+            RedisResponse::SimpleString(amount.to_string()),
+        ])),
+        RedisCommand::DECRBY(key, amount) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("DECRBY".to_string()),
+            RedisResponse::BulkString(Some(key)),
+            RedisResponse::SimpleString(amount.to_string()),
+        ])),
+        RedisCommand::SETRANGE(key, offset, value) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("SETRANGE".to_string()),
+            RedisResponse::BulkString(Some(key)),
+            RedisResponse::SimpleString(offset.to_string()),
+            RedisResponse::BulkString(Some(value)),
+        ])),
+        RedisCommand::APPEND(key, value) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("APPEND".to_string()),
+            RedisResponse::BulkString(Some(key)),
+            RedisResponse::BulkString(Some(value)),
+        ])),
+        RedisCommand::ZADD(key, score, value, _) => Some(RedisResponse::Array(vec![
             RedisResponse::SimpleString("ZADD".to_string()),
             RedisResponse::BulkString(Some(key)),
             RedisResponse::SimpleString(score.to_string()),
             RedisResponse::BulkString(Some(value)),
         ])),
+        RedisCommand::ZRANGESTORE(destination, source, min, max, by_score) => {
+            let mut array = vec![
+                RedisResponse::SimpleString("ZRANGESTORE".to_string()),
+                RedisResponse::BulkString(Some(destination)),
+                RedisResponse::BulkString(Some(source)),
+                RedisResponse::BulkString(Some(min)),
+                RedisResponse::BulkString(Some(max)),
+            ];
+            if by_score {
+                array.push(RedisResponse::SimpleString("BYSCORE".to_string()));
+            }
+            Some(RedisResponse::Array(array))
+        }
+        RedisCommand::HSET(key, pairs) | RedisCommand::HMSET(key, pairs) => {
+            Some(RedisResponse::Array(
+                [
+                    RedisResponse::SimpleString("HSET".to_string()),
+                    RedisResponse::BulkString(Some(key)),
+                ]
+                .into_iter()
+                .chain(pairs.into_iter().flat_map(|(field, value)| {
+                    [
+                        RedisResponse::BulkString(Some(field)),
+                        RedisResponse::BulkString(Some(value)),
+                    ]
+                }))
+                .collect(),
+            ))
+        }
+        RedisCommand::HDEL(key, fields) => Some(RedisResponse::Array(
+            [
+                RedisResponse::SimpleString("HDEL".to_string()),
+                RedisResponse::BulkString(Some(key)),
+            ]
+            .into_iter()
+            .chain(
+                fields
+                    .into_iter()
+                    .map(|f| RedisResponse::BulkString(Some(f))),
+            )
+            .collect(),
+        )),
+        RedisCommand::SADD(key, members) => Some(RedisResponse::Array(
+            [
+                RedisResponse::SimpleString("SADD".to_string()),
+                RedisResponse::BulkString(Some(key)),
+            ]
+            .into_iter()
+            .chain(
+                members
+                    .into_iter()
+                    .map(|m| RedisResponse::BulkString(Some(m))),
+            )
+            .collect(),
+        )),
+        RedisCommand::SREM(key, members) => Some(RedisResponse::Array(
+            [
+                RedisResponse::SimpleString("SREM".to_string()),
+                RedisResponse::BulkString(Some(key)),
+            ]
+            .into_iter()
+            .chain(
+                members
+                    .into_iter()
+                    .map(|m| RedisResponse::BulkString(Some(m))),
+            )
+            .collect(),
+        )),
+        RedisCommand::SINTERSTORE(destination, keys) => {
+            Some(store_response("SINTERSTORE", destination, keys))
+        }
+        RedisCommand::SUNIONSTORE(destination, keys) => {
+            Some(store_response("SUNIONSTORE", destination, keys))
+        }
+        RedisCommand::SDIFFSTORE(destination, keys) => {
+            Some(store_response("SDIFFSTORE", destination, keys))
+        }
+        RedisCommand::RENAME(key, destination) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("RENAME".to_string()),
+            RedisResponse::BulkString(Some(key)),
+            RedisResponse::BulkString(Some(destination)),
+        ])),
+        RedisCommand::COPY(key, destination, replace) => {
+            let mut args = vec![
+                RedisResponse::SimpleString("COPY".to_string()),
+                RedisResponse::BulkString(Some(key)),
+                RedisResponse::BulkString(Some(destination)),
+            ];
+            if replace {
+                args.push(RedisResponse::BulkString(Some("REPLACE".to_string())));
+            }
+            Some(RedisResponse::Array(args))
+        }
+        RedisCommand::PERSIST(key) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("PERSIST".to_string()),
+            RedisResponse::BulkString(Some(key)),
+        ])),
         RedisCommand::ZREM(key, value) => Some(RedisResponse::Array(vec![
             RedisResponse::SimpleString("ZREM".to_string()),
             RedisResponse::BulkString(Some(key)),
             RedisResponse::BulkString(Some(value)),
         ])),
-        RedisCommand::XADD(key, id, entries) => {
+        RedisCommand::XADD(key, id, entries, maxlen) => {
             let mut array = vec![
                 RedisResponse::SimpleString("XADD".to_string()),
                 RedisResponse::BulkString(Some(key)),
             ];
 
+            if let Some(maxlen) = maxlen {
+                array.push(RedisResponse::BulkString(Some("MAXLEN".to_string())));
+                array.push(RedisResponse::BulkString(Some(maxlen.to_string())));
+            }
+
             if let Some(stream_id) = id {
                 array.push(RedisResponse::BulkString(Some(stream_id)));
             } else {
@@ -95,6 +486,85 @@ fn command_to_response(command: RedisCommand) -> Option<RedisResponse> {
 
             Some(RedisResponse::Array(array))
         }
+        RedisCommand::XDEL(key, ids) => Some(RedisResponse::Array(
+            [
+                RedisResponse::SimpleString("XDEL".to_string()),
+                RedisResponse::BulkString(Some(key)),
+            ]
+            .into_iter()
+            .chain(
+                ids.into_iter()
+                    .map(|id| RedisResponse::BulkString(Some(id))),
+            )
+            .collect(),
+        )),
+        RedisCommand::XGROUPCREATE(key, group, id, mkstream) => {
+            let mut array = vec![
+                RedisResponse::SimpleString("XGROUP".to_string()),
+                RedisResponse::SimpleString("CREATE".to_string()),
+                RedisResponse::BulkString(Some(key)),
+                RedisResponse::BulkString(Some(group)),
+                RedisResponse::BulkString(Some(id)),
+            ];
+            if mkstream {
+                array.push(RedisResponse::SimpleString("MKSTREAM".to_string()));
+            }
+            Some(RedisResponse::Array(array))
+        }
+        RedisCommand::XGROUPSETID(key, group, id, entries_read) => {
+            let mut array = vec![
+                RedisResponse::SimpleString("XGROUP".to_string()),
+                RedisResponse::SimpleString("SETID".to_string()),
+                RedisResponse::BulkString(Some(key)),
+                RedisResponse::BulkString(Some(group)),
+                RedisResponse::BulkString(Some(id)),
+            ];
+            if let Some(entries_read) = entries_read {
+                array.push(RedisResponse::SimpleString("ENTRIESREAD".to_string()));
+                array.push(RedisResponse::SimpleString(entries_read.to_string()));
+            }
+            Some(RedisResponse::Array(array))
+        }
+        RedisCommand::XCLAIM(key, group, consumer, min_idle_time, ids, options) => {
+            let mut array = vec![
+                RedisResponse::SimpleString("XCLAIM".to_string()),
+                RedisResponse::BulkString(Some(key)),
+                RedisResponse::BulkString(Some(group)),
+                RedisResponse::BulkString(Some(consumer)),
+                RedisResponse::SimpleString(min_idle_time.to_string()),
+            ];
+            array.extend(
+                ids.into_iter()
+                    .map(|id| RedisResponse::BulkString(Some(id))),
+            );
+            let numeric = [
+                ("IDLE", options.idle),
+                ("TIME", options.time),
+                ("RETRYCOUNT", options.retrycount),
+            ];
+            for (name, value) in numeric {
+                if let Some(value) = value {
+                    array.push(RedisResponse::SimpleString(name.to_string()));
+                    array.push(RedisResponse::SimpleString(value.to_string()));
+                }
+            }
+            for (name, set) in [("FORCE", options.force), ("JUSTID", options.justid)] {
+                if set {
+                    array.push(RedisResponse::SimpleString(name.to_string()));
+                }
+            }
+            if let Some(lastid) = options.lastid {
+                array.push(RedisResponse::SimpleString("LASTID".to_string()));
+                array.push(RedisResponse::BulkString(Some(lastid)));
+            }
+            Some(RedisResponse::Array(array))
+        }
+        RedisCommand::XTRIM(key, strategy, threshold) => Some(RedisResponse::Array(vec![
+            RedisResponse::SimpleString("XTRIM".to_string()),
+            RedisResponse::BulkString(Some(key)),
+            RedisResponse::SimpleString(strategy),
+            RedisResponse::BulkString(Some(threshold)),
+        ])),
         RedisCommand::GEOADD(key, longitude, latitude, member) => Some(RedisResponse::Array(vec![
             RedisResponse::SimpleString("GEOADD".to_string()),
             RedisResponse::BulkString(Some(key)),
@@ -1,5 +1,15 @@
+use rand::seq::IteratorRandom;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
 use super::{MemoryStorage, Storage, Unit};
-use regex;
+use crate::commands::BitRange;
+use crate::storage::encoding_config::EncodingConfig;
+use crate::storage::glob::glob_match;
+
+// Redis caps string values at 512MB
+const MAX_STRING_SIZE: usize = 512 * 1024 * 1024;
+const NOT_AN_INTEGER: &str = "value is not an integer or out of range";
+const NOT_A_FLOAT: &str = "value is not a valid float";
 
 impl Storage for MemoryStorage {
     fn get(&mut self, key: &str) -> Option<String> {
@@ -20,19 +30,108 @@ impl Storage for MemoryStorage {
         self.storage.insert(key, unit);
     }
 
-    fn set_with_expiry(&mut self, key: String, value: String, expiry: u128) {
-        log::debug!("Setting expiry for key '{}' to {}", key, expiry);
-        let unit = Unit::new_string(
-            value,
-            Some(
-                expiry
-                    + std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis(),
-            ),
-        );
-        self.storage.insert(key.clone(), unit);
+    fn set_keep_ttl(&mut self, key: String, value: String) {
+        let expiry = self
+            .storage
+            .get(&key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| unit.expiry);
+        self.storage.insert(key, Unit::new_string(value, expiry));
+    }
+
+    fn set_with_expiry(&mut self, key: String, value: String, deadline: u128) {
+        log::debug!("Setting expiry for key '{}' to {}", key, deadline);
+        self.storage.insert(key, Unit::new_string(value, Some(deadline)));
+    }
+
+    fn set_expiry(&mut self, key: &str, deadline: Option<u128>) -> bool {
+        match self.storage.get_mut(key).filter(|unit| !unit.is_expired()) {
+            Some(unit) => {
+                unit.expiry = deadline;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expire(&mut self, key: &str, deadline: u128) -> bool {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        if deadline > now {
+            return self.set_expiry(key, Some(deadline));
+        }
+        let live = self
+            .storage
+            .get(key)
+            .is_some_and(|unit| !unit.is_expired());
+        self.delete(key);
+        live
+    }
+
+    fn persist(&mut self, key: &str) -> bool {
+        self.storage
+            .get_mut(key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| unit.expiry.take())
+            .is_some()
+    }
+
+    fn rename(&mut self, key: &str, destination: String) -> Result<(), String> {
+        match self.storage.remove(key) {
+            Some(unit) if !unit.is_expired() => {
+                self.storage.insert(destination, unit);
+                Ok(())
+            }
+            _ => Err("no such key".to_string()),
+        }
+    }
+
+    fn copy(&mut self, key: &str, destination: String, replace: bool) -> bool {
+        let Some(mut unit) = self
+            .storage
+            .get(key)
+            .filter(|unit| !unit.is_expired())
+            .cloned()
+        else {
+            return false;
+        };
+        let occupied = self
+            .storage
+            .get(&destination)
+            .is_some_and(|unit| !unit.is_expired());
+        if occupied && !replace {
+            return false;
+        }
+        unit.touch();
+        self.storage.insert(destination, unit);
+        true
+    }
+
+    fn ttl(&self, key: &str) -> i64 {
+        match self.pttl(key) {
+            remaining if remaining < 0 => remaining,
+            // Rounded to the nearest second like Redis
+            remaining => (remaining + 500) / 1000,
+        }
+    }
+
+    fn pttl(&self, key: &str) -> i64 {
+        match self.storage.get(key).filter(|unit| !unit.is_expired()) {
+            Some(Unit {
+                expiry: Some(expiry),
+                ..
+            }) => {
+                let now = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_millis();
+                expiry.saturating_sub(now) as i64
+            }
+            Some(_) => -1,
+            None => -2,
+        }
     }
 
     fn delete(&mut self, key: &str) -> bool {
@@ -40,86 +139,383 @@ impl Storage for MemoryStorage {
         self.storage.remove(key).is_some()
     }
 
-    fn exists(&self, key: &str) -> bool {
-        self.storage.contains_key(key)
+    fn exists(&mut self, key: &str) -> bool {
+        match self.storage.get(key) {
+            Some(unit) if unit.is_expired() => {
+                log::debug!("Key '{}' has expired", key);
+                self.delete(key);
+                false
+            }
+            Some(_) => true,
+            None => false,
+        }
     }
 
     fn delete_multiple(&mut self, keys: Vec<String>) -> usize {
         let mut deleted = 0;
         for key in keys {
-            if self.delete(&key) {
+            // Expired keys are dropped without counting as deleted
+            if self.exists(&key) && self.delete(&key) {
                 deleted += 1;
             }
         }
         deleted
     }
 
-    fn exists_multiple(&self, keys: &[String]) -> usize {
+    fn exists_multiple(&mut self, keys: &[String]) -> usize {
         keys.iter().filter(|key| self.exists(key)).count()
     }
 
-    fn incr(&mut self, key: String) -> Option<i64> {
-        log::debug!("Incrementing value for key '{}'", key);
+    fn get_multiple(&self, keys: &[String]) -> Vec<Option<String>> {
+        keys.iter()
+            .map(|key| self.live_string(key).cloned())
+            .collect()
+    }
+
+    fn set_multiple(&mut self, pairs: Vec<(String, String)>) {
+        log::debug!("Setting {} keys", pairs.len());
+        for (key, value) in pairs {
+            self.set(key, value);
+        }
+    }
+
+    fn getset(&mut self, key: String, value: String) -> Option<String> {
+        let previous = self.live_string(&key).cloned();
+        self.set(key, value);
+        previous
+    }
+
+    fn getdel(&mut self, key: &str) -> Option<String> {
+        let value = self.live_string(key).cloned()?;
+        self.delete(key);
+        Some(value)
+    }
+
+    fn setnx(&mut self, key: String, value: String) -> bool {
+        if self.exists(&key) {
+            return false;
+        }
+        self.set(key, value);
+        true
+    }
+
+    fn incr_by(&mut self, key: String, delta: i64) -> Result<i64, String> {
+        log::debug!("Incrementing value for key '{}' by {}", key, delta);
         if let Some(unit) = self.storage.get_mut(&key) {
             if unit.implementation.is_string() {
                 if let Some(current_value) = unit.implementation.as_string() {
                     if unit.is_expired() {
                         log::debug!("Key '{}' has expired", key);
                         self.delete(&key);
-                        // Initialize to 1 if expired
-                        let unit = Unit::new_string("1".to_string(), None);
+                        // Start over from 0 if expired
+                        let unit = Unit::new_string(delta.to_string(), None);
                         self.storage.insert(key, unit);
-                        return Some(1);
+                        return Ok(delta);
                     }
 
-                    match current_value.parse::<i64>() {
-                        Ok(num) => {
-                            if let Some(new_value) = num.checked_add(1) {
-                                unit.implementation =
-                                    Unit::new_string(new_value.to_string(), unit.expiry)
-                                        .implementation;
-                                return Some(new_value);
-                            } else {
-                                return None; // Integer overflow occurred
-                            }
-                        }
-                        Err(_) => return None, // Value is not an integer
-                    }
+                    // Like Redis, only the canonical form counts: no sign
+                    // other than '-', no leading zeros and no "-0"
+                    let num = current_value
+                        .parse::<i64>()
+                        .ok()
+                        .filter(|num| num.to_string() == *current_value)
+                        .ok_or_else(|| NOT_AN_INTEGER.to_string())?;
+                    let new_value = num
+                        .checked_add(delta)
+                        .ok_or_else(|| "increment or decrement would overflow".to_string())?;
+                    // Rewritten in place so the TTL survives; a write resets the idle time
+                    unit.implementation =
+                        Unit::new_string(new_value.to_string(), unit.expiry).implementation;
+                    unit.touch();
+                    return Ok(new_value);
                 }
-                return None;
+                Err(NOT_AN_INTEGER.to_string())
             } else {
-                return None; // Value is not a string
+                Err(NOT_AN_INTEGER.to_string()) // Value is not a string
             }
         } else {
-            // Key does not exist, initialize to 1
-            let unit = Unit::new_string("1".to_string(), None);
+            // Key does not exist, count from 0
+            let unit = Unit::new_string(delta.to_string(), None);
             self.storage.insert(key, unit);
-            return Some(1);
+            Ok(delta)
+        }
+    }
+
+    fn incr_by_float(&mut self, key: String, delta: f64) -> Result<String, String> {
+        let current = match self.storage.get(&key).filter(|unit| !unit.is_expired()) {
+            Some(unit) => unit
+                .implementation
+                .as_string()
+                .and_then(|value| parse_float(value))
+                .ok_or_else(|| NOT_A_FLOAT.to_string())?,
+            None => 0.0,
+        };
+        let sum = current + delta;
+        if !sum.is_finite() {
+            return Err("increment would produce NaN or Infinity".to_string());
+        }
+        // Rust prints the shortest representation that round-trips, so
+        // 10.5 + 0.1 is stored as "10.6" and whole numbers lose their ".0"
+        let value = sum.to_string();
+        match self.storage.get_mut(&key).filter(|unit| !unit.is_expired()) {
+            Some(unit) => {
+                unit.implementation = Unit::new_string(value.clone(), unit.expiry).implementation;
+                unit.touch();
+            }
+            None => {
+                self.storage
+                    .insert(key, Unit::new_string(value.clone(), None));
+            }
         }
+        Ok(value)
     }
 
-    fn config_get(&self, parameter: &str) -> Option<String> {
+    // String commands work on the stored text, even for values reported with the "int" encoding
+    fn strlen(&self, key: &str) -> usize {
+        self.storage
+            .get(key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| unit.implementation.as_string())
+            .map_or(0, |value| value.len())
+    }
+
+    fn getrange(&self, key: &str, start: i64, end: i64) -> String {
+        log::debug!("GETRANGE on key '{}', start {}, end {}", key, start, end);
+        let value = match self
+            .storage
+            .get(key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| unit.implementation.as_string())
+        {
+            Some(value) => value.as_bytes(),
+            None => return String::new(),
+        };
+
+        match byte_range(value.len(), start, end) {
+            Some((start, end)) => String::from_utf8_lossy(&value[start..=end]).to_string(),
+            None => String::new(),
+        }
+    }
+
+    fn setrange(&mut self, key: String, offset: usize, value: String) -> Result<usize, String> {
+        log::debug!("SETRANGE on key '{}', offset {}", key, offset);
+        let (mut bytes, expiry) = match self
+            .storage
+            .get(&key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| Some((unit.implementation.as_string()?, unit.expiry)))
+        {
+            Some((current, expiry)) => (current.clone().into_bytes(), expiry),
+            None => (Vec::new(), None),
+        };
+
+        if value.is_empty() {
+            return Ok(bytes.len());
+        }
+        if offset + value.len() > MAX_STRING_SIZE {
+            return Err("string exceeds maximum allowed size (proto-max-bulk-len)".to_string());
+        }
+
+        // Gaps between the current end and the offset are zero-padded
+        if bytes.len() < offset + value.len() {
+            bytes.resize(offset + value.len(), 0);
+        }
+        bytes[offset..offset + value.len()].copy_from_slice(value.as_bytes());
+
+        let length = bytes.len();
+        let value = String::from_utf8_lossy(&bytes).to_string();
+        self.storage.insert(key, Unit::new_string(value, expiry));
+        Ok(length)
+    }
+
+    fn append(&mut self, key: String, value: &str) -> Result<usize, String> {
+        log::debug!("Appending {} bytes to key '{}'", value.len(), key);
+        if self.live_string(&key).is_none() {
+            self.delete(&key);
+            self.storage
+                .insert(key.clone(), Unit::new_string(String::new(), None));
+        }
+        let Some(unit) = self.storage.get_mut(&key) else {
+            return Ok(0);
+        };
+        let Some(current) = unit.implementation.as_string_mut() else {
+            return Ok(0);
+        };
+        if current.len() + value.len() > MAX_STRING_SIZE {
+            return Err("string exceeds maximum allowed size (proto-max-bulk-len)".to_string());
+        }
+        current.push_str(value);
+        let length = current.len();
+        unit.touch();
+        Ok(length)
+    }
+
+    fn bitcount(&self, key: &str, range: Option<&BitRange>) -> usize {
+        log::debug!("BITCOUNT on key '{}', range {:?}", key, range);
+        let Some(value) = self.live_string(key) else {
+            return 0;
+        };
+        let bytes = value.as_bytes();
+        bit_span(bytes.len(), range)
+            .map_or(0, |(start, end)| (start..=end).filter(|&i| bit_at(bytes, i)).count())
+    }
+
+    fn bitpos(&self, key: &str, bit: bool, range: Option<&BitRange>) -> i64 {
+        log::debug!("BITPOS on key '{}', bit {}, range {:?}", key, bit, range);
+        // A missing key is all zeros
+        let Some(value) = self.live_string(key) else {
+            return if bit { -1 } else { 0 };
+        };
+        let bytes = value.as_bytes();
+        let Some((start, end)) = bit_span(bytes.len(), range) else {
+            return -1;
+        };
+        if let Some(pos) = (start..=end).find(|&i| bit_at(bytes, i) == bit) {
+            return pos as i64;
+        }
+        // Without an explicit end the string is treated as padded with zeros,
+        // so a clear bit is found just past it
+        if !bit && range.is_none_or(|range| range.end.is_none()) {
+            return end as i64 + 1;
+        }
+        -1
+    }
+
+    fn config_get(&self, pattern: &str) -> Vec<(String, String)> {
+        let pattern = pattern.to_lowercase();
+        let parameters = [
+            ("dir", self.dir.clone()),
+            ("dbfilename", self.dbfilename.clone()),
+        ]
+        .into_iter()
+        .chain(
+            EncodingConfig::PARAMETERS
+                .into_iter()
+                .map(|name| (name, self.encoding_config.get(name))),
+        );
+
+        parameters
+            .filter(|(name, _)| glob_match(&pattern, name))
+            .filter_map(|(name, value)| Some((name.to_string(), value?)))
+            .collect()
+    }
+
+    fn config_set(&mut self, parameter: &str, value: &str) -> Result<(), String> {
         match parameter.to_lowercase().as_str() {
-            "dir" => self.dir.clone(),
-            "dbfilename" => self.dbfilename.clone(),
-            _ => None,
+            "dir" => self.dir = Some(value.to_string()),
+            "dbfilename" => self.dbfilename = Some(value.to_string()),
+            parameter => self.encoding_config.set(parameter, value)?,
         }
+        Ok(())
     }
 
     fn get_keys(&self, pattern: &str) -> Vec<String> {
-        let regex_pattern = pattern.replace("*", ".*").replace("?", ".");
-        let regex = match regex::Regex::new(&format!("^{}$", regex_pattern)) {
-            Ok(r) => r,
-            Err(e) => {
-                log::error!("Invalid pattern '{}': {}", pattern, e);
-                return vec![];
-            }
+        self.live_keys()
+            .filter(|key| glob_match(pattern, key))
+            .cloned()
+            .collect()
+    }
+
+    // Cursors are key hashes: a page holds the keys hashing from the cursor
+    // up, and the next cursor is the hash of the first key left out, so keys
+    // added or removed meanwhile don't shift the rest of the scan. Like
+    // Redis, COUNT bounds how many keys are visited and MATCH filters them
+    // afterwards, so a page can come back empty before the scan is over.
+    fn scan(&self, cursor: usize, pattern: Option<&str>, count: usize) -> (usize, Vec<String>) {
+        let mut keys: Vec<(usize, &String)> = self
+            .live_keys()
+            .map(|key| (scan_cursor(key), key))
+            .filter(|(hash, _)| *hash >= cursor)
+            .collect();
+        // Only the page and the key after it need ordering
+        if keys.len() > count {
+            keys.select_nth_unstable(count);
+            keys.truncate(count + 1);
+        }
+        keys.sort_unstable();
+
+        let next = if keys.len() > count {
+            keys.pop().map_or(0, |(hash, _)| hash)
+        } else {
+            0
         };
+        let page = keys
+            .into_iter()
+            .filter(|(_, key)| pattern.is_none_or(|pattern| glob_match(pattern, key)))
+            .map(|(_, key)| key.clone())
+            .collect();
+        (next, page)
+    }
+
+    fn random_key(&self) -> Option<String> {
+        self.live_keys().choose(&mut rand::rng()).cloned()
+    }
+}
 
+impl MemoryStorage {
+    fn live_string(&self, key: &str) -> Option<&String> {
         self.storage
-            .keys()
-            .filter(|key| regex.is_match(key))
-            .cloned()
-            .collect()
+            .get(key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| unit.implementation.as_string())
+    }
+
+    fn live_keys(&self) -> impl Iterator<Item = &String> {
+        self.storage
+            .iter()
+            .filter(|(_, unit)| !unit.is_expired())
+            .map(|(key, _)| key)
+    }
+}
+
+// Resolve byte range indices the way Redis does: negative indices count from the
+// end, a start before the beginning clamps to 0, an end past the last byte
+// clamps to it, and anything left with start > end is an empty range
+fn byte_range(len: usize, start: i64, end: i64) -> Option<(usize, usize)> {
+    if len == 0 {
+        return None;
+    }
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.clamp(0, len - 1);
+    if start > end {
+        return None;
     }
+    Some((start as usize, end as usize))
+}
+
+// The inclusive bit indices a BITCOUNT/BITPOS range covers. Byte ranges cover
+// every bit of their bytes; BIT ranges resolve negative indices against the
+// length in bits.
+fn bit_span(len: usize, range: Option<&BitRange>) -> Option<(usize, usize)> {
+    let Some(range) = range else {
+        return (len > 0).then(|| (0, len * 8 - 1));
+    };
+    let end = range.end.unwrap_or(-1);
+    if range.bit {
+        byte_range(len * 8, range.start, end)
+    } else {
+        byte_range(len, range.start, end).map(|(start, end)| (start * 8, end * 8 + 7))
+    }
+}
+
+// Where SCAN finds a key; never 0, which starts and ends a scan
+fn scan_cursor(key: &str) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() as usize).max(1)
+}
+
+// Bits are numbered from the most significant bit of the first byte
+fn bit_at(bytes: &[u8], index: usize) -> bool {
+    bytes[index / 8] & (0x80 >> (index % 8)) != 0
+}
+
+// Stored floats are plain decimal or exponent notation, without the
+// "inf"/"nan" spellings Rust would also accept
+fn parse_float(value: &str) -> Option<f64> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
 }
@@ -1,4 +1,5 @@
 use super::{MemoryStorage, Storage, StorageGeo};
+use crate::storage::geo_shape::GeoShape;
 use crate::storage::zset_member::ZSetMember;
 
 impl StorageGeo for MemoryStorage {
@@ -133,50 +134,46 @@ impl StorageGeo for MemoryStorage {
         key: &str,
         longitude: f64,
         latitude: f64,
-        use_radius: bool,
-        distance: f64,
+        shape: GeoShape,
         unit: String,
     ) -> Option<Vec<String>> {
         log::debug!(
-            "Searching geo set '{}' from point ({}, {}) with distance {} {}",
+            "Searching geo set '{}' from point ({}, {}) within {:?} {}",
             key,
             longitude,
             latitude,
-            distance,
+            shape,
             unit
         );
 
         let dist_unit = DistUnit::from_str(unit)?;
-        let distance_meters = GeoUtils::convert_distance(distance, dist_unit);
-
-        let unit = self.storage.get(key);
-        match unit {
-            Some(u) => {
-                if u.is_expired() || !u.implementation.is_zset() {
-                    log::debug!("Key '{}' has expired or is not a geo set", key);
-                    return None;
-                }
+        let unit = self.storage.get(key)?;
+        if unit.is_expired() || !unit.implementation.is_zset() {
+            log::debug!("Key '{}' has expired or is not a geo set", key);
+            return None;
+        }
 
-                if let Some(zset) = u.implementation.as_zset() {
-                    let center_score = GeoUtils::calculate_score(longitude, latitude);
-                    let mut results = Vec::new();
-
-                    for member in zset.iter() {
-                        let dist = GeoUtils::calculate_distance(center_score, member.score);
-                        if (use_radius && dist <= distance_meters)
-                            || (!use_radius && dist == distance_meters)
-                        {
-                            results.push(member.member.clone());
-                        }
+        let zset = unit.implementation.as_zset()?;
+        let results = zset
+            .iter()
+            .filter(|member| {
+                let (member_lon, member_lat) = GeoUtils::decode_score(member.score);
+                match shape {
+                    GeoShape::Radius(radius) => {
+                        GeoUtils::haversine(longitude, latitude, member_lon, member_lat)
+                            <= dist_unit.to_meters(radius)
                     }
-
-                    Some(results)
-                } else {
-                    None
+                    GeoShape::Box(width, height) => GeoUtils::in_box(
+                        (longitude, latitude),
+                        (member_lon, member_lat),
+                        dist_unit.to_meters(width),
+                        dist_unit.to_meters(height),
+                    ),
                 }
-            }
-            None => None,
-        }
+            })
+            .map(|member| member.member.clone())
+            .collect();
+        Some(results)
     }
 }
 
@@ -188,6 +185,15 @@ enum DistUnit {
 }
 
 impl DistUnit {
+    pub fn to_meters(&self, distance: f64) -> f64 {
+        match self {
+            DistUnit::Meters => distance,
+            DistUnit::Kilometers => distance * 1000.0,
+            DistUnit::Miles => distance * 1609.344,
+            DistUnit::Feet => distance * 0.3048,
+        }
+    }
+
     pub fn from_str(unit: String) -> Option<Self> {
         match unit.to_lowercase().as_str() {
             "m" => Some(DistUnit::Meters),
@@ -207,6 +213,8 @@ impl GeoUtils {
     const MIN_LONGITUDE: f64 = -180.0;
     const MAX_LONGITUDE: f64 = 180.0;
 
+    const EARTH_RADIUS: f64 = 6372797.560856; // meters, as used by Redis
+
     const LATITUDE_RANGE: f64 = Self::MAX_LATITUDE - Self::MIN_LATITUDE;
     const LONGITUDE_RANGE: f64 = Self::MAX_LONGITUDE - Self::MIN_LONGITUDE;
 
@@ -292,24 +300,29 @@ impl GeoUtils {
     pub fn calculate_distance(from: f64, to: f64) -> f64 {
         let (lon1, lat1) = Self::decode_score(from);
         let (lon2, lat2) = Self::decode_score(to);
+        Self::haversine(lon1, lat1, lon2, lat2)
+    }
+
+    pub fn haversine(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
         let lat1_rad = lat1.to_radians();
         let lat2_rad = lat2.to_radians();
         let d_lat = lat2_rad - lat1_rad;
         let d_lon = (lon2 - lon1).to_radians();
-        const R: f64 = 6372797.560856; // Radius of the Earth in meters
         let a = (d_lat / 2.0).sin().powi(2)
             + lat1_rad.cos() * lat2_rad.cos() * (d_lon / 2.0).sin().powi(2);
         let c = 2.0 * a.sqrt().asin();
-        R * c
+        Self::EARTH_RADIUS * c
     }
 
-    pub fn convert_distance(distance_meters: f64, unit: DistUnit) -> f64 {
-        match unit {
-            DistUnit::Meters => distance_meters,
-            DistUnit::Kilometers => distance_meters / 1000.0,
-            DistUnit::Miles => distance_meters / 1609.344,
-            DistUnit::Feet => distance_meters * 3.28084,
+    // Like Redis, a point is in the box when its north-south distance from the
+    // center is within half the height and its east-west distance, measured
+    // along the point's own latitude, is within half the width
+    pub fn in_box(center: (f64, f64), point: (f64, f64), width: f64, height: f64) -> bool {
+        let lat_distance = Self::EARTH_RADIUS * (point.1 - center.1).to_radians().abs();
+        if lat_distance > height / 2.0 {
+            return false;
         }
+        Self::haversine(point.0, point.1, center.0, point.1) <= width / 2.0
     }
 }
 
@@ -366,7 +379,7 @@ mod tests {
         let score2 = GeoUtils::calculate_score(-118.4, 33.94);
         let distance = GeoUtils::calculate_distance(score1, score2);
         assert!(
-            (distance - 2886444.062365684).abs() < 1e-6,
+            (distance - 2887258.464949194).abs() < 1e-6,
             "Distance in meters should match"
         );
     }
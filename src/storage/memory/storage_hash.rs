@@ -0,0 +1,86 @@
+use super::{MemoryStorage, Storage, StorageHash, Unit};
+use crate::storage::hash_member::OrderedHash;
+
+impl StorageHash for MemoryStorage {
+    fn hset(&mut self, key: String, pairs: Vec<(String, String)>) -> usize {
+        log::debug!("Setting {} fields in hash '{}'", pairs.len(), key);
+        let live_hash = self
+            .storage
+            .get(&key)
+            .is_some_and(|unit| !unit.is_expired() && unit.implementation.is_hash());
+        if !live_hash {
+            log::debug!("Key '{}' has expired or is not a hash", key);
+            self.delete(&key);
+            self.storage
+                .insert(key.clone(), Unit::new_hash(OrderedHash::new(), None));
+        }
+
+        let hash = match self
+            .storage
+            .get_mut(&key)
+            .and_then(|unit| unit.implementation.as_hash_mut())
+        {
+            Some(hash) => hash,
+            None => return 0,
+        };
+        let mut added = 0;
+        for (field, value) in pairs {
+            if hash.insert(field, value).is_none() {
+                added += 1;
+            }
+        }
+        added
+    }
+
+    fn hgetall(&self, key: &str) -> Vec<(String, String)> {
+        self.live_hash(key).map_or_else(Vec::new, |hash| {
+            hash.iter()
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect()
+        })
+    }
+
+    fn hget(&self, key: &str, field: &str) -> Option<String> {
+        self.live_hash(key)?.get(field).cloned()
+    }
+
+    fn hdel(&mut self, key: &str, fields: &[String]) -> usize {
+        if self.live_hash(key).is_none() {
+            return 0;
+        }
+        let Some(hash) = self
+            .storage
+            .get_mut(key)
+            .and_then(|unit| unit.implementation.as_hash_mut())
+        else {
+            return 0;
+        };
+        let removed = fields
+            .iter()
+            .filter(|field| hash.remove(field).is_some())
+            .count();
+        self.remove_if_empty(key);
+        removed
+    }
+
+    fn hlen(&self, key: &str) -> usize {
+        self.live_hash(key).map_or(0, |hash| hash.len())
+    }
+
+    fn hexists(&self, key: &str, field: &str) -> bool {
+        self.hget(key, field).is_some()
+    }
+
+    fn hstrlen(&self, key: &str, field: &str) -> usize {
+        self.hget(key, field).map_or(0, |value| value.len())
+    }
+}
+
+impl MemoryStorage {
+    fn live_hash(&self, key: &str) -> Option<&OrderedHash> {
+        self.storage
+            .get(key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| unit.implementation.as_hash())
+    }
+}
@@ -3,19 +3,15 @@ use mio::Token;
 use std::time::{Duration, Instant};
 
 impl StorageList for MemoryStorage {
-    fn rpush(&mut self, key: String, value: Vec<String>) -> usize {
+    fn rpush(&mut self, key: String, value: Vec<String>) -> Option<usize> {
         log::debug!("RPUSH on key '{}', value '{}'", key, value.join(", "));
 
         let list_length = if self.exists(&key) {
             if !self.storage.get(&key).unwrap().implementation.is_list() {
-                log::debug!("Key '{}' exists but is not a list, converting to list", key);
-                let existing_value = self.storage.remove(&key).unwrap();
-                let new_list = vec![existing_value.implementation.as_string().unwrap().clone()];
-                let unit = Unit::new_list(new_list, None);
-                self.storage.insert(key.clone(), unit);
-            } else {
-                log::debug!("Key '{}' already exists, appending to list", key);
+                log::debug!("Key '{}' exists but is not a list", key);
+                return None;
             }
+            log::debug!("Key '{}' already exists, appending to list", key);
             self.storage
                 .get_mut(&key)
                 .unwrap()
@@ -33,67 +29,36 @@ impl StorageList for MemoryStorage {
             value.len()
         };
 
-        // Unblock any clients waiting for this key
-        self.unblock_clients_for_key(&key, true);
-
-        list_length
+        Some(list_length)
     }
 
     fn lrange(&self, key: &str, start: i64, end: i64) -> Option<Vec<String>> {
         log::debug!("LRANGE on key '{}', start {}, end {}", key, start, end);
 
-        let mut start_idx = start;
-        let mut end_idx = end;
-
-        let list = self.storage.get(key)?.implementation.as_list()?;
-
-        if start_idx < 0 {
-            start_idx = list.len() as i64 + start_idx; // Handle negative start index
-        }
-
-        if end_idx < 0 {
-            end_idx = list.len() as i64 + end_idx; // Handle negative end index
-        }
-
-        let start = start_idx.max(0) as usize; // Ensure start is not negative
-        let end = end_idx.max(0) as usize; // Ensure end is not negative
-
-        if !self.exists(key) {
+        let Some(unit) = self.storage.get(key).filter(|unit| !unit.is_expired()) else {
             log::debug!("Key '{}' does not exist in list", key);
             return None;
-        }
-        if start > end {
-            log::debug!("Invalid range: start {} is greater than end {}", start, end);
-            return Some(vec![]);
-        }
-
-        if start >= list.len() {
-            log::debug!("Start index {} is out of bounds for key '{}'", start, key);
-            return Some(vec![]); // Return empty list if start is out of bounds
-        }
-
-        let end = if end >= list.len() {
-            list.len() - 1 // Adjust end to the last index if it exceeds the list length
-        } else {
-            end
         };
+        let list = unit.implementation.as_list()?;
 
-        Some(list[start..=end].to_vec())
+        match normalize_range(list.len(), start, end) {
+            Some((start, end)) => Some(list[start..=end].to_vec()),
+            None => {
+                log::debug!("Range {}..{} is empty for key '{}'", start, end, key);
+                Some(vec![])
+            }
+        }
     }
 
-    fn lpush(&mut self, key: String, value: Vec<String>) -> usize {
+    fn lpush(&mut self, key: String, value: Vec<String>) -> Option<usize> {
         log::debug!("LPUSH on key '{}', value '{}'", key, value.join(", "));
 
         let list_length = if self.exists(&key) {
-            if self.storage.get(&key).unwrap().implementation.is_list() {
-                log::debug!("Key '{}' exists and is a list, prepending to list", key);
-            } else {
-                log::debug!("Key '{}' exists but is not a list, converting to list", key);
-                let existing_value = self.storage.remove(&key).unwrap();
-                let new_list = vec![existing_value.implementation.as_string().unwrap().clone()];
-                let unit = Unit::new_list(new_list, None);
-                self.storage.insert(key.clone(), unit);
+            if !self.storage.get(&key).unwrap().implementation.is_list() {
+                log::debug!("Key '{}' exists but is not a list", key);
+                return None;
             }
+            log::debug!("Key '{}' already exists, prepending to list", key);
 
             let list = self
                 .storage
@@ -115,10 +80,11 @@ impl StorageList for MemoryStorage {
             value.len()
         };
 
-        // Unblock any clients waiting for this key
-        self.unblock_clients_for_key(&key, true);
+        Some(list_length)
+    }
 
-        list_length
+    fn serve_blocked_clients(&mut self, key: &str) {
+        self.unblock_clients_for_key(key, true);
     }
 
     fn llen(&self, key: &str) -> usize {
@@ -131,6 +97,10 @@ impl StorageList for MemoryStorage {
 
     fn lpop(&mut self, key: &str, count: usize) -> Option<Vec<String>> {
         log::debug!("LPOP on key '{}', count {}", key, count);
+        if !self.exists(key) {
+            log::debug!("Key '{}' does not exist in list", key);
+            return None;
+        }
         if let Some(list) = self
             .storage
             .get_mut(key)
@@ -141,16 +111,166 @@ impl StorageList for MemoryStorage {
                 return Some(vec![]);
             }
             let items_to_pop = list.drain(0..count.min(list.len())).collect();
+            self.remove_if_empty(key);
+            Some(items_to_pop)
+        } else {
+            log::debug!("Key '{}' does not hold a list", key);
+            None
+        }
+    }
+
+    fn rpop(&mut self, key: &str, count: usize) -> Option<Vec<String>> {
+        log::debug!("RPOP on key '{}', count {}", key, count);
+        if !self.exists(key) {
+            log::debug!("Key '{}' does not exist in list", key);
+            return None;
+        }
+        if let Some(list) = self
+            .storage
+            .get_mut(key)
+            .and_then(|unit| unit.implementation.as_list_mut())
+        {
             if list.is_empty() {
-                self.storage.remove(key); // Remove the key if the list is empty after popping
+                log::debug!("List for key '{}' is empty", key);
+                return Some(vec![]);
             }
+            let start = list.len() - count.min(list.len());
+            let items_to_pop = list.drain(start..).rev().collect();
+            self.remove_if_empty(key);
             Some(items_to_pop)
         } else {
-            log::debug!("Key '{}' does not exist in list", key);
+            log::debug!("Key '{}' does not hold a list", key);
             None
         }
     }
 
+    fn lindex(&self, key: &str, index: i64) -> Option<String> {
+        log::debug!("LINDEX on key '{}', index {}", key, index);
+        let list = self.live_list(key)?;
+        let index = normalize_index(list.len(), index)?;
+        list.get(index).cloned()
+    }
+
+    fn lset(&mut self, key: &str, index: i64, value: String) -> Result<(), String> {
+        log::debug!("LSET on key '{}', index {}", key, index);
+        if self.live_list(key).is_none() {
+            return Err("no such key".to_string());
+        }
+        let list = self
+            .storage
+            .get_mut(key)
+            .and_then(|unit| unit.implementation.as_list_mut())
+            .ok_or_else(|| "no such key".to_string())?;
+        let index = normalize_index(list.len(), index)
+            .filter(|&index| index < list.len())
+            .ok_or_else(|| "index out of range".to_string())?;
+        list[index] = value;
+        Ok(())
+    }
+
+    fn ltrim(&mut self, key: &str, start: i64, end: i64) {
+        log::debug!("LTRIM on key '{}', start {}, end {}", key, start, end);
+        if self.live_list(key).is_none() {
+            return;
+        }
+        let Some(list) = self
+            .storage
+            .get_mut(key)
+            .and_then(|unit| unit.implementation.as_list_mut())
+        else {
+            return;
+        };
+        match normalize_range(list.len(), start, end) {
+            Some((start, end)) => {
+                list.truncate(end + 1);
+                list.drain(..start);
+            }
+            None => list.clear(),
+        }
+        self.remove_if_empty(key);
+    }
+
+    fn lrem(&mut self, key: &str, count: i64, value: &str) -> usize {
+        log::debug!("LREM on key '{}', count {}", key, count);
+        if self.live_list(key).is_none() {
+            return 0;
+        }
+        let Some(list) = self
+            .storage
+            .get_mut(key)
+            .and_then(|unit| unit.implementation.as_list_mut())
+        else {
+            return 0;
+        };
+
+        let limit = if count == 0 {
+            usize::MAX
+        } else {
+            count.unsigned_abs() as usize
+        };
+        // A negative count removes the last matches, so the ones before them
+        // are skipped
+        let matches = list.iter().filter(|item| *item == value).count();
+        let removed = matches.min(limit);
+        let mut skip = if count < 0 { matches - removed } else { 0 };
+        let mut left = removed;
+        list.retain(|item| {
+            if left == 0 || item != value {
+                return true;
+            }
+            if skip > 0 {
+                skip -= 1;
+                return true;
+            }
+            left -= 1;
+            false
+        });
+
+        self.remove_if_empty(key);
+        removed
+    }
+
+    fn linsert(&mut self, key: &str, before: bool, pivot: &str, value: String) -> i64 {
+        log::debug!("LINSERT on key '{}', pivot '{}'", key, pivot);
+        if self.live_list(key).is_none() {
+            return 0;
+        }
+        let Some(list) = self
+            .storage
+            .get_mut(key)
+            .and_then(|unit| unit.implementation.as_list_mut())
+        else {
+            return 0;
+        };
+        let Some(position) = list.iter().position(|item| item == pivot) else {
+            return -1;
+        };
+        let index = if before { position } else { position + 1 };
+        list.insert(index, value);
+        list.len() as i64
+    }
+
+    fn lpos(&self, key: &str, element: &str, rank: i64, count: usize, maxlen: usize) -> Vec<usize> {
+        log::debug!("LPOS on key '{}', rank {}, count {}", key, rank, count);
+        let Some(list) = self.live_list(key) else {
+            return Vec::new();
+        };
+        // A negative rank scans from the tail, and MAXLEN counts from there too
+        let indices: Box<dyn Iterator<Item = usize>> = if rank < 0 {
+            Box::new((0..list.len()).rev())
+        } else {
+            Box::new(0..list.len())
+        };
+        let scanned = if maxlen == 0 { list.len() } else { maxlen };
+        let wanted = if count == 0 { usize::MAX } else { count };
+        indices
+            .take(scanned)
+            .filter(|&i| list[i] == element)
+            .skip(rank.unsigned_abs() as usize - 1)
+            .take(wanted)
+            .collect()
+    }
+
     fn blpop(&mut self, keys: Vec<String>, token: Token, timeout: u64) -> Option<Vec<String>> {
         log::debug!("BLPOP on keys '{:?}', timeout {}", keys, timeout);
 
@@ -163,9 +283,7 @@ impl StorageList for MemoryStorage {
             {
                 if !list.is_empty() {
                     let item = list.remove(0);
-                    if list.is_empty() {
-                        self.storage.remove(key); // Remove the key if the list is empty after popping
-                    }
+                    self.remove_if_empty(key);
                     return Some(vec![key.clone(), item]);
                 }
             }
@@ -206,9 +324,7 @@ impl StorageList for MemoryStorage {
             {
                 if !list.is_empty() {
                     let item = list.remove(list.len() - 1);
-                    if list.is_empty() {
-                        self.storage.remove(key); // Remove the key if the list is empty after popping
-                    }
+                    self.remove_if_empty(key);
                     return Some(vec![key.clone(), item]);
                 }
             }
@@ -237,3 +353,30 @@ impl StorageList for MemoryStorage {
         None
     }
 }
+
+impl MemoryStorage {
+    fn live_list(&self, key: &str) -> Option<&Vec<String>> {
+        self.storage
+            .get(key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| unit.implementation.as_list())
+    }
+}
+
+// Map a possibly negative index onto the list, None when it falls before the head
+fn normalize_index(len: usize, index: i64) -> Option<usize> {
+    let index = if index < 0 { len as i64 + index } else { index };
+    usize::try_from(index).ok()
+}
+
+// Turn an inclusive start/end pair, either of which may count from the tail,
+// into bounds within the list. None when the range selects nothing.
+fn normalize_range(len: usize, start: i64, end: i64) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let end = if end < 0 { len + end } else { end }.min(len - 1);
+    if start > end || start >= len || end < 0 {
+        return None;
+    }
+    Some((start as usize, end as usize))
+}
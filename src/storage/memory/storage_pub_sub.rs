@@ -1,22 +1,58 @@
 use super::{MemoryStorage, StoragePubSub};
+use crate::storage::glob::glob_match;
 
 impl StoragePubSub for MemoryStorage {
     fn subscribe(&mut self, token: mio::Token, channel: String) -> usize {
         self.add_subscriber(token, channel);
-        self.get_subscriptions(token).len()
+        self.get_subscriptions(token).len() + self.get_pattern_subscriptions(token).len()
     }
 
+    // Like Redis, the reply counts deliveries rather than clients: one for the
+    // channel subscription plus one per matching pattern, so a client
+    // subscribed to the channel and to two matching patterns counts three
+    // times (and receives the message three times)
     fn publish(&mut self, channel: String, message: String) -> usize {
         let subscribers = self.get_channel_subscriptions(channel.as_str());
         for token in subscribers.clone() {
             self.handle
                 .send_message(token, channel.clone(), message.clone());
         }
-        return subscribers.len();
+
+        let mut pattern_receivers = 0;
+        for (pattern, subscribers) in &self.pattern_pubsub {
+            if !glob_match(pattern, &channel) {
+                continue;
+            }
+            for &token in subscribers {
+                self.handle.send_pattern_message(
+                    token,
+                    pattern.clone(),
+                    channel.clone(),
+                    message.clone(),
+                );
+            }
+            pattern_receivers += subscribers.len();
+        }
+        subscribers.len() + pattern_receivers
+    }
+
+    fn psubscribe(&mut self, token: mio::Token, pattern: String) -> usize {
+        self.add_pattern_subscriber(token, pattern);
+        self.get_subscriptions(token).len() + self.get_pattern_subscriptions(token).len()
     }
 
     fn unsubscribe(&mut self, token: mio::Token, channel: String) -> usize {
         self.remove_subscriber(token, channel);
-        self.get_subscriptions(token).len()
+        self.get_subscriptions(token).len() + self.get_pattern_subscriptions(token).len()
+    }
+
+    fn punsubscribe(&mut self, token: mio::Token, pattern: String) -> usize {
+        if let Some(subscribers) = self.pattern_pubsub.get_mut(&pattern) {
+            subscribers.retain(|&t| t != token);
+            if subscribers.is_empty() {
+                self.pattern_pubsub.remove(&pattern);
+            }
+        }
+        self.get_subscriptions(token).len() + self.get_pattern_subscriptions(token).len()
     }
 }
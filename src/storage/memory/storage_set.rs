@@ -0,0 +1,119 @@
+use rand::seq::IteratorRandom;
+use std::collections::HashSet;
+
+use super::{MemoryStorage, Storage, StorageSet, Unit};
+
+impl StorageSet for MemoryStorage {
+    fn sadd(&mut self, key: String, members: Vec<String>) -> usize {
+        log::debug!("Adding {} members to set '{}'", members.len(), key);
+        if self.live_set(&key).is_none() {
+            log::debug!("Key '{}' has expired or is not a set", key);
+            self.delete(&key);
+            self.storage
+                .insert(key.clone(), Unit::new_set(HashSet::new(), None));
+        }
+
+        let Some(set) = self
+            .storage
+            .get_mut(&key)
+            .and_then(|unit| unit.implementation.as_set_mut())
+        else {
+            return 0;
+        };
+        members
+            .into_iter()
+            .filter(|member| set.insert(member.clone()))
+            .count()
+    }
+
+    fn srem(&mut self, key: &str, members: &[String]) -> usize {
+        if self.live_set(key).is_none() {
+            return 0;
+        }
+        let Some(set) = self
+            .storage
+            .get_mut(key)
+            .and_then(|unit| unit.implementation.as_set_mut())
+        else {
+            return 0;
+        };
+        let removed = members.iter().filter(|member| set.remove(*member)).count();
+        self.remove_if_empty(key);
+        removed
+    }
+
+    fn smembers(&self, key: &str) -> Vec<String> {
+        self.live_set(key)
+            .map(|set| set.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    fn sismember(&self, key: &str, member: &str) -> bool {
+        self.live_set(key).is_some_and(|set| set.contains(member))
+    }
+
+    fn scard(&self, key: &str) -> usize {
+        self.live_set(key).map_or(0, |set| set.len())
+    }
+
+    fn spop(&mut self, key: &str, count: usize) -> Vec<String> {
+        let popped: Vec<String> = match self.live_set(key) {
+            Some(set) => set.iter().cloned().choose_multiple(&mut rand::rng(), count),
+            None => return Vec::new(),
+        };
+        self.srem(key, &popped);
+        popped
+    }
+
+    fn sinter(&self, keys: &[String]) -> Vec<String> {
+        let Some((first, rest)) = keys.split_first() else {
+            return Vec::new();
+        };
+        let sets: Vec<Option<&HashSet<String>>> =
+            rest.iter().map(|key| self.live_set(key)).collect();
+        self.smembers(first)
+            .into_iter()
+            .filter(|member| {
+                sets.iter()
+                    .all(|set| set.is_some_and(|set| set.contains(member)))
+            })
+            .collect()
+    }
+
+    fn sunion(&self, keys: &[String]) -> Vec<String> {
+        let union: HashSet<String> = keys.iter().flat_map(|key| self.smembers(key)).collect();
+        union.into_iter().collect()
+    }
+
+    fn sdiff(&self, keys: &[String]) -> Vec<String> {
+        let Some((first, rest)) = keys.split_first() else {
+            return Vec::new();
+        };
+        self.smembers(first)
+            .into_iter()
+            .filter(|member| !rest.iter().any(|key| self.sismember(key, member)))
+            .collect()
+    }
+
+    fn sstore(&mut self, destination: String, members: Vec<String>) -> usize {
+        log::debug!("Storing {} members into '{}'", members.len(), destination);
+        // Whatever the destination held before is replaced, whatever its type
+        self.delete(&destination);
+        if members.is_empty() {
+            return 0;
+        }
+        let set: HashSet<String> = members.into_iter().collect();
+        let len = set.len();
+        self.storage.insert(destination, Unit::new_set(set, None));
+        len
+    }
+}
+
+impl MemoryStorage {
+    fn live_set(&self, key: &str) -> Option<&HashSet<String>> {
+        self.storage
+            .get(key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| unit.implementation.as_set())
+    }
+}
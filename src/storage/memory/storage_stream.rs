@@ -2,10 +2,14 @@ use mio::Token;
 use std::time::SystemTime;
 use std::time::{Duration, Instant};
 
-use super::{MemoryStorage, StorageStream, Unit};
+use super::{MemoryStorage, Replication, StorageStream, Unit};
+use crate::commands::RedisCommand;
 use crate::storage::{
     memory::BlockedClient,
-    stream_member::{StreamId, StreamMember, EMPTY_STREAM_ID},
+    stream_member::{
+        ConsumerGroup, ConsumerInfo, GroupInfo, PendingEntry, Stream, StreamEntries, StreamId,
+        StreamInfo, StreamMember, StreamReadResult, XClaimOptions, EMPTY_STREAM_ID,
+    },
 };
 
 impl StorageStream for MemoryStorage {
@@ -25,11 +29,8 @@ impl StorageStream for MemoryStorage {
                     return Err("Key does not exist or is not a stream".to_string());
                 }
                 if let Some(stream) = u.implementation.as_stream_mut() {
-                    let top_id = if let Some(last) = stream.last() {
-                        last.id.clone()
-                    } else {
-                        EMPTY_STREAM_ID.clone()
-                    };
+                    // Compared against the last generated ID, so deleted IDs are never reused
+                    let top_id = stream.last_id.clone();
                     let entry_id = generate_next_id(&top_id, &id);
                     if entry_id <= top_id {
                         log::debug!(
@@ -54,7 +55,7 @@ impl StorageStream for MemoryStorage {
                 }
             }
             None => {
-                let mut new_stream = Vec::new();
+                let mut new_stream = Stream::new();
                 let entry_id = generate_next_id(&EMPTY_STREAM_ID, &id);
                 if entry_id <= EMPTY_STREAM_ID {
                     log::debug!("Invalid stream ID '{}'", id);
@@ -70,10 +71,13 @@ impl StorageStream for MemoryStorage {
             }
         }
 
-        self.unblock_clients_for_key(&key, false);
         return Ok(result_id);
     }
 
+    fn serve_blocked_readers(&mut self, key: &str) {
+        self.unblock_clients_for_key(key, false);
+    }
+
     fn xrange(
         &self,
         key: &str,
@@ -91,7 +95,7 @@ impl StorageStream for MemoryStorage {
         let end_id = generate_query_id(&end);
 
         let mut result = Vec::new();
-        for member in stream {
+        for member in &stream.entries {
             if member.id >= start_id && member.id <= end_id {
                 result.push((member.id.to_string(), member.fields.clone()));
             }
@@ -103,33 +107,43 @@ impl StorageStream for MemoryStorage {
         &mut self,
         token: Token,
         block: Option<u64>,
+        count: Option<usize>,
         streams: Vec<(String, String)>,
-    ) -> Option<Vec<(String, Vec<(String, Vec<(String, String)>)>)>> {
+    ) -> Option<StreamReadResult> {
         log::debug!(
-            "XREAD called with block {:?} and streams {:?}",
+            "XREAD called with block {:?}, count {:?} and streams {:?}",
             block,
+            count,
             streams
         );
+        // `$` stands for the stream's last ID at the time of the call, so a
+        // blocked read only sees entries added later
+        let streams: Vec<(String, StreamId)> = streams
+            .into_iter()
+            .map(|(key, id)| {
+                let id = if id == "$" {
+                    self.live_stream(&key)
+                        .map_or(EMPTY_STREAM_ID, |stream| stream.last_id.clone())
+                } else {
+                    generate_query_id(&id)
+                };
+                (key, id)
+            })
+            .collect();
         let mut result = Vec::new();
 
-        for (key, id) in &streams {
-            let unit = self.storage.get(key)?;
-            if unit.is_expired() || !unit.implementation.is_stream() {
-                log::debug!("Key '{}' has expired or is not a stream", key);
+        for (key, last_id) in &streams {
+            // Missing streams and other types simply have no entries
+            let Some(stream) = self.live_stream(key) else {
                 continue;
-            }
-            let stream = unit.implementation.as_stream()?;
-            if id == "$" {
-                continue;
-            }
-            let last_id = generate_query_id(&id);
-
-            let mut entries = Vec::new();
-            for member in stream {
-                if member.id > last_id {
-                    entries.push((member.id.to_string(), member.fields.clone()));
-                }
-            }
+            };
+            let entries: Vec<(String, Vec<(String, String)>)> = stream
+                .entries
+                .iter()
+                .filter(|member| member.id > *last_id)
+                .take(count.unwrap_or(usize::MAX))
+                .map(|member| (member.id.to_string(), member.fields.clone()))
+                .collect();
             if !entries.is_empty() {
                 result.push((key.clone(), entries));
             }
@@ -137,18 +151,7 @@ impl StorageStream for MemoryStorage {
 
         if result.is_empty() && block.is_some() {
             log::debug!("No new entries found, would block for {:?}", block);
-            for (key, id) in streams {
-                let last_id: StreamId;
-                if id == "$" {
-                    let stream = self.storage.get(&key)?.implementation.as_stream()?;
-                    last_id = if let Some(last) = stream.last() {
-                        last.id.clone()
-                    } else {
-                        EMPTY_STREAM_ID.clone()
-                    };
-                } else {
-                    last_id = generate_query_id(&id);
-                }
+            for (key, last_id) in streams {
                 let timeout = block.unwrap_or(0);
                 let blocked_client = BlockedClient::new_stream(
                     token,
@@ -157,7 +160,8 @@ impl StorageStream for MemoryStorage {
                     } else {
                         None
                     },
-                    last_id.clone(),
+                    last_id,
+                    count,
                 );
                 self.blocked_clients
                     .entry(key)
@@ -170,6 +174,518 @@ impl StorageStream for MemoryStorage {
 
         Some(result)
     }
+
+    fn xlen(&self, key: &str) -> usize {
+        self.live_stream(key).map_or(0, |stream| stream.len())
+    }
+
+    fn xdel(&mut self, key: &str, ids: Vec<String>) -> usize {
+        log::debug!("XDEL called for key '{}' with ids {:?}", key, ids);
+        let ids: Vec<StreamId> = ids.iter().map(|id| generate_query_id(id)).collect();
+        match self.live_stream_mut(key) {
+            // Streams are kept even when this removes their last entry
+            Some(stream) => stream.remove_where(|member| ids.contains(&member.id)),
+            None => 0,
+        }
+    }
+
+    fn xtrim(&mut self, key: &str, strategy: &str, threshold: &str) -> Result<usize, String> {
+        log::debug!(
+            "XTRIM called for key '{}' with {} {}",
+            key,
+            strategy,
+            threshold
+        );
+        let removed = match strategy {
+            "MAXLEN" => {
+                let max_len = threshold
+                    .parse::<usize>()
+                    .map_err(|_| "value is not an integer or out of range".to_string())?;
+                match self.live_stream_mut(key) {
+                    Some(stream) => {
+                        // Entries are ordered by ID, so keep everything from the first survivor on
+                        let excess = stream.len().saturating_sub(max_len);
+                        match stream.entries.get(excess).map(|member| member.id.clone()) {
+                            Some(first_kept) => {
+                                stream.remove_where(|member| member.id < first_kept)
+                            }
+                            None => stream.remove_where(|_| true),
+                        }
+                    }
+                    None => 0,
+                }
+            }
+            "MINID" => {
+                let min_id = generate_query_id(threshold);
+                match self.live_stream_mut(key) {
+                    Some(stream) => stream.remove_where(|member| member.id < min_id),
+                    None => 0,
+                }
+            }
+            _ => return Err("syntax error".to_string()),
+        };
+        Ok(removed)
+    }
+
+    fn xinfo_stream(&self, key: &str) -> Option<StreamInfo> {
+        let unit = self.storage.get(key)?;
+        if unit.is_expired() {
+            return None;
+        }
+        let stream = unit.implementation.as_stream()?;
+        let entry = |member: &StreamMember| (member.id.to_string(), member.fields.clone());
+        Some(StreamInfo {
+            length: stream.len(),
+            last_generated_id: stream.last_id.to_string(),
+            max_deleted_id: stream.max_deleted_id.to_string(),
+            entries_added: stream.entries_added,
+            groups: stream.groups.len(),
+            first_entry: stream.entries.first().map(entry),
+            last_entry: stream.entries.last().map(entry),
+        })
+    }
+
+    fn xinfo_groups(&self, key: &str) -> Option<Vec<GroupInfo>> {
+        let stream = self.live_stream(key)?;
+        Some(
+            stream
+                .groups
+                .iter()
+                .map(|(name, group)| GroupInfo {
+                    name: name.clone(),
+                    consumers: group.consumers.len(),
+                    pending: group.pending.len(),
+                    last_delivered_id: group.last_delivered_id.to_string(),
+                    entries_read: group.entries_read,
+                    lag: group_lag(stream, group),
+                })
+                .collect(),
+        )
+    }
+
+    fn xinfo_consumers(&self, key: &str, group: &str) -> Result<Vec<ConsumerInfo>, String> {
+        let stream = self
+            .live_stream(key)
+            .ok_or_else(|| "ERR no such key".to_string())?;
+        let consumer_group = stream.groups.get(group).ok_or_else(|| {
+            format!(
+                "NOGROUP No such consumer group '{}' for key name '{}'",
+                group, key
+            )
+        })?;
+        let millis = |instant: Instant| instant.elapsed().as_millis() as u64;
+        Ok(consumer_group
+            .consumers
+            .iter()
+            .map(|(name, consumer)| ConsumerInfo {
+                name: name.clone(),
+                pending: consumer_group
+                    .pending
+                    .values()
+                    .filter(|pending| &pending.consumer == name)
+                    .count(),
+                idle: millis(consumer.seen_time),
+                inactive: consumer.active_time.map(millis),
+            })
+            .collect())
+    }
+
+    fn xgroup_create(
+        &mut self,
+        key: &str,
+        group: String,
+        id: &str,
+        mkstream: bool,
+    ) -> Result<(), String> {
+        log::debug!(
+            "XGROUP CREATE called for key '{}' and group '{}'",
+            key,
+            group
+        );
+        if self.live_stream_mut(key).is_none() {
+            if !mkstream {
+                return Err("ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.".to_string());
+            }
+            self.storage
+                .insert(key.to_string(), Unit::new_stream(Stream::new(), None));
+        }
+        let stream = self
+            .live_stream_mut(key)
+            .ok_or_else(|| "ERR no such key".to_string())?;
+        if stream.groups.contains_key(&group) {
+            return Err("BUSYGROUP Consumer Group name already exists".to_string());
+        }
+        let last_delivered_id = if id == "$" {
+            stream.last_id.clone()
+        } else {
+            generate_query_id(id)
+        };
+        let entries_read = entries_read_at(stream, &last_delivered_id);
+        stream
+            .groups
+            .insert(group, ConsumerGroup::new(last_delivered_id, entries_read));
+        Ok(())
+    }
+
+    fn xgroup_setid(
+        &mut self,
+        key: &str,
+        group: &str,
+        id: &str,
+        entries_read: Option<u64>,
+    ) -> Result<(), String> {
+        let stream = self.live_stream_mut(key).ok_or_else(|| {
+            "ERR The XGROUP subcommand requires the key to exist. Note that for CREATE you may want to use the MKSTREAM option to create an empty stream automatically.".to_string()
+        })?;
+        let last_delivered_id = if id == "$" {
+            stream.last_id.clone()
+        } else {
+            generate_query_id(id)
+        };
+        let entries_read = entries_read.or_else(|| entries_read_at(stream, &last_delivered_id));
+        let consumer_group = stream.groups.get_mut(group).ok_or_else(|| {
+            format!(
+                "NOGROUP No such consumer group '{}' for key name '{}'",
+                group, key
+            )
+        })?;
+        consumer_group.last_delivered_id = last_delivered_id;
+        consumer_group.entries_read = entries_read;
+        Ok(())
+    }
+
+    fn xclaim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle_time: u64,
+        ids: &[String],
+        options: &XClaimOptions,
+    ) -> Result<StreamEntries, String> {
+        let no_group = || {
+            format!(
+                "NOGROUP No such key '{}' or consumer group '{}'",
+                key, group
+            )
+        };
+        let stream = self.live_stream_mut(key).ok_or_else(no_group)?;
+        let consumer_group = stream.groups.get_mut(group).ok_or_else(no_group)?;
+        if let Some(lastid) = &options.lastid {
+            let lastid = generate_query_id(lastid);
+            if lastid > consumer_group.last_delivered_id {
+                consumer_group.last_delivered_id = lastid;
+            }
+        }
+
+        let now = unix_millis();
+        let delivery_time = options
+            .time
+            .or(options.idle.map(|idle| now.saturating_sub(idle)))
+            .unwrap_or(now);
+        let mut claimed = Vec::new();
+        let mut claims = Vec::new();
+        for id in ids {
+            let id = generate_query_id(id);
+            let Some(member) = stream.entries.iter().find(|member| member.id == id) else {
+                // Entries deleted from the stream can't be claimed and leave the PEL
+                consumer_group.pending.remove(&id);
+                continue;
+            };
+            if !consumer_group.pending.contains_key(&id) {
+                if !options.force {
+                    continue;
+                }
+                consumer_group.pending.insert(
+                    id.clone(),
+                    PendingEntry {
+                        consumer: consumer.to_string(),
+                        delivery_count: 0,
+                        delivery_time: now,
+                    },
+                );
+            }
+            let pending = consumer_group.pending.get_mut(&id).unwrap();
+            if now.saturating_sub(pending.delivery_time) < min_idle_time {
+                continue;
+            }
+            pending.consumer = consumer.to_string();
+            pending.delivery_time = delivery_time;
+            if let Some(retrycount) = options.retrycount {
+                pending.delivery_count = retrycount;
+            } else if !options.justid {
+                pending.delivery_count += 1;
+            }
+            claims.push((id, pending.clone()));
+            claimed.push((member.id.to_string(), member.fields.clone()));
+        }
+        let seen = consumer_group.seen(consumer);
+        if !claimed.is_empty() {
+            seen.active_time = Some(seen.seen_time);
+        }
+        let last_delivered_id = consumer_group.last_delivered_id.clone();
+        self.propagate_claims(key, group, claims, last_delivered_id);
+        Ok(claimed)
+    }
+
+    fn xreadgroup(
+        &mut self,
+        token: Token,
+        group: &str,
+        consumer: &str,
+        count: Option<usize>,
+        block: Option<u64>,
+        streams: Vec<(String, String)>,
+    ) -> Result<Option<StreamReadResult>, String> {
+        log::debug!(
+            "XREADGROUP called for group '{}' and consumer '{}' with streams {:?}",
+            group,
+            consumer,
+            streams
+        );
+        // Every stream must have the group before anything is delivered
+        for (key, _) in &streams {
+            let has_group = self
+                .live_stream_mut(key)
+                .is_some_and(|stream| stream.groups.contains_key(group));
+            if !has_group {
+                return Err(format!(
+                    "NOGROUP No such key '{}' or consumer group '{}' in XREADGROUP with GROUP option",
+                    key, group
+                ));
+            }
+        }
+
+        let mut result = Vec::new();
+        for (key, id) in &streams {
+            if id == ">" {
+                let entries = self.deliver_new_entries(key, group, consumer, count);
+                if !entries.is_empty() {
+                    result.push((key.clone(), entries));
+                }
+            } else {
+                // History reads reply for every stream, even without entries
+                let entries = self.pending_entries(key, group, consumer, id, count);
+                result.push((key.clone(), entries));
+            }
+        }
+
+        if result.is_empty() && block.is_some() {
+            log::debug!(
+                "No new entries for group '{}', blocking for {:?}",
+                group,
+                block
+            );
+            let timeout = block.unwrap_or(0);
+            for (key, _) in streams {
+                let blocked_client = BlockedClient::new_group(
+                    token,
+                    if timeout != 0 {
+                        Some(Instant::now() + Duration::from_millis(timeout))
+                    } else {
+                        None
+                    },
+                    group.to_string(),
+                    consumer.to_string(),
+                    count,
+                );
+                self.blocked_clients
+                    .entry(key)
+                    .or_default()
+                    .push(blocked_client);
+            }
+            self.handle.block_client(token, timeout);
+            return Ok(None);
+        }
+
+        Ok(Some(result))
+    }
+}
+
+impl MemoryStorage {
+    // Hand the entries the group has not delivered yet to `consumer`, moving
+    // the group's last delivered ID past them and recording them as pending
+    pub(super) fn deliver_new_entries(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        count: Option<usize>,
+    ) -> Vec<(String, Vec<(String, String)>)> {
+        let Some(stream) = self.live_stream_mut(key) else {
+            return Vec::new();
+        };
+        let Some(consumer_group) = stream.groups.get_mut(group) else {
+            return Vec::new();
+        };
+        let delivered: Vec<&StreamMember> = stream
+            .entries
+            .iter()
+            .filter(|member| member.id > consumer_group.last_delivered_id)
+            .take(count.unwrap_or(usize::MAX))
+            .collect();
+        let seen = consumer_group.seen(consumer);
+        if !delivered.is_empty() {
+            seen.active_time = Some(seen.seen_time);
+        }
+        consumer_group.entries_read = consumer_group
+            .entries_read
+            .map(|read| read + delivered.len() as u64);
+        let now = unix_millis();
+        let mut claims = Vec::new();
+        for member in &delivered {
+            consumer_group.last_delivered_id = member.id.clone();
+            let pending = PendingEntry {
+                consumer: consumer.to_string(),
+                delivery_count: 1,
+                delivery_time: now,
+            };
+            consumer_group
+                .pending
+                .insert(member.id.clone(), pending.clone());
+            claims.push((member.id.clone(), pending));
+        }
+        // Once caught up, the count is exact whatever happened before
+        if consumer_group.last_delivered_id >= stream.last_id {
+            consumer_group.entries_read = Some(stream.entries_added);
+        }
+        let last_delivered_id = consumer_group.last_delivered_id.clone();
+        let entries_read = consumer_group.entries_read;
+        let delivered: Vec<(String, Vec<(String, String)>)> = delivered
+            .into_iter()
+            .map(|member| (member.id.to_string(), member.fields.clone()))
+            .collect();
+
+        if !claims.is_empty() {
+            self.propagate_claims(key, group, claims, last_delivered_id.clone());
+            // XCLAIM leaves the read counter alone, so replicas get it separately
+            self.replicate_command(RedisCommand::XGROUPSETID(
+                key.to_string(),
+                group.to_string(),
+                last_delivered_id.to_string(),
+                entries_read,
+            ));
+        }
+        delivered
+    }
+
+    // Replicas learn of deliveries the way Redis tells them, as an XCLAIM
+    // forcing each entry into the consumer's PEL with its delivery count and
+    // time, which also carries the group's last delivered ID
+    fn propagate_claims(
+        &mut self,
+        key: &str,
+        group: &str,
+        claims: Vec<(StreamId, PendingEntry)>,
+        last_delivered_id: StreamId,
+    ) {
+        for (id, pending) in claims {
+            self.replicate_command(RedisCommand::XCLAIM(
+                key.to_string(),
+                group.to_string(),
+                pending.consumer,
+                0,
+                vec![id.to_string()],
+                XClaimOptions {
+                    time: Some(pending.delivery_time),
+                    retrycount: Some(pending.delivery_count),
+                    force: true,
+                    justid: true,
+                    lastid: Some(last_delivered_id.to_string()),
+                    ..XClaimOptions::default()
+                },
+            ));
+        }
+    }
+
+    // The consumer's pending entries after `id`. Entries deleted from the
+    // stream since delivery are left out.
+    fn pending_entries(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        id: &str,
+        count: Option<usize>,
+    ) -> Vec<(String, Vec<(String, String)>)> {
+        let after = generate_query_id(id);
+        let Some(stream) = self.live_stream_mut(key) else {
+            return Vec::new();
+        };
+        let Some(consumer_group) = stream.groups.get_mut(group) else {
+            return Vec::new();
+        };
+        consumer_group.seen(consumer);
+
+        let now = unix_millis();
+        let mut entries = Vec::new();
+        let mut claims = Vec::new();
+        for (pending_id, pending) in consumer_group.pending.iter_mut() {
+            if entries.len() >= count.unwrap_or(usize::MAX) {
+                break;
+            }
+            if *pending_id <= after || pending.consumer != consumer {
+                continue;
+            }
+            if let Some(member) = stream.entries.iter().find(|m| m.id == *pending_id) {
+                pending.delivery_count += 1;
+                pending.delivery_time = now;
+                claims.push((pending_id.clone(), pending.clone()));
+                entries.push((member.id.to_string(), member.fields.clone()));
+            }
+        }
+        let last_delivered_id = consumer_group.last_delivered_id.clone();
+        self.propagate_claims(key, group, claims, last_delivered_id);
+        entries
+    }
+
+    fn live_stream(&self, key: &str) -> Option<&Stream> {
+        self.storage
+            .get(key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| unit.implementation.as_stream())
+    }
+
+    fn live_stream_mut(&mut self, key: &str) -> Option<&mut Stream> {
+        let unit = self.storage.get_mut(key)?;
+        if unit.is_expired() {
+            return None;
+        }
+        unit.implementation.as_stream_mut()
+    }
+}
+
+// Like Redis, the read counter of a group starting at `id` is only known when
+// that is either end of the stream
+fn entries_read_at(stream: &Stream, id: &StreamId) -> Option<u64> {
+    if *id >= stream.last_id {
+        Some(stream.entries_added)
+    } else if *id == EMPTY_STREAM_ID {
+        Some(0)
+    } else {
+        None
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+// Entries added to the stream that the group has yet to deliver. Unknown when
+// the read counter is, or when deletions past the last delivered entry make
+// the distance unclear.
+fn group_lag(stream: &Stream, group: &ConsumerGroup) -> Option<u64> {
+    if group.last_delivered_id >= stream.last_id {
+        return Some(0);
+    }
+    if stream.max_deleted_id > group.last_delivered_id {
+        return None;
+    }
+    let entries_read = group.entries_read?;
+    Some(stream.entries_added.saturating_sub(entries_read))
 }
 
 fn generate_next_id(last_id: &StreamId, input: &str) -> StreamId {
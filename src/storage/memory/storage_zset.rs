@@ -1,49 +1,68 @@
-use crate::storage::zset_member::ZSetMember;
+use crate::commands::ZAddOptions;
+use crate::storage::zset_member::{ScoreBound, ZAddOutcome, ZSetMember};
 
 use super::{MemoryStorage, Storage, StorageZSet, Unit};
 
 impl StorageZSet for MemoryStorage {
-    fn zadd(&mut self, key: String, score: f64, member: String) -> usize {
+    fn zadd(
+        &mut self,
+        key: String,
+        score: f64,
+        member: String,
+        options: &ZAddOptions,
+    ) -> ZAddOutcome {
         log::debug!(
             "Adding member '{}' with score {} to sorted set '{}'",
             member,
             score,
             key
         );
-        let unit = self.storage.get_mut(&key);
-        match unit {
-            Some(u) => {
-                if u.is_expired() || !u.implementation.is_zset() {
-                    log::debug!("Key '{}' has expired or is not a sorted set", key);
-                    self.delete(&key);
-                    let mut new_set = std::collections::BTreeSet::new();
-                    new_set.insert(ZSetMember { score, member });
-                    let new_unit = Unit::new_zset(new_set, None);
-                    self.storage.insert(key, new_unit);
-                    return 1;
-                }
-                if let Some(zset) = u.implementation.as_zset_mut() {
-                    // Check if member already exists
-                    if zset.iter().any(|m| m.member == member) {
-                        // ZSetMember exists, update score
-                        zset.retain(|m| m.member != member); // Remove old entry
-                        zset.insert(ZSetMember { score, member }); // Insert updated entry
-                        return 0;
-                    } else {
-                        zset.insert(ZSetMember { score, member });
-                        return 1;
-                    }
-                }
-                0
+        // Only an expired key is dropped, any other type is left for WRONGTYPE
+        if !self.exists(&key) {
+            log::debug!("Key '{}' does not exist, creating sorted set", key);
+            if options.xx {
+                return ZAddOutcome::Skipped;
             }
-            None => {
-                let mut new_set = std::collections::BTreeSet::new();
-                new_set.insert(ZSetMember { score, member });
-                let new_unit = Unit::new_zset(new_set, None);
-                self.storage.insert(key, new_unit);
-                1
+            let mut new_set = std::collections::BTreeSet::new();
+            new_set.insert(ZSetMember { score, member });
+            self.storage.insert(key, Unit::new_zset(new_set, None));
+            return ZAddOutcome::Added;
+        }
+
+        let Some(zset) = self
+            .storage
+            .get_mut(&key)
+            .and_then(|u| u.implementation.as_zset_mut())
+        else {
+            return ZAddOutcome::Skipped;
+        };
+        let existing = zset.iter().find(|m| m.member == member).map(|m| m.score);
+        let Some(current) = existing else {
+            if options.xx {
+                return ZAddOutcome::Skipped;
             }
+            zset.insert(ZSetMember { score, member });
+            return ZAddOutcome::Added;
+        };
+
+        if options.nx {
+            return ZAddOutcome::Skipped;
+        }
+        let score = if options.incr { current + score } else { score };
+        // Only adding the two infinities gets here, as NaN is never parsed
+        if score.is_nan() {
+            return ZAddOutcome::NaN;
+        }
+        // GT and LT only restrict updates, new members are always added
+        if (options.gt && score <= current) || (options.lt && score >= current) {
+            return ZAddOutcome::Skipped;
         }
+        if current == score {
+            return ZAddOutcome::Unchanged;
+        }
+        zset.retain(|m| m.member != member); // Remove old entry
+        zset.insert(ZSetMember { score, member }); // Insert updated entry
+        ZAddOutcome::Updated
     }
 
     fn zrank(&self, key: &str, member: &str) -> Option<usize> {
@@ -128,6 +147,55 @@ impl StorageZSet for MemoryStorage {
         }
     }
 
+    fn zrangebyscore(&self, key: &str, min: &ScoreBound, max: &ScoreBound) -> Vec<(String, f64)> {
+        log::debug!(
+            "Getting members of sorted set '{}' scored between {:?} and {:?}",
+            key,
+            min,
+            max
+        );
+        let Some(zset) = self
+            .storage
+            .get(key)
+            .filter(|unit| !unit.is_expired())
+            .and_then(|unit| unit.implementation.as_zset())
+        else {
+            return Vec::new();
+        };
+        // Members are ordered by score, so stop at the first past the maximum
+        zset.iter()
+            .skip_while(|m| !min.allows_above(m.score))
+            .take_while(|m| max.allows_below(m.score))
+            .map(|m| (m.member.clone(), m.score))
+            .collect()
+    }
+
+    fn zcount(&self, key: &str, min: &ScoreBound, max: &ScoreBound) -> usize {
+        self.zrangebyscore(key, min, max).len()
+    }
+
+    fn zmscore(&self, key: &str, members: &[String]) -> Vec<Option<f64>> {
+        members
+            .iter()
+            .map(|member| self.zscore(key, member))
+            .collect()
+    }
+
+    fn zstore(&mut self, destination: String, members: Vec<(String, f64)>) -> usize {
+        log::debug!("Storing {} members into '{}'", members.len(), destination);
+        self.delete(&destination);
+        if members.is_empty() {
+            return 0;
+        }
+        let zset: std::collections::BTreeSet<ZSetMember> = members
+            .into_iter()
+            .map(|(member, score)| ZSetMember { score, member })
+            .collect();
+        let len = zset.len();
+        self.storage.insert(destination, Unit::new_zset(zset, None));
+        len
+    }
+
     fn zscore(&self, key: &str, member: &str) -> Option<f64> {
         log::debug!(
             "Getting score of member '{}' in sorted set '{}'",
@@ -161,7 +229,9 @@ impl StorageZSet for MemoryStorage {
                 if let Some(zset) = u.implementation.as_zset_mut() {
                     let initial_len = zset.len();
                     zset.retain(|m| m.member != member);
-                    return zset.len() < initial_len;
+                    let removed = zset.len() < initial_len;
+                    self.remove_if_empty(key);
+                    return removed;
                 }
                 false
             }
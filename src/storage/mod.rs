@@ -1,48 +1,163 @@
 pub mod comm_utils;
+pub mod encoding_config;
 mod file_utils;
+pub mod geo_shape;
+pub mod glob;
+pub mod hash_member;
 pub mod memory;
 pub mod repl_config;
-mod stream_member;
+pub mod stream_member;
 pub mod unit;
-mod zset_member;
+pub mod zset_member;
 
 pub use memory::MemoryStorage;
 pub use unit::Unit;
 
-use crate::commands::RedisCommand;
+use crate::commands::{BitRange, RedisCommand, ZAddOptions};
+use geo_shape::GeoShape;
+use zset_member::{ScoreBound, ZAddOutcome};
 
 pub trait Storage {
     fn get(&mut self, key: &str) -> Option<String>;
     fn set(&mut self, key: String, value: String);
+    // SET KEEPTTL: like set, but a live key keeps its expiry
+    fn set_keep_ttl(&mut self, key: String, value: String);
+    // GETSET: the previous string value, if any
+    fn getset(&mut self, key: String, value: String) -> Option<String>;
+    fn getdel(&mut self, key: &str) -> Option<String>;
+    // Only sets when the key is missing, returning whether it did
+    fn setnx(&mut self, key: String, value: String) -> bool;
     fn delete(&mut self, key: &str) -> bool;
-    fn exists(&self, key: &str) -> bool;
+    // Expired keys count as missing and are removed on the way, like in `get`
+    fn exists(&mut self, key: &str) -> bool;
     fn delete_multiple(&mut self, keys: Vec<String>) -> usize;
-    fn exists_multiple(&self, keys: &[String]) -> usize;
-    fn set_with_expiry(&mut self, key: String, value: String, expiry: u128);
-    fn incr(&mut self, key: String) -> Option<i64>;
-    fn config_get(&self, parameter: &str) -> Option<String>;
+    fn exists_multiple(&mut self, keys: &[String]) -> usize;
+    // MGET: None for keys that are missing or hold another type
+    fn get_multiple(&self, keys: &[String]) -> Vec<Option<String>>;
+    fn set_multiple(&mut self, pairs: Vec<(String, String)>);
+    // Deadlines here are Unix times in milliseconds
+    fn set_with_expiry(&mut self, key: String, value: String, deadline: u128);
+    // Expire a live key at `deadline`, or never with None
+    fn set_expiry(&mut self, key: &str, deadline: Option<u128>) -> bool;
+    // EXPIRE and friends: like set_expiry, but a past deadline deletes the key right away
+    fn expire(&mut self, key: &str, deadline: u128) -> bool;
+    // Drop a live key's expiry, returning whether it had one
+    fn persist(&mut self, key: &str) -> bool;
+    // Move the whole value, TTL included, over whatever `destination` held
+    fn rename(&mut self, key: &str, destination: String) -> Result<(), String>;
+    // An independent copy of the value and its TTL. False when the source is
+    // missing, or the destination exists and `replace` isn't set.
+    fn copy(&mut self, key: &str, destination: String, replace: bool) -> bool;
+    // Time left in seconds or milliseconds: -1 without an expiry, -2 when missing
+    fn ttl(&self, key: &str) -> i64;
+    fn pttl(&self, key: &str) -> i64;
+    // INCR, INCRBY, DECR and DECRBY
+    fn incr_by(&mut self, key: String, delta: i64) -> Result<i64, String>;
+    // Replies with the new value as it was stored
+    fn incr_by_float(&mut self, key: String, delta: f64) -> Result<String, String>;
+    fn strlen(&self, key: &str) -> usize;
+    fn getrange(&self, key: &str, start: i64, end: i64) -> String;
+    fn setrange(&mut self, key: String, offset: usize, value: String) -> Result<usize, String>;
+    // Returns the length after appending, creating the key when missing
+    fn append(&mut self, key: String, value: &str) -> Result<usize, String>;
+    fn bitcount(&self, key: &str, range: Option<&BitRange>) -> usize;
+    // Position of the first bit set to `bit`, -1 when there is none
+    fn bitpos(&self, key: &str, bit: bool, range: Option<&BitRange>) -> i64;
+    fn config_get(&self, pattern: &str) -> Vec<(String, String)>;
+    fn config_set(&mut self, parameter: &str, value: &str) -> Result<(), String>;
     fn get_keys(&self, pattern: &str) -> Vec<String>;
+    fn scan(&self, cursor: usize, pattern: Option<&str>, count: usize) -> (usize, Vec<String>);
+    fn random_key(&self) -> Option<String>;
 }
 
 pub trait StorageList {
-    fn rpush(&mut self, key: String, value: Vec<String>) -> usize;
+    // Pushes return the new length, None when the key holds another type
+    fn rpush(&mut self, key: String, value: Vec<String>) -> Option<usize>;
     fn lrange(&self, key: &str, start: i64, end: i64) -> Option<Vec<String>>;
-    fn lpush(&mut self, key: String, value: Vec<String>) -> usize;
+    fn lpush(&mut self, key: String, value: Vec<String>) -> Option<usize>;
+    // Hand pushed elements to clients blocked on the key, once the push
+    // itself has been propagated
+    fn serve_blocked_clients(&mut self, key: &str);
     fn llen(&self, key: &str) -> usize;
+    // None when the key is missing; a count of 0 pops nothing from a list
     fn lpop(&mut self, key: &str, count: usize) -> Option<Vec<String>>;
+    // Pops from the tail, last element first
+    fn rpop(&mut self, key: &str, count: usize) -> Option<Vec<String>>;
+    fn lindex(&self, key: &str, index: i64) -> Option<String>;
+    // Fails with "no such key" or "index out of range"
+    fn lset(&mut self, key: &str, index: i64, value: String) -> Result<(), String>;
+    // Keeps only the inclusive range, deleting the key when nothing is left
+    fn ltrim(&mut self, key: &str, start: i64, end: i64);
+    // Removes up to |count| occurrences, from the tail when count is negative
+    // and all of them when it is 0
+    fn lrem(&mut self, key: &str, count: i64, value: &str) -> usize;
+    // The new length, -1 when the pivot is missing and 0 when the key is
+    fn linsert(&mut self, key: &str, before: bool, pivot: &str, value: String) -> i64;
+    // Indexes of matches starting from the |rank|th one, from the tail when
+    // rank is negative. A count or maxlen of 0 means no limit.
+    fn lpos(&self, key: &str, element: &str, rank: i64, count: usize, maxlen: usize)
+        -> Vec<usize>;
     fn blpop(&mut self, keys: Vec<String>, token: mio::Token, timeout: u64) -> Option<Vec<String>>;
     fn brpop(&mut self, keys: Vec<String>, token: mio::Token, timeout: u64) -> Option<Vec<String>>;
 }
 
+pub trait StorageSet {
+    // Returns how many of the members were new
+    fn sadd(&mut self, key: String, members: Vec<String>) -> usize;
+    fn srem(&mut self, key: &str, members: &[String]) -> usize;
+    fn smembers(&self, key: &str) -> Vec<String>;
+    fn sismember(&self, key: &str, member: &str) -> bool;
+    fn scard(&self, key: &str) -> usize;
+    // Removes and returns up to `count` random members
+    fn spop(&mut self, key: &str, count: usize) -> Vec<String>;
+    // Missing keys count as empty sets
+    fn sinter(&self, keys: &[String]) -> Vec<String>;
+    fn sunion(&self, keys: &[String]) -> Vec<String>;
+    // Members of the first set that are in none of the others
+    fn sdiff(&self, keys: &[String]) -> Vec<String>;
+    // Replaces the destination with a set of the members, or deletes it when
+    // there are none. Returns the size of the stored set.
+    fn sstore(&mut self, destination: String, members: Vec<String>) -> usize;
+}
+
 pub trait StorageZSet {
-    fn zadd(&mut self, key: String, score: f64, member: String) -> usize;
+    // With `incr` the score is added to the member's current one
+    fn zadd(
+        &mut self,
+        key: String,
+        score: f64,
+        member: String,
+        options: &ZAddOptions,
+    ) -> ZAddOutcome;
     fn zrank(&self, key: &str, member: &str) -> Option<usize>;
     fn zrange(&self, key: &str, start: i64, end: i64) -> Option<Vec<String>>;
     fn zcard(&self, key: &str) -> usize;
+    // Members with their scores, lowest first
+    fn zrangebyscore(&self, key: &str, min: &ScoreBound, max: &ScoreBound) -> Vec<(String, f64)>;
+    fn zcount(&self, key: &str, min: &ScoreBound, max: &ScoreBound) -> usize;
     fn zscore(&self, key: &str, member: &str) -> Option<f64>;
+    // One entry per member, in the order asked for
+    fn zmscore(&self, key: &str, members: &[String]) -> Vec<Option<f64>>;
+    // Replaces the destination with the members, or deletes it when there are
+    // none. Returns the size of the stored set.
+    fn zstore(&mut self, destination: String, members: Vec<(String, f64)>) -> usize;
     fn zrem(&mut self, key: &str, member: &str) -> bool;
 }
 
+// Hashes keep fields in insertion order, which is the order Redis replies
+// with for small (listpack-encoded) hashes
+pub trait StorageHash {
+    // Returns how many of the fields were new
+    fn hset(&mut self, key: String, pairs: Vec<(String, String)>) -> usize;
+    fn hget(&self, key: &str, field: &str) -> Option<String>;
+    fn hdel(&mut self, key: &str, fields: &[String]) -> usize;
+    fn hgetall(&self, key: &str) -> Vec<(String, String)>;
+    fn hlen(&self, key: &str) -> usize;
+    fn hexists(&self, key: &str, field: &str) -> bool;
+    // Length of the field's value, 0 when the field or key is missing
+    fn hstrlen(&self, key: &str, field: &str) -> usize;
+}
+
 pub trait StorageStream {
     fn xadd(
         &mut self,
@@ -50,18 +165,72 @@ pub trait StorageStream {
         id: String,
         fields: Vec<(String, String)>,
     ) -> Result<String, String>;
+    // Wake readers blocked on the key, once the XADD itself has been propagated
+    fn serve_blocked_readers(&mut self, key: &str);
     fn xrange(
         &self,
         key: &str,
         start: String,
         end: String,
     ) -> Option<Vec<(String, Vec<(String, String)>)>>;
+    // `$` reads only entries added after the call, COUNT caps each stream
     fn xread(
         &mut self,
         token: mio::Token,
         block: Option<u64>,
+        count: Option<usize>,
         streams: Vec<(String, String)>,
-    ) -> Option<Vec<(String, Vec<(String, Vec<(String, String)>)>)>>;
+    ) -> Option<stream_member::StreamReadResult>;
+    fn xlen(&self, key: &str) -> usize;
+    fn xdel(&mut self, key: &str, ids: Vec<String>) -> usize;
+    fn xtrim(&mut self, key: &str, strategy: &str, threshold: &str) -> Result<usize, String>;
+    fn xinfo_stream(&self, key: &str) -> Option<stream_member::StreamInfo>;
+    fn xinfo_groups(&self, key: &str) -> Option<Vec<stream_member::GroupInfo>>;
+    // Errors carry their own Redis error code
+    fn xinfo_consumers(
+        &self,
+        key: &str,
+        group: &str,
+    ) -> Result<Vec<stream_member::ConsumerInfo>, String>;
+    // Create a group that delivers entries after `id`, or after the last one for "$"
+    fn xgroup_create(
+        &mut self,
+        key: &str,
+        group: String,
+        id: &str,
+        mkstream: bool,
+    ) -> Result<(), String>;
+    // Move the group's last delivered ID, "$" being the stream's last entry
+    fn xgroup_setid(
+        &mut self,
+        key: &str,
+        group: &str,
+        id: &str,
+        entries_read: Option<u64>,
+    ) -> Result<(), String>;
+    // Hand pending entries idle for at least `min_idle_time` milliseconds over
+    // to `consumer`, returning the claimed entries
+    fn xclaim(
+        &mut self,
+        key: &str,
+        group: &str,
+        consumer: &str,
+        min_idle_time: u64,
+        ids: &[String],
+        options: &stream_member::XClaimOptions,
+    ) -> Result<stream_member::StreamEntries, String>;
+    // Reading ">" delivers new entries and adds them to the consumer's pending
+    // entries, any other ID replays the consumer's pending entries after it.
+    // Errors carry their own Redis error code.
+    fn xreadgroup(
+        &mut self,
+        token: mio::Token,
+        group: &str,
+        consumer: &str,
+        count: Option<usize>,
+        block: Option<u64>,
+        streams: Vec<(String, String)>,
+    ) -> Result<Option<stream_member::StreamReadResult>, String>;
 }
 
 pub trait StorageGeo {
@@ -73,26 +242,49 @@ pub trait StorageGeo {
         member: String,
     ) -> Result<usize, String>;
     fn geopos(&self, key: &str, member: Vec<String>) -> Vec<Option<(f64, f64)>>;
+    // Distance in meters; GEODIST replies with 4 decimals like Redis
     fn geodist(&self, key: &str, member1: &str, member2: &str) -> Option<f64>;
     fn geosearch(
         &self,
         key: &str,
         longitude: f64,
         latitude: f64,
-        use_radius: bool,
-        distance: f64,
+        shape: GeoShape,
         unit: String,
     ) -> Option<Vec<String>>;
 }
 
 pub trait StoragePubSub {
-    fn subscribe(&mut self, token: mio::Token, channel: String) -> usize;
     fn publish(&mut self, channel: String, message: String) -> usize;
+    // The rest return the client's channel and pattern subscriptions combined
+    fn subscribe(&mut self, token: mio::Token, channel: String) -> usize;
+    fn psubscribe(&mut self, token: mio::Token, pattern: String) -> usize;
     fn unsubscribe(&mut self, token: mio::Token, channel: String) -> usize;
+    fn punsubscribe(&mut self, token: mio::Token, pattern: String) -> usize;
 }
 
 pub trait Replication {
     fn add_replication_client(&mut self, token: mio::Token);
+    // Forget a replica, or a client blocked in WAIT, once it disconnects
+    fn remove_replication_client(&mut self, token: mio::Token);
+    fn replica_count(&self) -> usize;
     fn send_file(&self, token: mio::Token);
-    fn replicate_command(&self, command: RedisCommand);
+    fn replicate_command(&mut self, command: RedisCommand);
+    // Buffer replicated commands until the end of a transaction
+    fn start_transaction_propagation(&mut self);
+    fn end_transaction_propagation(&mut self);
+    // Record REPLCONF ACK from a replica and serve any WAIT it satisfies
+    fn ack_replica(&mut self, token: mio::Token, offset: u64);
+    fn acked_replicas(&self, offset: u64) -> usize;
+    // Block a WAIT until `numreplicas` replicas ack `offset`, asking them for
+    // an ACK straight away
+    fn wait_for_replicas(
+        &mut self,
+        token: mio::Token,
+        offset: u64,
+        numreplicas: usize,
+        timeout: u64,
+    );
+    // The reply for a WAIT that timed out, None if the client wasn't waiting
+    fn take_wait_timeout(&mut self, token: mio::Token) -> Option<usize>;
 }
@@ -4,7 +4,6 @@ use rand::distr::{Alphanumeric, SampleString};
 pub struct MasterConfig {
     pub host: String,
     pub port: u16,
-    pub connected_slaves: usize,
     pub replication_id: String,
     pub replication_offset: u64,
 }
@@ -14,7 +13,6 @@ impl MasterConfig {
         MasterConfig {
             host,
             port,
-            connected_slaves: 0,
             replication_id: MasterConfig::generate_replication_id(),
             replication_offset: 0,
         }
@@ -24,10 +22,10 @@ impl MasterConfig {
         Alphanumeric.sample_string(&mut rand::rng(), 40)
     }
 
-    pub fn to_string(&self) -> String {
+    pub fn to_string(&self, connected_slaves: usize) -> String {
         format!(
             "role:master\nconnected_slaves:{}\nmaster_replid:{}\nmaster_repl_offset:{}",
-            self.connected_slaves, self.replication_id, self.replication_offset
+            connected_slaves, self.replication_id, self.replication_offset
         )
     }
 }
@@ -62,8 +60,8 @@ impl SlaveConfig {
 
     pub fn to_string(&self) -> String {
         format!(
-            "role:slave\nmaster_host:{}\nmaster_port:{}\nmaster_replid:{}\nmaster_repl_offset:{}\nconnected:{}",
-            self.master_host, self.master_port, self.replication_id, self.replication_offset, self.connected
+            "role:slave\nmaster_host:{}\nmaster_port:{}\nmaster_replid:{}\nslave_repl_offset:{}\nmaster_repl_offset:{}\nconnected:{}",
+            self.master_host, self.master_port, self.replication_id, self.replication_offset, self.replication_offset, self.connected
         )
     }
 }
@@ -90,9 +88,9 @@ impl ReplConfig {
         }
     }
 
-    pub fn to_string(&self) -> String {
+    pub fn to_string(&self, connected_slaves: usize) -> String {
         match self {
-            ReplConfig::Master(cfg) => cfg.to_string(),
+            ReplConfig::Master(cfg) => cfg.to_string(connected_slaves),
             ReplConfig::Slave(cfg) => cfg.to_string(),
         }
     }
@@ -118,4 +116,18 @@ impl ReplConfig {
             ReplConfig::Slave(cfg) => cfg.replication_id.clone(),
         }
     }
+
+    pub fn get_replication_offset(&self) -> u64 {
+        match self {
+            ReplConfig::Master(cfg) => cfg.replication_offset,
+            ReplConfig::Slave(cfg) => cfg.replication_offset,
+        }
+    }
+
+    pub fn add_replication_offset(&mut self, bytes: u64) {
+        match self {
+            ReplConfig::Master(cfg) => cfg.replication_offset += bytes,
+            ReplConfig::Slave(cfg) => cfg.replication_offset += bytes,
+        }
+    }
 }
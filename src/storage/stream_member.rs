@@ -1,9 +1,168 @@
+use std::collections::BTreeMap;
+use std::time::Instant;
+
 #[derive(Debug, Clone)]
 pub struct StreamMember {
     pub id: StreamId,
     pub fields: Vec<(String, String)>,
 }
 
+#[derive(Debug, Clone)]
+pub struct Stream {
+    pub entries: Vec<StreamMember>,
+    pub last_id: StreamId,  // Highest ID ever generated, survives deletions
+    pub entries_added: u64, // Total entries ever added, not the current length
+    pub max_deleted_id: StreamId, // Highest ID removed by XDEL or XTRIM
+    pub groups: BTreeMap<String, ConsumerGroup>,
+}
+
+// An entry delivered to a consumer but not yet acknowledged
+#[derive(Debug, Clone)]
+pub struct PendingEntry {
+    pub consumer: String,
+    pub delivery_count: u64,
+    pub delivery_time: u64, // unix time in milliseconds of the last delivery
+}
+
+// XCLAIM modifiers. Group reads reach replicas as an XCLAIM using them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct XClaimOptions {
+    pub idle: Option<u64>,       // milliseconds since the last delivery to record
+    pub time: Option<u64>,       // unix time in milliseconds of the last delivery
+    pub retrycount: Option<u64>, // delivery count to record
+    pub force: bool,             // claim entries that are not pending yet
+    pub justid: bool,            // reply with IDs, leaving the delivery count alone
+    pub lastid: Option<String>,  // move the group's last delivered ID up to this one
+}
+
+// When a consumer last tried to read, and last actually got entries
+#[derive(Debug, Clone)]
+pub struct Consumer {
+    pub seen_time: Instant,
+    pub active_time: Option<Instant>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsumerGroup {
+    pub last_delivered_id: StreamId,
+    pub pending: BTreeMap<StreamId, PendingEntry>, // the group's PEL
+    pub consumers: BTreeMap<String, Consumer>,
+    // Entries delivered since the stream began, None when it can't be known
+    // (the group started from an arbitrary ID)
+    pub entries_read: Option<u64>,
+}
+
+impl ConsumerGroup {
+    pub fn new(last_delivered_id: StreamId, entries_read: Option<u64>) -> Self {
+        ConsumerGroup {
+            last_delivered_id,
+            pending: BTreeMap::new(),
+            consumers: BTreeMap::new(),
+            entries_read,
+        }
+    }
+
+    // Record a read attempt by `name`, creating the consumer on first use
+    pub fn seen(&mut self, name: &str) -> &mut Consumer {
+        let consumer = self
+            .consumers
+            .entry(name.to_string())
+            .or_insert_with(|| Consumer {
+                seen_time: Instant::now(),
+                active_time: None,
+            });
+        consumer.seen_time = Instant::now();
+        consumer
+    }
+}
+
+impl Stream {
+    pub fn new() -> Self {
+        Stream {
+            entries: Vec::new(),
+            last_id: EMPTY_STREAM_ID,
+            entries_added: 0,
+            max_deleted_id: EMPTY_STREAM_ID,
+            groups: BTreeMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn push(&mut self, member: StreamMember) {
+        self.last_id = member.id.clone();
+        self.entries_added += 1;
+        self.entries.push(member);
+    }
+
+    // Remove the entries matching `predicate`, returning how many were removed
+    pub fn remove_where<F: Fn(&StreamMember) -> bool>(&mut self, predicate: F) -> usize {
+        let initial_len = self.entries.len();
+        let mut max_deleted_id = self.max_deleted_id.clone();
+        self.entries.retain(|member| {
+            if predicate(member) {
+                max_deleted_id = max_deleted_id.clone().max(member.id.clone());
+                false
+            } else {
+                true
+            }
+        });
+        self.max_deleted_id = max_deleted_id;
+        initial_len - self.entries.len()
+    }
+}
+
+impl Default for Stream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// One consumer group, as listed by XINFO GROUPS
+#[derive(Debug, Clone)]
+pub struct GroupInfo {
+    pub name: String,
+    pub consumers: usize,
+    pub pending: usize,
+    pub last_delivered_id: String,
+    pub entries_read: Option<u64>,
+    pub lag: Option<u64>, // entries not delivered to the group yet
+}
+
+// One consumer of a group, as listed by XINFO CONSUMERS. Times are in
+// milliseconds.
+#[derive(Debug, Clone)]
+pub struct ConsumerInfo {
+    pub name: String,
+    pub pending: usize,
+    pub idle: u64,
+    pub inactive: Option<u64>, // None until the consumer has read an entry
+}
+
+// Entry IDs with their fields, as claimed by XCLAIM
+pub type StreamEntries = Vec<(String, Vec<(String, String)>)>;
+
+// Entries per stream key, as returned by XREAD and XREADGROUP
+pub type StreamReadResult = Vec<(String, Vec<(String, Vec<(String, String)>)>)>;
+
+// Snapshot of a stream's metadata for XINFO STREAM
+#[derive(Debug, Clone)]
+pub struct StreamInfo {
+    pub length: usize,
+    pub last_generated_id: String,
+    pub max_deleted_id: String,
+    pub entries_added: u64,
+    pub groups: usize,
+    pub first_entry: Option<(String, Vec<(String, String)>)>,
+    pub last_entry: Option<(String, Vec<(String, String)>)>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct StreamId {
     pub timestamp: u64,
@@ -1,15 +1,17 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashSet};
+use std::time::Instant;
 use super::zset_member::ZSetMember;
-use super::stream_member::StreamMember;
+use super::stream_member::Stream;
+use super::hash_member::OrderedHash;
 
 #[derive(Debug, Clone)]
 pub enum Implementation {
     STRING(String),
     LIST(Vec<String>),
-    STREAM(Vec<StreamMember>),
-    SET,
+    STREAM(Stream),
+    SET(HashSet<String>),
     ZSET(BTreeSet<ZSetMember>), // (score, member)
-    HASH,
+    HASH(OrderedHash), // (field, value) in insertion order
 }
 
 impl Implementation {
@@ -26,7 +28,7 @@ impl Implementation {
     }
 
     pub fn is_set(&self) -> bool {
-        matches!(self, Implementation::SET)
+        matches!(self, Implementation::SET(_))
     }
 
     pub fn is_zset(&self) -> bool {
@@ -34,7 +36,20 @@ impl Implementation {
     }
 
     pub fn is_hash(&self) -> bool {
-        matches!(self, Implementation::HASH)
+        matches!(self, Implementation::HASH(_))
+    }
+
+    // Lists, sets, sorted sets and hashes are deleted once their last element is removed.
+    // Streams persist while empty, matching Redis, since they carry metadata
+    // such as the last generated ID.
+    pub fn is_empty_collection(&self) -> bool {
+        match self {
+            Implementation::LIST(l) => l.is_empty(),
+            Implementation::SET(s) => s.is_empty(),
+            Implementation::ZSET(z) => z.is_empty(),
+            Implementation::HASH(h) => h.is_empty(),
+            _ => false,
+        }
     }
 
     pub fn as_string(&self) -> Option<&String> {
@@ -45,6 +60,14 @@ impl Implementation {
         }
     }
 
+    pub fn as_string_mut(&mut self) -> Option<&mut String> {
+        if let Implementation::STRING(ref mut s) = self {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
     pub fn as_list(&self) -> Option<&Vec<String>> {
         if let Implementation::LIST(ref l) = self {
             Some(l)
@@ -77,6 +100,22 @@ impl Implementation {
         }
     }
 
+    pub fn as_set(&self) -> Option<&HashSet<String>> {
+        if let Implementation::SET(ref s) = self {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_set_mut(&mut self) -> Option<&mut HashSet<String>> {
+        if let Implementation::SET(ref mut s) = self {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
     pub fn as_zset(&self) -> Option<&BTreeSet<ZSetMember>> {
         if let Implementation::ZSET(ref z) = self {
             Some(z)
@@ -93,7 +132,23 @@ impl Implementation {
         }
     }
 
-    pub fn as_stream(&self) -> Option<&Vec<StreamMember>> {
+    pub fn as_hash(&self) -> Option<&OrderedHash> {
+        if let Implementation::HASH(ref h) = self {
+            Some(h)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_hash_mut(&mut self) -> Option<&mut OrderedHash> {
+        if let Implementation::HASH(ref mut h) = self {
+            Some(h)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_stream(&self) -> Option<&Stream> {
         if let Implementation::STREAM(ref s) = self {
             Some(s)
         } else {
@@ -101,7 +156,7 @@ impl Implementation {
         }
     }
 
-    pub fn as_stream_mut(&mut self) -> Option<&mut Vec<StreamMember>> {
+    pub fn as_stream_mut(&mut self) -> Option<&mut Stream> {
         if let Implementation::STREAM(ref mut s) = self {
             Some(s)
         } else {
@@ -114,6 +169,7 @@ impl Implementation {
 pub struct Unit {
     pub implementation: Implementation,
     pub expiry: Option<u128>,
+    pub last_access: Instant, // for OBJECT IDLETIME, reset by every access
 }
 
 impl Unit {
@@ -121,6 +177,7 @@ impl Unit {
         Unit {
             implementation: Implementation::STRING(value),
             expiry,
+            last_access: Instant::now(),
         }
     }
 
@@ -128,6 +185,15 @@ impl Unit {
         Unit {
             implementation: Implementation::LIST(value),
             expiry,
+            last_access: Instant::now(),
+        }
+    }
+
+    pub fn new_set(value: HashSet<String>, expiry: Option<u128>) -> Self {
+        Unit {
+            implementation: Implementation::SET(value),
+            expiry,
+            last_access: Instant::now(),
         }
     }
 
@@ -135,13 +201,23 @@ impl Unit {
         Unit {
             implementation: Implementation::ZSET(value),
             expiry,
+            last_access: Instant::now(),
+        }
+    }
+
+    pub fn new_hash(value: OrderedHash, expiry: Option<u128>) -> Self {
+        Unit {
+            implementation: Implementation::HASH(value),
+            expiry,
+            last_access: Instant::now(),
         }
     }
 
-    pub fn new_stream(value: Vec<StreamMember>, expiry: Option<u128>) -> Self {
+    pub fn new_stream(value: Stream, expiry: Option<u128>) -> Self {
         Unit {
             implementation: Implementation::STREAM(value),
             expiry,
+            last_access: Instant::now(),
         }
     }
 
@@ -155,4 +231,12 @@ impl Unit {
         }
         false
     }
+
+    pub fn touch(&mut self) {
+        self.last_access = Instant::now();
+    }
+
+    pub fn idle_seconds(&self) -> u64 {
+        self.last_access.elapsed().as_secs()
+    }
 }
@@ -1,5 +1,54 @@
 use std::cmp::Ordering;
 
+// What a ZADD did to one member
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZAddOutcome {
+    Added,
+    Updated,   // existing member, new score
+    Unchanged, // existing member, same score
+    Skipped,   // left alone because of NX, XX, GT or LT
+    NaN,       // left alone because INCR would have made the score NaN
+}
+
+// One end of a ZRANGEBYSCORE interval: a score, `(score` to exclude it, or
+// -inf/+inf
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoreBound {
+    pub score: f64,
+    pub exclusive: bool,
+}
+
+impl ScoreBound {
+    pub fn parse(bound: &str) -> Result<Self, String> {
+        let (score, exclusive) = match bound.strip_prefix('(') {
+            Some(score) => (score, true),
+            None => (bound, false),
+        };
+        match score.parse::<f64>() {
+            Ok(score) if !score.is_nan() => Ok(ScoreBound { score, exclusive }),
+            _ => Err("min or max is not a float".to_string()),
+        }
+    }
+
+    // Whether `score` is on the inner side of this bound used as a minimum
+    pub fn allows_above(&self, score: f64) -> bool {
+        if self.exclusive {
+            score > self.score
+        } else {
+            score >= self.score
+        }
+    }
+
+    // Whether `score` is on the inner side of this bound used as a maximum
+    pub fn allows_below(&self, score: f64) -> bool {
+        if self.exclusive {
+            score < self.score
+        } else {
+            score <= self.score
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ZSetMember {
     pub score: f64,
@@ -7,8 +56,11 @@ pub struct ZSetMember {
 }
 
 impl ZSetMember {
+    // Exact, like Redis: a tolerance would tie distinct small scores, and
+    // ties are ordered by member, so the set's order would stop following
+    // the scores (and stop being transitive)
     fn same_score(&self, other: &Self) -> bool {
-        (self.score - other.score).abs() < f64::EPSILON
+        self.score == other.score
     }
 
     fn is_nan(&self) -> bool {